@@ -0,0 +1,123 @@
+//! Measures the allocations of the listing read path: an owned copy of the messages
+//! ([`data::get_all_sorted`]) against the shared snapshot ([`data::get_all_shared`]), each
+//! serialized to JSON the way `GET /api/posts` does.
+//!
+//! Run with `cargo bench --bench read_path`. The store holds [`MESSAGES`] generated messages in a
+//! temporary data file, served from memory.
+
+use actix_posts::handler::data::{self, Message, SortOrder};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// The number of messages in the store.
+const MESSAGES: i32 = 10_000;
+
+/// The number of reads measured per variant.
+const ROUNDS: u32 = 100;
+
+/// Counts the allocations made through the system allocator.
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+/// The allocations and time spent by [`ROUNDS`] reads.
+struct Measurement {
+    allocations: usize,
+    bytes: usize,
+    elapsed: Duration,
+}
+
+fn measure(read: impl Fn() -> usize) -> Measurement {
+    // Warm the snapshot up, so only the reads themselves are counted.
+    read();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes = ALLOCATED_BYTES.load(Ordering::Relaxed);
+    let started = Instant::now();
+    for _ in 0..ROUNDS {
+        std::hint::black_box(read());
+    }
+    Measurement {
+        allocations: ALLOCATIONS.load(Ordering::Relaxed) - allocations,
+        bytes: ALLOCATED_BYTES.load(Ordering::Relaxed) - bytes,
+        elapsed: started.elapsed(),
+    }
+}
+
+fn report(name: &str, measurement: &Measurement) {
+    println!(
+        "{:<24} {:>12} {:>14} {:>12.2?}",
+        name,
+        measurement.allocations / ROUNDS as usize,
+        measurement.bytes / ROUNDS as usize,
+        measurement.elapsed / ROUNDS
+    );
+}
+
+fn main() {
+    let dir = std::env::temp_dir().join(format!("actix-posts-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("data.json");
+    let messages: Vec<Message> = (1..=MESSAGES)
+        .map(|id| Message {
+            id,
+            posted: format!(
+                "2024-01-01 {:02}:{:02}:{:02}Z",
+                id / 3600 % 24,
+                id / 60 % 60,
+                id % 60
+            ),
+            sender: format!("sender-{}", id % 50),
+            content: format!("message number {} of the read path benchmark", id),
+            ..Default::default()
+        })
+        .collect();
+    std::fs::write(&file, serde_json::to_string(&messages).unwrap()).unwrap();
+    std::env::set_var("ACTIX_POSTS_DATA_FILES", &file);
+    std::env::set_var("ACTIX_POSTS_IN_MEMORY", "1");
+    data::load_into_memory();
+
+    println!(
+        "{} messages, per read: {:<11} {:>12} {:>14} {:>12}",
+        MESSAGES, "", "allocations", "bytes", "time"
+    );
+    let owned = measure(|| {
+        let messages = data::get_all_sorted(SortOrder::Newest);
+        serde_json::to_vec(&messages).unwrap().len()
+    });
+    report("get_all_sorted + json", &owned);
+    let shared = measure(|| {
+        let messages = data::get_all_shared(SortOrder::Newest);
+        serde_json::to_vec(&messages).unwrap().len()
+    });
+    report("get_all_shared + json", &shared);
+    println!(
+        "the shared snapshot saves {} allocations and {} bytes per read",
+        owned.allocations.saturating_sub(shared.allocations) / ROUNDS as usize,
+        owned.bytes.saturating_sub(shared.bytes) / ROUNDS as usize
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
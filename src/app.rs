@@ -0,0 +1,297 @@
+//! Assembly of the application: routes, shared state, and middleware.
+//!
+//! `main.rs` serves [`build`] on every worker through [`serve`]; tests serve the same application
+//! through [`actix_web::test::init_service`].
+
+use crate::config;
+use crate::handler::api::{
+    api_admin_show, api_batch_update, api_bulk_delete, api_bulk_tag, api_changes, api_create,
+    api_default, api_delete, api_diff, api_export, api_export_post, api_export_sign, api_first,
+    api_grouped, api_import, api_index, api_latest, api_patch, api_poll, api_preview, api_purge,
+    api_random, api_raw, api_react, api_reload, api_repair, api_search, api_senders,
+    api_set_status, api_set_tags, api_show, api_stats_daily, api_stats_range, api_stats_storage,
+    api_touch, api_update, api_upload, json_config, API_VERSION_HEADER,
+};
+use crate::handler::client_ip::client_ip;
+use crate::handler::events::api_stream;
+use crate::handler::feed::{ics, rss, sitemap};
+use crate::handler::health::health;
+use crate::handler::middleware::{concurrency_limit, maintenance_guard, string_ids};
+use crate::handler::routes::{
+    create, destroy, display_time, edit, index, login, login_form, logout, moderation,
+    moderation_confirm, moderation_delete, new, not_found, react, restore, robots, root, show,
+    show_diff, update,
+};
+use crate::handler::signing::UrlSigner;
+use crate::handler::upload::UPLOADS_PATH;
+use actix_files::Files;
+use actix_session::storage::CookieSessionStore;
+use actix_session::SessionMiddleware;
+use actix_web::body::MessageBody;
+use actix_web::cookie::Key;
+use actix_web::dev::{Server, ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::middleware::{from_fn, DefaultHeaders, Logger, NormalizePath};
+use actix_web::{web, App, Error, HttpServer};
+use actix_web_flash_messages::storage::SessionMessageStore;
+use actix_web_flash_messages::FlashMessagesFramework;
+use std::net::TcpListener;
+
+fn build_cookie_session_middleware(key: Key) -> SessionMiddleware<CookieSessionStore> {
+    SessionMiddleware::builder(CookieSessionStore::default(), key).build()
+}
+
+fn build_logger() -> Logger {
+    let logger = Logger::new(r#"%{client_ip}xi "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T"#)
+        .custom_request_replace("client_ip", |req| {
+            client_ip(req.peer_addr(), req.headers())
+                .map(|ip| ip.to_string())
+                .unwrap_or_else(|| "-".to_string())
+        });
+    config::get()
+        .log_exclude
+        .iter()
+        .fold(logger, |logger, path| logger.exclude(path.clone()))
+}
+
+/// Registers version 1 of the API. Mounted under both `/api/v1` and `/api`, so it can be
+/// reused when a `v2` scope is added.
+pub fn api_v1(cfg: &mut web::ServiceConfig) {
+    cfg.app_data(json_config())
+        .service(api_index)
+        .service(api_show)
+        .service(api_first)
+        .service(api_latest)
+        .service(api_random)
+        .service(api_poll)
+        .service(api_changes)
+        .service(api_grouped)
+        .service(api_search)
+        .service(api_create)
+        .service(api_preview)
+        .service(api_upload)
+        .service(api_update)
+        .service(api_patch)
+        .service(api_batch_update)
+        .service(api_diff)
+        .service(api_react)
+        .service(api_set_tags)
+        .service(api_bulk_tag)
+        .service(api_set_status)
+        .service(api_touch)
+        .service(api_export_post)
+        .service(api_delete)
+        .service(api_stats_range)
+        .service(api_stats_daily)
+        .service(api_stats_storage)
+        .service(api_senders)
+        .service(api_stream)
+        .service(api_bulk_delete)
+        .service(api_import)
+        .service(api_purge)
+        .service(api_admin_show)
+        .service(api_raw)
+        .service(api_repair)
+        .service(api_reload)
+        .service(api_export)
+        .service(api_export_sign)
+        .default_service(web::to(api_default));
+}
+
+/// Adds the `X-Api-Version` header to every response of an API scope.
+fn api_version(version: &str) -> DefaultHeaders {
+    DefaultHeaders::new().add((API_VERSION_HEADER, version))
+}
+
+/// Loads the HTML templates and registers their custom filters.
+pub fn templates() -> tera::Tera {
+    let mut tera = tera::Tera::new("templates/**/*.html").unwrap();
+    tera.register_filter("display_time", display_time);
+    tera
+}
+
+/// Builds the application served by each worker.
+///
+/// # Arguments
+/// - `key`: The secret of the session cookies, also used to sign export URLs (see
+///   [`UrlSigner`]). Every worker must get the same key.
+pub fn build(
+    key: Key,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody>,
+        Error = Error,
+        InitError = (),
+    >,
+> {
+    let message_framework = FlashMessagesFramework::builder(SessionMessageStore::default()).build();
+    App::new()
+        .app_data(web::Data::new(templates()))
+        .app_data(web::Data::new(UrlSigner::new(key.signing())))
+        .service(root)
+        .service(index)
+        .service(new)
+        .service(create)
+        .service(edit)
+        .service(update)
+        .service(destroy)
+        .service(restore)
+        .service(show)
+        .service(show_diff)
+        .service(react)
+        .service(login_form)
+        .service(login)
+        .service(logout)
+        .service(moderation)
+        .service(moderation_confirm)
+        .service(moderation_delete)
+        .service(rss)
+        .service(ics)
+        .service(sitemap)
+        .service(robots)
+        .service(health)
+        .service(Files::new(UPLOADS_PATH, &config::get().upload_dir))
+        .service(
+            web::scope("/api/v1")
+                .configure(api_v1)
+                .wrap(from_fn(string_ids))
+                .wrap(api_version("1")),
+        )
+        // Unversioned paths are an alias of the current version.
+        .service(
+            web::scope("/api")
+                .configure(api_v1)
+                .wrap(from_fn(string_ids))
+                .wrap(api_version("1")),
+        )
+        .default_service(web::to(not_found))
+        .wrap(from_fn(maintenance_guard))
+        .wrap(from_fn(concurrency_limit))
+        // `/api/posts/` and `/api/posts` reach the same handler.
+        .wrap(NormalizePath::trim())
+        .wrap(build_logger())
+        .wrap(message_framework)
+        .wrap(build_cookie_session_middleware(key))
+}
+
+/// Starts a server running [`build`] on `listener`, with the given session `key`.
+///
+/// With `ACTIX_POSTS_HTTP2` the listener also accepts cleartext HTTP/2 from clients that start
+/// with the HTTP/2 preface; other connections are served over HTTP/1.1 either way.
+pub fn serve(key: Key, listener: TcpListener) -> std::io::Result<Server> {
+    let server = HttpServer::new(move || build(key.clone()));
+    let server = if config::get().http2 {
+        server.listen_auto_h2c(listener)?
+    } else {
+        server.listen(listener)?
+    };
+    Ok(server.run())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{self, TestEnv};
+    use actix_rt::net::TcpStream;
+    use actix_web::cookie::Key;
+    use actix_web::test::{self, TestRequest};
+    use std::net::TcpListener;
+    use std::sync::{Mutex, Once};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// The access log lines written so far.
+    static ACCESS_LOG: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+    /// Records the lines written by the access logger (see [`super::build_logger`]).
+    struct AccessLogRecorder;
+
+    impl log::Log for AccessLogRecorder {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata
+                .target()
+                .starts_with("actix_web::middleware::logger")
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                ACCESS_LOG.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[actix_web::test]
+    async fn excluded_paths_are_left_out_of_the_access_log() {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            log::set_logger(&AccessLogRecorder).unwrap();
+            log::set_max_level(log::LevelFilter::Info);
+        });
+        let _env = TestEnv::with(|config| {
+            config.log_exclude = vec!["/health".to_string(), "/robots.txt".to_string()];
+        });
+        let app = testing::service!();
+
+        for uri in ["/health", "/robots.txt", "/posts", "/api/posts"] {
+            test::call_service(&app, TestRequest::get().uri(uri).to_request()).await;
+        }
+        let logged = ACCESS_LOG.lock().unwrap().clone();
+        let logged = |path: &str| {
+            let request_line = format!("\"GET {} HTTP/1.1\"", path);
+            logged.iter().any(|line| line.contains(&request_line))
+        };
+        assert!(logged("/posts") && logged("/api/posts"));
+        assert!(!logged("/health") && !logged("/robots.txt"));
+    }
+
+    /// Sends `GET /robots.txt` over HTTP/2 with prior knowledge.
+    async fn get_over_http2(
+        stream: TcpStream,
+    ) -> Result<http::Response<h2::RecvStream>, h2::Error> {
+        let (mut client, connection) = h2::client::handshake(stream).await?;
+        actix_rt::spawn(async move {
+            let _ = connection.await;
+        });
+        let request = http::Request::get("http://localhost/robots.txt")
+            .body(())
+            .unwrap();
+        let (response, _) = client.send_request(request, true)?;
+        response.await
+    }
+
+    #[actix_web::test]
+    async fn http2_is_accepted_next_to_http1_when_enabled() {
+        for http2 in [true, false] {
+            let _env = TestEnv::with(|config| config.http2 = http2);
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let address = listener.local_addr().unwrap();
+            let server = super::serve(Key::generate(), listener).unwrap();
+            let handle = server.handle();
+            actix_rt::spawn(server);
+
+            let stream = TcpStream::connect(address).await.unwrap();
+            match get_over_http2(stream).await {
+                Ok(response) if http2 => {
+                    assert_eq!(response.version(), http::Version::HTTP_2);
+                    assert_eq!(response.status(), http::StatusCode::OK);
+                }
+                Ok(response) => panic!("HTTP/2 is disabled, got {:?}", response),
+                Err(error) => assert!(!http2, "{}", error),
+            }
+
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            stream
+                .write_all(
+                    b"GET /robots.txt HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                )
+                .await
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+
+            handle.stop(true).await;
+        }
+    }
+}
@@ -0,0 +1,536 @@
+//! Runtime configuration read from environment variables.
+//!
+//! The configuration is loaded once, the first time [`get`] is called (the server does this at
+//! startup), and is shared by the handlers and the data layer for the lifetime of the process.
+//!
+//! ## Variables
+//!
+//! - **`ACTIX_POSTS_MAINTENANCE`**: When set to a truthy value (`1`, `true`, `yes`, `on`), every
+//!   mutating route answers `503 Service Unavailable` while reads keep working.
+//! - **`ACTIX_POSTS_DATA_FILES`**: A comma-separated list of JSON data files. Reads merge every
+//!   file (the first file wins on duplicate ids), while writes only go to the first (primary)
+//!   file. Defaults to `data.json`.
+//! - **`ACTIX_POSTS_MAX_CONTENT`**: The maximum number of characters allowed in a message's
+//!   content. Defaults to `2000`.
+//! - **`ACTIX_POSTS_MIN_CONTENT`**: The minimum number of characters in a message's content,
+//!   counted after trimming surrounding whitespace, e.g. `3` to refuse "+1" posts. Defaults to
+//!   `1`; `0` is treated as `1`, since content is always required.
+//! - **`ACTIX_POSTS_TRUSTED_PROXIES`**: A comma-separated list of CIDR ranges (e.g.
+//!   `127.0.0.1/32,10.0.0.0/8`) whose `X-Forwarded-For` header is trusted. Empty by default, so
+//!   the header is ignored. Invalid entries are skipped with a warning.
+//! - **`ACTIX_POSTS_UNIQUE_SENDER_CONTENT`**: When truthy, creating a message whose sender and
+//!   content exactly match an existing message is rejected. Disabled by default.
+//! - **`ACTIX_POSTS_EDIT_WINDOW_MINUTES`**: The number of minutes after posting during which a
+//!   message can be edited. Later updates are refused, except for API requests carrying the API
+//!   key. When unset, messages can always be edited.
+//! - **`ACTIX_POSTS_MAX_BODY`**: The maximum size in bytes of an API request body after
+//!   decompression (bodies may be sent with `Content-Encoding: gzip`). Larger bodies are rejected
+//!   with `413 Payload Too Large`. Defaults to `2097152` (2 MiB).
+//! - **`ACTIX_POSTS_UPLOAD_DIR`**: The directory uploaded images are stored in and served from
+//!   under `/uploads`. Created at startup. Defaults to `uploads`.
+//! - **`ACTIX_POSTS_MAX_UPLOAD`**: The maximum size in bytes of an uploaded image. Larger files are
+//!   rejected with `413 Payload Too Large`. Defaults to `5242880` (5 MiB).
+//! - **`ACTIX_POSTS_ROBOTS_DISALLOW`**: A comma-separated list of path patterns served as
+//!   `Disallow` rules in `/robots.txt`. Defaults to `/posts/*/delete,/posts/*/edit,/posts/new`;
+//!   set it to an empty value to allow everything.
+//! - **`ACTIX_POSTS_API_KEY`**: The key expected in the `X-Api-Key` header of privileged API
+//!   requests. When unset, privileged routes are disabled.
+//! - **`ACTIX_POSTS_PURGE_TOKEN`**: The confirmation token required by `POST /api/admin/purge`.
+//!   When unset, purging is disabled.
+//! - **`ACTIX_POSTS_ALLOW_ANONYMOUS`**: When falsy (`0`, `false`, `no`, `off`), posts must name
+//!   their sender: an empty sender or `anonymous` is rejected, and the new post form no longer
+//!   suggests `anonymous`. Enabled by default.
+//! - **`ACTIX_POSTS_HISTORY_FILE`**: The JSON file the previous revisions of edited messages are
+//!   kept in. Defaults to `history.json`.
+//! - **`ACTIX_POSTS_CHANGES_FILE`**: The JSON file the change journal read by
+//!   `GET /api/posts/changes` is kept in (see [`crate::handler::data::changes_since`]). Defaults
+//!   to `changes.json`.
+//! - **`ACTIX_POSTS_VIEWS_FILE`**: The JSON file the number of views of each post page is kept in,
+//!   used by the "most viewed" order of the board. Views are counted in memory and written every
+//!   [`crate::handler::data::VIEWS_FLUSH_INTERVAL`]. Defaults to `views.json`.
+//! - **`ACTIX_POSTS_SITEMAP_MAX_URLS`**: The maximum number of URLs in one `/sitemap.xml` document.
+//!   Larger sitemaps are split into pages listed by a sitemap index. Defaults to `50000`, the
+//!   limit of the sitemap protocol.
+//! - **`ACTIX_POSTS_FILTER_WORDS`**: A comma-separated list of words that may not appear in the
+//!   sender or content of a message, matched case-insensitively. Empty by default.
+//! - **`ACTIX_POSTS_FILTER_MODE`**: `reject` (default) refuses messages containing a filtered
+//!   word with a validation error; `mask` stores them with the words replaced by `*`s.
+//! - **`ACTIX_POSTS_MAX_IN_FLIGHT`**: The maximum number of requests handled at the same time.
+//!   Further requests are answered with `503 Service Unavailable` and a `Retry-After` header.
+//!   Unset or `0` means unlimited.
+//! - **`ACTIX_POSTS_MAX_SUBSCRIBERS`**: The maximum number of clients connected to
+//!   `GET /api/stream` at the same time. Further clients are answered with
+//!   `503 Service Unavailable`. Unset or `0` means unlimited.
+//! - **`ACTIX_POSTS_ROOT_REDIRECT`**: The path or URL `/` redirects to. Defaults to `/posts`.
+//! - **`ACTIX_POSTS_CACHE_TTL`**: The maximum age in seconds of a cached `GET /api/posts` response.
+//!   Cached responses are dropped on every write anyway; the TTL only bounds how long a change
+//!   made outside the server (e.g. an edited data file) can go unnoticed. `0` disables the cache.
+//!   Defaults to `30`.
+//! - **`ACTIX_POSTS_MAX_TAGS`**: The maximum number of tags on a message, counted after
+//!   normalization. Defaults to `10`.
+//! - **`ACTIX_POSTS_MAX_TAG_LENGTH`**: The maximum number of characters in a tag. Defaults to
+//!   `30`.
+//! - **`ACTIX_POSTS_STRINGS_FILE`**: A JSON file overriding the wording of flash messages, see
+//!   [`crate::handler::strings`]. Unset by default, which keeps the built-in texts.
+//! - **`ACTIX_POSTS_LOG_EXCLUDE`**: A comma-separated list of request paths left out of the access
+//!   log, matched exactly (e.g. the probes of an orchestrator). Defaults to
+//!   `/health,/favicon.ico`; set it to an empty value to log every request.
+//! - **`ACTIX_POSTS_XML_INVALID_CHARS`**: What `?format=xml` does with characters XML cannot
+//!   represent, such as control characters in a message: `reject` (default) answers
+//!   `406 Not Acceptable`, `strip` removes them, and `replace` substitutes `U+FFFD`. See
+//!   [`crate::handler::xml`].
+//! - **`ACTIX_POSTS_POST_COOLDOWN_SECONDS`**: The minimum number of seconds between two posts
+//!   from the same browser session on the web form. Earlier attempts re-render the form with an
+//!   error. Unset or `0` disables the cooldown. The API is not affected.
+//! - **`ACTIX_POSTS_WRITE_RETRIES`**: How many more times a write to a data file is attempted after
+//!   a transient failure (e.g. an interrupted system call) before giving up. Defaults to `2`;
+//!   `0` disables retries.
+//! - **`ACTIX_POSTS_WRITE_RETRY_DELAY_MS`**: The pause in milliseconds before the first retry of
+//!   a write to the primary data file, doubled before each further retry. The pause holds up
+//!   neither the worker nor other writes, and the change is applied again to the stored messages
+//!   afterwards. The history, journal, and views files are retried at once. Defaults to `50`.
+//! - **`ACTIX_POSTS_MAX_SENDER`**: The maximum number of characters allowed in a message's sender.
+//!   Defaults to `80`.
+//! - **`ACTIX_POSTS_RESERVED_SENDERS`**: A comma-separated list of sender names, matched
+//!   case-insensitively, that only API requests carrying the API key, or a user logged in under
+//!   that name (see `ACTIX_POSTS_USERS`), may post under. Defaults to `admin,system,moderator`;
+//!   set it to an empty value to reserve nothing.
+//! - **`ACTIX_POSTS_STRICT_JSON`**: When truthy, API request bodies with a field the endpoint does
+//!   not know (e.g. a misspelled `sendr`) are rejected with `400 Bad Request` naming the field,
+//!   instead of the field being ignored. Disabled by default. See [`crate::handler::strict`].
+//! - **`ACTIX_POSTS_DISPLAY_TZ`**: The time zone the HTML pages show timestamps in: `local` (the
+//!   server's zone, default), `utc`, or a fixed offset such as `+09:00`. Timestamps are stored in
+//!   UTC whatever this setting (see [`crate::handler::data::STORED_FORMAT`]).
+//! - **`ACTIX_POSTS_MAX_PER_SENDER`**: The maximum number of posts a sender may have. Unset or
+//!   `0` means unlimited.
+//! - **`ACTIX_POSTS_SENDER_QUOTA_POLICY`**: What happens to a post beyond that number: `reject`
+//!   (default) refuses it, `evict` removes the sender's oldest post to make room (see
+//!   [`crate::handler::data::QuotaPolicy`]).
+//! - **`ACTIX_POSTS_BATCH_MODE`**: How `PUT /api/posts/batch` handles a batch in which some
+//!   updates fail: `strict` (default) writes nothing, `lenient` writes the others (see
+//!   [`crate::handler::data::BatchMode`]).
+//! - **`ACTIX_POSTS_SLUG_STRATEGY`**: How the slug of a new post is made unique when another post
+//!   already has it: `id-suffix` (default) appends the post's ID (`hello-world-42`),
+//!   `counter-suffix` the first free number from 2 (`hello-world-2`). See
+//!   [`crate::handler::data::SlugStrategy`].
+//! - **`ACTIX_POSTS_IN_MEMORY`**: When truthy, every data file is loaded into memory at startup
+//!   and all reads are served from there; writes update both memory and the primary file.
+//!   Changes made to the files outside the server are only picked up by `POST
+//!   /api/admin/reload` (see [`crate::handler::data::reload`]). Disabled by default, in which case
+//!   the files are checked for changes on every read.
+//! - **`ACTIX_POSTS_HTTP2`**: When truthy, the listener also accepts cleartext HTTP/2 (h2c) from
+//!   clients that start with the HTTP/2 preface ("prior knowledge"); other connections keep using
+//!   HTTP/1.1. Disabled by default. The server does not terminate TLS, so HTTP/2 over TLS is left
+//!   to a reverse proxy. Keep-alive keeps its default of 5 seconds: HTTP/1.1 closes idle
+//!   connections after it, while HTTP/2 uses it as the interval of its PING frames and closes the
+//!   connection when one goes unanswered. Responses are not compressed by the server under either
+//!   protocol; HTTP/2 only compresses headers (HPACK).
+//! - **`ACTIX_POSTS_USERS`**: A whitespace-separated list of `name:hash` accounts posters log in
+//!   with at `/login`, where the hash is the salted Argon2 hash of the password as a PHC string
+//!   (e.g. from `printf %s "$PASSWORD" | argon2 "$(openssl rand -base64 12)" -id -e`). Entries
+//!   are not separated by commas, since the hashes contain some. When set, posting and editing
+//!   require a login, and posts are made and kept under the name of the logged-in user (see
+//!   [`crate::handler::auth`]). Invalid entries are
+//!   skipped with a warning. Empty by default, in which case anyone may post under any name.
+//! - **`ACTIX_POSTS_UNDO_SECONDS`**: How many seconds after deleting a post from its page the
+//!   deletion can be undone, by the same session only, with the button the list shows meanwhile.
+//!   Defaults to `30`; `0` disables undo. Deleted posts are kept in memory for that long, so a restart forgets them.
+//! - **`ACTIX_POSTS_MODERATORS`**: A comma-separated list of account names from
+//!   `ACTIX_POSTS_USERS` that may use the moderation page at `/moderation`, e.g. to delete every
+//!   post of a sender. Empty by default, in which case nobody can.
+
+use crate::handler::auth::Account;
+use crate::handler::client_ip::Cidr;
+use crate::handler::data::{BatchMode, DisplayZone, QuotaPolicy, SlugStrategy};
+use crate::handler::filter::FilterMode;
+use crate::handler::validation::MAX_SENDER_LEN;
+use crate::handler::xml::InvalidCharMode;
+use std::sync::LazyLock;
+
+static CONFIG: LazyLock<Config> = LazyLock::new(Config::from_env);
+
+static DEFAULT_DATA_FILENAME: &str = "data.json";
+
+const DEFAULT_MAX_CONTENT: usize = 2000;
+
+const DEFAULT_MIN_CONTENT: usize = 1;
+
+const DEFAULT_MAX_BODY: usize = 2 * 1024 * 1024;
+
+static DEFAULT_UPLOAD_DIR: &str = "uploads";
+
+static DEFAULT_ROBOTS_DISALLOW: [&str; 3] = ["/posts/*/delete", "/posts/*/edit", "/posts/new"];
+
+const DEFAULT_MAX_UPLOAD: usize = 5 * 1024 * 1024;
+
+static DEFAULT_HISTORY_FILENAME: &str = "history.json";
+
+static DEFAULT_CHANGES_FILENAME: &str = "changes.json";
+
+static DEFAULT_VIEWS_FILENAME: &str = "views.json";
+
+const DEFAULT_CACHE_TTL: u64 = 30;
+
+static DEFAULT_ROOT_REDIRECT: &str = "/posts";
+
+const DEFAULT_SITEMAP_MAX_URLS: usize = 50_000;
+
+const DEFAULT_MAX_TAGS: usize = 10;
+
+const DEFAULT_MAX_TAG_LENGTH: usize = 30;
+
+const DEFAULT_WRITE_RETRIES: u32 = 2;
+
+const DEFAULT_WRITE_RETRY_DELAY_MS: u64 = 50;
+
+const DEFAULT_UNDO_SECONDS: u64 = 30;
+
+static DEFAULT_RESERVED_SENDERS: [&str; 3] = ["admin", "system", "moderator"];
+
+static DEFAULT_LOG_EXCLUDE: [&str; 2] = ["/health", "/favicon.ico"];
+
+/// Settings that control the behavior of the application.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Whether the board is in maintenance mode (writes are rejected).
+    pub maintenance: bool,
+
+    /// The data files to read, primary (writable) file first. Never empty.
+    pub data_files: Vec<String>,
+
+    /// The maximum number of characters allowed in a message's content.
+    pub max_content: usize,
+
+    /// The minimum number of characters in a message's content, after trimming. At least `1`.
+    pub min_content: usize,
+
+    /// The proxies allowed to report the client address through `X-Forwarded-For`.
+    pub trusted_proxies: Vec<Cidr>,
+
+    /// Whether `(sender, content)` pairs must be unique across messages.
+    pub unique_sender_content: bool,
+
+    /// The number of minutes after posting during which a message can be edited.
+    pub edit_window_minutes: Option<i64>,
+
+    /// The maximum size in bytes of a decompressed API request body.
+    pub max_body: usize,
+
+    /// The directory uploaded images are stored in.
+    pub upload_dir: String,
+
+    /// The maximum size in bytes of an uploaded image.
+    pub max_upload: usize,
+
+    /// The path patterns crawlers are asked not to visit.
+    pub robots_disallow: Vec<String>,
+
+    /// The key that authenticates privileged API requests.
+    pub api_key: Option<String>,
+
+    /// The confirmation token required to purge the store.
+    pub purge_token: Option<String>,
+
+    /// The file the revisions of edited messages are stored in.
+    pub history_file: String,
+
+    /// The file the change journal is stored in.
+    pub changes_file: String,
+
+    /// The file the view counts of the post pages are stored in.
+    pub views_file: String,
+
+    /// Whether messages may be posted as `anonymous`.
+    pub allow_anonymous: bool,
+
+    /// The maximum age in seconds of a cached API response (`0` disables caching).
+    pub cache_ttl: u64,
+
+    /// The path or URL the root path redirects to.
+    pub root_redirect: String,
+
+    /// The maximum number of requests handled at the same time, if limited.
+    pub max_in_flight: Option<usize>,
+
+    /// The maximum number of event stream subscribers, `None` for no limit.
+    pub max_subscribers: Option<usize>,
+
+    /// The words that may not appear in messages.
+    pub filter_words: Vec<String>,
+
+    /// Whether messages containing filtered words are rejected or masked.
+    pub filter_mode: FilterMode,
+
+    /// The maximum number of URLs in one sitemap document. Never `0`.
+    pub sitemap_max_urls: usize,
+
+    /// The file overriding the wording of flash messages, if any.
+    pub strings_file: Option<String>,
+
+    /// The maximum number of tags on a message.
+    pub max_tags: usize,
+
+    /// The maximum number of characters in a tag.
+    pub max_tag_length: usize,
+
+    /// The request paths left out of the access log.
+    pub log_exclude: Vec<String>,
+
+    /// What XML responses do with characters XML cannot represent.
+    pub xml_invalid_chars: InvalidCharMode,
+
+    /// The minimum number of seconds between two posts from one session, if limited.
+    pub post_cooldown_seconds: Option<i64>,
+
+    /// The number of retries after a transient write failure.
+    pub write_retries: u32,
+
+    /// The pause in milliseconds before the first retry of a failed write.
+    pub write_retry_delay_ms: u64,
+
+    /// The maximum number of characters allowed in a message's sender.
+    pub max_sender: usize,
+
+    /// The sender names reserved to requests carrying the API key or logged in under them.
+    pub reserved_senders: Vec<String>,
+
+    /// Whether API request bodies with unknown fields are rejected.
+    pub strict_json: bool,
+
+    /// The time zone the HTML pages show timestamps in.
+    pub display_tz: DisplayZone,
+
+    /// The maximum number of posts per sender, if limited.
+    pub max_per_sender: Option<usize>,
+
+    /// How posts beyond `max_per_sender` are handled.
+    pub sender_quota_policy: QuotaPolicy,
+
+    /// How batch updates with failed items are handled.
+    pub batch_mode: BatchMode,
+
+    /// How colliding slugs are made unique.
+    pub slug_strategy: SlugStrategy,
+
+    /// Whether the data files are held in memory instead of being read on demand.
+    pub in_memory: bool,
+
+    /// Whether cleartext HTTP/2 is accepted next to HTTP/1.1.
+    pub http2: bool,
+
+    /// The accounts posters log in with. Empty when login is disabled.
+    pub users: Vec<Account>,
+
+    /// The names of the accounts that may moderate the board.
+    pub moderators: Vec<String>,
+
+    /// The number of seconds during which a deleted post can be restored, `0` if never.
+    pub undo_seconds: u64,
+}
+
+impl Config {
+    /// Builds the configuration from the process environment, falling back to defaults for
+    /// variables that are unset or cannot be parsed.
+    pub fn from_env() -> Self {
+        Config {
+            maintenance: env_flag("ACTIX_POSTS_MAINTENANCE", false),
+            data_files: env_list("ACTIX_POSTS_DATA_FILES")
+                .filter(|files| !files.is_empty())
+                .unwrap_or_else(|| vec![DEFAULT_DATA_FILENAME.to_string()]),
+            max_content: env_parse("ACTIX_POSTS_MAX_CONTENT").unwrap_or(DEFAULT_MAX_CONTENT),
+            min_content: env_parse("ACTIX_POSTS_MIN_CONTENT")
+                .unwrap_or(DEFAULT_MIN_CONTENT)
+                .max(1),
+            trusted_proxies: env_list("ACTIX_POSTS_TRUSTED_PROXIES")
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|entry| {
+                    entry
+                        .parse()
+                        .map_err(|error| log::warn!("ignoring trusted proxy {}", error))
+                        .ok()
+                })
+                .collect(),
+            unique_sender_content: env_flag("ACTIX_POSTS_UNIQUE_SENDER_CONTENT", false),
+            edit_window_minutes: env_parse("ACTIX_POSTS_EDIT_WINDOW_MINUTES")
+                .filter(|minutes: &i64| *minutes >= 0),
+            max_body: env_parse("ACTIX_POSTS_MAX_BODY").unwrap_or(DEFAULT_MAX_BODY),
+            upload_dir: env_string("ACTIX_POSTS_UPLOAD_DIR")
+                .unwrap_or_else(|| DEFAULT_UPLOAD_DIR.to_string()),
+            max_upload: env_parse("ACTIX_POSTS_MAX_UPLOAD").unwrap_or(DEFAULT_MAX_UPLOAD),
+            robots_disallow: env_list("ACTIX_POSTS_ROBOTS_DISALLOW").unwrap_or_else(|| {
+                DEFAULT_ROBOTS_DISALLOW
+                    .iter()
+                    .map(|path| path.to_string())
+                    .collect()
+            }),
+            api_key: env_string("ACTIX_POSTS_API_KEY"),
+            purge_token: env_string("ACTIX_POSTS_PURGE_TOKEN"),
+            history_file: env_string("ACTIX_POSTS_HISTORY_FILE")
+                .unwrap_or_else(|| DEFAULT_HISTORY_FILENAME.to_string()),
+            changes_file: env_string("ACTIX_POSTS_CHANGES_FILE")
+                .unwrap_or_else(|| DEFAULT_CHANGES_FILENAME.to_string()),
+            views_file: env_string("ACTIX_POSTS_VIEWS_FILE")
+                .unwrap_or_else(|| DEFAULT_VIEWS_FILENAME.to_string()),
+            allow_anonymous: env_flag("ACTIX_POSTS_ALLOW_ANONYMOUS", true),
+            cache_ttl: env_parse("ACTIX_POSTS_CACHE_TTL").unwrap_or(DEFAULT_CACHE_TTL),
+            root_redirect: env_string("ACTIX_POSTS_ROOT_REDIRECT")
+                .unwrap_or_else(|| DEFAULT_ROOT_REDIRECT.to_string()),
+            max_in_flight: env_parse("ACTIX_POSTS_MAX_IN_FLIGHT").filter(|max: &usize| *max > 0),
+            max_subscribers: env_parse("ACTIX_POSTS_MAX_SUBSCRIBERS")
+                .filter(|max: &usize| *max > 0),
+            filter_words: env_list("ACTIX_POSTS_FILTER_WORDS").unwrap_or_default(),
+            filter_mode: env_string("ACTIX_POSTS_FILTER_MODE")
+                .and_then(|mode| {
+                    FilterMode::parse(&mode.to_ascii_lowercase()).or_else(|| {
+                        log::warn!("ignoring unknown filter mode {}", mode);
+                        None
+                    })
+                })
+                .unwrap_or_default(),
+            sitemap_max_urls: env_parse("ACTIX_POSTS_SITEMAP_MAX_URLS")
+                .filter(|max: &usize| *max > 0)
+                .unwrap_or(DEFAULT_SITEMAP_MAX_URLS),
+            strings_file: env_string("ACTIX_POSTS_STRINGS_FILE"),
+            max_tags: env_parse("ACTIX_POSTS_MAX_TAGS").unwrap_or(DEFAULT_MAX_TAGS),
+            max_tag_length: env_parse("ACTIX_POSTS_MAX_TAG_LENGTH")
+                .unwrap_or(DEFAULT_MAX_TAG_LENGTH),
+            log_exclude: env_list("ACTIX_POSTS_LOG_EXCLUDE").unwrap_or_else(|| {
+                DEFAULT_LOG_EXCLUDE
+                    .iter()
+                    .map(|path| path.to_string())
+                    .collect()
+            }),
+            xml_invalid_chars: env_string("ACTIX_POSTS_XML_INVALID_CHARS")
+                .and_then(|mode| {
+                    InvalidCharMode::parse(&mode.to_ascii_lowercase()).or_else(|| {
+                        log::warn!("ignoring unknown XML invalid character mode {}", mode);
+                        None
+                    })
+                })
+                .unwrap_or_default(),
+            post_cooldown_seconds: env_parse("ACTIX_POSTS_POST_COOLDOWN_SECONDS")
+                .filter(|seconds: &i64| *seconds > 0),
+            write_retries: env_parse("ACTIX_POSTS_WRITE_RETRIES").unwrap_or(DEFAULT_WRITE_RETRIES),
+            write_retry_delay_ms: env_parse("ACTIX_POSTS_WRITE_RETRY_DELAY_MS")
+                .unwrap_or(DEFAULT_WRITE_RETRY_DELAY_MS),
+            max_sender: env_parse("ACTIX_POSTS_MAX_SENDER").unwrap_or(MAX_SENDER_LEN),
+            reserved_senders: env_list("ACTIX_POSTS_RESERVED_SENDERS").unwrap_or_else(|| {
+                DEFAULT_RESERVED_SENDERS
+                    .iter()
+                    .map(|name| name.to_string())
+                    .collect()
+            }),
+            strict_json: env_flag("ACTIX_POSTS_STRICT_JSON", false),
+            display_tz: env_string("ACTIX_POSTS_DISPLAY_TZ")
+                .and_then(|zone| {
+                    DisplayZone::parse(&zone).or_else(|| {
+                        log::warn!("ignoring unknown display time zone {}", zone);
+                        None
+                    })
+                })
+                .unwrap_or_default(),
+            max_per_sender: env_parse("ACTIX_POSTS_MAX_PER_SENDER").filter(|max: &usize| *max > 0),
+            sender_quota_policy: env_string("ACTIX_POSTS_SENDER_QUOTA_POLICY")
+                .and_then(|policy| {
+                    QuotaPolicy::parse(&policy.to_ascii_lowercase()).or_else(|| {
+                        log::warn!("ignoring unknown sender quota policy {}", policy);
+                        None
+                    })
+                })
+                .unwrap_or_default(),
+            batch_mode: env_string("ACTIX_POSTS_BATCH_MODE")
+                .and_then(|mode| {
+                    BatchMode::parse(&mode.to_ascii_lowercase()).or_else(|| {
+                        log::warn!("ignoring unknown batch mode {}", mode);
+                        None
+                    })
+                })
+                .unwrap_or_default(),
+            slug_strategy: env_string("ACTIX_POSTS_SLUG_STRATEGY")
+                .and_then(|strategy| {
+                    SlugStrategy::parse(&strategy.to_ascii_lowercase()).or_else(|| {
+                        log::warn!("ignoring unknown slug strategy {}", strategy);
+                        None
+                    })
+                })
+                .unwrap_or_default(),
+            in_memory: env_flag("ACTIX_POSTS_IN_MEMORY", false),
+            http2: env_flag("ACTIX_POSTS_HTTP2", false),
+            users: std::env::var("ACTIX_POSTS_USERS")
+                .unwrap_or_default()
+                .split_whitespace()
+                .filter_map(|entry| {
+                    entry
+                        .parse()
+                        .map_err(|error| log::warn!("ignoring user account {}", error))
+                        .ok()
+                })
+                .collect(),
+            moderators: env_list("ACTIX_POSTS_MODERATORS").unwrap_or_default(),
+            undo_seconds: env_parse("ACTIX_POSTS_UNDO_SECONDS").unwrap_or(DEFAULT_UNDO_SECONDS),
+        }
+    }
+}
+
+/// Returns the process-wide configuration, loading it from the environment on first use.
+pub fn get() -> &'static Config {
+    #[cfg(test)]
+    if let Some(config) = *OVERRIDE
+        .read()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+    {
+        return config;
+    }
+    &CONFIG
+}
+
+/// The configuration returned by [`get`] instead of the environment's, set by tests.
+#[cfg(test)]
+static OVERRIDE: std::sync::RwLock<Option<&'static Config>> = std::sync::RwLock::new(None);
+
+/// Makes [`get`] return `config` (or the environment's again, for `None`) until the next call.
+///
+/// The configuration is leaked, since `get` hands out `'static` references; tests create few.
+#[cfg(test)]
+pub(crate) fn set_override(config: Option<Config>) {
+    *OVERRIDE
+        .write()
+        .unwrap_or_else(std::sync::PoisonError::into_inner) =
+        config.map(|config| &*Box::leak(Box::new(config)));
+}
+
+fn env_flag(name: &str, default: bool) -> bool {
+    std::env::var(name)
+        .map(|value| {
+            matches!(
+                value.trim().to_ascii_lowercase().as_str(),
+                "1" | "true" | "yes" | "on"
+            )
+        })
+        .unwrap_or(default)
+}
+
+fn env_list(name: &str) -> Option<Vec<String>> {
+    std::env::var(name).ok().map(|value| {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(str::to_string)
+            .collect()
+    })
+}
+
+fn env_parse<T: std::str::FromStr>(name: &str) -> Option<T> {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+}
+
+fn env_string(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
@@ -1,3 +1,21 @@
 pub mod api;
+pub mod auth;
+pub mod cache;
+pub mod client_ip;
 pub mod data;
+pub mod diff;
+pub mod events;
+pub mod feed;
+pub mod filter;
+pub mod health;
+pub mod macros;
+pub mod merge;
+pub mod middleware;
 pub mod routes;
+pub mod search;
+pub mod signing;
+pub mod strict;
+pub mod strings;
+pub mod upload;
+pub mod validation;
+pub mod xml;
@@ -15,12 +15,105 @@
 //! - **`ApiResponse`**
 //!   - A struct representing the overall structure of an API response. Contains a status field
 //!     to indicate the response status (e.g., success or failure) alongside the `ResponseContent`.
+//!
+//! - **`ErrorCode`**
+//!   - A machine-readable code attached to every error response, so clients can branch on the
+//!     kind of failure without parsing the human-readable reason.
+//!
+//! ## Versions
+//!
+//! The API is served under `/api/v1`, and `/api` is an alias of the current version. Every API
+//! response carries the version it was served by in an [`API_VERSION_HEADER`] header, e.g.
+//! `X-Api-Version: 1`.
+//!
+//! ## Error Codes
+//!
+//! Error responses carry a `code` next to `status`; successful responses omit it:
+//!
+//! ```json
+//! { "status": "Error", "code": "NOT_FOUND", "result": { "Reason": "Post not found" } }
+//! ```
+//!
+//! | Code                     | HTTP status | Meaning                                          |
+//! |--------------------------|-------------|--------------------------------------------------|
+//! | `BAD_REQUEST`            | 400         | Malformed query, body, or parameter              |
+//! | `UNAUTHORIZED`           | 401         | Missing or wrong API key, or posting needs login |
+//! | `FORBIDDEN`              | 403         | Operation refused (e.g. wrong confirmation)      |
+//! | `EDIT_WINDOW_CLOSED`     | 403         | The message can no longer be edited              |
+//! | `SIGNATURE_EXPIRED`      | 403         | The signed URL has expired                       |
+//! | `INVALID_SIGNATURE`      | 403         | The signed URL was tampered with                 |
+//! | `NOT_FOUND`              | 404         | Unknown route, message, or revision              |
+//! | `METHOD_NOT_ALLOWED`     | 405         | The route exists but not for this method         |
+//! | `NOT_ACCEPTABLE`         | 406         | Unsupported `format`                             |
+//! | `DUPLICATE`              | 409         | Conflicts with an existing message               |
+//! | `INVALID_TRANSITION`     | 409         | The status change needs `reopen`                 |
+//! | `CURSOR_EXPIRED`         | 410         | The change journal no longer reaches the cursor  |
+//! | `PRECONDITION_FAILED`    | 412         | A conditional request header did not match       |
+//! | `PAYLOAD_TOO_LARGE`      | 413         | Body, field, or file over the size limit         |
+//! | `UNSUPPORTED_MEDIA_TYPE` | 415         | Disallowed upload type                           |
+//! | `VALIDATION_FAILED`      | 422         | Field-level validation errors, see `Errors`      |
+//! | `BATCH_ABORTED`          | 424         | A strict batch update failed and was not applied |
+//! | `INTERNAL`               | 500         | Unexpected server failure                        |
+//! | `MAINTENANCE`            | 503         | Writes disabled by maintenance mode              |
+//! | `READ_ONLY`              | 503         | Writes disabled because storage failed           |
+//! | `OVERLOADED`             | 503         | Over the concurrency or subscriber limit         |
+//! | `IDS_EXHAUSTED`          | 507         | No message IDs left to allocate                  |
+
+mod camel;
+pub(crate) mod public;
 
+use crate::config;
+use crate::handler::auth;
+use crate::handler::auth::{ApiKeyCheck, EditRefused, LoginRequired};
+use crate::handler::cache;
+use crate::handler::client_ip::{client_ip, request_metadata};
 use crate::handler::data;
-use crate::handler::data::{get, get_all, Message};
-use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
-use chrono::Local;
+use crate::handler::data::{
+    get, get_all_shared, BatchMode, BatchOutcome, BatchUpdate, Change, DailyCount, DataError,
+    Export, ImportMode, ImportReport, Message, MessageFilter, PostStatus, RepairReport,
+    SenderCount, SortOrder, StatusFilter, StorageStats, StoredMessage, TimeRange, DATE_FORMAT,
+};
+use crate::handler::diff;
+use crate::handler::diff::{DiffMode, RevisionDiff};
+use crate::handler::events;
+use crate::handler::events::Event;
+use crate::handler::merge::{merge_patch, MERGE_PATCH_CONTENT_TYPE};
+use crate::handler::middleware::RETRY_AFTER_SECONDS;
+use crate::handler::search;
+use crate::handler::search::SearchResult;
+use crate::handler::signing::{SignatureError, UrlSigner};
+use crate::handler::strict;
+use crate::handler::upload;
+use crate::handler::upload::{PendingUpload, UploadError};
+use crate::handler::validation;
+use crate::handler::validation::FieldError;
+use crate::handler::xml;
+use crate::handler::xml::XmlError;
+use actix_multipart::{Field, Multipart};
+use actix_session::{Session, SessionExt};
+use actix_web::dev::{Payload, ResourceDef};
+use actix_web::error::{InternalError, JsonPayloadError};
+use actix_web::http::header::{
+    Accept, Header, HeaderName, HeaderValue, HttpDate, IfUnmodifiedSince,
+};
+use actix_web::http::{header, StatusCode};
+use actix_web::web::Bytes;
+use actix_web::{
+    delete, get, patch, post, put, web, Error, FromRequest, HttpRequest, HttpResponse, Responder,
+};
+use camel::{CamelContent, CamelMessage, CamelResponse};
+use chrono::{DateTime, NaiveDate, Utc};
+use futures_util::future::LocalBoxFuture;
+use futures_util::StreamExt;
+use public::PublicMessage;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::ops::Deref;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::broadcast::error::RecvError;
 
 /// Represents the content of an API response.
 ///
@@ -29,28 +122,179 @@ use serde::{Deserialize, Serialize};
 /// a single item, an error reason, or no payload at all.
 ///
 /// ### Variants
-/// - `Items(Vec<Message>)`: Represents a collection of `Message` objects.
+/// - `Items(Arc<Vec<Message>>)`: Represents a collection of `Message` objects, possibly shared
+///   with the data layer (see [`data::get_all_shared`]).
+/// - `Batch(Vec<Option<Message>>)`: Represents messages fetched by ID, with `null` for missing IDs.
 /// - `Item(Message)`: Represents a single `Message` object.
+/// - `Record(Message)`: Represents a single message with its storage-only fields, for API key
+///   holders.
 /// - `Reason(String)`: Represents a textual description of an error or explanation.
+/// - `Range(TimeRange)`: Represents the time span and size of the store.
+/// - `Errors(Vec<FieldError>)`: Represents field-level validation failures.
+/// - `Affected { .. }`: Represents the messages affected by a bulk operation.
+/// - `Import(ImportReport)`: Represents the outcome of an import.
+/// - `Conflict { .. }`: Represents a rejected change, with the ID of the conflicting message.
+/// - `SignedUrl { .. }`: Represents a temporary link and its expiry (Unix seconds).
+/// - `Diff(RevisionDiff)`: Represents the changes between two revisions of a message.
+/// - `Hits(Vec<SearchResult>)`: Represents search matches reduced to snippets.
+/// - `Days(Vec<DailyCount>)`: Represents the number of messages posted per day.
+/// - `Storage(StorageStats)`: Represents the disk usage of the store.
+/// - `Senders(Vec<SenderCount>)`: Represents the distinct senders, with or without counts.
+/// - `Page { .. }`: Represents one page of a cursor-paginated listing, with the cursor of the
+///   next page (`None` on the last page).
+/// - `Stored(StoredMessage)`: Represents a message exactly as persisted, for operators.
+/// - `Count(usize)`: Represents a number of matching messages.
+/// - `Changes { .. }`: Represents journal entries, with the cursor to continue from.
+/// - `Repair(RepairReport)`: Represents the fixes made to the data file.
+/// - `Grouped { .. }`: Represents messages grouped by day or sender, with the key to continue
+///   after (`None` on the last page).
+/// - `Updates(Vec<BatchItemResult>)`: Represents the result of each update of a batch.
 /// - `None`: Represents the absence of content or data.
 ///
+/// Messages are serialized as [`PublicMessage`](public::PublicMessage)s, without their
+/// storage-only fields, except in `Record`.
+///
 /// ### Derived Traits
 /// - `Serialize`: Allows the enum to be easily serialized (e.g., to JSON) via Serde.
 /// - `Debug`: Enables debugging with the `{:?}` formatter.
 #[derive(Serialize, Debug)]
 enum ResponseContent {
-    Items(Vec<Message>),
-    Item(Message),
+    Items(#[serde(serialize_with = "public::messages")] Arc<Vec<Message>>),
+    Batch(#[serde(serialize_with = "public::optional_messages")] Vec<Option<Message>>),
+    Item(#[serde(serialize_with = "public::message")] Message),
+    Record(Message),
     Reason(String),
+    Range(TimeRange),
+    Errors(Vec<FieldError>),
+    Affected {
+        count: usize,
+        ids: Vec<i32>,
+        dry_run: bool,
+    },
+    Import(ImportReport),
+    Conflict {
+        reason: String,
+        id: i32,
+    },
+    SignedUrl {
+        url: String,
+        expires: i64,
+    },
+    Diff(RevisionDiff),
+    Hits(Vec<SearchResult>),
+    Days(Vec<DailyCount>),
+    Storage(StorageStats),
+    Senders(Vec<SenderCount>),
+    Page {
+        #[serde(serialize_with = "public::messages")]
+        items: Vec<Message>,
+        next_cursor: Option<String>,
+    },
+    Stored(StoredMessage),
+    Count(usize),
+    Changes {
+        changes: Vec<Change>,
+        cursor: u64,
+    },
+    Repair(RepairReport),
+    Grouped {
+        #[serde(serialize_with = "public::groups")]
+        groups: BTreeMap<String, Vec<Message>>,
+        next_after: Option<String>,
+    },
+    Updates(Vec<BatchItemResult>),
     None,
 }
 
+impl ResponseContent {
+    /// Returns the messages carried by the content, or `None` when it does not carry messages.
+    fn messages(&self) -> Option<Vec<&Message>> {
+        match self {
+            ResponseContent::Items(items) => Some(items.iter().collect()),
+            ResponseContent::Batch(items) => Some(items.iter().flatten().collect()),
+            ResponseContent::Item(item) => Some(vec![item]),
+            ResponseContent::Page { items, .. } => Some(items.iter().collect()),
+            _ => None,
+        }
+    }
+}
+
+/// The result of one update of [`api_batch_update`].
+///
+/// `status` is the HTTP status the update would have received on its own (e.g. `200`, `404`, or
+/// `422`), or `424` when it was valid but not written because another update of a strict batch
+/// failed. Failed updates carry the matching `code` and either a `reason` or field `errors`;
+/// written ones carry the stored message as `item`.
+#[derive(Serialize, Debug)]
+struct BatchItemResult {
+    id: i32,
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<ErrorCode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+    #[serde(
+        skip_serializing_if = "Option::is_none",
+        serialize_with = "public::optional_message"
+    )]
+    item: Option<Message>,
+}
+
+impl BatchItemResult {
+    /// Builds the result of an update that was not written.
+    fn failed(id: i32, status: StatusCode, code: ErrorCode, reason: Option<String>) -> Self {
+        BatchItemResult {
+            id,
+            status: status.as_u16(),
+            code: Some(code),
+            reason,
+            errors: vec![],
+            item: None,
+        }
+    }
+
+    /// Builds the result of an update from its [`BatchOutcome`].
+    fn from_outcome(id: i32, outcome: BatchOutcome) -> Self {
+        match outcome {
+            BatchOutcome::Updated(message) => BatchItemResult {
+                id,
+                status: StatusCode::OK.as_u16(),
+                code: None,
+                reason: None,
+                errors: vec![],
+                item: Some(*message),
+            },
+            BatchOutcome::NotFound => BatchItemResult::failed(
+                id,
+                StatusCode::NOT_FOUND,
+                ErrorCode::NotFound,
+                Some("Post not found".to_string()),
+            ),
+            BatchOutcome::Failed(error) => {
+                let (status, code) = data_error_status(&error);
+                BatchItemResult::failed(id, status, code, Some(error.to_string()))
+            }
+            BatchOutcome::Skipped => BatchItemResult::failed(
+                id,
+                StatusCode::FAILED_DEPENDENCY,
+                ErrorCode::BatchAborted,
+                None,
+            ),
+        }
+    }
+}
+
 /// Represents the structure of an API response.
 ///
 /// The `ApiResponse` is a wrapper to provide a consistent API response format,
-/// containing two fields:
+/// containing the following fields:
 ///
 /// - `status`: A string to specify the response status (e.g., "success", "error").
+/// - `code`: The [`ErrorCode`] of an error response. Omitted for successful responses.
+/// - `sort`: The ordering of listed messages, see [`SortInfo`]. Omitted for responses that do not
+///   list messages.
 /// - `result`: The data of the response, represented by [`ResponseContent`].
 ///
 /// ### Derived Traits
@@ -59,14 +303,389 @@ enum ResponseContent {
 #[derive(Serialize, Debug)]
 struct ApiResponse {
     status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<ErrorCode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sort: Option<SortInfo>,
     result: ResponseContent,
 }
 
+/// A machine-readable identifier of an error, serialized in `SCREAMING_SNAKE_CASE`.
+///
+/// See the [module documentation](self) for the list of codes and their HTTP statuses.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+enum ErrorCode {
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    EditWindowClosed,
+    SignatureExpired,
+    InvalidSignature,
+    NotFound,
+    MethodNotAllowed,
+    NotAcceptable,
+    Duplicate,
+    InvalidTransition,
+    CursorExpired,
+    PreconditionFailed,
+    PayloadTooLarge,
+    UnsupportedMediaType,
+    ValidationFailed,
+    BatchAborted,
+    Internal,
+    Maintenance,
+    ReadOnly,
+    Overloaded,
+    IdsExhausted,
+}
+
+impl ErrorCode {
+    /// Returns the generic code of an HTTP error status, used when no more specific code applies.
+    fn for_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED => ErrorCode::Unauthorized,
+            StatusCode::FORBIDDEN => ErrorCode::Forbidden,
+            StatusCode::NOT_FOUND => ErrorCode::NotFound,
+            StatusCode::METHOD_NOT_ALLOWED => ErrorCode::MethodNotAllowed,
+            StatusCode::NOT_ACCEPTABLE => ErrorCode::NotAcceptable,
+            StatusCode::CONFLICT => ErrorCode::Duplicate,
+            StatusCode::GONE => ErrorCode::CursorExpired,
+            StatusCode::PRECONDITION_FAILED => ErrorCode::PreconditionFailed,
+            StatusCode::PAYLOAD_TOO_LARGE => ErrorCode::PayloadTooLarge,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => ErrorCode::UnsupportedMediaType,
+            StatusCode::UNPROCESSABLE_ENTITY => ErrorCode::ValidationFailed,
+            StatusCode::FAILED_DEPENDENCY => ErrorCode::BatchAborted,
+            StatusCode::SERVICE_UNAVAILABLE => ErrorCode::Overloaded,
+            StatusCode::INSUFFICIENT_STORAGE => ErrorCode::IdsExhausted,
+            status if status.is_client_error() => ErrorCode::BadRequest,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+/// Describes the effective ordering of a message listing.
+///
+/// ### Example Payload (JSON)
+/// ```json
+/// { "order": "newest", "keys": ["posted desc", "id desc"] }
+/// ```
+#[derive(Serialize, Debug)]
+struct SortInfo {
+    order: &'static str,
+    keys: [&'static str; 2],
+}
+
+impl From<SortOrder> for SortInfo {
+    fn from(order: SortOrder) -> Self {
+        SortInfo {
+            order: order.as_str(),
+            keys: order.keys(),
+        }
+    }
+}
+
 #[derive(Deserialize)]
 struct Queries {
     format: Option<String>,
+    delimiter: Option<String>,
+    fields: Option<String>,
+    order: Option<String>,
+    case: Option<String>,
+    ids: Option<String>,
+    missing: Option<String>,
+    cursor: Option<String>,
+    limit: Option<usize>,
+    status: Option<String>,
+    envelope: Option<bool>,
+}
+
+/// The header naming the API version that served a response.
+pub const API_VERSION_HEADER: &str = "x-api-version";
+
+/// The header carrying the [`data::generation_token`] of a listing (see [`api_index`]).
+pub const GENERATION_HEADER: &str = "x-generation";
+
+/// The header carrying the `next_cursor` of a page served without the response envelope (see
+/// [`api_page`]).
+pub const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+
+/// The maximum number of IDs accepted by a batch fetch (`GET /api/posts?ids=...`).
+const MAX_BATCH_IDS: usize = 100;
+
+/// The number of messages in a page when `limit` is not given.
+const DEFAULT_PAGE_LIMIT: usize = 20;
+
+/// The largest accepted `limit`.
+const MAX_PAGE_LIMIT: usize = 100;
+
+/// Prefixes the ID in a pagination cursor before encoding.
+const CURSOR_PREFIX: &str = "after:";
+
+/// Encodes the opaque cursor of the page after the message with ID `id`.
+fn encode_cursor(id: i32) -> String {
+    hex::encode(format!("{}{}", CURSOR_PREFIX, id))
+}
+
+/// Decodes a cursor made by [`encode_cursor`], returning the last ID already seen.
+fn decode_cursor(cursor: &str) -> Option<i32> {
+    let decoded = String::from_utf8(hex::decode(cursor).ok()?).ok()?;
+    decoded.strip_prefix(CURSOR_PREFIX)?.parse().ok()
+}
+
+/// The naming convention of object keys in the JSON responses of read routes.
+///
+/// ### Variants
+/// - `Snake`: Keys as stored, e.g. `expires_at` (the default).
+/// - `Camel`: camelCase keys, e.g. `expiresAt`, serialized through the types of [`camel`].
+///   Selected with `?case=camel`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum KeyCase {
+    #[default]
+    Snake,
+    Camel,
+}
+
+impl KeyCase {
+    /// Parses the `case` query parameter.
+    ///
+    /// ### Returns
+    /// - `Ok(case)` for `snake`, `camel`, or an absent parameter.
+    /// - `Err(reason)` for any other value.
+    fn parse(case: Option<&str>) -> Result<Self, String> {
+        match case {
+            None | Some("snake") => Ok(KeyCase::Snake),
+            Some("camel") => Ok(KeyCase::Camel),
+            Some(case) => Err(format!("Unknown case: {}", case)),
+        }
+    }
+}
+
+/// The field separator of CSV responses.
+///
+/// ### Variants
+/// - `Comma`: `,` (the default).
+/// - `Semicolon`: `;`, for spreadsheets in locales where the comma is the decimal separator.
+///   Selected with `?delimiter=semicolon`.
+/// - `Tab`: A tab character. Selected with `?delimiter=tab`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum CsvDelimiter {
+    #[default]
+    Comma,
+    Semicolon,
+    Tab,
+}
+
+impl CsvDelimiter {
+    /// Parses the `delimiter` query parameter.
+    ///
+    /// ### Returns
+    /// - `Ok(delimiter)` for `comma`, `semicolon`, `tab`, or an absent parameter.
+    /// - `Err(reason)` for any other value.
+    fn parse(delimiter: Option<&str>) -> Result<Self, String> {
+        match delimiter {
+            None | Some("comma") => Ok(CsvDelimiter::Comma),
+            Some("semicolon") => Ok(CsvDelimiter::Semicolon),
+            Some("tab") => Ok(CsvDelimiter::Tab),
+            Some(delimiter) => Err(format!(
+                "Unknown delimiter: {} (supported: comma, semicolon, tab)",
+                delimiter
+            )),
+        }
+    }
+
+    /// Returns the separator character.
+    fn as_char(self) -> char {
+        match self {
+            CsvDelimiter::Comma => ',',
+            CsvDelimiter::Semicolon => ';',
+            CsvDelimiter::Tab => '\t',
+        }
+    }
+
+    /// Joins the fields of a CSV row, terminated by CRLF. The fields must already be escaped
+    /// (see [`escape_csv`]).
+    fn row<S: AsRef<str>>(self, fields: &[S]) -> String {
+        let mut row = fields
+            .iter()
+            .map(AsRef::as_ref)
+            .collect::<Vec<&str>>()
+            .join(self.as_char().encode_utf8(&mut [0; 4]));
+        row.push_str("\r\n");
+        row
+    }
+}
+
+#[derive(Deserialize)]
+struct BulkDeleteQueries {
+    ids: Option<String>,
+    dry_run: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct PatchRequest {
+    sender: Option<String>,
+    content: Option<String>,
+    attachments: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct PurgeRequest {
+    confirm: Option<String>,
+}
+
+/// The body of an import: an export of `GET /export`, or a bare array of messages.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ImportBody {
+    Export(Export<serde_json::Value>),
+    Messages(Vec<serde_json::Value>),
+}
+
+#[derive(Deserialize)]
+struct ImportQueries {
+    mode: Option<String>,
+    dry_run: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct ExportQueries {
+    expires: Option<i64>,
+    signature: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DiffQueries {
+    from: Option<u32>,
+    to: Option<u32>,
+    mode: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ReactRequest {
+    emoji: String,
+}
+
+#[derive(Deserialize)]
+struct TagsRequest {
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct StatusRequest {
+    status: PostStatus,
+    #[serde(default)]
+    reopen: bool,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct BulkTagRequest {
+    sender: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    ids: Option<Vec<i32>>,
+    add: Vec<String>,
+    remove: Vec<String>,
+    dry_run: bool,
+}
+
+#[derive(Deserialize)]
+struct RepairQueries {
+    renumber: Option<bool>,
+    dry_run: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct ChangesQueries {
+    since: Option<u64>,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct GroupedQueries {
+    by: Option<String>,
+    after: Option<String>,
+    limit: Option<usize>,
+    status: Option<String>,
+    case: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RandomQueries {
+    format: Option<String>,
+    delimiter: Option<String>,
+    case: Option<String>,
+    sender: Option<String>,
+    tag: Option<String>,
+    envelope: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct SearchQueries {
+    q: Option<String>,
+    snippet: Option<bool>,
+    context: Option<usize>,
+    count_only: Option<bool>,
+    format: Option<String>,
+    delimiter: Option<String>,
+    case: Option<String>,
+    envelope: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct PollQueries {
+    since: Option<i32>,
+    timeout: Option<u64>,
+    case: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DailyQueries {
+    format: Option<String>,
+    delimiter: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
 }
 
+#[derive(Deserialize)]
+struct SendersQueries {
+    format: Option<String>,
+    delimiter: Option<String>,
+    counts: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct ExportPostQueries {
+    format: Option<String>,
+}
+
+/// The formats a single message can be exported in, see [`api_export_post`].
+const EXPORT_FORMATS: &[&str] = &["json", "xml", "md"];
+
+#[derive(Deserialize)]
+struct SignQueries {
+    ttl: Option<i64>,
+}
+
+/// The number of characters kept on each side of a match when `context` is not given.
+const DEFAULT_SNIPPET_CONTEXT: usize = 30;
+
+/// The largest accepted `context`, in characters.
+const MAX_SNIPPET_CONTEXT: usize = 200;
+
+/// How long a poll waits for new messages when `timeout` is not given, in seconds.
+const DEFAULT_POLL_TIMEOUT: u64 = 30;
+
+/// The longest a poll is held open, in seconds.
+const MAX_POLL_TIMEOUT: u64 = 60;
+
+/// The lifetime of a signed export URL when `ttl` is not given, in seconds.
+const DEFAULT_SIGNED_URL_TTL: i64 = 300;
+
+/// The longest accepted lifetime of a signed export URL, in seconds.
+const MAX_SIGNED_URL_TTL: i64 = 24 * 60 * 60;
+
 /// Handles requests to undefined API routes.
 ///
 /// This function returns an HTTP `404 Not Found` response with a JSON payload
@@ -80,6 +699,7 @@ struct Queries {
 /// ```json
 /// {
 ///     "status": "Error",
+///     "code": "NOT_FOUND",
 ///     "result": {
 ///         "Reason": "API not found"
 ///     }
@@ -98,106 +718,3013 @@ struct Queries {
 /// This function integrates with Actix Web's async framework and uses
 /// `HttpResponse::NotFound()` to automatically send a 404 status code.
 pub async fn api_not_found() -> impl Responder {
+    coded_error_response(StatusCode::NOT_FOUND, ErrorCode::NotFound, "API not found")
+}
+
+/// The routes served under the `/api` scope and the HTTP methods they accept.
+///
+/// The patterns mirror the route attributes of the handlers in this module and are used by
+/// [`api_default`] to tell a wrong method apart from an unknown path. A test compares the table
+/// with the route attributes of the handlers, so it cannot drift silently.
+const API_ROUTES: &[(&str, &str)] = &[
+    ("/posts", "GET"),
+    ("/posts/{id:\\d+}", "GET"),
+    ("/posts/{id:\\d+}", "PATCH"),
+    ("/posts/{id:\\d+}/diff", "GET"),
+    ("/posts/{id:\\d+}/react", "POST"),
+    ("/posts/{id:\\d+}/tags", "PUT"),
+    ("/posts/tag", "POST"),
+    ("/posts/{id:\\d+}/touch", "POST"),
+    ("/posts/{id:\\d+}/status", "POST"),
+    ("/posts/{id:\\d+}/export", "GET"),
+    ("/posts/first", "GET"),
+    ("/posts/latest", "GET"),
+    ("/posts/random", "GET"),
+    ("/posts/poll", "GET"),
+    ("/posts/changes", "GET"),
+    ("/posts/grouped", "GET"),
+    ("/posts/search", "GET"),
+    ("/stats/range", "GET"),
+    ("/stats/daily", "GET"),
+    ("/stats/storage", "GET"),
+    ("/senders", "GET"),
+    ("/stream", "GET"),
+    ("/posts/create", "POST"),
+    ("/posts/preview", "POST"),
+    ("/posts/upload", "POST"),
+    ("/posts/update", "PUT"),
+    ("/posts/batch", "PUT"),
+    ("/posts/{id:\\d+}/delete", "DELETE"),
+    ("/posts", "DELETE"),
+    ("/import", "POST"),
+    ("/admin/purge", "POST"),
+    ("/admin/posts/{id:\\d+}", "GET"),
+    ("/admin/posts/{id:\\d+}/raw", "GET"),
+    ("/admin/repair", "POST"),
+    ("/admin/reload", "POST"),
+    ("/export", "GET"),
+    ("/export/sign", "POST"),
+];
+
+/// Handles API requests that did not match any route.
+///
+/// If the path matches an API route that accepts other methods, this function returns an HTTP
+/// `405 Method Not Allowed` response with an `Allow` header listing the accepted methods.
+/// Otherwise the request is answered by [`api_not_found`].
+///
+/// ### Example Response Payload (JSON)
+/// ```json
+/// {
+///     "status": "Error",
+///     "code": "METHOD_NOT_ALLOWED",
+///     "result": {
+///         "Reason": "Method not allowed"
+///     }
+/// }
+/// ```
+pub async fn api_default(req: HttpRequest) -> HttpResponse {
+    let path = req.match_info().unprocessed();
+    let allowed: Vec<&str> = API_ROUTES
+        .iter()
+        .filter(|(pattern, _)| ResourceDef::new(*pattern).is_match(path))
+        .map(|(_, method)| *method)
+        .collect();
+    if allowed.is_empty() {
+        return api_not_found().await.respond_to(&req).map_into_boxed_body();
+    }
+
+    let mut response = coded_error_response(
+        StatusCode::METHOD_NOT_ALLOWED,
+        ErrorCode::MethodNotAllowed,
+        "Method not allowed",
+    );
+    if let Ok(allow) = HeaderValue::from_str(&allowed.join(", ")) {
+        response.headers_mut().insert(header::ALLOW, allow);
+    }
+    response
+}
+
+/// Builds the response returned by mutating API routes while maintenance mode is enabled.
+///
+/// The response carries an HTTP `503 Service Unavailable` status and a JSON payload whose
+/// reason is `"maintenance"`, so clients can tell a planned outage apart from other errors.
+///
+/// ### Example Response Payload (JSON)
+/// ```json
+/// {
+///     "status": "Error",
+///     "code": "MAINTENANCE",
+///     "result": {
+///         "Reason": "maintenance"
+///     }
+/// }
+/// ```
+pub fn api_maintenance() -> HttpResponse {
+    coded_error_response(
+        StatusCode::SERVICE_UNAVAILABLE,
+        ErrorCode::Maintenance,
+        "maintenance",
+    )
+}
+
+/// Builds the response returned to API requests shed by
+/// [`crate::handler::middleware::concurrency_limit`].
+///
+/// The response carries an HTTP `503 Service Unavailable` status, a `Retry-After` header, and a
+/// JSON payload whose reason is `"overloaded"`.
+pub fn api_overloaded() -> HttpResponse {
+    shed_response("overloaded")
+}
+
+/// Builds the response returned to event stream clients beyond `ACTIX_POSTS_MAX_SUBSCRIBERS`
+/// (see [`crate::handler::events`]).
+///
+/// Like [`api_overloaded`], but with the reason `"too many subscribers"`.
+pub fn api_too_many_subscribers() -> HttpResponse {
+    shed_response("too many subscribers")
+}
+
+/// Builds a `503 Service Unavailable` response coded `OVERLOADED`, asking the client to retry
+/// after [`RETRY_AFTER_SECONDS`].
+fn shed_response(reason: &str) -> HttpResponse {
+    let mut response = coded_error_response(
+        StatusCode::SERVICE_UNAVAILABLE,
+        ErrorCode::Overloaded,
+        reason,
+    );
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(RETRY_AFTER_SECONDS));
+    response
+}
+
+/// Builds the response returned by mutating API routes while the store is read-only because the
+/// data file cannot be written (see [`data::is_read_only`]).
+///
+/// The response carries an HTTP `503 Service Unavailable` status and the reason of
+/// [`DataError::ReadOnly`].
+pub fn api_read_only() -> HttpResponse {
+    data_error(DataError::ReadOnly)
+}
+
+/// Builds an HTTP `404 Not Found` response for a message that does not exist.
+fn post_not_found() -> HttpResponse {
+    coded_error_response(StatusCode::NOT_FOUND, ErrorCode::NotFound, "Post not found")
+}
+
+/// Returns the HTTP status and [`ErrorCode`] of a [`DataError`].
+///
+/// - `DataError::Duplicate` and `DataError::InvalidTransition` become `409 Conflict`.
+/// - `DataError::EditWindowClosed` becomes `403 Forbidden`.
+/// - `DataError::IdsExhausted` becomes `507 Insufficient Storage`.
+/// - `DataError::ReadOnly` and `DataError::Transient` become `503 Service Unavailable`.
+/// - `DataError::TooManyTags` and `DataError::QuotaExceeded` become `422 Unprocessable Entity`.
+/// - `DataError::Modified` becomes `412 Precondition Failed`.
+fn data_error_status(error: &DataError) -> (StatusCode, ErrorCode) {
+    match error {
+        DataError::Duplicate(_) => (StatusCode::CONFLICT, ErrorCode::Duplicate),
+        DataError::EditWindowClosed(_) => (StatusCode::FORBIDDEN, ErrorCode::EditWindowClosed),
+        DataError::IdsExhausted => (StatusCode::INSUFFICIENT_STORAGE, ErrorCode::IdsExhausted),
+        DataError::ReadOnly => (StatusCode::SERVICE_UNAVAILABLE, ErrorCode::ReadOnly),
+        DataError::Transient(_) => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::for_status(StatusCode::SERVICE_UNAVAILABLE),
+        ),
+        DataError::TooManyTags(_) | DataError::QuotaExceeded(_) => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::for_status(StatusCode::UNPROCESSABLE_ENTITY),
+        ),
+        DataError::Modified => (
+            StatusCode::PRECONDITION_FAILED,
+            ErrorCode::PreconditionFailed,
+        ),
+        DataError::InvalidTransition(..) => (StatusCode::CONFLICT, ErrorCode::InvalidTransition),
+    }
+}
+
+/// Maps a [`DataError`] to the matching HTTP error response (see [`data_error_status`]).
+///
+/// `DataError::Duplicate` carries the ID of the existing message; the other errors carry their
+/// reason.
+fn data_error(error: DataError) -> HttpResponse {
+    let (status, code) = data_error_status(&error);
+    if let DataError::Duplicate(id) = error {
+        let response = ApiResponse {
+            status: "Error".to_string(),
+            code: Some(code),
+            sort: None,
+            result: ResponseContent::Conflict {
+                reason: error.to_string(),
+                id,
+            },
+        };
+        return HttpResponse::build(status).json(response);
+    }
+    coded_error_response(status, code, &error.to_string())
+}
+
+/// Builds an error response with the given status and reason, coded after the status (see
+/// [`ErrorCode::for_status`]).
+fn error_response(status: StatusCode, reason: &str) -> HttpResponse {
+    coded_error_response(status, ErrorCode::for_status(status), reason)
+}
+
+/// Builds an error response with the given status, code, and reason.
+fn coded_error_response(status: StatusCode, code: ErrorCode, reason: &str) -> HttpResponse {
     let response = ApiResponse {
         status: "Error".to_string(),
-        result: ResponseContent::Reason("API not found".to_string()),
+        code: Some(code),
+        sort: None,
+        result: ResponseContent::Reason(reason.to_string()),
     };
 
-    HttpResponse::NotFound().json(response)
+    HttpResponse::build(status).json(response)
 }
 
-fn build_response(format: Option<&str>, response: &ApiResponse) -> impl Responder {
-    format
-        .map(|format| match format {
-            "xml" => HttpResponse::Ok()
-                .content_type("application/xml; charset=utf-8")
-                .body(serde_xml_rs::to_string(response).unwrap()),
-            _ => HttpResponse::Ok().json(response),
-        })
-        .unwrap_or(HttpResponse::Ok().json(response))
+/// Rejects requests that do not carry the configured API key.
+///
+/// ### Returns
+/// - `None` when the request is authorized.
+/// - `Some(response)` with `401 Unauthorized` for a missing or wrong key, or `403 Forbidden` when
+///   no API key is configured on the server.
+fn api_key_rejection(req: &HttpRequest) -> Option<HttpResponse> {
+    match auth::check_api_key(req) {
+        ApiKeyCheck::Authorized => None,
+        ApiKeyCheck::Missing => Some(error_response(
+            StatusCode::UNAUTHORIZED,
+            "A valid API key is required",
+        )),
+        ApiKeyCheck::Disabled => Some(error_response(
+            StatusCode::FORBIDDEN,
+            "API key authentication is not configured",
+        )),
+    }
 }
 
-#[get("/posts")]
-pub async fn api_index(query: web::Query<Queries>) -> impl Responder {
-    let posts = get_all();
+/// Builds the response to a post from a session that is not logged in while login is enabled
+/// (see [`auth::sender_identity`]).
+fn login_required() -> HttpResponse {
+    error_response(StatusCode::UNAUTHORIZED, "Log in to post")
+}
 
-    let format = query.format.as_deref();
-    let response = ApiResponse {
-        status: "OK".to_string(),
-        result: ResponseContent::Items(posts),
-    };
-    build_response(format, &response)
+/// Checks that the request may edit a post whose sender is `stored` into one whose sender is
+/// `sender` (see [`auth::check_editor`]). Requests with the API key may edit any post.
+fn check_editor_for(req: &HttpRequest, stored: &str, sender: &str) -> Result<(), EditRefused> {
+    if auth::has_api_key(req) {
+        return Ok(());
+    }
+    auth::check_editor(&req.get_session(), stored, sender)
 }
 
-#[get("/posts/{id}")]
-pub async fn api_show(id: web::Path<i32>, query: web::Query<Queries>) -> impl Responder {
-    let id = id.into_inner();
-    let post = get(id);
+/// Returns the HTTP status, [`ErrorCode`], and reason of an [`EditRefused`].
+///
+/// - `EditRefused::LoginRequired` becomes `401 Unauthorized`.
+/// - `EditRefused::NotOwner` becomes `403 Forbidden`.
+fn edit_refused_status(refused: EditRefused) -> (StatusCode, ErrorCode, &'static str) {
+    match refused {
+        EditRefused::LoginRequired => (
+            StatusCode::UNAUTHORIZED,
+            ErrorCode::Unauthorized,
+            "Log in to edit posts",
+        ),
+        EditRefused::NotOwner => (
+            StatusCode::FORBIDDEN,
+            ErrorCode::Forbidden,
+            "Posts can only be edited by their sender, under their name",
+        ),
+    }
+}
 
-    let format = query.format.as_deref();
-    let response = ApiResponse {
-        status: "OK".to_string(),
-        result: ResponseContent::Item(post),
-    };
-    build_response(format, &response)
+/// Builds the JSON extractor configuration shared by the API routes.
+///
+/// Request bodies sent with `Content-Encoding: gzip` (or `deflate`, `br`, `zstd`) are
+/// decompressed by the extractor before parsing, and the size limit applies to the decompressed
+/// bytes, so a small compressed body cannot expand without bound.
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(config::get().max_body)
+        .error_handler(json_error)
 }
 
-#[post("/posts/create")]
-pub async fn api_create(params: web::Json<Message>) -> impl Responder {
-    let Message {
-        sender, content, ..
-    } = params.0;
-    let now = Local::now();
-    let posted = now.format("%Y-%m-%d %H:%M:%S").to_string();
-    let mut message = Message {
-        id: 0,
-        posted,
-        sender,
-        content,
+/// Converts a JSON extraction failure into an API error response.
+///
+/// ### Returns
+/// - `413 Payload Too Large` when the (decompressed) body exceeds the configured limit.
+/// - `400 Bad Request` for malformed compressed data, bad JSON, or a wrong content type.
+fn json_error(err: JsonPayloadError, _req: &HttpRequest) -> Error {
+    let response = match &err {
+        JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => {
+            error_response(StatusCode::PAYLOAD_TOO_LARGE, "Request body is too large")
+        }
+        JsonPayloadError::Payload(_) => error_response(
+            StatusCode::BAD_REQUEST,
+            "Request body could not be read or decompressed",
+        ),
+        _ => error_response(StatusCode::BAD_REQUEST, &err.to_string()),
     };
-    message = data::create(message);
+    InternalError::from_response(err, response).into()
+}
 
-    let format = Some("json");
-    let response = ApiResponse {
-        status: "OK".to_string(),
-        result: ResponseContent::Item(message),
-    };
-    build_response(format, &response)
+/// A JSON request body, extracted like [`web::Json`] with [`json_config`].
+///
+/// When `ACTIX_POSTS_STRICT_JSON` is enabled, a body with a field `T` does not declare is
+/// rejected with `400 Bad Request` naming the field (see [`strict::unknown_field`]).
+pub struct ApiJson<T>(pub T);
+
+impl<T> ApiJson<T> {
+    /// Unwraps the deserialized body.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
 }
 
-#[put("/posts/update")]
-pub async fn api_update(params: web::Json<Message>) -> impl Responder {
-    let Message {
-        id,
-        posted,
-        sender,
-        content,
-    } = params.0;
-    let message = Message {
-        id,
-        posted,
-        sender,
-        content,
-    };
-    data::update(&message);
+impl<T> Deref for ApiJson<T> {
+    type Target = T;
 
-    let format = Some("json");
-    let response = ApiResponse {
-        status: "OK".to_string(),
-        result: ResponseContent::Item(message),
-    };
-    build_response(format, &response)
+    fn deref(&self) -> &T {
+        &self.0
+    }
 }
 
-#[delete("/posts/{id}/delete")]
-pub async fn api_delete(id: web::Path<i32>, query: web::Query<Queries>) -> impl Responder {
-    let id = id.into_inner();
-    data::remove(id);
+impl<T: DeserializeOwned + 'static> FromRequest for ApiJson<T> {
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Error>>;
 
-    let format = query.format.as_deref();
-    let response = ApiResponse {
-        status: "OK".to_string(),
-        result: ResponseContent::None,
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let body = web::Json::<serde_json::Value>::from_request(req, payload);
+        Box::pin(async move {
+            parse_body(body.await?.into_inner())
+                .map(ApiJson)
+                .map_err(|reason| {
+                    let response = error_response(StatusCode::BAD_REQUEST, &reason);
+                    Error::from(InternalError::from_response(reason, response))
+                })
+        })
+    }
+}
+
+/// Deserializes a JSON request body the way [`ApiJson`] does, for handlers that inspect the raw
+/// value first.
+///
+/// Returns the reason for `400 Bad Request` on failure.
+fn parse_body<T: DeserializeOwned>(value: serde_json::Value) -> Result<T, String> {
+    if config::get().strict_json {
+        if let Some(field) = strict::unknown_field::<T>(&value) {
+            return Err(format!("Unknown field: {}", field));
+        }
+    }
+    serde_json::from_value(value).map_err(|error| format!("Json deserialize error: {}", error))
+}
+
+/// Converts an [`UploadError`] into an API error response.
+///
+/// ### Returns
+/// - `413 Payload Too Large` for files over the size limit.
+/// - `415 Unsupported Media Type` for disallowed or mismatching content types.
+/// - `500 Internal Server Error` when the file cannot be written.
+fn upload_error(error: UploadError) -> HttpResponse {
+    let status = match error {
+        UploadError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        UploadError::UnsupportedType(_) | UploadError::ContentMismatch(_) => {
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        }
+        UploadError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
     };
-    build_response(format, &response)
+    error_response(status, &error.to_string())
+}
+
+/// Reads a multipart field into memory, stopping as soon as it exceeds `limit` bytes.
+///
+/// ### Returns
+/// - `Ok(Some(bytes))` with the field content.
+/// - `Ok(None)` when the field is larger than `limit`.
+/// - `Err(reason)` when the multipart body is malformed.
+async fn read_field(field: &mut Field, limit: usize) -> Result<Option<Vec<u8>>, String> {
+    let mut bytes = vec![];
+    while let Some(chunk) = field.next().await {
+        let chunk = chunk.map_err(|error| format!("Malformed multipart body: {}", error))?;
+        if bytes.len() + chunk.len() > limit {
+            return Ok(None);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Some(bytes))
+}
+
+/// Builds an HTTP `400 Bad Request` response carrying the given reason.
+fn bad_request(reason: String) -> HttpResponse {
+    coded_error_response(StatusCode::BAD_REQUEST, ErrorCode::BadRequest, &reason)
 }
+
+/// Builds an HTTP `422 Unprocessable Entity` response listing the rejected fields.
+///
+/// ### Example Response Payload (JSON)
+/// ```json
+/// {
+///     "status": "Error",
+///     "code": "VALIDATION_FAILED",
+///     "result": {
+///         "Errors": [
+///             { "field": "sender", "reason": "must be at most 80 characters" }
+///         ]
+///     }
+/// }
+/// ```
+fn unprocessable(errors: Vec<FieldError>) -> HttpResponse {
+    let response = ApiResponse {
+        status: "Error".to_string(),
+        code: Some(ErrorCode::ValidationFailed),
+        sort: None,
+        result: ResponseContent::Errors(errors),
+    };
+
+    HttpResponse::UnprocessableEntity().json(response)
+}
+
+/// Parses a comma-separated list of message IDs (e.g. `1,2,3`).
+///
+/// ### Returns
+/// - `Ok(ids)` with the IDs in request order.
+/// - `Err(reason)` when an entry is not a valid integer.
+fn parse_ids(ids: &str) -> Result<Vec<i32>, String> {
+    ids.split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| id.parse().map_err(|_| format!("Invalid id: {}", id)))
+        .collect()
+}
+
+/// Parses the `fields` query parameter (e.g. `id,sender`) into a list of message field names.
+///
+/// ### Returns
+/// - `Ok(None)` when the parameter is absent, meaning every field is returned.
+/// - `Ok(Some(fields))` with the requested field names in request order.
+/// - `Err(reason)` when a name does not match any field of `Message`.
+fn parse_fields(fields: Option<&str>) -> Result<Option<Vec<String>>, String> {
+    let Some(fields) = fields else {
+        return Ok(None);
+    };
+    let known = strict::struct_fields::<Message>().unwrap_or_default();
+    fields
+        .split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(|field| {
+            if known.contains(&field) && !public::PRIVATE_FIELDS.contains(&field) {
+                Ok(field.to_string())
+            } else {
+                Err(format!("Unknown field: {}", field))
+            }
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+}
+
+/// Returns the message objects of a serialized [`ApiResponse`], or of a bare message or array
+/// served with `?envelope=false` (see [`build_read_response`]).
+fn message_values(response: &mut serde_json::Value) -> Vec<&mut serde_json::Value> {
+    if response.get("result").is_none() {
+        return match response {
+            serde_json::Value::Array(items) => items.iter_mut().collect(),
+            message @ serde_json::Value::Object(_) => vec![message],
+            _ => vec![],
+        };
+    }
+    let Some(result) = response.get_mut("result") else {
+        return vec![];
+    };
+    match result {
+        serde_json::Value::Object(content) => content
+            .iter_mut()
+            .flat_map(|(variant, value)| match (variant.as_str(), value) {
+                ("Items" | "Batch", serde_json::Value::Array(items)) => items.iter_mut().collect(),
+                ("Item", item) => vec![item],
+                ("Page", page) => match page.get_mut("items") {
+                    Some(serde_json::Value::Array(items)) => items.iter_mut().collect(),
+                    _ => vec![],
+                },
+                ("Updates", serde_json::Value::Array(results)) => results
+                    .iter_mut()
+                    .filter_map(|result| result.get_mut("item"))
+                    .collect(),
+                ("Grouped", grouped) => match grouped.get_mut("groups") {
+                    Some(serde_json::Value::Object(groups)) => groups
+                        .values_mut()
+                        .filter_map(|group| match group {
+                            serde_json::Value::Array(items) => Some(items.iter_mut()),
+                            _ => None,
+                        })
+                        .flatten()
+                        .collect(),
+                    _ => vec![],
+                },
+                _ => vec![],
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Removes every key not listed in `fields` from the message objects of a serialized
+/// [`ApiResponse`].
+fn project_fields(response: &mut serde_json::Value, fields: &[String]) {
+    for message in message_values(response) {
+        if let serde_json::Value::Object(message) = message {
+            message.retain(|key, _| fields.contains(key));
+        }
+    }
+}
+
+/// Turns the numeric `id` of every message object in a serialized [`ApiResponse`] into a string,
+/// for `?id_as_string=true` (see [`crate::handler::middleware::string_ids`]).
+///
+/// Only message IDs are converted; other numbers, such as the IDs listed by bulk operations,
+/// are left as they are.
+pub fn stringify_ids(response: &mut serde_json::Value) {
+    for message in message_values(response) {
+        if let Some(id) = message.get_mut("id").filter(|id| id.is_number()) {
+            *id = serde_json::Value::String(id.to_string());
+        }
+    }
+}
+
+/// Builds a JSON response containing only the requested message fields, with keys in the
+/// requested case.
+///
+/// The projection and the key conversion only apply to JSON output; other formats are rendered
+/// in full by `build_response`. The on-disk format is unaffected.
+fn build_projected_response(
+    format: Option<&str>,
+    delimiter: CsvDelimiter,
+    fields: Option<&[String]>,
+    case: KeyCase,
+    response: &ApiResponse,
+) -> HttpResponse {
+    if format.unwrap_or("json") != "json" || (fields.is_none() && case == KeyCase::Snake) {
+        return build_response(format, delimiter, response);
+    }
+    if case == KeyCase::Camel {
+        if let Some(camel) = CamelResponse::new(response, fields) {
+            return HttpResponse::Ok().json(camel);
+        }
+    }
+    let mut value = serde_json::to_value(response).unwrap();
+    if let Some(fields) = fields {
+        project_fields(&mut value, fields);
+    }
+    HttpResponse::Ok().json(value)
+}
+
+/// Builds the response of a read route: as [`build_projected_response`], or, with
+/// `?envelope=false`, only the messages as a bare JSON object or array.
+///
+/// The unwrapped form applies to JSON results carrying messages (`Item`, `Items`, `Batch`,
+/// `Page`) or search hits; any other result, including every error, keeps the envelope. A page
+/// loses its `next_cursor`, so the caller sends it in a header instead (see [`api_page`]).
+fn build_read_response(
+    format: Option<&str>,
+    delimiter: CsvDelimiter,
+    fields: Option<&[String]>,
+    case: KeyCase,
+    envelope: bool,
+    response: &ApiResponse,
+) -> HttpResponse {
+    if envelope || format.unwrap_or("json") != "json" {
+        return build_projected_response(format, delimiter, fields, case, response);
+    }
+    if case == KeyCase::Camel {
+        return match CamelContent::new(&response.result, fields).and_then(|camel| camel.unwrapped())
+        {
+            Some(value) => HttpResponse::Ok().json(value),
+            None => build_projected_response(format, delimiter, fields, case, response),
+        };
+    }
+    // Messages go through their public form, as in the envelope.
+    let serializer = serde_json::value::Serializer;
+    let unwrapped = match &response.result {
+        ResponseContent::Item(item) => public::message(item, serializer),
+        ResponseContent::Items(items) => public::messages(items, serializer),
+        ResponseContent::Batch(items) => public::optional_messages(items, serializer),
+        ResponseContent::Page { items, .. } => public::messages(items, serializer),
+        ResponseContent::Hits(hits) => serde_json::to_value(hits),
+        _ => return build_projected_response(format, delimiter, fields, case, response),
+    };
+    let mut value = unwrapped.unwrap();
+    if let Some(fields) = fields {
+        project_fields(&mut value, fields);
+    }
+    HttpResponse::Ok().json(value)
+}
+
+/// The values accepted by the `format` query parameter.
+const SUPPORTED_FORMATS: &[&str] = &["json", "xml", "csv", "text", "jsonl"];
+
+/// The content type of JSON Lines responses.
+const JSONL_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// Quotes a CSV field when it contains the delimiter, a quote, or a line break (RFC 4180).
+fn escape_csv(value: &str, delimiter: CsvDelimiter) -> String {
+    if value.contains([delimiter.as_char(), '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Renders messages as CSV with a header row. Attachments are separated by spaces.
+fn messages_to_csv(messages: &[&Message], delimiter: CsvDelimiter) -> String {
+    let mut csv = delimiter.row(&[
+        "id",
+        "posted",
+        "sender",
+        "content",
+        "attachments",
+        "updated",
+    ]);
+    for message in messages {
+        csv.push_str(&delimiter.row(&[
+            message.id.to_string(),
+            escape_csv(&message.posted, delimiter),
+            escape_csv(&message.sender, delimiter),
+            escape_csv(&message.content, delimiter),
+            escape_csv(&message.attachments.join(" "), delimiter),
+            escape_csv(&message.updated, delimiter),
+        ]));
+    }
+    csv
+}
+
+/// Renders messages as plain text for command-line clients, one `#id [posted] sender: content`
+/// block per message. Continuation lines of the content are indented by four spaces.
+fn messages_to_text(messages: &[&Message]) -> String {
+    let mut text = String::new();
+    for message in messages {
+        let content = message
+            .content
+            .replace("\r\n", "\n")
+            .replace('\n', "\n    ");
+        text.push_str(&format!(
+            "#{} [{}] {}: {}\n",
+            message.id, message.posted, message.sender, content
+        ));
+    }
+    text
+}
+
+/// Serializes one message as a JSON Lines record, with the requested fields and key case.
+fn message_to_jsonl(message: &Message, fields: Option<&[String]>, case: KeyCase) -> Bytes {
+    let mut line = match (case, fields) {
+        (KeyCase::Camel, _) => serde_json::to_vec(&CamelMessage::new(message, fields)).unwrap(),
+        (KeyCase::Snake, None) => serde_json::to_vec(&PublicMessage::from(message)).unwrap(),
+        (KeyCase::Snake, Some(fields)) => {
+            let mut value = serde_json::to_value(PublicMessage::from(message)).unwrap();
+            if let serde_json::Value::Object(object) = &mut value {
+                object.retain(|key, _| fields.contains(key));
+            }
+            serde_json::to_vec(&value).unwrap()
+        }
+    };
+    line.push(b'\n');
+    Bytes::from(line)
+}
+
+/// Streams messages as JSON Lines, serializing each record only when the client is ready for it.
+fn stream_jsonl(
+    messages: Arc<Vec<Message>>,
+    fields: Option<Vec<String>>,
+    case: KeyCase,
+) -> HttpResponse {
+    let lines = (0..messages.len())
+        .map(move |i| Ok::<_, Error>(message_to_jsonl(&messages[i], fields.as_deref(), case)));
+    HttpResponse::Ok()
+        .content_type(JSONL_CONTENT_TYPE)
+        .streaming(futures_util::stream::iter(lines))
+}
+
+/// Returns the `format` to respond in: the query parameter when given, otherwise `text` when the
+/// `Accept` header prefers `text/plain`, or `jsonl` when it prefers `application/x-ndjson`.
+fn negotiated_format<'a>(req: &HttpRequest, format: Option<&'a str>) -> Option<&'a str> {
+    format.or_else(|| {
+        let accept = Accept::parse(req).ok()?;
+        match accept.preference().essence_str() {
+            "text/plain" => Some("text"),
+            JSONL_CONTENT_TYPE => Some("jsonl"),
+            _ => None,
+        }
+    })
+}
+
+/// Serializes senders as CSV, with a `count` column when the counts are included.
+fn senders_to_csv(senders: &[SenderCount], delimiter: CsvDelimiter) -> String {
+    let counted = senders.iter().any(|sender| sender.count.is_some());
+    let mut csv = if counted {
+        delimiter.row(&["sender", "count"])
+    } else {
+        delimiter.row(&["sender"])
+    };
+    for sender in senders {
+        let mut row = vec![escape_csv(&sender.sender, delimiter)];
+        if let Some(count) = sender.count {
+            row.push(count.to_string());
+        }
+        csv.push_str(&delimiter.row(&row));
+    }
+    csv
+}
+
+/// Serializes daily counts as CSV, with a header row.
+fn days_to_csv(days: &[DailyCount], delimiter: CsvDelimiter) -> String {
+    let mut csv = delimiter.row(&["date", "count"]);
+    for day in days {
+        csv.push_str(&delimiter.row(&[
+            day.date.format(DATE_FORMAT).to_string(),
+            day.count.to_string(),
+        ]));
+    }
+    csv
+}
+
+/// Builds the error response of a failed XML rendering (see [`xml::to_string`]).
+///
+/// ### Returns
+/// - `406 Not Acceptable` naming the character when the content cannot be represented in XML.
+/// - `500 Internal Server Error` when the serializer failed.
+fn xml_error(error: XmlError) -> HttpResponse {
+    let status = match error {
+        XmlError::InvalidChar(_) => StatusCode::NOT_ACCEPTABLE,
+        XmlError::Serialize(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    error_response(status, &error.to_string())
+}
+
+/// Serializes a response in the requested format.
+///
+/// ### Returns
+/// - The response as JSON when `format` is `json` or absent.
+/// - The response as XML when `format` is `xml`, or `406 Not Acceptable` when the content holds
+///   characters XML cannot represent (see [`crate::handler::xml`]).
+/// - The messages (or daily counts) as CSV when `format` is `csv` and the response carries
+///   messages (or daily counts), with fields separated by `delimiter`.
+/// - The messages as plain text (see [`messages_to_text`]) when `format` is `text`. Responses
+///   without messages are rendered as their status, followed by the reason if any.
+/// - The messages as JSON Lines, one object per line, when `format` is `jsonl`.
+/// - `406 Not Acceptable` listing the supported formats for any other value, for `csv` on a
+///   response without messages, or for `text` on other structured results.
+fn build_response(
+    format: Option<&str>,
+    delimiter: CsvDelimiter,
+    response: &ApiResponse,
+) -> HttpResponse {
+    match format.unwrap_or("json") {
+        "json" => HttpResponse::Ok().json(response),
+        "xml" => match xml::to_string(response) {
+            Ok(body) => HttpResponse::Ok()
+                .content_type("application/xml; charset=utf-8")
+                .body(body),
+            Err(error) => xml_error(error),
+        },
+        "csv" => {
+            let messages: Vec<&Message> = match &response.result {
+                ResponseContent::Days(days) => {
+                    return HttpResponse::Ok()
+                        .content_type("text/csv; charset=utf-8")
+                        .body(days_to_csv(days, delimiter))
+                }
+                ResponseContent::Senders(senders) => {
+                    return HttpResponse::Ok()
+                        .content_type("text/csv; charset=utf-8")
+                        .body(senders_to_csv(senders, delimiter))
+                }
+                content => match content.messages() {
+                    Some(messages) => messages,
+                    None => {
+                        return error_response(
+                            StatusCode::NOT_ACCEPTABLE,
+                            "The csv format is only available for posts",
+                        )
+                    }
+                },
+            };
+            HttpResponse::Ok()
+                .content_type("text/csv; charset=utf-8")
+                .body(messages_to_csv(&messages, delimiter))
+        }
+        "jsonl" => {
+            let Some(messages) = response.result.messages() else {
+                return error_response(
+                    StatusCode::NOT_ACCEPTABLE,
+                    "The jsonl format is only available for posts",
+                );
+            };
+            let lines: Vec<Bytes> = messages
+                .into_iter()
+                .map(|message| message_to_jsonl(message, None, KeyCase::Snake))
+                .collect();
+            HttpResponse::Ok()
+                .content_type(JSONL_CONTENT_TYPE)
+                .body(lines.concat())
+        }
+        "text" => {
+            let text = match &response.result {
+                ResponseContent::Reason(reason) => format!("{}: {}\n", response.status, reason),
+                ResponseContent::Count(count) => format!("{}\n", count),
+                ResponseContent::None => format!("{}\n", response.status),
+                content => match content.messages() {
+                    Some(messages) => messages_to_text(&messages),
+                    None => {
+                        return error_response(
+                            StatusCode::NOT_ACCEPTABLE,
+                            "The text format is only available for posts",
+                        )
+                    }
+                },
+            };
+            HttpResponse::Ok()
+                .content_type("text/plain; charset=utf-8")
+                .body(text)
+        }
+        format => {
+            unsupported_format(Some(format)).unwrap_or_else(|| HttpResponse::Ok().json(response))
+        }
+    }
+}
+
+/// Rejects `format` values that [`build_response`] cannot render.
+///
+/// Mutating routes call this before changing anything, so a typo such as `?format=xlm` does not
+/// apply the change and then fail.
+///
+/// ### Returns
+/// - `Some(response)` with `406 Not Acceptable` listing the supported formats.
+/// - `None` when the format is supported or absent.
+fn unsupported_format(format: Option<&str>) -> Option<HttpResponse> {
+    let format = format?;
+    (!SUPPORTED_FORMATS.contains(&format)).then(|| {
+        error_response(
+            StatusCode::NOT_ACCEPTABLE,
+            &format!(
+                "Unsupported format: {} (supported: {})",
+                format,
+                SUPPORTED_FORMATS.join(", ")
+            ),
+        )
+    })
+}
+
+/// Lists every message.
+///
+/// The `order` query parameter selects `newest` (default) or `oldest` first. The effective
+/// ordering, including the `id` tie-breaker, is reported in the `sort` field of the response.
+///
+/// With `ids=1,2,3`, only those messages are returned, in request order (see [`api_batch`]).
+///
+/// Without a `format` parameter, clients whose `Accept` header prefers `text/plain` (e.g.
+/// `curl -H 'Accept: text/plain'`) get the plain text rendering.
+///
+/// With `format=jsonl` (or `Accept: application/x-ndjson`), the messages are streamed as JSON
+/// Lines: one object per line, without the response envelope. `fields` and `case` apply to each
+/// object. Streamed listings bypass the response cache.
+///
+/// With `cursor` and/or `limit`, the listing is paginated by ID instead (see [`api_page`]).
+///
+/// Archived messages are left out unless `status` asks for them: `open`, `closed`, or
+/// `archived` lists only the messages with that status, and `all` lists every message. The
+/// status applies to streamed and paginated listings too, but not to `ids`.
+///
+/// Full listings are served from the response cache when possible (see [`cache`]).
+///
+/// With `envelope=false`, the JSON body is the bare array of messages, without the `status` and
+/// `result` wrapper (see [`build_read_response`]). Errors keep the envelope. The same parameter
+/// applies to the other read routes: a single post, `first`, `latest`, `random`, and `search`.
+///
+/// Every response carries the current [`data::generation_token`] in an [`GENERATION_HEADER`]
+/// header, e.g. `X-Generation: 1735214465000-12`. It changes whenever a message is created,
+/// changed, or deleted, so a client paging through the listing can compare it across fetches and
+/// start over when it moved. Reads leave it unchanged.
+#[get("/posts")]
+pub async fn api_index(req: HttpRequest, query: web::Query<Queries>) -> impl Responder {
+    // Taken before the listing is read, so a concurrent write shows up as a changed token on the
+    // next fetch rather than going unnoticed.
+    let token = data::generation_token();
+    let mut response = list_posts(&req, &query).await;
+    if let Ok(value) = HeaderValue::from_str(&token) {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(GENERATION_HEADER), value);
+    }
+    response
+}
+
+/// Builds the response of [`api_index`], without the generation header.
+async fn list_posts(req: &HttpRequest, query: &Queries) -> HttpResponse {
+    let delimiter = match CsvDelimiter::parse(query.delimiter.as_deref()) {
+        Ok(delimiter) => delimiter,
+        Err(reason) => return bad_request(reason),
+    };
+    let case = match KeyCase::parse(query.case.as_deref()) {
+        Ok(case) => case,
+        Err(reason) => return bad_request(reason),
+    };
+    let fields = match parse_fields(query.fields.as_deref()) {
+        Ok(fields) => fields,
+        Err(reason) => return bad_request(reason),
+    };
+    let format = negotiated_format(req, query.format.as_deref());
+    if let Some(ids) = query.ids.as_deref() {
+        return api_batch(ids, query, format, delimiter, fields.as_deref(), case);
+    }
+    let status = match query.status.as_deref() {
+        Some(status) => match StatusFilter::parse(status) {
+            Some(status) => status,
+            None => {
+                return bad_request(
+                    "status must be one of open, closed, archived, or all".to_string(),
+                )
+            }
+        },
+        None => StatusFilter::default(),
+    };
+    if query.cursor.is_some() || query.limit.is_some() {
+        return api_page(query, status, format, delimiter, fields.as_deref(), case);
+    }
+    let order = match query.order.as_deref() {
+        Some(order) => match SortOrder::parse(order) {
+            Some(order) => order,
+            None => return bad_request("order must be either newest or oldest".to_string()),
+        },
+        None => SortOrder::default(),
+    };
+    if format == Some("jsonl") {
+        return stream_jsonl(status.apply(get_all_shared(order)), fields, case);
+    }
+    let generation = data::generation();
+    let posts = status.apply(get_all_shared(order));
+    // Expiring messages does not move the generation, but it changes the number of messages.
+    let envelope = query.envelope.unwrap_or(true);
+    let key = format!(
+        "posts?format={}&delimiter={:?}&order={}&status={}&fields={}&case={:?}&envelope={}&count={}",
+        format.unwrap_or("json"),
+        delimiter,
+        order.as_str(),
+        status.as_str(),
+        fields.as_deref().unwrap_or_default().join(","),
+        case,
+        envelope,
+        posts.len()
+    );
+    if let Some(response) = cache::lookup(&key) {
+        return response;
+    }
+
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: Some(order.into()),
+        result: ResponseContent::Items(posts),
+    };
+    let response = build_read_response(
+        format,
+        delimiter,
+        fields.as_deref(),
+        case,
+        envelope,
+        &response,
+    );
+    cache::store(key, generation, response).await
+}
+
+/// Lists one page of messages in ascending ID order, starting after the `cursor`.
+///
+/// Unlike positions in a time-ordered listing, IDs never shift when messages are created, so
+/// walking the pages with `next_cursor` returns every message once, whatever happens meanwhile.
+/// `order` is ignored in this mode.
+///
+/// ### Query Parameters
+/// - `cursor`: The `next_cursor` of the previous page; omit it for the first page.
+/// - `limit`: The page size, [`DEFAULT_PAGE_LIMIT`] by default and at most [`MAX_PAGE_LIMIT`].
+///
+/// ### Returns
+/// - `200 OK` with `Page`, whose `next_cursor` is `null` on the last page. With
+///   `envelope=false`, the bare array of messages, and the cursor in a [`NEXT_CURSOR_HEADER`]
+///   header, absent on the last page.
+/// - `400 Bad Request` for an invalid cursor or an out-of-range limit.
+fn api_page(
+    query: &Queries,
+    status: StatusFilter,
+    format: Option<&str>,
+    delimiter: CsvDelimiter,
+    fields: Option<&[String]>,
+    case: KeyCase,
+) -> HttpResponse {
+    let after = match query.cursor.as_deref() {
+        Some(cursor) => match decode_cursor(cursor) {
+            Some(after) => after,
+            None => return bad_request("Invalid cursor".to_string()),
+        },
+        None => i32::MIN,
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+    if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+        return bad_request(format!("limit must be between 1 and {}", MAX_PAGE_LIMIT));
+    }
+    let mut items = data::get_since(after);
+    items.retain(|message| status.matches(message));
+    let next_cursor = if items.len() > limit {
+        items.truncate(limit);
+        items.last().map(|message| encode_cursor(message.id))
+    } else {
+        None
+    };
+
+    let envelope = query.envelope.unwrap_or(true);
+    let header = next_cursor
+        .as_deref()
+        .filter(|_| !envelope)
+        .and_then(|cursor| HeaderValue::from_str(cursor).ok());
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Page { items, next_cursor },
+    };
+    let mut response = build_read_response(format, delimiter, fields, case, envelope, &response);
+    if let Some(value) = header {
+        response
+            .headers_mut()
+            .insert(HeaderName::from_static(NEXT_CURSOR_HEADER), value);
+    }
+    response
+}
+
+/// Fetches the messages listed in the `ids` query parameter.
+///
+/// Missing IDs are left out by default; with `missing=null` they are kept as `null` entries, so
+/// the result lines up with the request.
+///
+/// ### Returns
+/// - `200 OK` with `Items` (omitted missing IDs) or `Batch` (`null` placeholders).
+/// - `400 Bad Request` for an invalid ID, more than [`MAX_BATCH_IDS`] IDs, or an unknown
+///   `missing` mode.
+fn api_batch(
+    ids: &str,
+    query: &Queries,
+    format: Option<&str>,
+    delimiter: CsvDelimiter,
+    fields: Option<&[String]>,
+    case: KeyCase,
+) -> HttpResponse {
+    let ids = match parse_ids(ids) {
+        Ok(ids) => ids,
+        Err(reason) => return bad_request(reason),
+    };
+    if ids.len() > MAX_BATCH_IDS {
+        return bad_request(format!(
+            "At most {} ids can be fetched at once",
+            MAX_BATCH_IDS
+        ));
+    }
+    let found = data::get_many(&ids);
+    let result = match query.missing.as_deref() {
+        None | Some("omit") => {
+            ResponseContent::Items(Arc::new(found.into_iter().flatten().collect()))
+        }
+        Some("null") => ResponseContent::Batch(found),
+        Some(mode) => return bad_request(format!("Unknown missing mode: {}", mode)),
+    };
+
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result,
+    };
+    let envelope = query.envelope.unwrap_or(true);
+    build_read_response(format, delimiter, fields, case, envelope, &response)
+}
+
+#[get("/posts/{id:\\d+}")]
+pub async fn api_show(
+    req: HttpRequest,
+    id: web::Path<i32>,
+    query: web::Query<Queries>,
+) -> impl Responder {
+    let delimiter = match CsvDelimiter::parse(query.delimiter.as_deref()) {
+        Ok(delimiter) => delimiter,
+        Err(reason) => return bad_request(reason),
+    };
+    let case = match KeyCase::parse(query.case.as_deref()) {
+        Ok(case) => case,
+        Err(reason) => return bad_request(reason),
+    };
+    let fields = match parse_fields(query.fields.as_deref()) {
+        Ok(fields) => fields,
+        Err(reason) => return bad_request(reason),
+    };
+    let Some(post) = get(id.into_inner()) else {
+        return post_not_found();
+    };
+    let last_modified = post.modified_at();
+
+    let format = negotiated_format(&req, query.format.as_deref());
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Item(post),
+    };
+    let envelope = query.envelope.unwrap_or(true);
+    let mut response = build_read_response(
+        format,
+        delimiter,
+        fields.as_deref(),
+        case,
+        envelope,
+        &response,
+    );
+    if let Some(last_modified) = last_modified {
+        let date = HttpDate::from(SystemTime::from(last_modified)).to_string();
+        if let Ok(value) = HeaderValue::from_str(&date) {
+            response.headers_mut().insert(header::LAST_MODIFIED, value);
+        }
+    }
+    response
+}
+
+#[get("/posts/first")]
+pub async fn api_first(req: HttpRequest, query: web::Query<Queries>) -> impl Responder {
+    let delimiter = match CsvDelimiter::parse(query.delimiter.as_deref()) {
+        Ok(delimiter) => delimiter,
+        Err(reason) => return bad_request(reason),
+    };
+    let case = match KeyCase::parse(query.case.as_deref()) {
+        Ok(case) => case,
+        Err(reason) => return bad_request(reason),
+    };
+    let Some(post) = data::first() else {
+        return post_not_found();
+    };
+
+    let format = negotiated_format(&req, query.format.as_deref());
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Item(post),
+    };
+    let envelope = query.envelope.unwrap_or(true);
+    build_read_response(format, delimiter, None, case, envelope, &response)
+}
+
+#[get("/posts/latest")]
+pub async fn api_latest(req: HttpRequest, query: web::Query<Queries>) -> impl Responder {
+    let delimiter = match CsvDelimiter::parse(query.delimiter.as_deref()) {
+        Ok(delimiter) => delimiter,
+        Err(reason) => return bad_request(reason),
+    };
+    let case = match KeyCase::parse(query.case.as_deref()) {
+        Ok(case) => case,
+        Err(reason) => return bad_request(reason),
+    };
+    let Some(post) = data::latest() else {
+        return post_not_found();
+    };
+
+    let format = negotiated_format(&req, query.format.as_deref());
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Item(post),
+    };
+    let envelope = query.envelope.unwrap_or(true);
+    build_read_response(format, delimiter, None, case, envelope, &response)
+}
+
+/// Returns a random message, optionally picked among those of one `sender` or with one `tag`.
+///
+/// Answers `404 Not Found` when no message matches.
+#[get("/posts/random")]
+pub async fn api_random(req: HttpRequest, query: web::Query<RandomQueries>) -> impl Responder {
+    let delimiter = match CsvDelimiter::parse(query.delimiter.as_deref()) {
+        Ok(delimiter) => delimiter,
+        Err(reason) => return bad_request(reason),
+    };
+    let case = match KeyCase::parse(query.case.as_deref()) {
+        Ok(case) => case,
+        Err(reason) => return bad_request(reason),
+    };
+    let Some(post) = data::random(query.sender.as_deref(), query.tag.as_deref()) else {
+        return post_not_found();
+    };
+
+    let format = negotiated_format(&req, query.format.as_deref());
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Item(post),
+    };
+    let envelope = query.envelope.unwrap_or(true);
+    build_read_response(format, delimiter, None, case, envelope, &response)
+}
+
+/// Searches the sender and content of the messages, ignoring case.
+///
+/// Whole messages are returned by default. With `snippet=true`, each match is reduced to a
+/// snippet of `context` characters (default [`DEFAULT_SNIPPET_CONTEXT`], capped at
+/// [`MAX_SNIPPET_CONTEXT`]) on each side of the first match in the content (see [`search`]).
+///
+/// With `count_only=true`, only the number of matches is returned, for search-as-you-type UIs
+/// (see [`search::search_count`]). The `format`, `case`, and `envelope` parameters apply as on
+/// other routes.
+///
+/// ### Returns
+/// - `200 OK` with `Items` or, with `snippet=true`, `Hits`, newest first. With
+///   `count_only=true`, `Count` instead.
+/// - `400 Bad Request` when `q` is missing or blank.
+#[get("/posts/search")]
+pub async fn api_search(query: web::Query<SearchQueries>) -> impl Responder {
+    let delimiter = match CsvDelimiter::parse(query.delimiter.as_deref()) {
+        Ok(delimiter) => delimiter,
+        Err(reason) => return bad_request(reason),
+    };
+    let case = match KeyCase::parse(query.case.as_deref()) {
+        Ok(case) => case,
+        Err(reason) => return bad_request(reason),
+    };
+    let Some(q) = query.q.as_deref().filter(|q| !q.trim().is_empty()) else {
+        return bad_request("The q parameter is required".to_string());
+    };
+    if query.count_only.unwrap_or(false) {
+        let response = ApiResponse {
+            status: "OK".to_string(),
+            code: None,
+            sort: None,
+            result: ResponseContent::Count(search::search_count(q)),
+        };
+        return build_response(query.format.as_deref(), delimiter, &response);
+    }
+    let result = if query.snippet.unwrap_or(false) {
+        let context = query
+            .context
+            .unwrap_or(DEFAULT_SNIPPET_CONTEXT)
+            .min(MAX_SNIPPET_CONTEXT);
+        ResponseContent::Hits(search::search_with_snippets(q, context))
+    } else {
+        ResponseContent::Items(Arc::new(search::search(q)))
+    };
+
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: Some(SortOrder::Newest.into()),
+        result,
+    };
+    let envelope = query.envelope.unwrap_or(true);
+    build_read_response(
+        query.format.as_deref(),
+        delimiter,
+        None,
+        case,
+        envelope,
+        &response,
+    )
+}
+
+/// Waits for messages newer than `since`, for clients that cannot use the event stream.
+///
+/// Answers immediately when such messages exist. Otherwise the request is held open until a
+/// message is created or `timeout` seconds elapse (default [`DEFAULT_POLL_TIMEOUT`], capped at
+/// [`MAX_POLL_TIMEOUT`]). `case=camel` applies as for the listing.
+///
+/// ### Returns
+/// - `200 OK` with `Items`: every message with an ID greater than `since`, in ascending ID
+///   order, or an empty list on timeout.
+/// - `400 Bad Request` when `since` is missing.
+#[get("/posts/poll")]
+pub async fn api_poll(query: web::Query<PollQueries>) -> impl Responder {
+    let Some(since) = query.since else {
+        return bad_request("The since parameter is required".to_string());
+    };
+    let case = match KeyCase::parse(query.case.as_deref()) {
+        Ok(case) => case,
+        Err(reason) => return bad_request(reason),
+    };
+    let timeout = query
+        .timeout
+        .unwrap_or(DEFAULT_POLL_TIMEOUT)
+        .min(MAX_POLL_TIMEOUT);
+    let mut receiver = events::subscribe();
+    let mut posts = data::get_since(since);
+    if posts.is_empty() {
+        let created = actix_rt::time::timeout(Duration::from_secs(timeout), async {
+            loop {
+                match receiver.recv().await {
+                    Ok(Event::Created(message)) if message.id <= since => continue,
+                    Ok(Event::Deleted(_)) => continue,
+                    Ok(_) | Err(RecvError::Lagged(_)) => return true,
+                    Err(RecvError::Closed) => return false,
+                }
+            }
+        })
+        .await;
+        if created == Ok(true) {
+            posts = data::get_since(since);
+        }
+    }
+
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Items(Arc::new(posts)),
+    };
+    build_projected_response(None, CsvDelimiter::Comma, None, case, &response)
+}
+
+/// Lists the creations, updates, and deletions recorded after the journal entry `since`, for
+/// clients mirroring the board (see [`data::changes_since`]).
+///
+/// ### Query Parameters
+/// - `since`: The `seq` of the last change already applied. Defaults to `0`.
+/// - `limit`: The maximum number of changes returned, from 1 to [`MAX_PAGE_LIMIT`] (default
+///   [`DEFAULT_PAGE_LIMIT`]).
+///
+/// ### Returns
+/// - `200 OK` with `Changes`: the changes in journal order and the `cursor` to pass as `since`
+///   next time (unchanged when there were none).
+/// - `400 Bad Request` for an invalid `limit`.
+/// - `410 Gone` with `CURSOR_EXPIRED` when the journal no longer reaches `since`; the client has
+///   to read every message again and continue from the current cursor.
+///
+/// ### Example Response Payload (JSON)
+/// ```json
+/// {
+///     "status": "OK",
+///     "result": {
+///         "Changes": {
+///             "changes": [{ "seq": 8, "kind": "deleted", "id": 3, "at": "2024-12-26 12:01:05Z" }],
+///             "cursor": 8
+///         }
+///     }
+/// }
+/// ```
+#[get("/posts/changes")]
+pub async fn api_changes(query: web::Query<ChangesQueries>) -> impl Responder {
+    let since = query.since.unwrap_or_default();
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+    if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+        return bad_request(format!("limit must be between 1 and {}", MAX_PAGE_LIMIT));
+    }
+    let Some(mut changes) = data::changes_since(since) else {
+        return coded_error_response(
+            StatusCode::GONE,
+            ErrorCode::CursorExpired,
+            "The change journal no longer reaches this cursor",
+        );
+    };
+    changes.truncate(limit);
+    let cursor = changes.last().map_or(since, |change| change.seq);
+
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Changes { changes, cursor },
+    };
+    HttpResponse::Ok().json(response)
+}
+
+/// Lists messages grouped by the day they were posted on or by sender, for timeline views (see
+/// [`data::grouped_by_day`] and [`data::grouped_by_sender`]).
+///
+/// Groups are keyed by day (`YYYY-MM-DD`, UTC) in chronological order, or by sender
+/// in alphabetical order. Pages hold whole groups, so a group is never split across pages.
+///
+/// ### Query Parameters
+/// - `by`: `day` (the default) or `sender`.
+/// - `after`: The `next_after` of the previous page; omit it for the first page.
+/// - `limit`: The maximum number of groups returned, from 1 to [`MAX_PAGE_LIMIT`] (default
+///   [`DEFAULT_PAGE_LIMIT`]).
+/// - `status`: As for the listing; archived messages are left out by default.
+/// - `case`: `camel` for camelCase keys (`nextAfter`), as for the listing.
+///
+/// ### Returns
+/// - `200 OK` with `Grouped`, whose `next_after` is `null` on the last page.
+/// - `400 Bad Request` for an unknown `by` or `status`, or an invalid `limit`.
+///
+/// ### Example Response Payload (JSON)
+/// ```json
+/// {
+///     "status": "OK",
+///     "result": {
+///         "Grouped": {
+///             "groups": {
+///                 "2024-12-25": [{ "id": 1, "sender": "Nao", "content": "Merry Christmas" }],
+///                 "2024-12-26": [{ "id": 2, "sender": "Shino", "content": "Good morning" }]
+///             },
+///             "next_after": null
+///         }
+///     }
+/// }
+/// ```
+#[get("/posts/grouped")]
+pub async fn api_grouped(query: web::Query<GroupedQueries>) -> impl Responder {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+    if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+        return bad_request(format!("limit must be between 1 and {}", MAX_PAGE_LIMIT));
+    }
+    let case = match KeyCase::parse(query.case.as_deref()) {
+        Ok(case) => case,
+        Err(reason) => return bad_request(reason),
+    };
+    let status = match query.status.as_deref() {
+        Some(status) => match StatusFilter::parse(status) {
+            Some(status) => status,
+            None => {
+                return bad_request(
+                    "status must be one of open, closed, archived, or all".to_string(),
+                )
+            }
+        },
+        None => StatusFilter::Active,
+    };
+    let mut groups = match query.by.as_deref().unwrap_or("day") {
+        "day" => data::grouped_by_day(status),
+        "sender" => data::grouped_by_sender(status),
+        _ => return bad_request("by must be day or sender".to_string()),
+    };
+    if let Some(after) = &query.after {
+        groups = groups.split_off(after);
+        groups.remove(after);
+    }
+    let next_after = if groups.len() > limit {
+        let rest_key = groups.keys().nth(limit).cloned().unwrap_or_default();
+        groups.split_off(&rest_key);
+        groups.keys().next_back().cloned()
+    } else {
+        None
+    };
+
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Grouped { groups, next_after },
+    };
+    build_projected_response(None, CsvDelimiter::Comma, None, case, &response)
+}
+
+#[get("/stats/range")]
+pub async fn api_stats_range(query: web::Query<Queries>) -> impl Responder {
+    let case = match KeyCase::parse(query.case.as_deref()) {
+        Ok(case) => case,
+        Err(reason) => return bad_request(reason),
+    };
+    let range = data::time_range();
+
+    let format = query.format.as_deref();
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Range(range),
+    };
+    build_projected_response(format, CsvDelimiter::Comma, None, case, &response)
+}
+
+/// Reports the size of the data files and the number of stored messages, so operators can watch
+/// the store grow.
+///
+/// ### Returns
+/// - `200 OK` with `Storage`. Missing data files are listed with a size of `0`.
+#[get("/stats/storage")]
+pub async fn api_stats_storage(query: web::Query<Queries>) -> impl Responder {
+    let case = match KeyCase::parse(query.case.as_deref()) {
+        Ok(case) => case,
+        Err(reason) => return bad_request(reason),
+    };
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Storage(data::storage_stats()),
+    };
+    build_projected_response(
+        query.format.as_deref(),
+        CsvDelimiter::Comma,
+        None,
+        case,
+        &response,
+    )
+}
+
+/// Lists the distinct senders, sorted, for author directories and sender filters.
+///
+/// ### Query Parameters
+/// - `counts`: When `true`, each sender comes with their number of messages.
+/// - `format`: `json` (default), `xml`, or `csv`.
+/// - `delimiter`: The CSV field separator, `comma` (default), `semicolon`, or `tab`.
+///
+/// ### Returns
+/// - `200 OK` with `Senders`.
+#[get("/senders")]
+pub async fn api_senders(query: web::Query<SendersQueries>) -> impl Responder {
+    let delimiter = match CsvDelimiter::parse(query.delimiter.as_deref()) {
+        Ok(delimiter) => delimiter,
+        Err(reason) => return bad_request(reason),
+    };
+    let mut senders = data::distinct_senders();
+    if !query.counts.unwrap_or(false) {
+        senders.iter_mut().for_each(|sender| sender.count = None);
+    }
+
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Senders(senders),
+    };
+    build_response(query.format.as_deref(), delimiter, &response)
+}
+
+/// Parses an optional `YYYY-MM-DD` query parameter.
+///
+/// ### Returns
+/// - `Ok(None)` when the parameter is absent.
+/// - `Err(reason)` when it is not a valid date.
+fn parse_date(name: &str, value: Option<&str>) -> Result<Option<NaiveDate>, String> {
+    value
+        .map(|value| {
+            NaiveDate::parse_from_str(value, DATE_FORMAT)
+                .map_err(|_| format!("{} must be a date in YYYY-MM-DD format", name))
+        })
+        .transpose()
+}
+
+/// Counts the messages posted per calendar day, for activity charts.
+///
+/// Days are UTC days, like the stored `posted` timestamps, whatever the server's time zone. Days
+/// without messages are left out.
+///
+/// ### Query Parameters
+/// - `from`, `to`: Optional first and last day to include (`YYYY-MM-DD`, inclusive).
+/// - `format`: `json` (default), `xml`, or `csv` (`date,count` rows).
+/// - `delimiter`: The CSV field separator, `comma` (default), `semicolon`, or `tab`.
+///
+/// ### Returns
+/// - `200 OK` with `Days`, oldest day first.
+/// - `400 Bad Request` for an invalid date or when `from` is after `to`.
+#[get("/stats/daily")]
+pub async fn api_stats_daily(query: web::Query<DailyQueries>) -> impl Responder {
+    let delimiter = match CsvDelimiter::parse(query.delimiter.as_deref()) {
+        Ok(delimiter) => delimiter,
+        Err(reason) => return bad_request(reason),
+    };
+    let range = parse_date("from", query.from.as_deref())
+        .and_then(|from| parse_date("to", query.to.as_deref()).map(|to| (from, to)));
+    let (from, to) = match range {
+        Ok(range) => range,
+        Err(reason) => return bad_request(reason),
+    };
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            return bad_request("from must not be after to".to_string());
+        }
+    }
+    let included = from.unwrap_or(NaiveDate::MIN)..=to.unwrap_or(NaiveDate::MAX);
+    let days = data::counts_by_day()
+        .into_iter()
+        .filter(|day| included.contains(&day.date))
+        .collect();
+
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Days(days),
+    };
+    build_response(query.format.as_deref(), delimiter, &response)
+}
+
+#[post("/posts/create")]
+pub async fn api_create(
+    req: HttpRequest,
+    params: ApiJson<Message>,
+    session: Session,
+) -> impl Responder {
+    let Message {
+        sender,
+        content,
+        attachments,
+        expires_at,
+        ..
+    } = params.0;
+    // A logged-in user always posts under their own name, whatever the body says.
+    let sender = match auth::sender_identity(&session) {
+        Ok(identity) => identity.unwrap_or(sender),
+        Err(LoginRequired) => return login_required(),
+    };
+    let posted = data::current_timestamp();
+    let mut message = Message {
+        id: 0,
+        posted,
+        sender,
+        content,
+        attachments,
+        updated: String::new(),
+        reactions: BTreeMap::new(),
+        tags: vec![],
+        status: PostStatus::Open,
+        expires_at,
+        metadata: Some(Box::new(request_metadata(&req))),
+        slug: String::new(),
+    };
+    if let Err(errors) = validate_for(&req, &message) {
+        return unprocessable(errors);
+    }
+    message = match data::with_retries(|| data::create(message.clone())).await {
+        Ok(message) => message,
+        Err(error) => return data_error(error),
+    };
+
+    let format = Some("json");
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Item(message),
+    };
+    build_response(format, CsvDelimiter::Comma, &response)
+}
+
+/// Shows how a message would be stored, without storing it.
+///
+/// The body is the same as for `POST /posts/create`, and the message goes through the same
+/// validation and transforms: the timestamp is assigned, filtered words are masked, and the
+/// duplicate and quota checks apply (see [`data::preview`]). The returned message has the
+/// placeholder ID `0`, since IDs are only allocated on creation. Works in maintenance mode.
+///
+/// ### Returns
+/// - `200 OK` with the message as it would be stored.
+/// - The error responses of `POST /posts/create` (e.g. `422 Unprocessable Entity`) when it would
+///   be rejected.
+#[post("/posts/preview")]
+pub async fn api_preview(req: HttpRequest, params: ApiJson<Message>) -> impl Responder {
+    let Message {
+        sender,
+        content,
+        attachments,
+        expires_at,
+        ..
+    } = params.0;
+    let message = Message {
+        posted: data::current_timestamp(),
+        sender,
+        content,
+        attachments,
+        expires_at,
+        ..Default::default()
+    };
+    if let Err(errors) = validate_for(&req, &message) {
+        return unprocessable(errors);
+    }
+    let message = match data::preview(message) {
+        Ok(message) => message,
+        Err(error) => return data_error(error),
+    };
+
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Item(message),
+    };
+    HttpResponse::Ok().json(response)
+}
+
+/// Creates a message from a `multipart/form-data` body, storing uploaded images.
+///
+/// ### Fields
+/// - `sender`, `content`: The message text.
+/// - `attachments`: An attachment URL. May be repeated.
+/// - `image`: An image file (`image/png`, `image/jpeg`, or `image/gif`). May be repeated; each
+///   stored file is appended to the message's attachments as `/uploads/<sha256>.<ext>`.
+///
+/// Files are only written once the whole message passed validation. As with `POST /posts/create`,
+/// a logged-in user posts under their own name, whatever the `sender` field says.
+///
+/// ### Returns
+/// - `200 OK` with the created message.
+/// - `400 Bad Request` for a malformed multipart body or non-UTF-8 text fields.
+/// - `401 Unauthorized` when login is enabled and the session is not logged in.
+/// - `413 Payload Too Large` when an image or a text field exceeds its size limit.
+/// - `415 Unsupported Media Type` when an image has a disallowed type.
+/// - `422 Unprocessable Entity` when the message fails validation.
+#[post("/posts/upload")]
+pub async fn api_upload(
+    req: HttpRequest,
+    mut payload: Multipart,
+    session: Session,
+) -> impl Responder {
+    let identity = match auth::sender_identity(&session) {
+        Ok(identity) => identity,
+        Err(LoginRequired) => return login_required(),
+    };
+    let config = config::get();
+    let mut message = Message::default();
+    let mut uploads: Vec<PendingUpload> = vec![];
+    while let Some(field) = payload.next().await {
+        let mut field = match field {
+            Ok(field) => field,
+            Err(error) => return bad_request(format!("Malformed multipart body: {}", error)),
+        };
+        let name = field.name().unwrap_or_default().to_string();
+        if name == "image" {
+            let content_type = field
+                .content_type()
+                .map(|mime| mime.essence_str().to_string())
+                .unwrap_or_default();
+            if let Err(error) = upload::check_content_type(&content_type) {
+                return upload_error(error);
+            }
+            let bytes = match read_field(&mut field, config.max_upload).await {
+                Ok(Some(bytes)) => bytes,
+                Ok(None) => return upload_error(UploadError::TooLarge(config.max_upload)),
+                Err(reason) => return bad_request(reason),
+            };
+            match upload::prepare_image(&content_type, bytes) {
+                Ok(upload) => uploads.push(upload),
+                Err(error) => return upload_error(error),
+            }
+            continue;
+        }
+        let value = match read_field(&mut field, config.max_body).await {
+            Ok(Some(bytes)) => match String::from_utf8(bytes) {
+                Ok(value) => value,
+                Err(_) => return bad_request(format!("Field {} is not valid UTF-8", name)),
+            },
+            Ok(None) => {
+                return error_response(
+                    StatusCode::PAYLOAD_TOO_LARGE,
+                    &format!("Field {} is too large", name),
+                )
+            }
+            Err(reason) => return bad_request(reason),
+        };
+        match name.as_str() {
+            "sender" => message.sender = value,
+            "content" => message.content = value,
+            "attachments" => message.attachments.push(value),
+            _ => {}
+        }
+    }
+    if let Some(identity) = identity {
+        message.sender = identity;
+    }
+    message.posted = data::current_timestamp();
+    message
+        .attachments
+        .extend(uploads.iter().map(PendingUpload::url_path));
+    if let Err(errors) = validate_for(&req, &message) {
+        return unprocessable(errors);
+    }
+    for upload in &uploads {
+        if let Err(error) = upload.save() {
+            return upload_error(error);
+        }
+    }
+    let message = match data::with_retries(|| data::create(message.clone())).await {
+        Ok(message) => message,
+        Err(error) => return data_error(error),
+    };
+
+    let format = Some("json");
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Item(message),
+    };
+    build_response(format, CsvDelimiter::Comma, &response)
+}
+
+/// Checks the `If-Unmodified-Since` header of a request against a stored message.
+///
+/// The stored message's `updated` timestamp (or `posted`, if it was never updated) is compared
+/// with one-second precision, as HTTP dates have no finer resolution.
+///
+/// ### Returns
+/// - `Some(response)` with `412 Precondition Failed` when the message changed after the date.
+/// - `None` when the header is absent or malformed, the message has no parseable timestamp, or
+///   the message is unchanged since the date.
+fn unmodified_since_failed(req: &HttpRequest, stored: &Message) -> Option<HttpResponse> {
+    let since = IfUnmodifiedSince::parse(req).ok()?;
+    let since = DateTime::<Utc>::from(SystemTime::from(since.0));
+    let modified = stored.modified_at()?;
+    (modified.timestamp() > since.timestamp()).then(|| {
+        error_response(
+            StatusCode::PRECONDITION_FAILED,
+            "The post was modified after the If-Unmodified-Since date",
+        )
+    })
+}
+
+/// Validates and stores a changed message on behalf of an API request.
+///
+/// Validates `message`, accepting reserved sender names when the request carries the API key
+/// (see [`Message::validate_privileged`]), or the name of the user logged in on its session (see
+/// [`Message::validate_as`]).
+fn validate_for(req: &HttpRequest, message: &Message) -> Result<(), Vec<FieldError>> {
+    if auth::has_api_key(req) {
+        message.validate_privileged()
+    } else {
+        message.validate_as(auth::identity(&req.get_session()).as_deref())
+    }
+}
+
+/// Shared by [`api_update`] and [`api_patch`], once the message passed validation: checks that
+/// the session may edit the post (see [`check_editor_for`]) and `If-Unmodified-Since`, then
+/// applies the edit window unless the request carries the API key.
+async fn store_update(req: &HttpRequest, message: Message) -> HttpResponse {
+    if let Some(stored) = get(message.id) {
+        if let Err(refused) = check_editor_for(req, &stored.sender, &message.sender) {
+            let (status, code, reason) = edit_refused_status(refused);
+            return coded_error_response(status, code, reason);
+        }
+        if let Some(response) = unmodified_since_failed(req, &stored) {
+            return response;
+        }
+    }
+    let privileged = auth::has_api_key(req);
+    let result = data::with_retries(|| {
+        if privileged {
+            data::update_ignoring_edit_window(&message)
+        } else {
+            data::update(&message)
+        }
+    })
+    .await;
+    if let Err(error) = result {
+        return data_error(error);
+    }
+    let message = get(message.id).unwrap_or(message);
+
+    let format = Some("json");
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Item(message),
+    };
+    build_response(format, CsvDelimiter::Comma, &response)
+}
+
+/// Replaces a message.
+///
+/// ### Returns
+/// - `200 OK` with the stored message, including its new `updated` timestamp.
+/// - `401 Unauthorized` when login is enabled and the session is not logged in, unless the
+///   request carries the API key.
+/// - `403 Forbidden` outside the edit window (`ACTIX_POSTS_EDIT_WINDOW_MINUTES`), or when a
+///   logged-in user edits another user's post or changes its sender, unless the request carries
+///   the API key (see [`auth::check_editor`]).
+/// - `412 Precondition Failed` when the message changed after the `If-Unmodified-Since` date.
+/// - `422 Unprocessable Entity` when the message fails validation.
+#[put("/posts/update")]
+pub async fn api_update(req: HttpRequest, params: ApiJson<Message>) -> impl Responder {
+    let Message {
+        id,
+        posted,
+        sender,
+        content,
+        attachments,
+        expires_at,
+        ..
+    } = params.0;
+    let message = Message {
+        id,
+        posted,
+        sender,
+        content,
+        attachments,
+        updated: String::new(),
+        reactions: BTreeMap::new(),
+        tags: vec![],
+        status: PostStatus::Open,
+        expires_at,
+        metadata: None,
+        slug: String::new(),
+    };
+    if let Err(errors) = validate_for(&req, &message) {
+        return unprocessable(errors);
+    }
+    store_update(&req, message).await
+}
+
+/// Returns the body field a validation error belongs to: `attachments[1]` belongs to
+/// `attachments`.
+fn top_level_field(error: &FieldError) -> &str {
+    error.field.split('[').next().unwrap_or_default()
+}
+
+/// The fields a merge patch may change, and whether `null` clears them. `null` on the others is
+/// rejected, since they cannot be empty.
+const MERGE_PATCH_FIELDS: &[(&str, bool)] = &[
+    ("sender", false),
+    ("content", false),
+    ("attachments", true),
+    ("expires_at", true),
+];
+
+/// Changes some fields of a message, leaving the absent ones untouched.
+///
+/// The body may contain `sender`, `content`, and `attachments`. The merged message is validated,
+/// so e.g. an empty `content` is rejected, but only the errors of the fields in the body are
+/// reported: stored values left untouched were accepted when written, and a later change of the
+/// limits (or a reserved sender set with the API key) must not block other edits. The same
+/// rules as [`api_update`] apply otherwise.
+///
+/// Sent with `Content-Type: application/merge-patch+json`, the body is a JSON Merge Patch
+/// instead (see [`merge_patch`]): besides the fields above, it may set `expires_at`, and `null`
+/// clears `attachments` or `expires_at`. `null` on `sender` or `content`, and any other field,
+/// are rejected with `422`.
+///
+/// ### Returns
+/// The same responses as [`api_update`], plus `404 Not Found` for an unknown ID.
+#[patch("/posts/{id:\\d+}")]
+pub async fn api_patch(
+    req: HttpRequest,
+    id: web::Path<i32>,
+    params: ApiJson<serde_json::Value>,
+) -> impl Responder {
+    let Some(mut message) = get(id.into_inner()) else {
+        return post_not_found();
+    };
+    let is_merge_patch = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with(MERGE_PATCH_CONTENT_TYPE));
+    if is_merge_patch {
+        return apply_merge_patch(&req, message, &params).await;
+    }
+    let PatchRequest {
+        sender,
+        content,
+        attachments,
+    } = match parse_body(params.into_inner()) {
+        Ok(patch) => patch,
+        Err(reason) => return bad_request(reason),
+    };
+    let patched = [
+        ("sender", sender.is_some()),
+        ("content", content.is_some()),
+        ("attachments", attachments.is_some()),
+    ];
+    if let Some(sender) = sender {
+        message.sender = sender;
+    }
+    if let Some(content) = content {
+        message.content = content;
+    }
+    if let Some(attachments) = attachments {
+        message.attachments = attachments;
+    }
+    if let Err(errors) = validate_for(&req, &message) {
+        let errors: Vec<FieldError> = errors
+            .into_iter()
+            .filter(|error| patched.contains(&(top_level_field(error), true)))
+            .collect();
+        if !errors.is_empty() {
+            return unprocessable(errors);
+        }
+    }
+    store_update(&req, message).await
+}
+
+/// Applies the JSON Merge Patch `patch` to `message` for [`api_patch`], then validates and
+/// stores the result.
+///
+/// As with a plain patch, only the errors of the fields in the patch are reported.
+async fn apply_merge_patch(
+    req: &HttpRequest,
+    message: Message,
+    patch: &serde_json::Value,
+) -> HttpResponse {
+    let Some(fields) = patch.as_object() else {
+        return unprocessable(vec![FieldError {
+            field: String::new(),
+            reason: "must be an object".to_string(),
+        }]);
+    };
+    let errors: Vec<FieldError> = fields
+        .iter()
+        .filter_map(|(field, value)| {
+            let reason = match MERGE_PATCH_FIELDS.iter().find(|(name, _)| name == field) {
+                None => "cannot be changed by a merge patch",
+                Some((_, false)) if value.is_null() => "cannot be null",
+                Some(_) => return None,
+            };
+            Some(FieldError {
+                field: field.clone(),
+                reason: reason.to_string(),
+            })
+        })
+        .collect();
+    if !errors.is_empty() {
+        return unprocessable(errors);
+    }
+    let mut merged = serde_json::to_value(&message).unwrap_or_default();
+    merge_patch(&mut merged, patch);
+    let message = match validation::parse_record(&merged) {
+        Ok(message) => message,
+        Err(errors) => return unprocessable(errors),
+    };
+    if let Err(errors) = validate_for(req, &message) {
+        let errors: Vec<FieldError> = errors
+            .into_iter()
+            .filter(|error| fields.contains_key(top_level_field(error)))
+            .collect();
+        if !errors.is_empty() {
+            return unprocessable(errors);
+        }
+    }
+    store_update(req, message).await
+}
+
+/// Checks one update of [`api_batch_update`] against the stored message, the way [`api_patch`]
+/// does. Returns the result to report when the update is rejected before reaching the store.
+fn check_batch_update(req: &HttpRequest, update: &BatchUpdate) -> Option<BatchItemResult> {
+    let Some(mut message) = get(update.id) else {
+        return Some(BatchItemResult::from_outcome(
+            update.id,
+            BatchOutcome::NotFound,
+        ));
+    };
+    let stored_sender = message.sender.clone();
+    let changes = &update.changes;
+    changes.apply_to(&mut message);
+    if let Err(refused) = check_editor_for(req, &stored_sender, &message.sender) {
+        let (status, code, reason) = edit_refused_status(refused);
+        return Some(BatchItemResult::failed(
+            update.id,
+            status,
+            code,
+            Some(reason.to_string()),
+        ));
+    }
+    let mut errors: Vec<FieldError> = validate_for(req, &message)
+        .err()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|error| changes.touches(top_level_field(error)))
+        .collect();
+    if let Some(tags) = &changes.tags {
+        errors.extend(validation::check_tags("tags", tags));
+    }
+    if errors.is_empty() {
+        return None;
+    }
+    let status = StatusCode::UNPROCESSABLE_ENTITY;
+    Some(BatchItemResult {
+        errors,
+        ..BatchItemResult::failed(update.id, status, ErrorCode::ValidationFailed, None)
+    })
+}
+
+/// Applies partial updates to several messages in a single write.
+///
+/// The body is an array of at most [`MAX_PAGE_LIMIT`] updates, each changing any of `sender`,
+/// `content`, `attachments`, `tags`, and `status` of one message:
+///
+/// ```json
+/// [
+///     { "id": 1, "changes": { "tags": ["rust"], "status": "closed" } },
+///     { "id": 2, "changes": { "content": "Fixed a typo" } }
+/// ]
+/// ```
+///
+/// Every update is validated like [`api_patch`] first. With `ACTIX_POSTS_BATCH_MODE=strict` (the
+/// default), nothing is written unless every update can be applied; with `lenient`, the valid
+/// updates are written and the others reported. Edits obey the edit window and, with login
+/// enabled, the sender rules of [`auth::check_editor`] unless the request carries the API key;
+/// `If-Unmodified-Since` is not supported. See [`data::update_many`].
+///
+/// ### Returns
+/// - `200 OK` with `Updates`, one [`BatchItemResult`] per update in request order. In lenient
+///   mode, some of them may have failed.
+/// - `400 Bad Request` for an empty or oversized batch.
+/// - `424 Failed Dependency` with `BATCH_ABORTED` and `Updates` when an update failed in strict
+///   mode; the valid updates are reported with status `424` and nothing was written.
+#[put("/posts/batch")]
+pub async fn api_batch_update(
+    req: HttpRequest,
+    params: ApiJson<Vec<BatchUpdate>>,
+) -> impl Responder {
+    let updates = params.into_inner();
+    if updates.is_empty() || updates.len() > MAX_PAGE_LIMIT {
+        return bad_request(format!(
+            "A batch must contain between 1 and {} updates",
+            MAX_PAGE_LIMIT
+        ));
+    }
+    let mode = config::get().batch_mode;
+    let rejections: Vec<Option<BatchItemResult>> = updates
+        .iter()
+        .map(|update| check_batch_update(&req, update))
+        .collect();
+    let rejected = rejections.iter().any(Option::is_some);
+    let outcomes = if rejected && mode == BatchMode::Strict {
+        vec![BatchOutcome::Skipped; updates.len()]
+    } else {
+        let valid: Vec<BatchUpdate> = updates
+            .iter()
+            .zip(&rejections)
+            .filter(|(_, rejection)| rejection.is_none())
+            .map(|(update, _)| update.clone())
+            .collect();
+        let check_window = !auth::has_api_key(&req);
+        match data::with_retries(|| data::update_many(&valid, check_window, mode)).await {
+            Ok(outcomes) => outcomes,
+            Err(error) => return data_error(error),
+        }
+    };
+    let mut outcomes = outcomes.into_iter();
+    let results: Vec<BatchItemResult> = updates
+        .iter()
+        .zip(rejections)
+        .map(|(update, rejection)| {
+            rejection.unwrap_or_else(|| {
+                let outcome = outcomes.next().unwrap_or(BatchOutcome::Skipped);
+                BatchItemResult::from_outcome(update.id, outcome)
+            })
+        })
+        .collect();
+
+    let aborted = mode == BatchMode::Strict
+        && results
+            .iter()
+            .any(|result| result.status != StatusCode::OK.as_u16());
+    let response = ApiResponse {
+        status: if aborted { "Error" } else { "OK" }.to_string(),
+        code: aborted.then_some(ErrorCode::BatchAborted),
+        sort: None,
+        result: ResponseContent::Updates(results),
+    };
+    if aborted {
+        HttpResponse::FailedDependency().json(response)
+    } else {
+        HttpResponse::Ok().json(response)
+    }
+}
+
+/// Adds a reaction to a message.
+///
+/// The body is `{"emoji": "👍"}`; the emoji must be one of [`validation::REACTIONS`]. With
+/// `?case=camel`, the message is returned with camelCase keys as on read routes.
+///
+/// ### Returns
+/// - `200 OK` with the updated message.
+/// - `400 Bad Request` for an unknown `case`.
+/// - `404 Not Found` for an unknown ID.
+/// - `422 Unprocessable Entity` for an emoji outside the allowlist.
+#[post("/posts/{id:\\d+}/react")]
+pub async fn api_react(
+    id: web::Path<i32>,
+    query: web::Query<Queries>,
+    params: ApiJson<ReactRequest>,
+) -> impl Responder {
+    let case = match KeyCase::parse(query.case.as_deref()) {
+        Ok(case) => case,
+        Err(reason) => return bad_request(reason),
+    };
+    if let Err(error) = validation::check_reaction(&params.emoji) {
+        return unprocessable(vec![error]);
+    }
+    let id = id.into_inner();
+    let message = match data::with_retries(|| data::react(id, &params.emoji)).await {
+        Ok(Some(message)) => message,
+        Ok(None) => return post_not_found(),
+        Err(error) => return data_error(error),
+    };
+
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Item(message),
+    };
+    build_projected_response(None, CsvDelimiter::Comma, None, case, &response)
+}
+
+/// Replaces the tags of a message, leaving the rest of it untouched.
+///
+/// The body is `{"tags": ["rust", "web"]}`. Tags are trimmed, lowercased, and deduplicated; an
+/// empty list clears them.
+///
+/// ### Returns
+/// - `200 OK` with the updated message.
+/// - `404 Not Found` for an unknown ID.
+/// - `422 Unprocessable Entity` when the tags exceed the configured limits.
+#[put("/posts/{id:\\d+}/tags")]
+pub async fn api_set_tags(id: web::Path<i32>, params: ApiJson<TagsRequest>) -> impl Responder {
+    let errors = validation::check_tags("tags", &params.tags);
+    if !errors.is_empty() {
+        return unprocessable(errors);
+    }
+    let id = id.into_inner();
+    let message = match data::with_retries(|| data::set_tags(id, &params.tags)).await {
+        Ok(Some(message)) => message,
+        Ok(None) => return post_not_found(),
+        Err(error) => return data_error(error),
+    };
+
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Item(message),
+    };
+    HttpResponse::Ok().json(response)
+}
+
+/// Moves a message to another workflow status (see [`PostStatus`]).
+///
+/// ```json
+/// { "status": "closed" }
+/// ```
+///
+/// Archived messages stay archived unless the body also sets `"reopen": true`. `updated` is left
+/// untouched.
+///
+/// ### Returns
+/// - `200 OK` with the updated message.
+/// - `400 Bad Request` for an unknown status.
+/// - `404 Not Found` for an unknown ID.
+/// - `409 Conflict` (`INVALID_TRANSITION`) when leaving `archived` without `reopen`.
+#[post("/posts/{id:\\d+}/status")]
+pub async fn api_set_status(id: web::Path<i32>, params: ApiJson<StatusRequest>) -> impl Responder {
+    let id = id.into_inner();
+    let set_status = || data::set_status(id, params.status, params.reopen);
+    let message = match data::with_retries(set_status).await {
+        Ok(Some(message)) => message,
+        Ok(None) => return post_not_found(),
+        Err(error) => return data_error(error),
+    };
+
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Item(message),
+    };
+    HttpResponse::Ok().json(response)
+}
+
+/// Marks a message as freshly updated without changing it.
+///
+/// The request must carry the API key. Only the `updated` timestamp moves.
+///
+/// ### Returns
+/// - `200 OK` with the updated message.
+/// - `401`/`403` when the API key check fails.
+/// - `404 Not Found` for an unknown ID.
+#[post("/posts/{id:\\d+}/touch")]
+pub async fn api_touch(req: HttpRequest, id: web::Path<i32>) -> impl Responder {
+    if let Some(response) = api_key_rejection(&req) {
+        return response;
+    }
+    let id = id.into_inner();
+    let message = match data::with_retries(|| data::touch(id)).await {
+        Ok(Some(message)) => message,
+        Ok(None) => return post_not_found(),
+        Err(error) => return data_error(error),
+    };
+
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Item(message),
+    };
+    HttpResponse::Ok().json(response)
+}
+
+/// Adds or removes tags on every message matching a filter, for moderators.
+///
+/// The request must carry the API key. The body selects messages by `sender`, `from`/`to`
+/// (`YYYY-MM-DD`, inclusive), and/or `ids`; at least one criterion is required so a typo cannot
+/// retag the whole board:
+///
+/// ```json
+/// { "sender": "Nao", "add": ["rust"], "remove": ["draft"], "dry_run": true }
+/// ```
+///
+/// ### Returns
+/// - `200 OK` with the number and IDs of the messages whose tags changed.
+/// - `400 Bad Request` for an invalid date, an empty filter, or no tags to add or remove.
+/// - `401`/`403` when the API key check fails.
+/// - `422 Unprocessable Entity` when the added tags exceed the configured limits, or a message
+///   would end up with too many tags.
+#[post("/posts/tag")]
+pub async fn api_bulk_tag(req: HttpRequest, params: ApiJson<BulkTagRequest>) -> impl Responder {
+    if let Some(response) = api_key_rejection(&req) {
+        return response;
+    }
+    let params = params.into_inner();
+    let range = parse_date("from", params.from.as_deref())
+        .and_then(|from| parse_date("to", params.to.as_deref()).map(|to| (from, to)));
+    let (from, to) = match range {
+        Ok(range) => range,
+        Err(reason) => return bad_request(reason),
+    };
+    let filter = MessageFilter {
+        sender: params.sender,
+        from,
+        to,
+        ids: params.ids,
+    };
+    if filter.is_empty() {
+        return bad_request("At least one of sender, from, to, or ids is required".to_string());
+    }
+    if params.add.is_empty() && params.remove.is_empty() {
+        return bad_request("At least one tag to add or remove is required".to_string());
+    }
+    let errors = validation::check_tags("add", &params.add);
+    if !errors.is_empty() {
+        return unprocessable(errors);
+    }
+    let bulk_tag = || data::bulk_tag(&filter, &params.add, &params.remove, params.dry_run);
+    let changed = match data::with_retries(bulk_tag).await {
+        Ok(changed) => changed,
+        Err(error) => return data_error(error),
+    };
+
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Affected {
+            count: changed.len(),
+            ids: changed,
+            dry_run: params.dry_run,
+        },
+    };
+    HttpResponse::Ok().json(response)
+}
+
+/// Shows what changed between two revisions of a message's content.
+///
+/// `from` defaults to the revision before `to`, and `to` to the latest revision. `mode` selects
+/// a `line` (default) or `word` diff.
+///
+/// ### Returns
+/// - `200 OK` with `Diff`.
+/// - `400 Bad Request` for an unknown `mode`.
+/// - `404 Not Found` when the message or either revision does not exist.
+#[get("/posts/{id:\\d+}/diff")]
+pub async fn api_diff(id: web::Path<i32>, query: web::Query<DiffQueries>) -> impl Responder {
+    let mode = match query.mode.as_deref() {
+        Some(mode) => match DiffMode::parse(mode) {
+            Some(mode) => mode,
+            None => return bad_request("mode must be either line or word".to_string()),
+        },
+        None => DiffMode::default(),
+    };
+    let Some(diff) = diff::between(id.into_inner(), query.from, query.to, mode) else {
+        return error_response(StatusCode::NOT_FOUND, "Revision not found");
+    };
+
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Diff(diff),
+    };
+    HttpResponse::Ok().json(response)
+}
+
+#[delete("/posts/{id:\\d+}/delete")]
+pub async fn api_delete(id: web::Path<i32>, query: web::Query<Queries>) -> impl Responder {
+    let case = match KeyCase::parse(query.case.as_deref()) {
+        Ok(case) => case,
+        Err(reason) => return bad_request(reason),
+    };
+    if let Some(response) = unsupported_format(query.format.as_deref()) {
+        return response;
+    }
+    let id = id.into_inner();
+    match data::with_retries(|| data::remove(id)).await {
+        Ok(Some(_)) => {}
+        Ok(None) => return post_not_found(),
+        Err(error) => return data_error(error),
+    }
+
+    let format = query.format.as_deref();
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::None,
+    };
+    build_projected_response(format, CsvDelimiter::Comma, None, case, &response)
+}
+
+#[delete("/posts")]
+pub async fn api_bulk_delete(query: web::Query<BulkDeleteQueries>) -> impl Responder {
+    let ids = match query.ids.as_deref().map(parse_ids) {
+        Some(Ok(ids)) if !ids.is_empty() => ids,
+        Some(Err(reason)) => return bad_request(reason),
+        _ => return bad_request("The ids parameter is required".to_string()),
+    };
+    let dry_run = query.dry_run.unwrap_or(false);
+    let removed = match data::with_retries(|| data::remove_many(&ids, dry_run)).await {
+        Ok(removed) => removed,
+        Err(error) => return data_error(error),
+    };
+
+    let format = Some("json");
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Affected {
+            count: removed.len(),
+            ids: removed,
+            dry_run,
+        },
+    };
+    build_response(format, CsvDelimiter::Comma, &response)
+}
+
+/// Imports messages, either an export of `GET /export` or a bare array of messages as in the
+/// data file.
+///
+/// Every record is checked against the current message schema first (see
+/// [`validation::parse_record`]) and then validated like a new message. Any error rejects the
+/// whole import, with one entry per problem named `[index].field`. An export written with a newer
+/// [`data::SCHEMA_VERSION`] is refused. Messages without `posted` are stamped with the current
+/// time. The records are then stored like new messages (see [`data::import`]): filtered words are
+/// masked, and a record that breaks the unique `(sender, content)` constraint or the per-sender
+/// quota rejects the whole import as well.
+///
+/// ### Query Parameters
+/// - `mode`: `append` (default) or `replace` (see [`ImportMode`]).
+/// - `dry_run`: When `true`, only reports what would be imported.
+///
+/// ### Returns
+/// - `200 OK` with `Import`.
+/// - `400 Bad Request` for an unknown mode or a body that is neither an array nor an export.
+/// - `422 Unprocessable Entity` with `Errors` when a record does not match the schema or fails
+///   validation, or the export is too new. Nothing is imported.
+/// - `422 Unprocessable Entity` with `Import` listing the `rejected` records when a record breaks
+///   a rule of the store. Nothing is imported.
+#[post("/import")]
+pub async fn api_import(
+    req: HttpRequest,
+    query: web::Query<ImportQueries>,
+    params: ApiJson<ImportBody>,
+) -> impl Responder {
+    let mode = match query.mode.as_deref() {
+        Some(mode) => match ImportMode::parse(mode) {
+            Some(mode) => mode,
+            None => return bad_request(format!("Unknown import mode: {}", mode)),
+        },
+        None => ImportMode::default(),
+    };
+    let records = match params.into_inner() {
+        ImportBody::Export(export) if export.schema_version > data::SCHEMA_VERSION => {
+            return unprocessable(vec![FieldError {
+                field: "schema_version".to_string(),
+                reason: format!(
+                    "is {}, but this server reads up to version {}",
+                    export.schema_version,
+                    data::SCHEMA_VERSION
+                ),
+            }]);
+        }
+        ImportBody::Export(export) => export.messages,
+        ImportBody::Messages(records) => records,
+    };
+    let mut messages = Vec::with_capacity(records.len());
+    let mut errors: Vec<FieldError> = vec![];
+    for (index, record) in records.iter().enumerate() {
+        let record_errors = match validation::parse_record(record) {
+            Ok(message) => {
+                let result = validate_for(&req, &message);
+                messages.push(message);
+                result.err().unwrap_or_default()
+            }
+            Err(record_errors) => record_errors,
+        };
+        errors.extend(record_errors.into_iter().map(|error| FieldError {
+            field: match error.field.as_str() {
+                "" => format!("[{}]", index),
+                field => format!("[{}].{}", index, field),
+            },
+            reason: error.reason,
+        }));
+    }
+    if !errors.is_empty() {
+        return unprocessable(errors);
+    }
+    let now = data::current_timestamp();
+    messages
+        .iter_mut()
+        .filter(|message| message.posted.is_empty())
+        .for_each(|message| message.posted = now.clone());
+    let dry_run = query.dry_run.unwrap_or(false);
+    let import = || data::import(messages.clone(), mode, dry_run);
+    let report = match data::with_retries(import).await {
+        Ok(report) => report,
+        Err(error) => return data_error(error),
+    };
+    if !report.rejected.is_empty() {
+        return HttpResponse::UnprocessableEntity().json(ApiResponse {
+            status: "Error".to_string(),
+            code: Some(ErrorCode::ValidationFailed),
+            sort: None,
+            result: ResponseContent::Import(report),
+        });
+    }
+
+    let format = Some("json");
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Import(report),
+    };
+    build_response(format, CsvDelimiter::Comma, &response)
+}
+
+/// Empties the store after a double check.
+///
+/// The request must carry the API key and a JSON body whose `confirm` value matches the
+/// configured `ACTIX_POSTS_PURGE_TOKEN`. Purging is disabled when no token is configured. Every
+/// attempt is written to the `audit` log target.
+///
+/// ### Returns
+/// - `200 OK` with the number and IDs of the removed messages.
+/// - `401`/`403` when the API key check fails.
+/// - `403 Forbidden` when the confirmation token is missing, wrong, or not configured.
+#[post("/admin/purge")]
+pub async fn api_purge(req: HttpRequest, params: ApiJson<PurgeRequest>) -> impl Responder {
+    let client = client_ip(req.peer_addr(), req.headers());
+    if let Some(response) = api_key_rejection(&req) {
+        log::warn!(target: "audit", "purge rejected: unauthenticated request from {:?}", client);
+        return response;
+    }
+    let confirmed = match (
+        config::get().purge_token.as_deref(),
+        params.confirm.as_deref(),
+    ) {
+        (Some(expected), Some(provided)) => {
+            auth::constant_time_eq(expected.as_bytes(), provided.as_bytes())
+        }
+        _ => false,
+    };
+    if !confirmed {
+        log::warn!(target: "audit", "purge rejected: missing or wrong confirmation token from {:?}", client);
+        return error_response(
+            StatusCode::FORBIDDEN,
+            "A valid confirmation token is required",
+        );
+    }
+
+    let removed = match data::with_retries(data::clear).await {
+        Ok(removed) => removed,
+        Err(error) => {
+            log::warn!(target: "audit", "purge failed from {:?}: {}", client, error);
+            return data_error(error);
+        }
+    };
+    log::warn!(target: "audit", "purge executed from {:?}: {} posts removed", client, removed.len());
+
+    let format = Some("json");
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Affected {
+            count: removed.len(),
+            ids: removed,
+            dry_run: false,
+        },
+    };
+    build_response(format, CsvDelimiter::Comma, &response)
+}
+
+/// The part of a download requested by a `Range` header.
+///
+/// # Variants
+/// - `Full`: No usable range was requested; the whole body is sent.
+/// - `Partial(start, end)`: The bytes from `start` to `end`, both inclusive.
+/// - `Unsatisfiable`: The range starts past the end of the body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ByteRange {
+    Full,
+    Partial(usize, usize),
+    Unsatisfiable,
+}
+
+/// Resolves a `Range` header against a body of `len` bytes.
+///
+/// Only a single `bytes=` range is supported (`start-end`, `start-`, or `-suffix`); malformed
+/// headers and multiple ranges are ignored, as the specification allows, and get the full body.
+fn byte_range(range: Option<&str>, len: usize) -> ByteRange {
+    let Some(spec) = range.and_then(|range| range.trim().strip_prefix("bytes=")) else {
+        return ByteRange::Full;
+    };
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => match suffix.parse::<usize>() {
+            Ok(0) => return ByteRange::Unsatisfiable,
+            Ok(suffix) => (len.saturating_sub(suffix), len.saturating_sub(1)),
+            Err(_) => return ByteRange::Full,
+        },
+        (start, "") => match start.parse() {
+            Ok(start) => (start, len.saturating_sub(1)),
+            Err(_) => return ByteRange::Full,
+        },
+        (start, end) => match (start.parse(), end.parse::<usize>()) {
+            (Ok(start), Ok(end)) if start <= end => (start, end.min(len.saturating_sub(1))),
+            _ => return ByteRange::Full,
+        },
+    };
+    if start >= len {
+        ByteRange::Unsatisfiable
+    } else {
+        ByteRange::Partial(start, end)
+    }
+}
+
+/// Repairs the primary data file after manual edits or a partial write (see [`data::repair`]).
+///
+/// The request must carry the API key. Every repair is written to the `audit` log target.
+///
+/// ### Query Parameters
+/// - `renumber`: When `false`, messages with a duplicate ID are dropped instead of renumbered.
+///   Defaults to `true`.
+/// - `dry_run`: When `true`, only reports what would be fixed.
+///
+/// ### Returns
+/// - `200 OK` with `Repair`.
+/// - `401`/`403` when the API key check fails.
+///
+/// ### Example Response Payload (JSON)
+/// ```json
+/// {
+///     "status": "OK",
+///     "result": {
+///         "Repair": {
+///             "kept": 5,
+///             "dropped": [{ "index": 2, "reason": "not an object" }],
+///             "renumbered": [{ "index": 4, "from": 3, "to": 6 }],
+///             "truncated": false,
+///             "dry_run": false
+///         }
+///     }
+/// }
+/// ```
+#[post("/admin/repair")]
+pub async fn api_repair(req: HttpRequest, query: web::Query<RepairQueries>) -> impl Responder {
+    if let Some(response) = api_key_rejection(&req) {
+        return response;
+    }
+    let client = client_ip(req.peer_addr(), req.headers());
+    let dry_run = query.dry_run.unwrap_or(false);
+    let renumber = query.renumber.unwrap_or(true);
+    let report = match data::with_retries(|| data::repair(renumber, dry_run)).await {
+        Ok(report) => report,
+        Err(error) => return data_error(error),
+    };
+    if !dry_run {
+        log::warn!(
+            target: "audit",
+            "repair executed from {:?}: {} kept, {} dropped, {} renumbered, truncated: {}",
+            client,
+            report.kept,
+            report.dropped.len(),
+            report.renumbered.len(),
+            report.truncated
+        );
+    }
+
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Repair(report),
+    };
+    HttpResponse::Ok().json(response)
+}
+
+/// Reads the data files from disk again, for changes made outside the server (see
+/// [`data::reload`]). Needed in memory mode (`ACTIX_POSTS_IN_MEMORY`), where the files are
+/// otherwise never read again.
+///
+/// The request must carry the API key. Every reload is written to the `audit` log target.
+///
+/// ### Returns
+/// - `200 OK` with `Count`: the number of messages read.
+/// - `401`/`403` when the API key check fails.
+#[post("/admin/reload")]
+pub async fn api_reload(req: HttpRequest) -> impl Responder {
+    if let Some(response) = api_key_rejection(&req) {
+        return response;
+    }
+    let client = client_ip(req.peer_addr(), req.headers());
+    let count = data::reload();
+    log::warn!(
+        target: "audit",
+        "reload executed from {:?}: {} messages",
+        client,
+        count
+    );
+
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Count(count),
+    };
+    HttpResponse::Ok().json(response)
+}
+
+/// Shows a message with every stored field, including the [`data::Metadata`] public routes
+/// leave out (see [`public`]).
+///
+/// The request must carry the API key.
+///
+/// ### Returns
+/// - `200 OK` with `Record`.
+/// - `401`/`403` when the API key check fails.
+/// - `404 Not Found` for an unknown ID.
+#[get("/admin/posts/{id:\\d+}")]
+pub async fn api_admin_show(req: HttpRequest, id: web::Path<i32>) -> impl Responder {
+    if let Some(response) = api_key_rejection(&req) {
+        return response;
+    }
+    let Some(message) = get(id.into_inner()) else {
+        return post_not_found();
+    };
+
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Record(message),
+    };
+    HttpResponse::Ok().json(response)
+}
+
+/// Shows a message exactly as persisted, with its file and recorded revisions (see
+/// [`StoredMessage`]), for debugging data issues.
+///
+/// The request must carry the API key. The response is always JSON, and `fields` projections do
+/// not apply.
+///
+/// ### Returns
+/// - `200 OK` with `Stored`.
+/// - `401`/`403` when the API key check fails.
+/// - `404 Not Found` for an unknown ID.
+#[get("/admin/posts/{id:\\d+}/raw")]
+pub async fn api_raw(req: HttpRequest, id: web::Path<i32>) -> impl Responder {
+    if let Some(response) = api_key_rejection(&req) {
+        return response;
+    }
+    let Some(stored) = data::stored(id.into_inner()) else {
+        return post_not_found();
+    };
+
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::Stored(stored),
+    };
+    HttpResponse::Ok().json(response)
+}
+
+/// Downloads every message as an [`Export`]: the messages, in the format of the data file, with
+/// the [`data::SCHEMA_VERSION`] they follow. `POST /import` reads it back.
+///
+/// The request must either carry the API key or be a signed URL issued by [`api_export_sign`]
+/// (`?expires=...&signature=...`), so a download link can be shared without the key.
+///
+/// Interrupted downloads can be resumed with a single `Range: bytes=...` header (see
+/// [`byte_range`]). The export is rebuilt on every request, so the response carries an `ETag`
+/// of its content: a client sending it back in `If-Range` gets the full export instead of a
+/// mismatched part when posts changed in between.
+///
+/// ### Returns
+/// - `200 OK` with an `application/json` attachment.
+/// - `206 Partial Content` with the requested bytes and a `Content-Range` header.
+/// - `403 Forbidden` for an expired or tampered signature.
+/// - `401`/`403` when neither a valid signature nor the API key is provided.
+/// - `416 Range Not Satisfiable` when the range starts past the end of the export.
+#[get("/export")]
+pub async fn api_export(
+    req: HttpRequest,
+    signer: web::Data<UrlSigner>,
+    query: web::Query<ExportQueries>,
+) -> impl Responder {
+    match (query.expires, query.signature.as_deref()) {
+        (Some(expires), Some(signature)) => {
+            let now = Utc::now().timestamp();
+            match signer.verify(req.path(), expires, signature, now) {
+                Ok(()) => {}
+                Err(SignatureError::Expired) => {
+                    return coded_error_response(
+                        StatusCode::FORBIDDEN,
+                        ErrorCode::SignatureExpired,
+                        "The signed URL has expired",
+                    )
+                }
+                Err(SignatureError::Invalid) => {
+                    return coded_error_response(
+                        StatusCode::FORBIDDEN,
+                        ErrorCode::InvalidSignature,
+                        "Invalid signature",
+                    )
+                }
+            }
+        }
+        _ => {
+            if let Some(response) = api_key_rejection(&req) {
+                return response;
+            }
+        }
+    }
+
+    let export = Export {
+        schema_version: data::SCHEMA_VERSION,
+        messages: data::get_all_sorted(SortOrder::Oldest),
+    };
+    let body = match serde_json::to_vec(&export) {
+        Ok(body) => body,
+        Err(error) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, &error.to_string()),
+    };
+    let etag = format!("\"{}\"", hex::encode(Sha256::digest(&body)));
+    let header_str = |name| {
+        req.headers()
+            .get(name)
+            .and_then(|value: &HeaderValue| value.to_str().ok())
+    };
+    let range = match header_str(header::IF_RANGE) {
+        Some(if_range) if if_range.trim() != etag => ByteRange::Full,
+        _ => byte_range(header_str(header::RANGE), body.len()),
+    };
+
+    let mut builder = match range {
+        ByteRange::Full => HttpResponse::Ok(),
+        ByteRange::Partial(start, end) => {
+            let mut builder = HttpResponse::PartialContent();
+            builder.insert_header((
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", start, end, body.len()),
+            ));
+            builder
+        }
+        ByteRange::Unsatisfiable => {
+            return HttpResponse::RangeNotSatisfiable()
+                .insert_header((header::CONTENT_RANGE, format!("bytes */{}", body.len())))
+                .finish()
+        }
+    };
+    builder
+        .content_type("application/json")
+        .insert_header((header::ACCEPT_RANGES, "bytes"))
+        .insert_header((header::ETAG, etag))
+        .insert_header((
+            header::CONTENT_DISPOSITION,
+            r#"attachment; filename="posts.json""#,
+        ));
+    match range {
+        ByteRange::Partial(start, end) => builder.body(body[start..=end].to_vec()),
+        _ => builder.body(body),
+    }
+}
+
+/// Renders a message as a small Markdown document: a heading, the sender, dates, and tags, then
+/// the content and the attachment links.
+fn message_to_markdown(message: &Message) -> String {
+    let mut markdown = format!(
+        "# Post {}\n\n- Sender: {}\n- Posted: {}\n",
+        message.id, message.sender, message.posted
+    );
+    if !message.updated.is_empty() {
+        markdown.push_str(&format!("- Updated: {}\n", message.updated));
+    }
+    if !message.tags.is_empty() {
+        markdown.push_str(&format!("- Tags: {}\n", message.tags.join(", ")));
+    }
+    markdown.push_str(&format!("\n{}\n", message.content.replace("\r\n", "\n")));
+    if !message.attachments.is_empty() {
+        markdown.push_str("\n## Attachments\n\n");
+        for attachment in &message.attachments {
+            markdown.push_str(&format!("- <{}>\n", attachment));
+        }
+    }
+    markdown
+}
+
+/// Downloads a single message as a file named `post-{id}.{format}`.
+///
+/// ### Query Parameters
+/// - `format`: `json` (default, the bare message), `xml`, or `md` (see
+///   [`message_to_markdown`]).
+///
+/// ### Returns
+/// - `200 OK` with the message and a `Content-Disposition: attachment` header.
+/// - `404 Not Found` for an unknown ID.
+/// - `406 Not Acceptable` for any other format, or for `xml` when the message holds characters
+///   XML cannot represent.
+#[get("/posts/{id:\\d+}/export")]
+pub async fn api_export_post(
+    id: web::Path<i32>,
+    query: web::Query<ExportPostQueries>,
+) -> impl Responder {
+    let format = query.format.as_deref().unwrap_or("json");
+    if !EXPORT_FORMATS.contains(&format) {
+        return error_response(
+            StatusCode::NOT_ACCEPTABLE,
+            &format!(
+                "Unsupported format: {} (supported: {})",
+                format,
+                EXPORT_FORMATS.join(", ")
+            ),
+        );
+    }
+    let Some(message) = get(id.into_inner()) else {
+        return post_not_found();
+    };
+
+    let disposition = format!(r#"attachment; filename="post-{}.{}""#, message.id, format);
+    let mut response = HttpResponse::Ok();
+    response.insert_header((header::CONTENT_DISPOSITION, disposition));
+    match format {
+        "xml" => match xml::to_string(&PublicMessage::from(&message)) {
+            Ok(body) => response
+                .content_type("application/xml; charset=utf-8")
+                .body(body),
+            Err(error) => xml_error(error),
+        },
+        "md" => response
+            .content_type("text/markdown; charset=utf-8")
+            .body(message_to_markdown(&message)),
+        _ => response.json(PublicMessage::from(&message)),
+    }
+}
+
+/// Issues a signed URL for [`api_export`], valid for `ttl` seconds (default
+/// [`DEFAULT_SIGNED_URL_TTL`], at most [`MAX_SIGNED_URL_TTL`]).
+///
+/// ### Returns
+/// - `200 OK` with the absolute URL and its expiry.
+/// - `400 Bad Request` for an out-of-range `ttl`.
+/// - `401`/`403` when the API key check fails.
+#[post("/export/sign")]
+pub async fn api_export_sign(
+    req: HttpRequest,
+    signer: web::Data<UrlSigner>,
+    query: web::Query<SignQueries>,
+) -> impl Responder {
+    if let Some(response) = api_key_rejection(&req) {
+        return response;
+    }
+    let ttl = query.ttl.unwrap_or(DEFAULT_SIGNED_URL_TTL);
+    if !(1..=MAX_SIGNED_URL_TTL).contains(&ttl) {
+        return bad_request(format!(
+            "ttl must be between 1 and {} seconds",
+            MAX_SIGNED_URL_TTL
+        ));
+    }
+    let Ok(mut url) = req.url_for_static("api_export") else {
+        return error_response(StatusCode::INTERNAL_SERVER_ERROR, "Export route not found");
+    };
+    let expires = Utc::now().timestamp() + ttl;
+    let signature = signer.sign(url.path(), expires);
+    url.query_pairs_mut()
+        .append_pair("expires", &expires.to_string())
+        .append_pair("signature", &signature);
+
+    let format = Some("json");
+    let response = ApiResponse {
+        status: "OK".to_string(),
+        code: None,
+        sort: None,
+        result: ResponseContent::SignedUrl {
+            url: url.to_string(),
+            expires,
+        },
+    };
+    build_response(format, CsvDelimiter::Comma, &response)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,169 @@
+//! camelCase views of the read responses, served for `?case=camel`.
+//!
+//! The default JSON uses the field names of the Rust types, which are also the names in the data
+//! file. The types below borrow from an [`ApiResponse`] and rename its keys with
+//! `#[serde(rename_all = "camelCase")]`, so the stored format never changes.
+
+use super::{ApiResponse, ErrorCode, ResponseContent, SortInfo};
+use crate::handler::data::{Message, PostStatus, StorageStats, TimeRange};
+use crate::handler::search::SearchResult;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// A [`Message`] with camelCase keys (e.g. `expiresAt`), reduced to the requested fields.
+///
+/// Fields that were not requested are left out of the JSON object.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct CamelMessage<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    posted: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sender: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attachments: Option<&'a [String]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    updated: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reactions: Option<&'a BTreeMap<String, u32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<&'a [String]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<PostStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<&'a str>,
+}
+
+impl<'a> CamelMessage<'a> {
+    /// Borrows `message`, keeping only `fields` (named as in [`Message`]) when given.
+    pub(super) fn new(message: &'a Message, fields: Option<&[String]>) -> Self {
+        let wanted = |field: &str| match fields {
+            Some(fields) => fields.iter().any(|f| f == field),
+            None => true,
+        };
+        CamelMessage {
+            id: wanted("id").then_some(message.id),
+            posted: wanted("posted").then_some(message.posted.as_str()),
+            sender: wanted("sender").then_some(message.sender.as_str()),
+            content: wanted("content").then_some(message.content.as_str()),
+            attachments: wanted("attachments").then_some(message.attachments.as_slice()),
+            updated: wanted("updated").then_some(message.updated.as_str()),
+            reactions: wanted("reactions").then_some(&message.reactions),
+            tags: wanted("tags").then_some(message.tags.as_slice()),
+            status: wanted("status").then_some(message.status),
+            expires_at: message
+                .expires_at
+                .as_deref()
+                .filter(|_| wanted("expires_at")),
+        }
+    }
+}
+
+/// The camelCase form of the [`ResponseContent`] variants served by read routes.
+///
+/// Search hits, time ranges, and storage statistics only have single-word keys, so they are
+/// serialized as they are.
+#[derive(Serialize, Debug)]
+#[serde(rename_all_fields = "camelCase")]
+pub(super) enum CamelContent<'a> {
+    Items(Vec<CamelMessage<'a>>),
+    Batch(Vec<Option<CamelMessage<'a>>>),
+    Item(CamelMessage<'a>),
+    Page {
+        items: Vec<CamelMessage<'a>>,
+        next_cursor: Option<&'a str>,
+    },
+    Grouped {
+        groups: BTreeMap<&'a str, Vec<CamelMessage<'a>>>,
+        next_after: Option<&'a str>,
+    },
+    Hits(&'a [SearchResult]),
+    Range(&'a TimeRange),
+    Storage(&'a StorageStats),
+    Count(usize),
+    None,
+}
+
+impl<'a> CamelContent<'a> {
+    /// Borrows `content`, keeping only `fields` of its messages when given.
+    ///
+    /// Returns `None` for contents that no read route serves, which keep their snake_case form.
+    pub(super) fn new(content: &'a ResponseContent, fields: Option<&[String]>) -> Option<Self> {
+        let messages = |messages: &'a [Message]| {
+            messages
+                .iter()
+                .map(|message| CamelMessage::new(message, fields))
+                .collect()
+        };
+        Some(match content {
+            ResponseContent::Items(items) => CamelContent::Items(messages(items)),
+            ResponseContent::Batch(items) => CamelContent::Batch(
+                items
+                    .iter()
+                    .map(|item| item.as_ref().map(|item| CamelMessage::new(item, fields)))
+                    .collect(),
+            ),
+            ResponseContent::Item(item) => CamelContent::Item(CamelMessage::new(item, fields)),
+            ResponseContent::Page { items, next_cursor } => CamelContent::Page {
+                items: messages(items),
+                next_cursor: next_cursor.as_deref(),
+            },
+            ResponseContent::Grouped { groups, next_after } => CamelContent::Grouped {
+                groups: groups
+                    .iter()
+                    .map(|(key, group)| (key.as_str(), messages(group)))
+                    .collect(),
+                next_after: next_after.as_deref(),
+            },
+            ResponseContent::Hits(hits) => CamelContent::Hits(hits),
+            ResponseContent::Range(range) => CamelContent::Range(range),
+            ResponseContent::Storage(storage) => CamelContent::Storage(storage),
+            ResponseContent::Count(count) => CamelContent::Count(*count),
+            ResponseContent::None => CamelContent::None,
+            _ => return None,
+        })
+    }
+
+    /// Returns the messages or search hits alone, as served with `?envelope=false`.
+    pub(super) fn unwrapped(&self) -> Option<serde_json::Value> {
+        let value = match self {
+            CamelContent::Items(items) | CamelContent::Page { items, .. } => {
+                serde_json::to_value(items)
+            }
+            CamelContent::Batch(items) => serde_json::to_value(items),
+            CamelContent::Item(item) => serde_json::to_value(item),
+            CamelContent::Hits(hits) => serde_json::to_value(hits),
+            _ => return None,
+        };
+        value.ok()
+    }
+}
+
+/// An [`ApiResponse`] whose result is a [`CamelContent`].
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct CamelResponse<'a> {
+    status: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<ErrorCode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sort: Option<&'a SortInfo>,
+    pub(super) result: CamelContent<'a>,
+}
+
+impl<'a> CamelResponse<'a> {
+    /// Borrows `response`, or returns `None` when its content has no camelCase form (see
+    /// [`CamelContent::new`]).
+    pub(super) fn new(response: &'a ApiResponse, fields: Option<&[String]>) -> Option<Self> {
+        Some(CamelResponse {
+            status: &response.status,
+            code: response.code,
+            sort: response.sort.as_ref(),
+            result: CamelContent::new(&response.result, fields)?,
+        })
+    }
+}
@@ -0,0 +1,102 @@
+//! The public wire form of messages.
+//!
+//! [`Message`] is the stored form and carries fields that must not leave the server, such as the
+//! client [`Metadata`](crate::handler::data::Metadata). Responses serialize messages through
+//! [`PublicMessage`] instead, with the `serialize_with` helpers below, so a field added to the
+//! stored form stays private until it is added here. Routes behind the API key serve the stored
+//! form as-is (see [`super::api_admin_show`]).
+
+use crate::handler::data::{Message, PostStatus};
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
+
+/// The fields of a [`Message`] served to every client.
+///
+/// Named `Message` when serialized, so XML documents keep their root element.
+#[derive(Serialize, Debug)]
+#[serde(rename = "Message")]
+pub(crate) struct PublicMessage<'a> {
+    id: i32,
+    posted: &'a str,
+    sender: &'a str,
+    content: &'a str,
+    attachments: &'a [String],
+    updated: &'a str,
+    reactions: &'a BTreeMap<String, u32>,
+    tags: &'a [String],
+    status: PostStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires_at: Option<&'a str>,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    slug: &'a str,
+}
+
+impl<'a> From<&'a Message> for PublicMessage<'a> {
+    fn from(message: &'a Message) -> Self {
+        PublicMessage {
+            id: message.id,
+            posted: &message.posted,
+            sender: &message.sender,
+            content: &message.content,
+            attachments: &message.attachments,
+            updated: &message.updated,
+            reactions: &message.reactions,
+            tags: &message.tags,
+            status: message.status,
+            expires_at: message.expires_at.as_deref(),
+            slug: &message.slug,
+        }
+    }
+}
+
+/// The fields of a [`Message`] that are left out of [`PublicMessage`].
+pub(crate) const PRIVATE_FIELDS: &[&str] = &["metadata"];
+
+/// Serializes a message as a [`PublicMessage`].
+pub(crate) fn message<S: Serializer>(message: &Message, serializer: S) -> Result<S::Ok, S::Error> {
+    PublicMessage::from(message).serialize(serializer)
+}
+
+/// Serializes an optional message as a [`PublicMessage`] or `null`.
+pub(crate) fn optional_message<S: Serializer>(
+    message: &Option<Message>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    message
+        .as_ref()
+        .map(PublicMessage::from)
+        .serialize(serializer)
+}
+
+/// Serializes messages as [`PublicMessage`]s.
+pub(crate) fn messages<S: Serializer>(
+    messages: &[Message],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(messages.iter().map(PublicMessage::from))
+}
+
+/// Serializes messages fetched by ID as [`PublicMessage`]s, with `null` for missing ones.
+pub(crate) fn optional_messages<S: Serializer>(
+    messages: &[Option<Message>],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(
+        messages
+            .iter()
+            .map(|message| message.as_ref().map(PublicMessage::from)),
+    )
+}
+
+/// Serializes groups of messages as [`PublicMessage`]s, keeping the group keys.
+pub(crate) fn groups<S: Serializer>(
+    groups: &BTreeMap<String, Vec<Message>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(groups.iter().map(|(key, messages)| {
+        (
+            key,
+            messages.iter().map(PublicMessage::from).collect::<Vec<_>>(),
+        )
+    }))
+}
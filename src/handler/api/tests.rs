@@ -0,0 +1,2871 @@
+use crate::config;
+use crate::handler::cache;
+use crate::handler::data;
+use crate::handler::data::{Message, Metadata, PostStatus};
+use crate::handler::filter::FilterMode;
+use crate::handler::signing::UrlSigner;
+use crate::handler::xml::InvalidCharMode;
+use crate::testing::{self, json, message, TestEnv};
+use actix_web::cookie::Key;
+use actix_web::http::StatusCode;
+use actix_web::test::{self, TestRequest};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+
+/// Returns the sorted keys of a JSON object.
+fn keys(value: &Value) -> Vec<&str> {
+    let mut keys: Vec<&str> = value
+        .as_object()
+        .expect("an object")
+        .keys()
+        .map(String::as_str)
+        .collect();
+    keys.sort();
+    keys
+}
+
+#[actix_web::test]
+async fn fields_projects_messages_to_the_requested_fields() {
+    let env = TestEnv::with(|_| {});
+    env.seed(&[message(1, "Nao", "hello"), message(2, "Kai", "hi")]);
+    let app = testing::service!();
+
+    let request = TestRequest::get().uri("/api/posts?fields=id,sender");
+    let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    let items = body["result"]["Items"].as_array().unwrap();
+    assert_eq!(items.len(), 2);
+    for item in items {
+        assert_eq!(keys(item), ["id", "sender"]);
+    }
+
+    let request = TestRequest::get().uri("/api/posts/1?fields=content");
+    let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    assert_eq!(body["result"]["Item"], json!({ "content": "hello" }));
+
+    let request = TestRequest::get().uri("/api/posts?fields=id,password");
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+/// Sends `body` to `POST /api/posts/create`.
+fn create_request(body: Value) -> TestRequest {
+    json(TestRequest::post().uri("/api/posts/create"), body)
+}
+
+#[actix_web::test]
+async fn missing_and_blank_fields_are_both_validation_errors() {
+    let env = TestEnv::with(|_| {});
+    let app = testing::service!();
+
+    for body in [
+        json!({ "content": "hello" }),
+        json!({ "sender": "", "content": "hello" }),
+        json!({ "sender": "   ", "content": "hello" }),
+    ] {
+        let response = test::call_service(&app, create_request(body.clone()).to_request()).await;
+        assert_eq!(
+            response.status(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "{}",
+            body
+        );
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["code"], "VALIDATION_FAILED");
+        assert_eq!(
+            body["result"]["Errors"],
+            json!([{ "field": "sender", "reason": "is required" }])
+        );
+    }
+
+    let response = test::call_service(&app, create_request(json!({})).to_request()).await;
+    let body: Value = test::read_body_json(response).await;
+    let fields: Vec<&str> = body["result"]["Errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, ["sender", "content"]);
+    assert!(env.stored().is_empty());
+}
+
+/// Returns the routes declared by the handler attributes of `source` (e.g.
+/// `#[get("/posts")]`), as the pattern, the method, and the name of the handler.
+fn declared_routes(source: &str) -> Vec<(String, String, String)> {
+    let mut lines = source.lines();
+    let mut routes = vec![];
+    while let Some(line) = lines.next() {
+        let Some((method, rest)) = line
+            .strip_prefix("#[")
+            .and_then(|line| line.split_once("(\""))
+        else {
+            continue;
+        };
+        if !["get", "post", "put", "patch", "delete"].contains(&method) {
+            continue;
+        }
+        let pattern = rest.trim_end_matches("\")]").replace("\\\\", "\\");
+        let handler = lines
+            .next()
+            .and_then(|line| line.strip_prefix("pub async fn "))
+            .and_then(|line| line.split('(').next())
+            .expect("a handler after the route attribute");
+        routes.push((pattern, method.to_uppercase(), handler.to_string()));
+    }
+    routes
+}
+
+#[test]
+fn api_routes_match_the_registered_handlers() {
+    let mut declared = declared_routes(include_str!("../api.rs"));
+    declared.extend(declared_routes(include_str!("../events.rs")));
+    let app = include_str!("../../app.rs");
+    for (_, _, handler) in &declared {
+        assert!(
+            app.contains(&format!(".service({})", handler)),
+            "{} is not registered",
+            handler
+        );
+    }
+
+    let mut declared: Vec<(String, String)> = declared
+        .into_iter()
+        .map(|(pattern, method, _)| (pattern, method))
+        .collect();
+    declared.sort();
+    let mut table: Vec<(String, String)> = super::API_ROUTES
+        .iter()
+        .map(|(pattern, method)| (pattern.to_string(), method.to_string()))
+        .collect();
+    table.sort();
+    assert_eq!(declared, table);
+}
+
+#[actix_web::test]
+async fn wrong_methods_are_405_and_unknown_paths_404() {
+    let _env = TestEnv::with(|_| {});
+    let app = testing::service!();
+
+    for (request, allow) in [
+        (TestRequest::get().uri("/api/posts/create"), "POST"),
+        (TestRequest::put().uri("/api/posts/1"), "GET, PATCH"),
+        (TestRequest::post().uri("/api/v1/stats/range"), "GET"),
+    ] {
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers().get("allow").unwrap(), allow);
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["code"], "METHOD_NOT_ALLOWED");
+    }
+
+    for request in [
+        TestRequest::get().uri("/api/posts/unknown"),
+        TestRequest::post().uri("/api/nothing"),
+        TestRequest::get().uri("/api/v1/posts/1/nothing"),
+    ] {
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(response.headers().get("allow").is_none());
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["code"], "NOT_FOUND");
+        assert_eq!(body["result"]["Reason"], "API not found");
+    }
+}
+
+#[actix_web::test]
+async fn dry_runs_report_the_affected_ids_without_changing_the_store() {
+    let env = TestEnv::with(|_| {});
+    let seeded = [message(1, "Nao", "hello"), message(2, "Kai", "hi")];
+    env.seed(&seeded);
+    let app = testing::service!();
+
+    let request = testing::with_key(TestRequest::delete().uri("/api/posts?ids=1,2&dry_run=true"));
+    let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    assert_eq!(
+        body["result"]["Affected"],
+        json!({ "count": 2, "ids": [1, 2], "dry_run": true })
+    );
+    assert_eq!(env.stored(), seeded);
+
+    let request = json(
+        testing::with_key(TestRequest::post().uri("/api/import?mode=replace&dry_run=true")),
+        json!([{ "sender": "Mio", "content": "new" }]),
+    );
+    let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    assert_eq!(body["result"]["Import"]["removed"], json!([1, 2]));
+    assert_eq!(body["result"]["Import"]["created"], json!([1]));
+    assert_eq!(body["result"]["Import"]["dry_run"], json!(true));
+    assert_eq!(env.stored(), seeded);
+}
+
+/// Sends `records` to `POST /api/import` with the API key.
+fn import_request(records: Value) -> TestRequest {
+    json(
+        testing::with_key(TestRequest::post().uri("/api/import")),
+        records,
+    )
+}
+
+#[actix_web::test]
+async fn imports_follow_the_rules_of_new_posts() {
+    let env = TestEnv::with(|config| {
+        config.unique_sender_content = true;
+        config.max_per_sender = Some(2);
+        config.filter_words = vec!["spam".to_string()];
+        config.filter_mode = FilterMode::Mask;
+    });
+    env.seed(&[message(1, "Nao", "hello")]);
+    let app = testing::service!();
+
+    let records = json!([
+        { "sender": "Kai", "content": "hello" },
+        { "sender": "Nao", "content": "hello" },
+        { "sender": "Nao", "content": "again" },
+        { "sender": "Nao", "content": "too many" },
+    ]);
+    let response = test::call_service(&app, import_request(records).to_request()).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: Value = test::read_body_json(response).await;
+    let rejected: Vec<(u64, &str)> = body["result"]["Import"]["rejected"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|record| {
+            (
+                record["index"].as_u64().unwrap(),
+                record["field"].as_str().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(rejected, [(1, "content"), (3, "sender")]);
+    assert_eq!(body["result"]["Import"]["created"], json!([]));
+    assert_eq!(env.stored(), [message(1, "Nao", "hello")]);
+
+    let records = json!([{ "sender": "Kai", "content": "buy spam" }]);
+    let response = test::call_service(&app, import_request(records).to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let stored = env.stored();
+    assert_eq!(stored.len(), 2);
+    assert_eq!(stored[1].content, "buy ****");
+}
+
+#[actix_web::test]
+async fn first_and_latest_follow_the_posted_time() {
+    let env = TestEnv::with(|_| {});
+    let app = testing::service!();
+    for uri in ["/api/posts/first", "/api/posts/latest"] {
+        let response = test::call_service(&app, TestRequest::get().uri(uri).to_request()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", uri);
+    }
+
+    let mut early = message(3, "Mio", "early");
+    early.posted = "2023-12-31 23:00:00Z".to_string();
+    let mut late = message(1, "Nao", "late");
+    late.posted = "2024-01-02 00:00:00Z".to_string();
+    env.seed(&[late, message(2, "Kai", "middle"), early]);
+
+    for (uri, id) in [("/api/posts/first", 3), ("/api/posts/latest", 1)] {
+        let request = TestRequest::get().uri(uri);
+        let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+        assert_eq!(body["result"]["Item"]["id"], id, "{}", uri);
+    }
+
+    let request = TestRequest::get().uri("/api/posts/latest?format=csv");
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(
+        response.headers().get("content-type").unwrap(),
+        "text/csv; charset=utf-8"
+    );
+    let body = test::read_body(response).await;
+    let csv = String::from_utf8_lossy(&body);
+    assert_eq!(csv.lines().count(), 2);
+    assert!(csv.lines().nth(1).unwrap().starts_with("1,"));
+}
+
+#[actix_web::test]
+async fn the_same_sender_and_content_conflict_when_unique() {
+    let env = TestEnv::with(|config| config.unique_sender_content = true);
+    let app = testing::service!();
+    let post = json!({ "sender": "Nao", "content": "hello" });
+
+    let response = test::call_service(&app, create_request(post.clone()).to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(response).await;
+    let id = body["result"]["Item"]["id"].clone();
+
+    let response = test::call_service(&app, create_request(post).to_request()).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["code"], "DUPLICATE");
+    assert_eq!(body["result"]["Conflict"]["id"], id);
+    assert_eq!(env.stored().len(), 1);
+
+    let other = json!({ "sender": "Kai", "content": "hello" });
+    let response = test::call_service(&app, create_request(other).to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn purge_needs_the_key_and_the_confirmation_token() {
+    let env = TestEnv::with(|config| config.purge_token = Some("wipe-it".to_string()));
+    let seeded = [message(1, "Nao", "hello"), message(2, "Kai", "hi")];
+    env.seed(&seeded);
+    let app = testing::service!();
+    let purge = |key: bool, body: Value| {
+        let request = TestRequest::post().uri("/api/admin/purge");
+        let request = if key {
+            testing::with_key(request)
+        } else {
+            request
+        };
+        json(request, body).to_request()
+    };
+
+    for (key, body, status) in [
+        (
+            false,
+            json!({ "confirm": "wipe-it" }),
+            StatusCode::UNAUTHORIZED,
+        ),
+        (true, json!({}), StatusCode::FORBIDDEN),
+        (true, json!({ "confirm": "wrong" }), StatusCode::FORBIDDEN),
+    ] {
+        let response = test::call_service(&app, purge(key, body.clone())).await;
+        assert_eq!(response.status(), status, "{}", body);
+        assert_eq!(env.stored(), seeded);
+    }
+
+    let response = test::call_service(&app, purge(true, json!({ "confirm": "wipe-it" }))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["result"]["Affected"]["count"], 2);
+    assert!(env.stored().is_empty());
+}
+
+#[actix_web::test]
+async fn purge_is_disabled_without_a_configured_token() {
+    let env = TestEnv::with(|config| config.purge_token = None);
+    env.seed(&[message(1, "Nao", "hello")]);
+    let app = testing::service!();
+
+    let request = json(
+        testing::with_key(TestRequest::post().uri("/api/admin/purge")),
+        json!({ "confirm": "" }),
+    );
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(env.stored().len(), 1);
+}
+
+/// Compresses `body` with gzip.
+fn gzip(body: &[u8]) -> Vec<u8> {
+    use std::io::Write;
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(body).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Sends `payload` to `POST /api/posts/create` as a gzipped JSON body.
+fn gzipped_create_request(payload: Vec<u8>) -> TestRequest {
+    TestRequest::post()
+        .uri("/api/posts/create")
+        .insert_header(("content-type", "application/json"))
+        .insert_header(("content-encoding", "gzip"))
+        .set_payload(payload)
+}
+
+#[actix_web::test]
+async fn gzipped_bodies_are_decompressed_within_the_size_limit() {
+    let env = TestEnv::with(|config| config.max_body = 1024);
+    let app = testing::service!();
+
+    let body = json!({ "sender": "Nao", "content": "compressed" }).to_string();
+    let request = gzipped_create_request(gzip(body.as_bytes()));
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(env.stored()[0].content, "compressed");
+
+    let request = gzipped_create_request(b"not gzip at all".to_vec());
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let padding = " ".repeat(100_000);
+    let body = format!(r#"{{"sender": "Nao", "content": "big"{}}}"#, padding);
+    let compressed = gzip(body.as_bytes());
+    assert!(compressed.len() < 1024);
+    let response = test::call_service(&app, gzipped_create_request(compressed).to_request()).await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(env.stored().len(), 1);
+}
+
+/// Returns the IDs of the `Items` of an API response.
+fn item_ids(body: &Value) -> Vec<i64> {
+    body["result"]["Items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["id"].as_i64().unwrap())
+        .collect()
+}
+
+#[actix_web::test]
+async fn equal_timestamps_are_ordered_by_id_and_the_sort_is_reported() {
+    let env = TestEnv::with(|_| {});
+    let app = testing::service!();
+    let same_time = |id| {
+        let mut message = message(id, "Nao", "hello");
+        message.posted = "2024-01-01 00:00:00Z".to_string();
+        message
+    };
+
+    for order in [[2, 5, 1, 4, 3], [4, 1, 3, 5, 2]] {
+        let mut messages: Vec<_> = order.into_iter().map(same_time).collect();
+        messages.push(message(6, "Kai", "later"));
+        env.seed(&messages);
+
+        let request = TestRequest::get().uri("/api/posts");
+        let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+        assert_eq!(item_ids(&body), [6, 5, 4, 3, 2, 1]);
+        assert_eq!(
+            body["sort"],
+            json!({ "order": "newest", "keys": ["posted desc", "id desc"] })
+        );
+
+        let request = TestRequest::get().uri("/api/posts?order=oldest");
+        let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+        assert_eq!(item_ids(&body), [1, 2, 3, 4, 5, 6]);
+        assert_eq!(
+            body["sort"],
+            json!({ "order": "oldest", "keys": ["posted asc", "id asc"] })
+        );
+    }
+}
+
+/// Returns the fields named by the `Errors` of an API response.
+fn error_fields(body: &Value) -> Vec<&str> {
+    body["result"]["Errors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|error| error["field"].as_str().unwrap())
+        .collect()
+}
+
+#[actix_web::test]
+async fn attachments_must_be_web_urls() {
+    let env = TestEnv::with(|_| {});
+    let app = testing::service!();
+
+    let post = json!({
+        "sender": "Nao",
+        "content": "look",
+        "attachments": ["https://example.com/cat.png", "http://example.com/page"],
+    });
+    let response = test::call_service(&app, create_request(post).to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        env.stored()[0].attachments,
+        ["https://example.com/cat.png", "http://example.com/page"]
+    );
+
+    let request = TestRequest::get().uri("/posts/1");
+    let html = test::call_and_read_body(&app, request.to_request()).await;
+    // Tera escapes `/` in attributes, which browsers decode again.
+    let html = String::from_utf8_lossy(&html).replace("&#x2F;", "/");
+    assert!(html.contains(r#"<img class="img-thumbnail mb-2" src="https://example.com/cat.png""#));
+    assert!(html.contains(r#"<a href="http://example.com/page""#));
+
+    let post = json!({
+        "sender": "Nao",
+        "content": "bad",
+        "attachments": [
+            "https://example.com/ok.png",
+            "ftp://example.com/file",
+            "javascript:alert(1)",
+            "not a url",
+        ],
+    });
+    let response = test::call_service(&app, create_request(post).to_request()).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(
+        error_fields(&body),
+        ["attachments[1]", "attachments[2]", "attachments[3]"]
+    );
+
+    let post = json!({
+        "sender": "Nao",
+        "content": "many",
+        "attachments": vec!["https://example.com/a.png"; 5],
+    });
+    let response = test::call_service(&app, create_request(post).to_request()).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(error_fields(&body), ["attachments"]);
+
+    let mut update = serde_json::to_value(&env.stored()[0]).unwrap();
+    update["attachments"] = json!(["file:///etc/passwd"]);
+    let request = json(TestRequest::put().uri("/api/posts/update"), update);
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(env.stored().len(), 1);
+    assert_eq!(env.stored()[0].attachments.len(), 2);
+}
+
+/// The boundary of the multipart bodies built by [`multipart`].
+const BOUNDARY: &str = "actix-posts-test-boundary";
+
+/// Builds a `POST /api/posts/upload` request from text fields and `(content type, bytes)` images.
+fn multipart(fields: &[(&str, &str)], images: &[(&str, &[u8])]) -> TestRequest {
+    let mut body = Vec::new();
+    for (name, value) in fields {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                BOUNDARY, name, value
+            )
+            .as_bytes(),
+        );
+    }
+    for (content_type, bytes) in images {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"image\"; filename=\"image\"\r\nContent-Type: {}\r\n\r\n",
+                BOUNDARY, content_type
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(bytes);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+    TestRequest::post()
+        .uri("/api/posts/upload")
+        .insert_header((
+            "content-type",
+            format!("multipart/form-data; boundary={}", BOUNDARY),
+        ))
+        .set_payload(body)
+}
+
+#[actix_web::test]
+async fn uploaded_images_are_stored_by_hash_and_served() {
+    let env = TestEnv::with(|config| config.max_upload = 64);
+    let app = testing::service!();
+    let gif: &[u8] = b"GIF89a\x01\x00\x01\x00\x00\x00\x00;";
+
+    let request = multipart(
+        &[("sender", "Nao"), ("content", "a dot")],
+        &[("image/gif", gif)],
+    );
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let stored = env.stored();
+    let path = &stored[0].attachments[0];
+    let name = path.strip_prefix("/uploads/").unwrap();
+    assert_eq!(name.len(), 64 + ".gif".len());
+    assert!(name.ends_with(".gif"));
+    let file = std::path::Path::new(&crate::config::get().upload_dir).join(name);
+    assert_eq!(std::fs::read(file).unwrap(), gif);
+
+    let response = test::call_service(&app, TestRequest::get().uri(path).to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(test::read_body(response).await, gif);
+
+    let request = multipart(
+        &[("sender", "Nao"), ("content", "svg")],
+        &[("image/svg+xml", b"<svg/>")],
+    );
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let large = [gif, &[0; 64]].concat();
+    let request = multipart(
+        &[("sender", "Nao"), ("content", "big")],
+        &[("image/gif", &large)],
+    );
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(env.stored().len(), 1);
+}
+
+#[actix_web::test]
+async fn case_camel_renames_the_keys_of_every_read_route() {
+    let env = TestEnv::with(|_| {});
+    let mut expiring = message(1, "Nao", "hello");
+    expiring.expires_at = Some("2099-01-01 00:00:00Z".to_string());
+    env.seed(&[expiring, message(2, "Kai", "hello again")]);
+    let app = testing::service!();
+    let get = |uri: &str| TestRequest::get().uri(uri).to_request();
+
+    let body: Value = test::call_and_read_body_json(&app, get("/api/posts/1")).await;
+    assert!(body["result"]["Item"].get("expires_at").is_some());
+    let body: Value = test::call_and_read_body_json(&app, get("/api/posts/1?case=camel")).await;
+    assert_eq!(body["result"]["Item"]["expiresAt"], "2099-01-01 00:00:00Z");
+    assert!(body["result"]["Item"].get("expires_at").is_none());
+
+    let body: Value = test::call_and_read_body_json(&app, get("/api/posts?case=camel")).await;
+    assert!(body["result"]["Items"][1].get("expiresAt").is_some());
+    assert_eq!(body["sort"]["order"], "newest");
+
+    let uri = "/api/posts?case=camel&fields=id,expires_at";
+    let body: Value = test::call_and_read_body_json(&app, get(uri)).await;
+    assert_eq!(keys(&body["result"]["Items"][1]), ["expiresAt", "id"]);
+    assert_eq!(keys(&body["result"]["Items"][0]), ["id"]);
+
+    let uri = "/api/posts?case=camel&limit=1";
+    let body: Value = test::call_and_read_body_json(&app, get(uri)).await;
+    assert_eq!(keys(&body["result"]["Page"]), ["items", "nextCursor"]);
+
+    let uri = "/api/posts?case=camel&envelope=false";
+    let body: Value = test::call_and_read_body_json(&app, get(uri)).await;
+    assert_eq!(body[1]["expiresAt"], "2099-01-01 00:00:00Z");
+
+    let uri = "/api/posts/grouped?by=sender&case=camel";
+    let body: Value = test::call_and_read_body_json(&app, get(uri)).await;
+    assert_eq!(keys(&body["result"]["Grouped"]), ["groups", "nextAfter"]);
+    assert!(body["result"]["Grouped"]["groups"]["Nao"][0]
+        .get("expiresAt")
+        .is_some());
+
+    let uri = "/api/posts/search?q=hello&case=camel";
+    let body: Value = test::call_and_read_body_json(&app, get(uri)).await;
+    assert!(body["result"]["Items"][1].get("expiresAt").is_some());
+
+    let response = test::call_service(&app, get("/api/posts?format=jsonl&case=camel")).await;
+    let body = test::read_body(response).await;
+    let last: Value =
+        serde_json::from_str(String::from_utf8_lossy(&body).lines().last().unwrap()).unwrap();
+    assert!(last.get("expiresAt").is_some());
+
+    let request = json(
+        TestRequest::post().uri("/api/posts/1/react?case=camel"),
+        json!({ "emoji": "👍" }),
+    );
+    let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    assert_eq!(body["result"]["Item"]["expiresAt"], "2099-01-01 00:00:00Z");
+    assert_eq!(body["result"]["Item"]["reactions"]["👍"], 1);
+
+    let response = test::call_service(&app, get("/api/posts?case=kebab")).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let stored = std::fs::read_to_string(&crate::config::get().data_files[0]).unwrap();
+    assert!(stored.contains("\"expires_at\""));
+    assert!(!stored.contains("expiresAt"));
+}
+
+#[actix_web::test]
+async fn updates_are_refused_outside_the_edit_window() {
+    let env = TestEnv::with(|config| config.edit_window_minutes = Some(10));
+    let posted_ago = |id, minutes| {
+        let mut message = message(id, "Nao", "before");
+        message.posted = (chrono::Utc::now() - chrono::Duration::minutes(minutes))
+            .format(crate::handler::data::STORED_FORMAT)
+            .to_string();
+        message
+    };
+    env.seed(&[posted_ago(1, 9), posted_ago(2, 11)]);
+    let app = testing::service!();
+    let update = |id: i32| {
+        json(
+            TestRequest::put().uri("/api/posts/update"),
+            json!({ "id": id, "sender": "Nao", "content": "after" }),
+        )
+    };
+
+    let response = test::call_service(&app, update(1).to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = test::call_service(&app, update(2).to_request()).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["code"], "EDIT_WINDOW_CLOSED");
+    assert_eq!(env.stored()[1].content, "before");
+
+    let response = test::call_service(&app, testing::with_key(update(2)).to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let contents: Vec<String> = env.stored().into_iter().map(|m| m.content).collect();
+    assert_eq!(contents, ["after", "after"]);
+}
+
+#[actix_web::test]
+async fn stale_if_unmodified_since_updates_are_rejected() {
+    let env = TestEnv::with(|_| {});
+    let mut stored = message(1, "Nao", "before");
+    stored.updated = "2024-06-01 12:00:00Z".to_string();
+    env.seed(&[stored.clone()]);
+    let app = testing::service!();
+    let stale = "Sat, 01 Jun 2024 11:59:59 GMT";
+    let current = "Sat, 01 Jun 2024 12:00:00 GMT";
+    let put = |since: &str| {
+        json(
+            TestRequest::put().uri("/api/posts/update"),
+            json!({ "id": 1, "sender": "Nao", "content": "put" }),
+        )
+        .insert_header(("if-unmodified-since", since))
+    };
+    let patch = |since: &str| {
+        json(
+            TestRequest::patch().uri("/api/posts/1"),
+            json!({ "content": "patched" }),
+        )
+        .insert_header(("if-unmodified-since", since))
+    };
+
+    for request in [put(stale), patch(stale)] {
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["code"], "PRECONDITION_FAILED");
+        assert_eq!(env.stored(), [stored.clone()]);
+    }
+
+    let response = test::call_service(&app, patch(current).to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(env.stored()[0].content, "patched");
+
+    // The stored message was just updated, so the earlier date is now stale too.
+    let response = test::call_service(&app, put(current).to_request()).await;
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    assert_eq!(env.stored()[0].content, "patched");
+}
+
+#[actix_web::test]
+async fn unsupported_formats_are_not_acceptable() {
+    let env = TestEnv::with(|_| {});
+    env.seed(&[message(1, "Nao", "hello")]);
+    let app = testing::service!();
+
+    for uri in [
+        "/api/posts?format=xlm",
+        "/api/posts/1?format=yaml",
+        "/api/posts/latest?format=JSON",
+    ] {
+        let response = test::call_service(&app, TestRequest::get().uri(uri).to_request()).await;
+        assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE, "{}", uri);
+        let body: Value = test::read_body_json(response).await;
+        let reason = body["result"]["Reason"].as_str().unwrap();
+        assert!(
+            reason.ends_with("(supported: json, xml, csv, text, jsonl)"),
+            "{}",
+            reason
+        );
+    }
+
+    let request = TestRequest::delete().uri("/api/posts/1/delete?format=xlm");
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+    assert_eq!(env.stored().len(), 1);
+
+    for format in ["json", "csv", "text", "jsonl"] {
+        let uri = format!("/api/posts?format={}", format);
+        let response = test::call_service(&app, TestRequest::get().uri(&uri).to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK, "{}", format);
+    }
+}
+
+#[actix_web::test]
+async fn batch_fetch_keeps_the_request_order_and_handles_missing_ids() {
+    let env = TestEnv::with(|_| {});
+    env.seed(&[message(1, "Nao", "one"), message(2, "Kai", "two")]);
+    let app = testing::service!();
+    let get = |uri: &str| TestRequest::get().uri(uri).to_request();
+
+    let body: Value = test::call_and_read_body_json(&app, get("/api/posts?ids=2,9,1")).await;
+    assert_eq!(item_ids(&body), [2, 1]);
+
+    let uri = "/api/posts?ids=2,9,1&missing=null";
+    let body: Value = test::call_and_read_body_json(&app, get(uri)).await;
+    let batch = body["result"]["Batch"].as_array().unwrap();
+    assert_eq!(batch.len(), 3);
+    assert_eq!(batch[0]["id"], 2);
+    assert!(batch[1].is_null());
+    assert_eq!(batch[2]["id"], 1);
+
+    let too_many: Vec<String> = (1..=101).map(|id| id.to_string()).collect();
+    let uri = format!("/api/posts?ids={}", too_many.join(","));
+    for uri in [
+        uri.as_str(),
+        "/api/posts?ids=1,x",
+        "/api/posts?ids=1&missing=skip",
+    ] {
+        let response = test::call_service(&app, get(uri)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", uri);
+    }
+}
+
+#[actix_web::test]
+async fn signed_export_urls_expire_and_resist_tampering() {
+    let env = TestEnv::with(|_| {});
+    env.seed(&[message(1, "Nao", "hello")]);
+    let key = Key::generate();
+    let app = test::init_service(crate::app::build(key.clone())).await;
+    let get = |uri: &str| TestRequest::get().uri(uri).to_request();
+
+    let response = test::call_service(&app, get("/api/export")).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let request = testing::with_key(TestRequest::post().uri("/api/export/sign?ttl=60"));
+    let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    let url = url::Url::parse(body["result"]["SignedUrl"]["url"].as_str().unwrap()).unwrap();
+    let signed = format!("{}?{}", url.path(), url.query().unwrap());
+    let response = test::call_service(&app, get(&signed)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let export: Value = test::read_body_json(response).await;
+    assert_eq!(export["messages"][0]["content"], "hello");
+
+    let signature = url
+        .query_pairs()
+        .find(|(name, _)| name == "signature")
+        .unwrap()
+        .1
+        .into_owned();
+    let expires = body["result"]["SignedUrl"]["expires"].as_i64().unwrap();
+    let mut tampered = signature.clone().into_bytes();
+    tampered[0] = if tampered[0] == b'0' { b'1' } else { b'0' };
+    let tampered = String::from_utf8(tampered).unwrap();
+    for uri in [
+        format!("{}?expires={}&signature={}", url.path(), expires, tampered),
+        format!(
+            "{}?expires={}&signature={}",
+            url.path(),
+            expires + 3600,
+            signature
+        ),
+    ] {
+        let response = test::call_service(&app, get(&uri)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN, "{}", uri);
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["code"], "INVALID_SIGNATURE");
+    }
+
+    let expired = chrono::Utc::now().timestamp() - 1;
+    let signature = UrlSigner::new(key.signing()).sign(url.path(), expired);
+    let uri = format!("{}?expires={}&signature={}", url.path(), expired, signature);
+    let response = test::call_service(&app, get(&uri)).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["code"], "SIGNATURE_EXPIRED");
+}
+
+#[actix_web::test]
+async fn creating_past_the_largest_id_is_a_clean_error() {
+    let env = TestEnv::with(|_| {});
+    let seeded = [message(i32::MAX, "Nao", "last")];
+    env.seed(&seeded);
+    let app = testing::service!();
+
+    let post = json!({ "sender": "Kai", "content": "one more" });
+    let response = test::call_service(&app, create_request(post).to_request()).await;
+    assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["code"], "IDS_EXHAUSTED");
+    assert_eq!(env.stored(), seeded);
+
+    let result = crate::handler::data::create_many(vec![message(0, "Kai", "batch")]);
+    assert_eq!(result, Err(crate::handler::data::DataError::IdsExhausted));
+    assert_eq!(env.stored(), seeded);
+}
+
+#[actix_web::test]
+async fn diff_compares_two_stored_revisions() {
+    let env = TestEnv::with(|_| {});
+    env.seed(&[message(1, "Nao", "one\ntwo\n")]);
+    let app = testing::service!();
+    let update = json(
+        TestRequest::put().uri("/api/posts/update"),
+        json!({ "id": 1, "sender": "Nao", "content": "one\nthree\n" }),
+    );
+    let response = test::call_service(&app, update.to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let get = |uri: &str| TestRequest::get().uri(uri).to_request();
+
+    let body: Value =
+        test::call_and_read_body_json(&app, get("/api/posts/1/diff?from=1&to=2")).await;
+    assert_eq!(
+        body["result"]["Diff"],
+        json!({
+            "id": 1,
+            "from": 1,
+            "to": 2,
+            "mode": "line",
+            "changes": [
+                { "tag": "equal", "value": "one\n" },
+                { "tag": "delete", "value": "two\n" },
+                { "tag": "insert", "value": "three\n" },
+            ],
+        })
+    );
+
+    let body: Value = test::call_and_read_body_json(&app, get("/api/posts/1/diff?mode=word")).await;
+    let tags: Vec<&str> = body["result"]["Diff"]["changes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|change| change["tag"].as_str().unwrap())
+        .collect();
+    assert_eq!(tags, ["equal", "delete", "insert", "equal"]);
+
+    for uri in ["/api/posts/1/diff?from=1&to=3", "/api/posts/9/diff"] {
+        let response = test::call_service(&app, get(uri)).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", uri);
+    }
+
+    let response = test::call_service(&app, get("/posts/1/diff?from=1&to=2")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let html = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+    assert!(html.contains("two") && html.contains("three"));
+}
+
+#[actix_web::test]
+async fn cached_listings_are_dropped_by_writes() {
+    let env = TestEnv::with(|config| config.cache_ttl = 30);
+    env.seed(&[message(1, "Nao", "hello")]);
+    let app = testing::service!();
+    let list = |uri: &str| TestRequest::get().uri(uri).to_request();
+    let cache_status = |headers: &actix_web::http::header::HeaderMap| {
+        headers
+            .get(cache::CACHE_HEADER)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string()
+    };
+
+    let response = test::call_service(&app, list("/api/posts")).await;
+    assert_eq!(cache_status(response.headers()), "MISS");
+    let response = test::call_service(&app, list("/api/posts")).await;
+    assert_eq!(cache_status(response.headers()), "HIT");
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(item_ids(&body), [1]);
+    let response = test::call_service(&app, list("/api/posts?order=oldest")).await;
+    assert_eq!(
+        cache_status(response.headers()),
+        "MISS",
+        "queries are cached apart"
+    );
+
+    let response = test::call_service(
+        &app,
+        create_request(json!({ "sender": "Kai", "content": "again" })).to_request(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = test::call_service(&app, list("/api/posts")).await;
+    assert_eq!(cache_status(response.headers()), "MISS");
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(item_ids(&body), [2, 1]);
+}
+
+#[actix_web::test]
+async fn deleting_a_missing_post_is_not_found() {
+    let env = TestEnv::with(|_| {});
+    env.seed(&[message(1, "Nao", "hello")]);
+    let app = testing::service!();
+    let delete = |id: i32| {
+        testing::with_key(TestRequest::delete().uri(&format!("/api/posts/{}/delete", id)))
+            .to_request()
+    };
+
+    let response = test::call_service(&app, delete(2)).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["code"], "NOT_FOUND");
+    assert_eq!(env.stored().len(), 1);
+
+    let response = test::call_service(&app, delete(1)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(env.stored().is_empty());
+    let response = test::call_service(&app, delete(1)).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn reads_are_served_while_the_data_file_cannot_be_written() {
+    let mut missing_dir = std::path::PathBuf::new();
+    let _env = TestEnv::with(|config| {
+        let dir = std::path::Path::new(&config.data_files[0])
+            .parent()
+            .unwrap();
+        missing_dir = dir.join("missing");
+        let archive = dir.join("archive.json");
+        std::fs::write(
+            &archive,
+            serde_json::to_string(&[message(1, "Nao", "archived")]).unwrap(),
+        )
+        .unwrap();
+        config.data_files = vec![
+            missing_dir.join("data.json").to_string_lossy().into_owned(),
+            archive.to_string_lossy().into_owned(),
+        ];
+    });
+    let app = testing::service!();
+    let create = || create_request(json!({ "sender": "Kai", "content": "new" })).to_request();
+    let health = || TestRequest::get().uri("/health").to_request();
+
+    let response = test::call_service(&app, create()).await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["code"], "READ_ONLY");
+    let body: Value = test::call_and_read_body_json(&app, health()).await;
+    assert_eq!(body["status"], "read_only");
+    assert_eq!(body["writable"], false);
+
+    let response = test::call_service(&app, create()).await;
+    assert_eq!(
+        response.status(),
+        StatusCode::SERVICE_UNAVAILABLE,
+        "mutations are refused up front"
+    );
+    let request = TestRequest::get().uri("/api/posts").to_request();
+    let body: Value = test::call_and_read_body_json(&app, request).await;
+    assert_eq!(item_ids(&body), [1]);
+    let request = TestRequest::get().uri("/api/posts/1").to_request();
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    std::fs::create_dir_all(&missing_dir).unwrap();
+    assert!(data::probe_writable());
+    let body: Value = test::call_and_read_body_json(&app, health()).await;
+    assert_eq!(body["status"], "ok");
+    let response = test::call_service(&app, create()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn a_long_poll_is_answered_by_a_concurrent_create() {
+    let env = TestEnv::with(|_| {});
+    env.seed(&[message(1, "Nao", "hello")]);
+    let app = testing::service!();
+    let poll = |uri: &str| TestRequest::get().uri(uri).to_request();
+
+    let body: Value = test::call_and_read_body_json(&app, poll("/api/posts/poll?since=0")).await;
+    assert_eq!(item_ids(&body), [1], "existing posts are answered at once");
+
+    let started = std::time::Instant::now();
+    let (body, _) = futures_util::join!(
+        test::call_and_read_body_json::<_, _, Value>(
+            &app,
+            poll("/api/posts/poll?since=1&timeout=10")
+        ),
+        async {
+            actix_rt::time::sleep(std::time::Duration::from_millis(100)).await;
+            let request = create_request(json!({ "sender": "Kai", "content": "news" }));
+            test::call_service(&app, request.to_request()).await
+        }
+    );
+    assert_eq!(item_ids(&body), [2]);
+    assert!(started.elapsed() < std::time::Duration::from_secs(5));
+
+    let body: Value =
+        test::call_and_read_body_json(&app, poll("/api/posts/poll?since=2&timeout=1")).await;
+    assert_eq!(
+        body["result"]["Items"],
+        json!([]),
+        "a timeout answers an empty list"
+    );
+}
+
+#[actix_web::test]
+async fn search_snippets_mark_the_match_within_its_context() {
+    let env = TestEnv::with(|_| {});
+    env.seed(&[
+        message(1, "Nao", "needle at the start of a long text"),
+        message(2, "Kai", "a long text with the needle in the middle of it"),
+        message(3, "Rin", "ends with NEEDLE"),
+        message(4, "Needle", "nothing to see"),
+        message(5, "Sho", "unrelated"),
+    ]);
+    let app = testing::service!();
+
+    let request = TestRequest::get().uri("/api/posts/search?q=needle&snippet=true&context=5");
+    let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    let hits: Vec<(i64, Value)> = body["result"]["Hits"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|hit| (hit["id"].as_i64().unwrap(), hit["snippet"].clone()))
+        .collect();
+    assert_eq!(
+        hits,
+        [
+            (4, Value::Null),
+            (3, json!("…with [[NEEDLE]]")),
+            (2, json!("… the [[needle]] in t…")),
+            (1, json!("[[needle]] at t…")),
+        ]
+    );
+
+    let request = TestRequest::get().uri("/api/posts/search?q=needle");
+    let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    assert_eq!(
+        body["result"]["Items"][0]["content"], "nothing to see",
+        "whole messages are returned by default"
+    );
+}
+
+#[actix_web::test]
+async fn reactions_from_the_allowlist_are_counted() {
+    let env = TestEnv::with(|_| {});
+    env.seed(&[message(1, "Nao", "hello")]);
+    let app = testing::service!();
+    let react = |id: i32, emoji: &str| {
+        json(
+            TestRequest::post().uri(&format!("/api/posts/{}/react", id)),
+            json!({ "emoji": emoji }),
+        )
+        .to_request()
+    };
+
+    let responses =
+        futures_util::future::join_all((0..5).map(|_| test::call_service(&app, react(1, "👍"))))
+            .await;
+    assert!(responses.iter().all(|r| r.status() == StatusCode::OK));
+    let body: Value = test::call_and_read_body_json(&app, react(1, "🎉")).await;
+    assert_eq!(
+        body["result"]["Item"]["reactions"],
+        json!({ "👍": 5, "🎉": 1 })
+    );
+
+    for emoji in ["🦀", "", "👍👍"] {
+        let response = test::call_service(&app, react(1, emoji)).await;
+        assert_eq!(
+            response.status(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "{:?}",
+            emoji
+        );
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(error_fields(&body), ["emoji"]);
+    }
+    let response = test::call_service(&app, react(2, "👍")).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(env.stored()[0].reactions.len(), 2);
+
+    let request = TestRequest::post()
+        .uri("/posts/1/react")
+        .set_form([("emoji", "🦀")]);
+    test::call_service(&app, request.to_request()).await;
+    let request = TestRequest::post()
+        .uri("/posts/1/react")
+        .set_form([("emoji", "❤️")]);
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let html =
+        test::call_and_read_body(&app, TestRequest::get().uri("/posts/1").to_request()).await;
+    let html = String::from_utf8_lossy(&html);
+    assert!(html.contains("👍 5") && html.contains("🎉 1") && html.contains("❤️ 1"));
+    assert!(!html.contains("🦀"));
+}
+
+#[actix_web::test]
+async fn errors_carry_a_stable_code_next_to_the_reason() {
+    let env = TestEnv::with(|_| {});
+    env.seed(&[message(1, "Nao", "hello")]);
+    let app = testing::service!();
+    let scenarios = [
+        (
+            TestRequest::get().uri("/api/posts?case=kebab"),
+            StatusCode::BAD_REQUEST,
+            "BAD_REQUEST",
+        ),
+        (
+            TestRequest::get().uri("/api/posts/9"),
+            StatusCode::NOT_FOUND,
+            "NOT_FOUND",
+        ),
+        (
+            TestRequest::get().uri("/api/nowhere"),
+            StatusCode::NOT_FOUND,
+            "NOT_FOUND",
+        ),
+        (
+            TestRequest::get().uri("/api/admin/posts/1/raw"),
+            StatusCode::UNAUTHORIZED,
+            "UNAUTHORIZED",
+        ),
+        (
+            TestRequest::get().uri("/api/posts?format=yaml"),
+            StatusCode::NOT_ACCEPTABLE,
+            "NOT_ACCEPTABLE",
+        ),
+    ];
+    for (request, status, code) in scenarios {
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(response.status(), status, "{}", code);
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(body["status"], "Error");
+        assert_eq!(body["code"], code);
+        assert!(body["result"]["Reason"].is_string(), "{}", body);
+    }
+    drop(env);
+
+    let _env = TestEnv::with(|config| config.maintenance = true);
+    let app = testing::service!();
+    let response = test::call_service(
+        &app,
+        create_request(json!({ "sender": "Nao", "content": "hi" })).to_request(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["code"], "MAINTENANCE");
+    assert_eq!(body["result"]["Reason"], "maintenance");
+}
+
+#[actix_web::test]
+async fn daily_stats_count_the_posts_of_each_day() {
+    let env = TestEnv::with(|_| {});
+    let posted_at = |id: i32, posted: &str| {
+        let mut posted_message = message(id, "Nao", &format!("post {}", id));
+        posted_message.posted = posted.to_string();
+        posted_message
+    };
+    env.seed(&[
+        posted_at(1, "2024-03-01 11:00:00Z"),
+        posted_at(2, "2024-03-04 12:00:00Z"),
+        posted_at(3, "2024-03-01 12:30:00Z"),
+        posted_at(4, "2024-03-02 12:00:00Z"),
+        posted_at(5, "not a time"),
+    ]);
+    let app = testing::service!();
+    let get = |uri: &str| TestRequest::get().uri(uri).to_request();
+
+    let body: Value = test::call_and_read_body_json(&app, get("/api/stats/daily")).await;
+    assert_eq!(
+        body["result"]["Days"],
+        json!([
+            { "date": "2024-03-01", "count": 2 },
+            { "date": "2024-03-02", "count": 1 },
+            { "date": "2024-03-04", "count": 1 },
+        ])
+    );
+
+    let uri = "/api/stats/daily?from=2024-03-02&to=2024-03-03&format=csv";
+    let csv = test::call_and_read_body(&app, get(uri)).await;
+    let csv = String::from_utf8_lossy(&csv);
+    assert_eq!(
+        csv.lines().collect::<Vec<_>>(),
+        ["date,count", "2024-03-02,1"]
+    );
+
+    for uri in [
+        "/api/stats/daily?from=2024-03-04&to=2024-03-01",
+        "/api/stats/daily?from=March",
+    ] {
+        let response = test::call_service(&app, get(uri)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", uri);
+    }
+}
+
+#[actix_web::test]
+async fn trailing_slashes_reach_the_same_routes() {
+    let env = TestEnv::with(|_| {});
+    env.seed(&[message(1, "Nao", "hello")]);
+    let app = testing::service!();
+
+    for path in [
+        "/api/posts",
+        "/api/v1/posts",
+        "/api/posts/1",
+        "/posts",
+        "/posts/1",
+        "/posts/new",
+    ] {
+        for uri in [path.to_string(), format!("{}/", path)] {
+            let response =
+                test::call_service(&app, TestRequest::get().uri(&uri).to_request()).await;
+            assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+        }
+    }
+    let body: Value =
+        test::call_and_read_body_json(&app, TestRequest::get().uri("/api/posts/1/").to_request())
+            .await;
+    assert_eq!(body["result"]["Item"]["id"], 1);
+
+    let request =
+        create_request(json!({ "sender": "Kai", "content": "slash" })).uri("/api/posts/create/");
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(env.stored().len(), 2);
+}
+
+#[actix_web::test]
+async fn tags_are_replaced_normalized_and_cleared() {
+    let env = TestEnv::with(|_| {});
+    env.seed(&[message(1, "Nao", "hello")]);
+    let app = testing::service!();
+    let set_tags = |id: i32, tags: Value| {
+        json(
+            TestRequest::put().uri(&format!("/api/posts/{}/tags", id)),
+            json!({ "tags": tags }),
+        )
+        .to_request()
+    };
+
+    let body: Value =
+        test::call_and_read_body_json(&app, set_tags(1, json!([" Rust", "web", "rust", "WEB "])))
+            .await;
+    assert_eq!(body["result"]["Item"]["tags"], json!(["rust", "web"]));
+    assert_eq!(body["result"]["Item"]["content"], "hello");
+    let body: Value = test::call_and_read_body_json(&app, set_tags(1, json!(["actix"]))).await;
+    assert_eq!(
+        body["result"]["Item"]["tags"],
+        json!(["actix"]),
+        "tags are replaced"
+    );
+    assert_eq!(env.stored()[0].tags, ["actix"]);
+
+    let body: Value = test::call_and_read_body_json(&app, set_tags(1, json!([]))).await;
+    assert_eq!(body["result"]["Item"]["tags"], json!([]));
+    assert!(env.stored()[0].tags.is_empty());
+
+    let response = test::call_service(&app, set_tags(2, json!(["rust"]))).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn client_metadata_is_only_served_to_key_holders() {
+    let env = TestEnv::with(|_| {});
+    let app = testing::service!();
+    let request = create_request(json!({ "sender": "Nao", "content": "hello" }))
+        .peer_addr("192.0.2.7:4321".parse().unwrap())
+        .insert_header(("user-agent", "posts-test/1.0"));
+    let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    assert!(body["result"]["Item"].get("metadata").is_none());
+    let metadata = env.stored()[0]
+        .metadata
+        .clone()
+        .expect("stored with the post");
+    assert_eq!(metadata.ip.as_deref(), Some("192.0.2.7"));
+    assert_eq!(metadata.user_agent.as_deref(), Some("posts-test/1.0"));
+
+    for uri in [
+        "/api/posts",
+        "/api/posts/1",
+        "/api/posts?ids=1",
+        "/api/posts?format=jsonl",
+        "/api/posts?case=camel",
+        "/api/posts/1/export",
+        "/api/posts/search?q=hello",
+    ] {
+        let body = test::call_and_read_body(&app, TestRequest::get().uri(uri).to_request()).await;
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains("hello"), "{}", uri);
+        assert!(
+            !body.contains("metadata") && !body.contains("192.0.2.7"),
+            "{}: {}",
+            uri,
+            body
+        );
+    }
+    let request = TestRequest::get().uri("/api/posts?fields=id,metadata");
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let request = TestRequest::get().uri("/api/admin/posts/1");
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let request = testing::with_key(TestRequest::get().uri("/api/admin/posts/1"));
+    let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    assert_eq!(
+        body["result"]["Record"]["metadata"],
+        json!({ "ip": "192.0.2.7", "user_agent": "posts-test/1.0" })
+    );
+    let request = testing::with_key(TestRequest::get().uri("/api/admin/posts/2"));
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let request = json(
+        TestRequest::put().uri("/api/posts/update"),
+        json!({ "id": 1, "sender": "Nao", "content": "edited", "metadata": { "ip": "x" } }),
+    );
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        env.stored()[0].metadata,
+        Some(metadata),
+        "edits keep the metadata"
+    );
+}
+
+#[actix_web::test]
+async fn the_raw_route_reveals_fields_the_public_route_hides() {
+    let env = TestEnv::with(|_| {});
+    let app = testing::service!();
+    let request = create_request(json!({ "sender": "Nao", "content": "hello" }))
+        .insert_header(("user-agent", "posts-test/1.0"));
+    test::call_service(&app, request.to_request()).await;
+    let request = json(
+        TestRequest::put().uri("/api/posts/update"),
+        json!({ "id": 1, "sender": "Nao", "content": "edited" }),
+    );
+    test::call_service(&app, request.to_request()).await;
+
+    let request = TestRequest::get().uri("/api/posts/1");
+    let public: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    assert!(public["result"]["Item"].get("metadata").is_none());
+
+    let request = TestRequest::get().uri("/api/admin/posts/1/raw");
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let request = testing::with_key(TestRequest::get().uri("/api/admin/posts/1/raw"));
+    let raw: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    let stored = &raw["result"]["Stored"];
+    assert_eq!(stored["file"], json!(config::get().data_files[0]));
+    assert_eq!(stored["stored"]["content"], "edited");
+    assert_eq!(stored["stored"]["metadata"]["user_agent"], "posts-test/1.0");
+    assert_eq!(stored["history"][0]["content"], "hello");
+    assert_eq!(env.stored().len(), 1);
+
+    let request = testing::with_key(TestRequest::get().uri("/api/admin/posts/2/raw"));
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn bulk_tagging_applies_to_every_post_of_a_sender() {
+    let env = TestEnv::with(|_| {});
+    let mut tagged = message(2, "Nao", "again");
+    tagged.tags = vec!["draft".to_string()];
+    env.seed(&[message(1, "Nao", "hello"), tagged, message(3, "Kai", "hi")]);
+    let app = testing::service!();
+    let tag = |body: Value| json(TestRequest::post().uri("/api/posts/tag"), body);
+
+    let body = json!({ "sender": "Nao", "add": ["Rust"], "remove": ["draft"] });
+    let response = test::call_service(&app, tag(body.clone()).to_request()).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let request = testing::with_key(tag(json!({ "add": ["rust"] })));
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(
+        response.status(),
+        StatusCode::BAD_REQUEST,
+        "an empty filter"
+    );
+
+    let mut dry_run = body.clone();
+    dry_run["dry_run"] = json!(true);
+    let request = testing::with_key(tag(dry_run));
+    let result: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    assert_eq!(
+        result["result"]["Affected"],
+        json!({ "count": 2, "ids": [1, 2], "dry_run": true })
+    );
+    assert!(env.stored().iter().all(|message| message.tags != ["rust"]));
+
+    let request = testing::with_key(tag(body));
+    let result: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    assert_eq!(
+        result["result"]["Affected"],
+        json!({ "count": 2, "ids": [1, 2], "dry_run": false })
+    );
+    let tags: Vec<_> = env
+        .stored()
+        .into_iter()
+        .map(|message| message.tags)
+        .collect();
+    assert_eq!(
+        tags,
+        [vec!["rust".to_string()], vec!["rust".to_string()], vec![]]
+    );
+}
+
+#[actix_web::test]
+async fn storage_stats_report_the_size_of_each_data_file() {
+    let env = TestEnv::with(|config| {
+        let archive = std::path::Path::new(&config.data_files[0]).with_file_name("archive.json");
+        config
+            .data_files
+            .push(archive.to_string_lossy().into_owned());
+    });
+    env.seed(&[message(1, "Nao", "hello"), message(2, "Kai", "hi")]);
+    let app = testing::service!();
+
+    let request = TestRequest::get().uri("/api/stats/storage");
+    let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    let files = config::get().data_files.clone();
+    let size = std::fs::metadata(&files[0]).unwrap().len();
+    assert_eq!(
+        body["result"]["Storage"],
+        json!({
+            "files": [
+                { "path": files[0], "bytes": size },
+                { "path": files[1], "bytes": 0 },
+            ],
+            "bytes": size,
+            "count": 2,
+        })
+    );
+}
+
+#[actix_web::test]
+async fn the_text_format_lays_out_one_block_per_post() {
+    let env = TestEnv::with(|_| {});
+    env.seed(&[message(1, "Nao", "hello"), message(2, "Kai", "two\nlines")]);
+    let app = testing::service!();
+    let text = |request: TestRequest| {
+        let app = &app;
+        async move {
+            let response = test::call_service(app, request.to_request()).await;
+            assert_eq!(
+                response.headers().get("content-type").unwrap(),
+                "text/plain; charset=utf-8"
+            );
+            String::from_utf8(test::read_body(response).await.to_vec()).unwrap()
+        }
+    };
+
+    assert_eq!(
+        text(TestRequest::get().uri("/api/posts?format=text&order=oldest")).await,
+        "#1 [2024-01-01 00:00:01Z] Nao: hello\n#2 [2024-01-01 00:00:02Z] Kai: two\n    lines\n"
+    );
+    let request = TestRequest::get()
+        .uri("/api/posts/1")
+        .insert_header(("accept", "text/plain"));
+    assert_eq!(
+        text(request).await,
+        "#1 [2024-01-01 00:00:01Z] Nao: hello\n"
+    );
+    let request = TestRequest::delete().uri("/api/posts/2/delete?format=text");
+    assert_eq!(text(request).await, "OK\n");
+}
+
+#[actix_web::test]
+async fn senders_are_listed_once_in_order() {
+    let env = TestEnv::with(|_| {});
+    env.seed(&[
+        message(1, "Nao", "hello"),
+        message(2, "Kai", "hi"),
+        message(3, "Nao", "again"),
+        message(4, "nao", "lowercase"),
+    ]);
+    let app = testing::service!();
+
+    let request = TestRequest::get().uri("/api/senders");
+    let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    assert_eq!(
+        body["result"]["Senders"],
+        json!([{ "sender": "Kai" }, { "sender": "Nao" }, { "sender": "nao" }])
+    );
+    let request = TestRequest::get().uri("/api/senders?counts=true");
+    let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    assert_eq!(
+        body["result"]["Senders"],
+        json!([
+            { "sender": "Kai", "count": 1 },
+            { "sender": "Nao", "count": 2 },
+            { "sender": "nao", "count": 1 },
+        ])
+    );
+    let request = TestRequest::get().uri("/api/senders?counts=true&format=csv");
+    let csv = test::call_and_read_body(&app, request.to_request()).await;
+    assert_eq!(csv, "sender,count\r\nKai,1\r\nNao,2\r\nnao,1\r\n");
+}
+
+#[actix_web::test]
+async fn tag_limits_are_enforced_at_their_boundaries() {
+    let env = TestEnv::with(|config| {
+        config.max_tags = 2;
+        config.max_tag_length = 5;
+    });
+    env.seed(&[message(1, "Nao", "hello"), message(2, "Kai", "hi")]);
+    let app = testing::service!();
+    let set_tags = |tags: Value| {
+        json(
+            TestRequest::put().uri("/api/posts/1/tags"),
+            json!({ "tags": tags }),
+        )
+        .to_request()
+    };
+    let errors = |body: &Value| body["result"]["Errors"].clone();
+
+    // Counted and measured after trimming, lowercasing, and removing duplicates.
+    let body: Value =
+        test::call_and_read_body_json(&app, set_tags(json!([" Rusty ", "rusty", "WEB"]))).await;
+    assert_eq!(body["result"]["Item"]["tags"], json!(["rusty", "web"]));
+
+    let response = test::call_service(&app, set_tags(json!(["a", "b", "c"]))).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["code"], "VALIDATION_FAILED");
+    assert_eq!(
+        errors(&body),
+        json!([{ "field": "tags", "reason": "must have at most 2 entries" }])
+    );
+    let response = test::call_service(&app, set_tags(json!(["ok", "sixsix"]))).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(
+        errors(&body),
+        json!([{ "field": "tags[1]", "reason": "must be at most 5 characters" }])
+    );
+    assert_eq!(env.stored()[0].tags, ["rusty", "web"]);
+
+    let bulk_tag = |body: Value| {
+        testing::with_key(json(TestRequest::post().uri("/api/posts/tag"), body)).to_request()
+    };
+    let response =
+        test::call_service(&app, bulk_tag(json!({ "ids": [2], "add": ["sixsix"] }))).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let response =
+        test::call_service(&app, bulk_tag(json!({ "ids": [1, 2], "add": ["more"] }))).await;
+    assert_eq!(
+        response.status(),
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "post 1 would get a third tag"
+    );
+    assert!(env.stored()[1].tags.is_empty(), "nothing is applied");
+    let response = test::call_service(&app, bulk_tag(json!({ "ids": [2], "add": ["more"] }))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(env.stored()[1].tags, ["more"]);
+}
+
+#[actix_web::test]
+async fn json_lines_hold_one_post_per_line() {
+    let env = TestEnv::with(|_| {});
+    let messages: Vec<_> = (1..=25)
+        .map(|id| message(id, "Nao", &format!("line {}\nwith a break", id)))
+        .collect();
+    env.seed(&messages);
+    let app = testing::service!();
+
+    for request in [
+        TestRequest::get().uri("/api/posts?format=jsonl&order=oldest"),
+        TestRequest::get()
+            .uri("/api/posts?order=oldest")
+            .insert_header(("accept", "application/x-ndjson")),
+    ] {
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "application/x-ndjson"
+        );
+        let body = test::read_body(response).await;
+        let body = std::str::from_utf8(&body).unwrap();
+        assert!(body.ends_with('\n'));
+        let lines: Vec<Value> = body
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), messages.len());
+        for (line, message) in lines.iter().zip(&messages) {
+            assert_eq!(line["id"], message.id);
+            assert_eq!(line["content"], message.content);
+        }
+    }
+}
+
+#[actix_web::test]
+async fn touching_a_post_only_moves_its_updated_time() {
+    let env = TestEnv::with(|_| {});
+    let mut touched = message(1, "Nao", "hello");
+    touched.updated = "2024-01-02 00:00:00Z".to_string();
+    touched.tags = vec!["rust".to_string()];
+    touched.reactions.insert("👍".to_string(), 2);
+    env.seed(&[touched.clone(), message(2, "Kai", "hi")]);
+    let app = testing::service!();
+
+    let response = test::call_service(
+        &app,
+        TestRequest::post().uri("/api/posts/1/touch").to_request(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let request = testing::with_key(TestRequest::post().uri("/api/posts/1/touch"));
+    let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    let stored = env.stored()[0].clone();
+    assert!(stored.updated > touched.updated, "{}", stored.updated);
+    assert_eq!(body["result"]["Item"]["updated"], stored.updated);
+    assert_eq!(
+        Message {
+            updated: touched.updated.clone(),
+            ..stored
+        },
+        touched
+    );
+    assert_eq!(env.stored()[1], message(2, "Kai", "hi"));
+
+    let request = testing::with_key(TestRequest::post().uri("/api/posts/3/touch"));
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn single_posts_export_as_named_files() {
+    let env = TestEnv::with(|_| {});
+    let mut exported = message(1, "Nao", "hello\r\nworld");
+    exported.tags = vec!["rust".to_string()];
+    exported.attachments = vec!["https://example.com/a.png".to_string()];
+    env.seed(&[exported]);
+    let app = testing::service!();
+    let export = |query: &str| {
+        let request = TestRequest::get().uri(&format!("/api/posts/1/export{}", query));
+        test::call_service(&app, request.to_request())
+    };
+
+    for (query, content_type, filename) in [
+        ("", "application/json", "post-1.json"),
+        ("?format=json", "application/json", "post-1.json"),
+        (
+            "?format=xml",
+            "application/xml; charset=utf-8",
+            "post-1.xml",
+        ),
+        ("?format=md", "text/markdown; charset=utf-8", "post-1.md"),
+    ] {
+        let response = export(query).await;
+        assert_eq!(response.status(), StatusCode::OK, "{}", query);
+        let headers = response.headers();
+        assert_eq!(headers.get("content-type").unwrap(), content_type);
+        assert_eq!(
+            headers.get("content-disposition").unwrap(),
+            &format!(r#"attachment; filename="{}""#, filename)
+        );
+    }
+    let body: Value = test::read_body_json(export("").await).await;
+    assert_eq!(body["content"], "hello\r\nworld");
+    let markdown = test::read_body(export("?format=md").await).await;
+    assert_eq!(
+        markdown,
+        "# Post 1\n\n- Sender: Nao\n- Posted: 2024-01-01 00:00:01Z\n- Tags: rust\n\nhello\nworld\n\n\
+         ## Attachments\n\n- <https://example.com/a.png>\n"
+    );
+
+    assert_eq!(
+        export("?format=pdf").await.status(),
+        StatusCode::NOT_ACCEPTABLE
+    );
+    let request = TestRequest::get().uri("/api/posts/2/export?format=md");
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn cursor_pages_neither_repeat_nor_skip_posts_created_in_between() {
+    let env = TestEnv::with(|_| {});
+    env.seed(
+        &(1..=5)
+            .map(|id| message(id, "Nao", "hello"))
+            .collect::<Vec<_>>(),
+    );
+    let app = testing::service!();
+    let page = |cursor: Option<&str>| {
+        let uri = match cursor {
+            Some(cursor) => format!("/api/posts?limit=2&cursor={}", cursor),
+            None => "/api/posts?limit=2".to_string(),
+        };
+        test::call_and_read_body_json::<_, _, Value>(
+            &app,
+            TestRequest::get().uri(&uri).to_request(),
+        )
+    };
+
+    let mut seen = vec![];
+    let mut cursor: Option<String> = None;
+    loop {
+        let body = page(cursor.as_deref()).await;
+        let result = &body["result"]["Page"];
+        seen.extend(
+            result["items"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item["id"].as_i64().unwrap()),
+        );
+        if seen.len() == 2 {
+            // A new post, and a post on the page already served, change between two fetches.
+            let request = create_request(json!({ "sender": "Kai", "content": "new" }));
+            test::call_service(&app, request.to_request()).await;
+            let request = TestRequest::delete().uri("/api/posts/1/delete");
+            test::call_service(&app, request.to_request()).await;
+        }
+        match result["next_cursor"].as_str() {
+            Some(next) => cursor = Some(next.to_string()),
+            None => break,
+        }
+    }
+    assert_eq!(seen, [1, 2, 3, 4, 5, 6]);
+
+    let request = TestRequest::get().uri("/api/posts?limit=2&cursor=nonsense");
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let request = TestRequest::get().uri("/api/posts");
+    let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    assert_eq!(
+        item_ids(&body),
+        [6, 5, 4, 3, 2],
+        "without a cursor or limit, the whole listing is served"
+    );
+}
+
+#[actix_web::test]
+async fn exports_resume_from_byte_ranges() {
+    let env = TestEnv::with(|_| {});
+    env.seed(
+        &(1..=20)
+            .map(|id| message(id, "Nao", "hello"))
+            .collect::<Vec<_>>(),
+    );
+    let app = testing::service!();
+    let export = |range: Option<&str>, if_range: Option<&str>| {
+        let mut request = testing::with_key(TestRequest::get().uri("/api/export"));
+        if let Some(range) = range {
+            request = request.insert_header(("range", range));
+        }
+        if let Some(if_range) = if_range {
+            request = request.insert_header(("if-range", if_range));
+        }
+        test::call_service(&app, request.to_request())
+    };
+
+    let response = export(None, None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers().get("accept-ranges").unwrap(), "bytes");
+    let etag = response
+        .headers()
+        .get("etag")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let full = test::read_body(response).await;
+    let len = full.len();
+
+    let mut resumed = vec![];
+    for (range, content_range) in [
+        ("bytes=0-99".to_string(), format!("bytes 0-99/{}", len)),
+        (
+            "bytes=100-".to_string(),
+            format!("bytes 100-{}/{}", len - 1, len),
+        ),
+    ] {
+        let response = export(Some(&range), Some(&etag)).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT, "{}", range);
+        assert_eq!(
+            response.headers().get("content-range").unwrap(),
+            &content_range
+        );
+        resumed.extend_from_slice(&test::read_body(response).await);
+    }
+    assert_eq!(resumed, full);
+
+    let response = export(Some("bytes=-10"), None).await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(test::read_body(response).await, full[len - 10..]);
+
+    let response = export(Some(&format!("bytes={}-", len)), None).await;
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(
+        response.headers().get("content-range").unwrap(),
+        &format!("bytes */{}", len)
+    );
+
+    let response = export(Some("bytes=0-99"), Some("\"stale\"")).await;
+    assert_eq!(
+        response.status(),
+        StatusCode::OK,
+        "a stale If-Range gets it all"
+    );
+    assert_eq!(test::read_body(response).await, full);
+}
+
+#[actix_web::test]
+async fn statuses_filter_listings_and_follow_the_workflow() {
+    let env = TestEnv::with(|_| {});
+    // Data written before statuses existed has no `status` field.
+    std::fs::write(
+        &config::get().data_files[0],
+        r#"[{ "id": 1, "posted": "2024-01-01 00:00:01Z", "sender": "Nao", "content": "old" }]"#,
+    )
+    .unwrap();
+    data::reset();
+    let app = testing::service!();
+    for content in ["two", "three"] {
+        let request = create_request(json!({ "sender": "Kai", "content": content }));
+        test::call_service(&app, request.to_request()).await;
+    }
+    let set_status = |id: i32, body: Value| {
+        json(
+            TestRequest::post().uri(&format!("/api/posts/{}/status", id)),
+            body,
+        )
+        .to_request()
+    };
+    let listed = |query: &str| {
+        let request = TestRequest::get().uri(&format!("/api/posts{}", query));
+        test::call_and_read_body_json::<_, _, Value>(&app, request.to_request())
+    };
+
+    assert_eq!(env.stored()[0].status, PostStatus::Open);
+    let body: Value =
+        test::call_and_read_body_json(&app, set_status(2, json!({ "status": "closed" }))).await;
+    assert_eq!(body["result"]["Item"]["status"], "closed");
+    let response = test::call_service(&app, set_status(3, json!({ "status": "archived" }))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = test::call_service(&app, set_status(3, json!({ "status": "done" }))).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    assert_eq!(
+        item_ids(&listed("").await),
+        [2, 1],
+        "archived posts are hidden"
+    );
+    assert_eq!(item_ids(&listed("?status=open").await), [1]);
+    assert_eq!(item_ids(&listed("?status=closed").await), [2]);
+    assert_eq!(item_ids(&listed("?status=archived").await), [3]);
+    assert_eq!(item_ids(&listed("?status=all").await), [3, 2, 1]);
+
+    let response = test::call_service(&app, set_status(3, json!({ "status": "open" }))).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["code"], "INVALID_TRANSITION");
+    assert_eq!(env.stored()[2].status, PostStatus::Archived);
+    let request = set_status(3, json!({ "status": "open", "reopen": true }));
+    let response = test::call_service(&app, request).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(env.stored()[2].status, PostStatus::Open);
+    assert_eq!(item_ids(&listed("?status=open").await), [3, 1]);
+}
+
+#[actix_web::test]
+async fn control_characters_are_handled_before_xml_serialization() {
+    for (mode, expected) in [
+        (InvalidCharMode::Reject, None),
+        (InvalidCharMode::Strip, Some("bell")),
+        (InvalidCharMode::Replace, Some("bel\u{FFFD}l")),
+    ] {
+        let env = TestEnv::with(|config| config.xml_invalid_chars = mode);
+        env.seed(&[message(1, "Nao", "bel\u{7}l")]);
+        let app = testing::service!();
+
+        for uri in ["/api/posts/1?format=xml", "/api/posts/1/export?format=xml"] {
+            let response = test::call_service(&app, TestRequest::get().uri(uri).to_request()).await;
+            match expected {
+                None => {
+                    assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE, "{}", uri);
+                    let body: Value = test::read_body_json(response).await;
+                    assert_eq!(body["code"], "NOT_ACCEPTABLE");
+                    let reason = body["result"]["Reason"].as_str().unwrap();
+                    assert!(reason.contains("U+0007"), "{}", reason);
+                }
+                Some(content) => {
+                    assert_eq!(response.status(), StatusCode::OK, "{:?} {}", mode, uri);
+                    let body = test::read_body(response).await;
+                    let body = String::from_utf8_lossy(&body);
+                    assert!(
+                        body.contains(&format!("<content>{}</content>", content)),
+                        "{}",
+                        body
+                    );
+                }
+            }
+        }
+        drop(env);
+    }
+}
+
+#[actix_web::test]
+async fn the_unversioned_api_is_an_alias_of_v1() {
+    let env = TestEnv::with(|_| {});
+    env.seed(&[message(1, "Nao", "hello")]);
+    let app = testing::service!();
+
+    let mut bodies = vec![];
+    for uri in ["/api/posts", "/api/v1/posts"] {
+        let response = test::call_service(&app, TestRequest::get().uri(uri).to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+        assert_eq!(response.headers().get("x-api-version").unwrap(), "1");
+        bodies.push(test::read_body_json::<Value, _>(response).await);
+    }
+    assert_eq!(bodies[0], bodies[1]);
+    assert_eq!(item_ids(&bodies[1]), [1]);
+
+    let request =
+        create_request(json!({ "sender": "Kai", "content": "v1" })).uri("/api/v1/posts/create");
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers().get("x-api-version").unwrap(), "1");
+    assert_eq!(env.stored()[1].content, "v1");
+    let response =
+        test::call_service(&app, TestRequest::get().uri("/api/v2/posts").to_request()).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn strict_mode_rejects_unknown_fields_by_name() {
+    let env = TestEnv::with(|_| {});
+    let app = testing::service!();
+    let typo = json!({ "sendr": "Nao", "content": "hello" });
+
+    let response = test::call_service(&app, create_request(typo.clone()).to_request()).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(error_fields(&body), ["sender"], "the typo is ignored");
+    drop(app);
+    drop(env);
+
+    let env = TestEnv::with(|config| config.strict_json = true);
+    let app = testing::service!();
+    let response = test::call_service(&app, create_request(typo).to_request()).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["result"]["Reason"], "Unknown field: sendr");
+    assert!(env.stored().is_empty());
+
+    let request = create_request(json!({ "sender": "Nao", "content": "hello" }));
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(env.stored().len(), 1);
+}
+
+#[actix_web::test]
+async fn search_counts_match_the_full_results() {
+    let env = TestEnv::with(|_| {});
+    env.seed(&[
+        message(1, "Nao", "hello"),
+        message(2, "Kai", "hi"),
+        message(3, "Hélène", "bonjour"),
+        message(4, "Rei", "Hello again"),
+    ]);
+    let app = testing::service!();
+
+    for q in ["hel", "HELLO", "i", "nothing"] {
+        let request = TestRequest::get().uri(&format!("/api/posts/search?q={}", q));
+        let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+        let request = TestRequest::get().uri(&format!("/api/posts/search?q={}&count_only=true", q));
+        let count: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+        assert_eq!(count["result"]["Count"], item_ids(&body).len(), "{}", q);
+    }
+
+    let request = TestRequest::get().uri("/api/posts/search?q=hel&count_only=true&format=xml");
+    let xml = test::call_and_read_body(&app, request.to_request()).await;
+    let xml = String::from_utf8_lossy(&xml);
+    assert!(xml.contains("<Count>2</Count>"), "{}", xml);
+}
+
+/// Sets the `TZ` variable of the process until dropped.
+struct TimeZone(Option<std::ffi::OsString>);
+
+impl TimeZone {
+    fn set(zone: &str) -> Self {
+        let previous = std::env::var_os("TZ");
+        std::env::set_var("TZ", zone);
+        TimeZone(previous)
+    }
+}
+
+impl Drop for TimeZone {
+    fn drop(&mut self) {
+        match &self.0 {
+            Some(zone) => std::env::set_var("TZ", zone),
+            None => std::env::remove_var("TZ"),
+        }
+    }
+}
+
+#[actix_web::test]
+async fn stored_times_are_utc_whatever_the_server_time_zone() {
+    let env = TestEnv::with(|_| {});
+    // Nine hours ahead of UTC: POSIX counts offsets west of Greenwich.
+    let _zone = TimeZone::set("JST-9");
+    assert_eq!(chrono::Local::now().offset().local_minus_utc(), 9 * 3600);
+    let mut legacy = message(1, "Nao", "stored in local time by an older version");
+    legacy.posted = "2024-01-01 08:30:00".to_string();
+    let mut evening = message(2, "Kai", "the next morning in Japan");
+    evening.posted = "2024-01-01 20:00:00Z".to_string();
+    env.seed(&[legacy, evening]);
+    let app = testing::service!();
+
+    let before = chrono::Utc::now();
+    let request = create_request(json!({ "sender": "Rei", "content": "api" }));
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let request = TestRequest::post().uri("/posts/create").set_form([
+        ("id", "0"),
+        ("posted", ""),
+        ("sender", "Rei"),
+        ("content", "form"),
+    ]);
+    test::call_service(&app, request.to_request()).await;
+    let stored = env.stored();
+    assert_eq!(stored.len(), 4);
+    for message in &stored[2..] {
+        let posted =
+            chrono::NaiveDateTime::parse_from_str(&message.posted, data::STORED_FORMAT).unwrap();
+        let drift = posted.and_utc() - before;
+        assert!(drift.num_seconds().abs() <= 2, "{}", message.posted);
+    }
+
+    let request = TestRequest::get().uri("/api/stats/range");
+    let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    assert_eq!(body["result"]["Range"]["oldest"], "2023-12-31 23:30:00Z");
+    assert_eq!(body["result"]["Range"]["newest"], stored[3].posted);
+    let request = TestRequest::get().uri("/api/stats/daily?to=2024-01-01");
+    let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    assert_eq!(
+        body["result"]["Days"],
+        json!([
+            { "date": "2023-12-31", "count": 1 },
+            { "date": "2024-01-01", "count": 1 },
+        ])
+    );
+}
+
+#[actix_web::test]
+async fn random_posts_are_stored_posts_of_the_requested_subset() {
+    let env = TestEnv::with(|_| {});
+    let app = testing::service!();
+    let random_id = |uri: &'static str| {
+        let app = &app;
+        async move {
+            let request = TestRequest::get().uri(uri).to_request();
+            let response = test::call_service(app, request).await;
+            if response.status() == StatusCode::NOT_FOUND {
+                return None;
+            }
+            let body: Value = test::read_body_json(response).await;
+            body["result"]["Item"]["id"].as_i64()
+        }
+    };
+    assert_eq!(random_id("/api/posts/random").await, None, "empty store");
+
+    let mut tagged = message(4, "Kai", "tagged");
+    tagged.tags = vec!["rust".to_string()];
+    env.seed(&[
+        message(1, "Nao", "one"),
+        message(2, "Kai", "two"),
+        message(3, "Nao", "three"),
+        tagged,
+        message(5, "Mio", "five"),
+    ]);
+    let mut seen = HashSet::new();
+    for _ in 0..100 {
+        seen.insert(random_id("/api/posts/random").await.unwrap());
+    }
+    // Each pick is one of five, so 100 picks all landing on one post has odds of 5^-99.
+    assert!(seen.len() > 1, "{:?}", seen);
+    assert!(
+        seen.is_subset(&HashSet::from([1, 2, 3, 4, 5])),
+        "{:?}",
+        seen
+    );
+
+    for _ in 0..20 {
+        let id = random_id("/api/posts/random?sender=Nao").await.unwrap();
+        assert!([1, 3].contains(&id), "{}", id);
+        assert_eq!(random_id("/api/posts/random?tag=rust").await, Some(4));
+    }
+    assert_eq!(random_id("/api/posts/random?sender=Rei").await, None);
+
+    let request = TestRequest::get().uri("/api/posts/random?tag=rust&format=xml");
+    let xml = test::call_and_read_body(&app, request.to_request()).await;
+    assert!(String::from_utf8_lossy(&xml).contains("<id>4</id>"));
+}
+
+#[actix_web::test]
+async fn expired_posts_are_left_out_of_listings() {
+    let env = TestEnv::with(|_| {});
+    let mut expired = message(1, "Nao", "gone by now");
+    expired.expires_at = Some("2000-01-01 00:00:00Z".to_string());
+    let mut expiring = message(2, "Kai", "still here");
+    expiring.expires_at = Some("2999-01-01 00:00:00Z".to_string());
+    env.seed(&[expired, expiring, message(3, "Mio", "forever")]);
+    let app = testing::service!();
+    let get = |uri: &str| TestRequest::get().uri(uri).to_request();
+
+    let body: Value = test::call_and_read_body_json(&app, get("/api/posts")).await;
+    assert_eq!(item_ids(&body), [3, 2]);
+    let body: Value = test::call_and_read_body_json(&app, get("/api/posts/search?q=gone")).await;
+    assert_eq!(item_ids(&body), Vec::<i64>::new());
+    let response = test::call_service(&app, get("/api/posts/1")).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let html = test::call_and_read_body(&app, get("/posts")).await;
+    let html = String::from_utf8_lossy(&html);
+    assert!(!html.contains("gone by now") && html.contains("still here"));
+    assert_eq!(env.stored().len(), 3, "hidden, not removed");
+
+    let request = create_request(json!({
+        "sender": "Rei",
+        "content": "too late",
+        "expires_at": "2000-01-01 00:00:00Z",
+    }));
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(error_fields(&body), ["expires_at"]);
+}
+
+#[actix_web::test]
+async fn deletions_appear_in_the_changes_feed() {
+    let env = TestEnv::with(|_| {});
+    let app = testing::service!();
+    let changes = |since: u64| {
+        let app = &app;
+        async move {
+            let uri = format!("/api/posts/changes?since={}", since);
+            let request = TestRequest::get().uri(&uri).to_request();
+            let body: Value = test::call_and_read_body_json(app, request).await;
+            body["result"]["Changes"].clone()
+        }
+    };
+    for content in ["one", "two"] {
+        let request = create_request(json!({ "sender": "Nao", "content": content }));
+        test::call_service(&app, request.to_request()).await;
+    }
+    let cursor = changes(0).await["cursor"].as_u64().unwrap();
+
+    let request = TestRequest::delete().uri("/api/posts/1/delete");
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let feed = changes(cursor).await;
+    let entries = feed["changes"].as_array().unwrap();
+    assert_eq!(entries.len(), 1, "{}", feed);
+    assert_eq!(entries[0]["kind"], "deleted");
+    assert_eq!(entries[0]["id"], 1);
+    assert_eq!(entries[0]["seq"], cursor + 1);
+    assert!(entries[0]["at"].as_str().unwrap().ends_with('Z'));
+    assert_eq!(feed["cursor"], cursor + 1);
+
+    let kinds: Vec<_> = changes(0).await["changes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|change| (change["kind"].clone(), change["id"].clone()))
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            (json!("created"), json!(1)),
+            (json!("created"), json!(2)),
+            (json!("deleted"), json!(1)),
+        ]
+    );
+    assert_eq!(changes(cursor + 1).await["changes"], json!([]));
+    assert_eq!(env.stored().len(), 1);
+}
+
+#[actix_web::test]
+async fn patches_are_validated_on_the_fields_they_change() {
+    // The stored sender predates the reservation, so only a patch of the sender may report it.
+    let env = TestEnv::with(|config| config.reserved_senders = vec!["admin".to_string()]);
+    let stored = message(1, "admin", "before");
+    env.seed(std::slice::from_ref(&stored));
+    let app = testing::service!();
+    let patch = |body: Value| json(TestRequest::patch().uri("/api/posts/1"), body);
+
+    let response = test::call_service(&app, patch(json!({ "content": "" })).to_request()).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(error_fields(&body), ["content"]);
+    assert_eq!(env.stored(), [stored]);
+
+    let response =
+        test::call_service(&app, patch(json!({ "content": "after" })).to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let patched = &env.stored()[0];
+    assert_eq!(patched.content, "after");
+    assert_eq!(patched.sender, "admin");
+}
+
+#[actix_web::test]
+async fn string_ids_round_trip() {
+    let env = TestEnv::with(|_| {});
+    env.seed(&[message(1, "Nao", "one")]);
+    let app = testing::service!();
+
+    let request = TestRequest::get().uri("/api/posts/1?id_as_string=true");
+    let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    let mut fetched = body["result"]["Item"].clone();
+    assert_eq!(fetched["id"], "1");
+
+    fetched["content"] = json!("edited");
+    let request = json(
+        TestRequest::put().uri("/api/posts/update?id_as_string=true"),
+        fetched,
+    );
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["result"]["Item"]["id"], "1");
+    assert_eq!(env.stored()[0].id, 1);
+    assert_eq!(env.stored()[0].content, "edited");
+
+    let request = TestRequest::get().uri("/api/posts/1");
+    let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    assert_eq!(body["result"]["Item"]["id"], 1);
+}
+
+#[actix_web::test]
+async fn repair_renumbers_duplicates_and_drops_corrupt_records() {
+    let env = TestEnv::with(|_| {});
+    let record =
+        |id: i32, content: &str| serde_json::to_string(&message(id, "Nao", content)).unwrap();
+    let damaged = format!(
+        "[{}, {}, {}, 42, {{\"id\": 5, \"sen",
+        record(1, "one"),
+        record(2, "two"),
+        record(1, "copy")
+    );
+    std::fs::write(&config::get().data_files[0], &damaged).unwrap();
+    data::reset();
+    let app = testing::service!();
+    let repair = |query: &str| {
+        testing::with_key(TestRequest::post().uri(&format!("/api/admin/repair{}", query)))
+    };
+
+    let body: Value =
+        test::call_and_read_body_json(&app, repair("?dry_run=true").to_request()).await;
+    let expected = json!({
+        "kept": 3,
+        "dropped": [{ "index": 3, "reason": "not an object" }],
+        "renumbered": [{ "index": 2, "from": 1, "to": 3 }],
+        "truncated": true,
+        "dry_run": true
+    });
+    assert_eq!(body["result"]["Repair"], expected);
+    assert_eq!(
+        std::fs::read_to_string(&config::get().data_files[0]).unwrap(),
+        damaged
+    );
+
+    let body: Value = test::call_and_read_body_json(&app, repair("").to_request()).await;
+    let mut expected = expected;
+    expected["dry_run"] = json!(false);
+    assert_eq!(body["result"]["Repair"], expected);
+    let stored: Vec<_> = env
+        .stored()
+        .into_iter()
+        .map(|m| (m.id, m.content))
+        .collect();
+    assert_eq!(
+        stored,
+        [
+            (1, "one".to_string()),
+            (2, "two".to_string()),
+            (3, "copy".to_string())
+        ]
+    );
+
+    let body: Value = test::call_and_read_body_json(&app, repair("").to_request()).await;
+    assert_eq!(body["result"]["Repair"]["kept"], 3);
+    assert_eq!(body["result"]["Repair"]["dropped"], json!([]));
+    assert_eq!(body["result"]["Repair"]["renumbered"], json!([]));
+    assert_eq!(body["result"]["Repair"]["truncated"], false);
+}
+
+#[actix_web::test]
+async fn sender_quota_rejects_or_evicts_at_the_limit() {
+    for policy in [data::QuotaPolicy::Reject, data::QuotaPolicy::Evict] {
+        let env = TestEnv::with(|config| {
+            config.max_per_sender = Some(2);
+            config.sender_quota_policy = policy;
+        });
+        env.seed(&[message(1, "Nao", "one"), message(2, "Mio", "two")]);
+        let app = testing::service!();
+        let create = |sender: &str| {
+            create_request(json!({ "sender": sender, "content": "new" })).to_request()
+        };
+        let ids = || env.stored().iter().map(|m| m.id).collect::<Vec<_>>();
+
+        // Nao is one below the limit, so both policies accept without touching other posts.
+        let response = test::call_service(&app, create("Nao")).await;
+        assert_eq!(response.status(), StatusCode::OK, "{:?}", policy);
+        assert_eq!(ids(), [1, 2, 3]);
+
+        let response = test::call_service(&app, create("Nao")).await;
+        match policy {
+            data::QuotaPolicy::Reject => {
+                assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+                assert_eq!(ids(), [1, 2, 3]);
+            }
+            data::QuotaPolicy::Evict => {
+                assert_eq!(response.status(), StatusCode::OK);
+                assert_eq!(ids(), [2, 3, 4]);
+            }
+        }
+
+        let response = test::call_service(&app, create("Mio")).await;
+        assert_eq!(response.status(), StatusCode::OK, "{:?}", policy);
+        assert_eq!(env.stored().iter().filter(|m| m.sender == "Mio").count(), 2);
+    }
+}
+
+#[actix_web::test]
+async fn posts_are_grouped_by_day_and_by_sender() {
+    let env = TestEnv::with(|_| {});
+    let posted = |id: i32, sender: &str, posted: &str| Message {
+        posted: posted.to_string(),
+        ..message(id, sender, "hello")
+    };
+    env.seed(&[
+        posted(1, "Nao", "2024-01-02 09:00:00Z"),
+        posted(2, "Mio", "2024-01-01 23:59:59Z"),
+        posted(3, "Nao", "2024-01-03 00:00:00Z"),
+        posted(4, "Mio", "2024-01-02 18:30:00Z"),
+    ]);
+    let app = testing::service!();
+    let get = |uri: &str| TestRequest::get().uri(uri).to_request();
+    let groups = |body: &Value| -> Vec<(String, Vec<i64>)> {
+        body["result"]["Grouped"]["groups"]
+            .as_object()
+            .unwrap()
+            .iter()
+            .map(|(key, posts)| {
+                let ids = posts.as_array().unwrap().iter();
+                (
+                    key.clone(),
+                    ids.map(|post| post["id"].as_i64().unwrap()).collect(),
+                )
+            })
+            .collect()
+    };
+    let owned = |key: &str, ids: &[i64]| (key.to_string(), ids.to_vec());
+
+    let body: Value = test::call_and_read_body_json(&app, get("/api/posts/grouped")).await;
+    assert_eq!(
+        groups(&body),
+        [
+            owned("2024-01-01", &[2]),
+            owned("2024-01-02", &[1, 4]),
+            owned("2024-01-03", &[3]),
+        ]
+    );
+    assert_eq!(body["result"]["Grouped"]["next_after"], Value::Null);
+
+    let body: Value =
+        test::call_and_read_body_json(&app, get("/api/posts/grouped?by=day&limit=2")).await;
+    assert_eq!(
+        groups(&body),
+        [owned("2024-01-01", &[2]), owned("2024-01-02", &[1, 4])]
+    );
+    assert_eq!(body["result"]["Grouped"]["next_after"], "2024-01-02");
+    let uri = "/api/posts/grouped?by=day&limit=2&after=2024-01-02";
+    let body: Value = test::call_and_read_body_json(&app, get(uri)).await;
+    assert_eq!(groups(&body), [owned("2024-01-03", &[3])]);
+    assert_eq!(body["result"]["Grouped"]["next_after"], Value::Null);
+
+    let body: Value =
+        test::call_and_read_body_json(&app, get("/api/posts/grouped?by=sender")).await;
+    assert_eq!(
+        groups(&body),
+        [owned("Mio", &[2, 4]), owned("Nao", &[1, 3])]
+    );
+
+    let response = test::call_service(&app, get("/api/posts/grouped?by=month")).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn batch_updates_report_missing_ids_per_item() {
+    for mode in [data::BatchMode::Strict, data::BatchMode::Lenient] {
+        let env = TestEnv::with(|config| config.batch_mode = mode);
+        let seeded = [message(1, "Nao", "one"), message(2, "Mio", "two")];
+        env.seed(&seeded);
+        let app = testing::service!();
+        let request = json(
+            TestRequest::put().uri("/api/posts/batch"),
+            json!([
+                { "id": 1, "changes": { "tags": ["rust"] } },
+                { "id": 99, "changes": { "content": "nobody" } },
+                { "id": 2, "changes": { "content": "edited" } },
+            ]),
+        );
+        let response = test::call_service(&app, request.to_request()).await;
+        let status = response.status();
+        let body: Value = test::read_body_json(response).await;
+        let items = body["result"]["Updates"].as_array().unwrap();
+        let ids: Vec<_> = items.iter().map(|item| item["id"].clone()).collect();
+        assert_eq!(ids, [json!(1), json!(99), json!(2)]);
+        let statuses: Vec<_> = items.iter().map(|item| item["status"].clone()).collect();
+        assert_eq!(items[1]["code"], "NOT_FOUND", "{:?}", mode);
+
+        match mode {
+            data::BatchMode::Strict => {
+                assert_eq!(status, StatusCode::FAILED_DEPENDENCY);
+                assert_eq!(body["code"], "BATCH_ABORTED");
+                assert_eq!(statuses, [json!(424), json!(404), json!(424)]);
+                assert_eq!(env.stored(), seeded);
+            }
+            data::BatchMode::Lenient => {
+                assert_eq!(status, StatusCode::OK);
+                assert_eq!(statuses, [json!(200), json!(404), json!(200)]);
+                assert_eq!(items[0]["item"]["tags"], json!(["rust"]));
+                let stored = env.stored();
+                assert_eq!(stored[0].tags, ["rust"]);
+                assert_eq!(stored[1].content, "edited");
+                assert_eq!(stored.len(), 2);
+            }
+        }
+    }
+}
+
+#[actix_web::test]
+async fn csv_fields_are_quoted_for_the_chosen_delimiter() {
+    let env = TestEnv::with(|_| {});
+    env.seed(&[
+        message(1, "Nao", "one, \"two\"\nthree"),
+        message(2, "Nao", "a,b"),
+        message(3, "Nao", "a;b"),
+        message(4, "Nao", "a\tb"),
+    ]);
+    let app = testing::service!();
+    let csv = |delimiter: &str| {
+        let uri = format!("/api/posts?format=csv&delimiter={}", delimiter);
+        let app = &app;
+        async move {
+            let request = TestRequest::get().uri(&uri).to_request();
+            String::from_utf8(test::call_and_read_body(app, request).await.to_vec()).unwrap()
+        }
+    };
+    // The newest post comes first; only the content column needs quoting.
+    let expected = |d: &str, contents: [&str; 4]| {
+        let mut csv = [
+            "id",
+            "posted",
+            "sender",
+            "content",
+            "attachments",
+            "updated",
+        ]
+        .join(d);
+        csv.push_str("\r\n");
+        for (id, content) in (1..=4).rev().zip(contents) {
+            let posted = format!("2024-01-01 00:00:0{}Z", id);
+            csv.push_str(&[&id.to_string(), &posted, "Nao", content, "", ""].join(d));
+            csv.push_str("\r\n");
+        }
+        csv
+    };
+
+    let quoted = "\"one, \"\"two\"\"\nthree\"";
+    assert_eq!(
+        csv("comma").await,
+        expected(",", ["a\tb", "a;b", "\"a,b\"", quoted])
+    );
+    assert_eq!(
+        csv("semicolon").await,
+        expected(";", ["a\tb", "\"a;b\"", "a,b", quoted])
+    );
+    assert_eq!(
+        csv("tab").await,
+        expected("\t", ["\"a\tb\"", "a;b", "a,b", quoted])
+    );
+
+    let request = TestRequest::get().uri("/api/posts?format=csv&delimiter=pipe");
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn previews_are_transformed_but_never_stored() {
+    let env = TestEnv::with(|config| {
+        config.filter_words = vec!["spam".to_string()];
+        config.filter_mode = FilterMode::Mask;
+    });
+    let seeded = [message(1, "Nao", "hello")];
+    env.seed(&seeded);
+    let app = testing::service!();
+    let preview = |body: Value| json(TestRequest::post().uri("/api/posts/preview"), body);
+    let changes = || {
+        let path = &config::get().changes_file;
+        std::fs::read_to_string(path).unwrap_or_default()
+    };
+    let changes_before = changes();
+
+    let body = json!({ "sender": "Kai", "content": "buy spam now" });
+    let response = test::call_service(&app, preview(body).to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(response).await;
+    let item = &body["result"]["Item"];
+    assert_eq!(item["id"], 0);
+    assert_eq!(item["sender"], "Kai");
+    assert_eq!(item["content"], "buy **** now");
+    assert!(item["posted"].as_str().unwrap().ends_with('Z'));
+
+    let body = json!({ "sender": "Kai", "content": "" });
+    let response = test::call_service(&app, preview(body).to_request()).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    assert_eq!(env.stored(), seeded);
+    assert_eq!(changes(), changes_before);
+    // No ID was used up by the preview.
+    let request = create_request(json!({ "sender": "Kai", "content": "for real" }));
+    let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    assert_eq!(body["result"]["Item"]["id"], 2);
+}
+
+#[actix_web::test]
+async fn colliding_slugs_are_made_unique_with_the_configured_suffix() {
+    for (strategy, expected) in [
+        (
+            data::SlugStrategy::IdSuffix,
+            ["hello-world", "hello-world-3", "hello-world-4"],
+        ),
+        (
+            data::SlugStrategy::CounterSuffix,
+            ["hello-world", "hello-world-2", "hello-world-3"],
+        ),
+    ] {
+        let env = TestEnv::with(|config| config.slug_strategy = strategy);
+        env.seed(&[message(1, "Nao", "unrelated")]);
+        let app = testing::service!();
+
+        let mut slugs = vec![];
+        for content in ["Hello, world!", "hello world", "HELLO -- World\nagain"] {
+            let request = create_request(json!({ "sender": "Nao", "content": content }));
+            let body: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+            slugs.push(body["result"]["Item"]["slug"].clone());
+        }
+        assert_eq!(slugs, expected, "{:?}", strategy);
+
+        // Edits keep the slug, and the message stored before slugs existed receives a free one.
+        let request = json(
+            TestRequest::patch().uri("/api/posts/2"),
+            json!({ "content": "Something else" }),
+        );
+        test::call_service(&app, request.to_request()).await;
+        let request = json(
+            TestRequest::patch().uri("/api/posts/1"),
+            json!({ "content": "Hello world" }),
+        );
+        test::call_service(&app, request.to_request()).await;
+        let stored: Vec<_> = env.stored().into_iter().map(|m| m.slug).collect();
+        assert_eq!(stored[1..], expected);
+        assert!(!expected.contains(&stored[0].as_str()), "{}", stored[0]);
+        assert!(stored[0].starts_with("hello-world-"));
+
+        // Imported and restored messages give up slugs that were taken in the meantime.
+        let records = json!([{ "sender": "Kai", "content": "Hi", "slug": "hello-world" }]);
+        test::call_service(&app, import_request(records).to_request()).await;
+        data::remove(2).unwrap();
+        let request = create_request(json!({ "sender": "Nao", "content": "Hello world" }));
+        test::call_service(&app, request.to_request()).await;
+        let restored = data::restore(2).unwrap().unwrap();
+        assert_ne!(restored.slug, "hello-world", "{:?}", strategy);
+        let stored: Vec<_> = env.stored().into_iter().map(|m| m.slug).collect();
+        let unique: HashSet<_> = stored.iter().collect();
+        assert_eq!(unique.len(), 6, "{:?}", stored);
+    }
+}
+
+#[actix_web::test]
+async fn the_generation_token_changes_on_writes_only() {
+    let env = TestEnv::with(|_| {});
+    env.seed(&[message(1, "Nao", "one")]);
+    let app = testing::service!();
+    let generation = || async {
+        let request = TestRequest::get().uri("/api/posts?limit=1").to_request();
+        let response = test::call_service(&app, request).await;
+        let value = response.headers().get("x-generation").unwrap();
+        value.to_str().unwrap().to_string()
+    };
+
+    let first = generation().await;
+    let request = TestRequest::get().uri("/api/posts/1").to_request();
+    test::call_service(&app, request).await;
+    assert_eq!(generation().await, first);
+
+    let request = create_request(json!({ "sender": "Nao", "content": "two" }));
+    test::call_service(&app, request.to_request()).await;
+    let second = generation().await;
+    assert_ne!(second, first);
+    assert_eq!(generation().await, second);
+
+    let request = json(
+        TestRequest::post().uri("/api/posts/1/react"),
+        json!({ "emoji": "👍" }),
+    );
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_ne!(generation().await, second);
+}
+
+#[actix_web::test]
+async fn imports_are_checked_against_the_message_schema() {
+    let env = TestEnv::with(|_| {});
+    let seeded = [message(1, "Nao", "hello")];
+    env.seed(&seeded);
+    let app = testing::service!();
+    let import = |body: Value| {
+        let app = &app;
+        async move {
+            let response = test::call_service(app, import_request(body).to_request()).await;
+            let status = response.status();
+            let body: Value = test::read_body_json(response).await;
+            (status, body)
+        }
+    };
+
+    let (status, body) = import(json!({
+        "schema_version": data::SCHEMA_VERSION,
+        "messages": [
+            { "sender": "Kai", "content": "fine" },
+            { "sender": "Kai" },
+            { "sender": "Kai", "content": 5, "colour": "red" },
+        ],
+    }))
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        error_fields(&body),
+        ["[1].content", "[2].colour", "[2].content"]
+    );
+    let reason = body["result"]["Errors"][0]["reason"].as_str().unwrap();
+    assert!(reason.starts_with("is required"), "{}", reason);
+    assert_eq!(env.stored(), seeded);
+
+    let (status, body) = import(json!({
+        "schema_version": data::SCHEMA_VERSION + 1,
+        "messages": [{ "sender": "Kai", "content": "from the future" }],
+    }))
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error_fields(&body), ["schema_version"]);
+    assert_eq!(env.stored(), seeded);
+
+    // An export of this version is accepted as it is.
+    let request = testing::with_key(TestRequest::get().uri("/api/export"));
+    let export: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+    assert_eq!(export["schema_version"], data::SCHEMA_VERSION);
+    let (status, _) = import(export).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(env.stored().len(), 2);
+}
+
+#[actix_web::test]
+async fn envelope_false_returns_bare_messages() {
+    let env = TestEnv::with(|_| {});
+    let mut tracked = message(1, "Nao", "one");
+    tracked.metadata = Some(Box::new(Metadata {
+        ip: Some("192.0.2.1".to_string()),
+        user_agent: Some("curl/8.0".to_string()),
+    }));
+    env.seed(&[tracked, message(2, "Kai", "two")]);
+    let app = testing::service!();
+    let get = |uri: &str| TestRequest::get().uri(uri).to_request();
+
+    // Bare messages are still served in their public form.
+    for uri in [
+        "/api/posts/1?envelope=false",
+        "/api/posts?envelope=false",
+        "/api/posts?envelope=false&limit=5",
+        "/api/posts?envelope=false&ids=1,9&missing=null",
+        "/api/posts/first?envelope=false",
+    ] {
+        let body: Value = test::call_and_read_body_json(&app, get(uri)).await;
+        assert!(!body.to_string().contains("metadata"), "{}: {}", uri, body);
+    }
+
+    let item: Value = test::call_and_read_body_json(&app, get("/api/posts/1?envelope=false")).await;
+    assert_eq!(item["id"], 1);
+    assert_eq!(item["content"], "one");
+    // `status` is the workflow status of the message, not the one of an envelope.
+    assert_eq!(item["status"], "open");
+    assert!(item.get("result").is_none());
+
+    let list: Value = test::call_and_read_body_json(&app, get("/api/posts?envelope=false")).await;
+    let ids: Vec<_> = list
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m["id"].clone())
+        .collect();
+    assert_eq!(ids, [json!(2), json!(1)]);
+
+    let response = test::call_service(&app, get("/api/posts?envelope=false&limit=1")).await;
+    assert!(response.headers().contains_key("x-next-cursor"));
+    let page: Value = test::read_body_json(response).await;
+    assert_eq!(page.as_array().unwrap().len(), 1);
+
+    // Errors keep the envelope.
+    let response = test::call_service(&app, get("/api/posts/99?envelope=false")).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let body: Value = test::read_body_json(response).await;
+    assert_eq!(body["status"], "Error");
+    assert!(body["result"]["Reason"].is_string());
+}
+
+#[actix_web::test]
+async fn merge_patches_clear_with_null_and_leave_absent_fields() {
+    let env = TestEnv::with(|_| {});
+    let mut stored = message(1, "Nao", "before");
+    stored.attachments = vec!["https://example.com/a.png".to_string()];
+    stored.expires_at = Some("2099-01-01 00:00:00Z".to_string());
+    env.seed(&[stored]);
+    let app = testing::service!();
+    let merge_patch = |body: Value| {
+        TestRequest::patch()
+            .uri("/api/posts/1")
+            .insert_header(("content-type", "application/merge-patch+json"))
+            .set_payload(body.to_string())
+            .to_request()
+    };
+
+    let body = json!({ "attachments": null, "content": "after" });
+    let response = test::call_service(&app, merge_patch(body)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let patched = &env.stored()[0];
+    assert!(patched.attachments.is_empty());
+    assert_eq!(patched.content, "after");
+    assert_eq!(patched.sender, "Nao");
+    assert_eq!(patched.expires_at.as_deref(), Some("2099-01-01 00:00:00Z"));
+
+    let response = test::call_service(&app, merge_patch(json!({ "expires_at": null }))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(env.stored()[0].expires_at, None);
+
+    let before = env.stored();
+    for body in [json!({ "content": null }), json!({ "sender": null })] {
+        let response = test::call_service(&app, merge_patch(body.clone())).await;
+        assert_eq!(
+            response.status(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "{}",
+            body
+        );
+        let errors: Value = test::read_body_json(response).await;
+        let field = body.as_object().unwrap().keys().next().unwrap().clone();
+        assert_eq!(error_fields(&errors), [field.as_str()]);
+    }
+    assert_eq!(env.stored(), before);
+}
+
+#[actix_web::test]
+async fn content_shorter_than_the_minimum_is_rejected() {
+    let env = TestEnv::with(|config| config.min_content = 3);
+    let app = testing::service!();
+    let create =
+        |content: &str| create_request(json!({ "sender": "Nao", "content": content })).to_request();
+
+    // Counted in characters after trimming: "日本" is six bytes but two characters.
+    for content in ["+1", "  ab  ", "日本"] {
+        let response = test::call_service(&app, create(content)).await;
+        assert_eq!(
+            response.status(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "{:?}",
+            content
+        );
+        let body: Value = test::read_body_json(response).await;
+        assert_eq!(error_fields(&body), ["content"]);
+        let reason = &body["result"]["Errors"][0]["reason"];
+        assert_eq!(reason, "must be at least 3 characters");
+    }
+    for content in ["abc", " abc ", "日本語"] {
+        let response = test::call_service(&app, create(content)).await;
+        assert_eq!(response.status(), StatusCode::OK, "{:?}", content);
+    }
+    assert_eq!(env.stored().len(), 3);
+
+    let request = TestRequest::post().uri("/posts/create").set_form([
+        ("id", "0"),
+        ("posted", ""),
+        ("sender", "Nao"),
+        ("content", "+1"),
+    ]);
+    let response = test::call_service(&app, request.to_request()).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let html = test::read_body(response).await;
+    assert!(String::from_utf8_lossy(&html).contains("must be at least 3 characters"));
+    assert_eq!(env.stored().len(), 3);
+}
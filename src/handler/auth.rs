@@ -0,0 +1,266 @@
+//! Authentication: the API key of privileged API routes, and the optional login of posters.
+//!
+//! Clients authenticate privileged requests by sending the key configured in
+//! `ACTIX_POSTS_API_KEY` (see [`crate::config`]) in the `X-Api-Key` header. When no key is
+//! configured, privileged routes are disabled altogether. The API key identifies an operator, not
+//! a poster.
+//!
+//! Posters can log in when accounts are configured in `ACTIX_POSTS_USERS`. The name of the
+//! logged-in user is kept in the session, and new posts are then always made under it: the
+//! `sender` given by the client is ignored, so nobody can post under someone else's name (see
+//! [`sender_identity`]). Edits are held to the same rule: a user only edits their own posts, and
+//! cannot move them to another name (see [`check_editor`]). Without accounts, the `sender`
+//! remains the free-text value given by the client.
+//!
+//! Accounts listed in `ACTIX_POSTS_MODERATORS` are moderators: they can use the moderation page.
+//! Its forms, like the undo button of a deletion, carry a CSRF token bound to the session (see
+//! [`csrf_token`]), so another site cannot make a browser submit them.
+
+use crate::config;
+use crate::handler::signing::UrlSigner;
+use actix_session::Session;
+use actix_web::HttpRequest;
+use argon2::password_hash::{PasswordHash, PasswordVerifier};
+use argon2::Argon2;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The request header that carries the API key.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// The outcome of checking a request's API key.
+///
+/// # Variants
+/// - `Authorized`: The request carries the configured key.
+/// - `Missing`: The request carries no key or a wrong key.
+/// - `Disabled`: No key is configured, so privileged routes cannot be used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiKeyCheck {
+    Authorized,
+    Missing,
+    Disabled,
+}
+
+/// Compares two byte strings in time independent of where they differ.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Checks the `X-Api-Key` header of a request against the configured API key.
+pub fn check_api_key(req: &HttpRequest) -> ApiKeyCheck {
+    let Some(expected) = config::get().api_key.as_deref() else {
+        return ApiKeyCheck::Disabled;
+    };
+    let provided = req
+        .headers()
+        .get(API_KEY_HEADER)
+        .map(|value| value.as_bytes())
+        .unwrap_or_default();
+    if constant_time_eq(provided, expected.as_bytes()) {
+        ApiKeyCheck::Authorized
+    } else {
+        ApiKeyCheck::Missing
+    }
+}
+
+/// Returns `true` when the request carries the configured API key.
+pub fn has_api_key(req: &HttpRequest) -> bool {
+    check_api_key(req) == ApiKeyCheck::Authorized
+}
+
+/// The session key the name of the logged-in user is stored under.
+const IDENTITY_SESSION_KEY: &str = "identity";
+
+/// The session key the value signed by the CSRF token is stored under (see [`csrf_token`]).
+const CSRF_NONCE_SESSION_KEY: &str = "csrf_nonce";
+
+/// An account posters can log in with, configured in `ACTIX_POSTS_USERS`.
+///
+/// Only a salted Argon2 hash of the password is configured, so the configuration does not reveal
+/// the password itself, and a leaked hash is slow to guess from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    pub name: String,
+    password_hash: String,
+}
+
+impl FromStr for Account {
+    type Err = String;
+
+    /// Parses `name:hash`, where the hash is the Argon2 hash of the password as a PHC string
+    /// (`$argon2id$v=19$m=...,t=...,p=...$salt$hash`). The hash carries its own salt and cost
+    /// parameters.
+    ///
+    /// # Example
+    /// ```rust
+    /// use actix_posts::handler::auth::Account;
+    /// // printf %s secret | argon2 "$(openssl rand -base64 12)" -id -e
+    /// let hash = "$argon2id$v=19$m=1024,t=1,p=1$YWN0aXgtcG9zdHMtdGVzdA$Pn578KgMla+fiB7XMq7tbpgGKuPOH5OSj+Y76K8rbIo";
+    /// let account: Account = format!("nao:{}", hash).parse().unwrap();
+    /// assert_eq!(account.name, "nao");
+    /// assert!("nao:secret".parse::<Account>().is_err());
+    /// // Unsalted digests are not accepted.
+    /// let digest = "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b";
+    /// assert!(format!("nao:{}", digest).parse::<Account>().is_err());
+    /// ```
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (name, hash) = value
+            .split_once(':')
+            .ok_or_else(|| format!("missing password hash: {}", value))?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(format!("missing name: {}", value));
+        }
+        let hash = hash.trim();
+        PasswordHash::new(hash)
+            .ok()
+            .filter(|hash| hash.algorithm.as_str().starts_with("argon2"))
+            .ok_or_else(|| format!("invalid Argon2 password hash for {}", name))?;
+        Ok(Account {
+            name: name.to_string(),
+            password_hash: hash.to_string(),
+        })
+    }
+}
+
+impl Account {
+    /// Returns `true` when `password` is the password of the account.
+    fn verify(&self, password: &str) -> bool {
+        PasswordHash::new(&self.password_hash).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+    }
+}
+
+/// Returns `true` when accounts are configured, so posters log in and post under their name.
+pub fn login_enabled() -> bool {
+    !config::get().users.is_empty()
+}
+
+/// Logs the session in as `name` when `password` is the account's password.
+///
+/// The session is renewed first, so an identifier planted before the login is not reused.
+///
+/// # Returns
+/// `true` when the name and password match a configured account.
+pub fn log_in(session: &Session, name: &str, password: &str) -> bool {
+    let Some(account) = config::get()
+        .users
+        .iter()
+        .find(|account| account.name == name && account.verify(password))
+    else {
+        return false;
+    };
+    session.renew();
+    session.remove(CSRF_NONCE_SESSION_KEY);
+    session.insert(IDENTITY_SESSION_KEY, &account.name).is_ok()
+}
+
+/// Forgets the user logged in on the session.
+pub fn log_out(session: &Session) {
+    session.remove(IDENTITY_SESSION_KEY);
+    session.remove(CSRF_NONCE_SESSION_KEY);
+}
+
+/// Returns the name of the user logged in on the session, if any.
+///
+/// A name whose account has since been removed from the configuration is not returned.
+pub fn identity(session: &Session) -> Option<String> {
+    let name = session.get::<String>(IDENTITY_SESSION_KEY).ok().flatten()?;
+    config::get()
+        .users
+        .iter()
+        .any(|account| account.name == name)
+        .then_some(name)
+}
+
+/// Posting requires a login, and the session has none.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoginRequired;
+
+/// Returns the sender new posts of the session must use.
+///
+/// # Returns
+/// - `Ok(Some(name))`: The name of the logged-in user, which replaces the submitted sender.
+/// - `Ok(None)`: Login is disabled, so the submitted sender is kept.
+/// - `Err(LoginRequired)`: Login is enabled, but the session is not logged in.
+pub fn sender_identity(session: &Session) -> Result<Option<String>, LoginRequired> {
+    if !login_enabled() {
+        return Ok(None);
+    }
+    identity(session).map(Some).ok_or(LoginRequired)
+}
+
+/// Why a session may not edit a post (see [`check_editor`]).
+///
+/// # Variants
+/// - `LoginRequired`: Login is enabled, but the session is not logged in.
+/// - `NotOwner`: The post is not, or would no longer be, under the name of the logged-in user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditRefused {
+    LoginRequired,
+    NotOwner,
+}
+
+/// Checks that the session may edit a post whose sender is `stored` into one whose sender is
+/// `sender`.
+///
+/// With login enabled, a logged-in user only edits their own posts, and keeps them under their
+/// name, so edits cannot impersonate anyone. Without login, anyone may edit any post.
+pub fn check_editor(session: &Session, stored: &str, sender: &str) -> Result<(), EditRefused> {
+    match sender_identity(session) {
+        Ok(None) => Ok(()),
+        Ok(Some(name)) if name == stored && name == sender => Ok(()),
+        Ok(Some(_)) => Err(EditRefused::NotOwner),
+        Err(LoginRequired) => Err(EditRefused::LoginRequired),
+    }
+}
+
+/// Returns `true` when the user logged in on the session is a moderator.
+pub fn is_moderator(session: &Session) -> bool {
+    identity(session).is_some_and(|name| config::get().moderators.contains(&name))
+}
+
+/// Returns the CSRF token the forms of the session must carry, creating it on first use.
+///
+/// The token signs a value kept in the session with the server secret, so it cannot be guessed
+/// by another site. Logging in or out starts a new token.
+pub fn csrf_token(session: &Session, signer: &UrlSigner) -> String {
+    let nonce = session
+        .get::<String>(CSRF_NONCE_SESSION_KEY)
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| {
+            let nonce = new_nonce();
+            let _ = session.insert(CSRF_NONCE_SESSION_KEY, &nonce);
+            nonce
+        });
+    signer.sign(&format!("csrf:{}", nonce), 0)
+}
+
+/// Returns `true` when `token` is the CSRF token of the session (see [`csrf_token`]).
+///
+/// The comparison runs in constant time.
+pub fn check_csrf(session: &Session, signer: &UrlSigner, token: &str) -> bool {
+    let Some(nonce) = session.get::<String>(CSRF_NONCE_SESSION_KEY).ok().flatten() else {
+        return false;
+    };
+    signer
+        .verify(&format!("csrf:{}", nonce), 0, token, 0)
+        .is_ok()
+}
+
+/// Returns a value no other session is given, for [`csrf_token`].
+fn new_nonce() -> String {
+    use std::hash::{BuildHasher, Hasher};
+    static CALLS: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u64(CALLS.fetch_add(1, Ordering::Relaxed));
+    format!(
+        "{}-{:016x}",
+        chrono::Utc::now().timestamp_micros(),
+        hasher.finish()
+    )
+}
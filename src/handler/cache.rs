@@ -0,0 +1,112 @@
+//! In-memory cache of complete API responses.
+//!
+//! Responses are stored under a key built from the normalized query, together with the data
+//! generation they were computed at (see [`crate::handler::data::generation`]). Any write to the
+//! store moves the generation on, which invalidates every entry; entries older than
+//! `ACTIX_POSTS_CACHE_TTL` seconds are dropped as well (see [`crate::config`]).
+//!
+//! Served responses carry an `X-Cache` header: `HIT` when answered from the cache, `MISS`
+//! otherwise.
+
+use crate::config;
+use crate::handler::data;
+use actix_web::body::to_bytes;
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use actix_web::HttpResponse;
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// The header telling whether a response was served from the cache.
+pub const CACHE_HEADER: &str = "x-cache";
+
+/// The maximum number of cached responses. Stale entries are evicted first; when every entry
+/// is fresh, new responses are not cached.
+const MAX_ENTRIES: usize = 64;
+
+struct Entry {
+    generation: u64,
+    stored: Instant,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl Entry {
+    fn is_fresh(&self, generation: u64, ttl: Duration) -> bool {
+        self.generation == generation && self.stored.elapsed() < ttl
+    }
+
+    fn to_response(&self, cache_status: &'static str) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status).body(self.body.clone());
+        *response.headers_mut() = self.headers.clone();
+        response.headers_mut().insert(
+            HeaderName::from_static(CACHE_HEADER),
+            HeaderValue::from_static(cache_status),
+        );
+        response
+    }
+}
+
+static CACHE: LazyLock<Mutex<HashMap<String, Entry>>> = LazyLock::new(Default::default);
+
+fn ttl() -> Duration {
+    Duration::from_secs(config::get().cache_ttl)
+}
+
+/// Returns the cached response for `key`, marked as a `HIT`.
+///
+/// Returns `None` when caching is disabled, nothing is cached under `key`, or the cached
+/// response is stale.
+pub fn lookup(key: &str) -> Option<HttpResponse> {
+    let ttl = ttl();
+    if ttl.is_zero() {
+        return None;
+    }
+    let cache = CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    cache
+        .get(key)
+        .filter(|entry| entry.is_fresh(data::generation(), ttl))
+        .map(|entry| entry.to_response("HIT"))
+}
+
+/// Caches a successful response under `key` and returns it, marked as a `MISS`.
+///
+/// # Arguments
+/// - `key`: The normalized query the response answers.
+/// - `generation`: The data generation read *before* the response was computed, so a response
+///   racing with a write is never served as fresh.
+/// - `response`: The response to cache. Only `200 OK` responses are stored; others are returned
+///   unchanged.
+pub async fn store(key: String, generation: u64, response: HttpResponse) -> HttpResponse {
+    if response.status() != StatusCode::OK || ttl().is_zero() {
+        return response;
+    }
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = match to_bytes(response.into_body()).await {
+        Ok(body) => body,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let entry = Entry {
+        generation,
+        stored: Instant::now(),
+        status,
+        headers,
+        body,
+    };
+    let response = entry.to_response("MISS");
+
+    let ttl = ttl();
+    let current = data::generation();
+    let mut cache = CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    if cache.len() >= MAX_ENTRIES {
+        cache.retain(|_, entry| entry.is_fresh(current, ttl));
+    }
+    if cache.len() < MAX_ENTRIES || cache.contains_key(&key) {
+        cache.insert(key, entry);
+    }
+    response
+}
@@ -0,0 +1,203 @@
+//! Resolution of the client IP address behind reverse proxies.
+//!
+//! The `X-Forwarded-For` header is only honored when the direct peer is a trusted proxy listed in
+//! `ACTIX_POSTS_TRUSTED_PROXIES` (see [`crate::config`]). Otherwise the header is ignored and the
+//! socket address is used, so clients cannot spoof their IP by sending the header themselves.
+
+use crate::config;
+use crate::handler::data::Metadata;
+use actix_web::http::header::{self, HeaderMap};
+use actix_web::HttpRequest;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+/// An IP network in CIDR notation (e.g. `10.0.0.0/8` or `::1/128`).
+///
+/// A bare address without a prefix length is treated as a single-host network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    /// Returns `true` when `ip` belongs to this network.
+    ///
+    /// IPv4-mapped IPv6 addresses are compared as their IPv4 equivalent.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    /// Parses `address/prefix` or a bare address.
+    ///
+    /// # Example
+    /// ```rust
+    /// use actix_posts::handler::client_ip::Cidr;
+    /// let cidr: Cidr = "10.0.0.0/8".parse().unwrap();
+    /// assert!(cidr.contains("10.1.2.3".parse().unwrap()));
+    /// assert!(!cidr.contains("192.168.0.1".parse().unwrap()));
+    /// ```
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = match value.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (value, None),
+        };
+        let network = IpAddr::from_str(address.trim())
+            .map_err(|_| format!("invalid address: {}", value))?
+            .to_canonical();
+        let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .trim()
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= max_prefix)
+                .ok_or_else(|| format!("invalid prefix length: {}", value))?,
+            None => max_prefix,
+        };
+        Ok(Cidr { network, prefix })
+    }
+}
+
+/// Returns `true` when `ip` belongs to one of the configured trusted proxies.
+fn is_trusted(ip: IpAddr) -> bool {
+    config::get()
+        .trusted_proxies
+        .iter()
+        .any(|cidr| cidr.contains(ip))
+}
+
+/// Determines the IP address of the client that originated a request.
+///
+/// # Arguments
+/// - `peer`: The address of the direct peer of the connection.
+/// - `headers`: The request headers.
+///
+/// # Behavior
+/// - If the peer is not a trusted proxy, its address is returned and `X-Forwarded-For` is ignored.
+/// - Otherwise, the `X-Forwarded-For` chain is walked from right to left, skipping trusted proxies,
+///   and the first untrusted address is returned. If every hop is trusted, the left-most address
+///   is returned.
+/// - Malformed header values make the function fall back to the peer address.
+///
+/// # Returns
+/// The client address, or `None` when the peer address is unknown (e.g. in unit tests).
+pub fn client_ip(peer: Option<SocketAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+    let peer = peer?.ip().to_canonical();
+    if !is_trusted(peer) {
+        return Some(peer);
+    }
+    let forwarded: Option<Vec<IpAddr>> = headers
+        .get_all("x-forwarded-for")
+        .flat_map(|value| value.to_str().ok().into_iter().flat_map(|v| v.split(',')))
+        .map(|hop| {
+            IpAddr::from_str(hop.trim())
+                .ok()
+                .map(|ip| ip.to_canonical())
+        })
+        .collect();
+    match forwarded {
+        Some(hops) if !hops.is_empty() => hops
+            .iter()
+            .rev()
+            .find(|hop| !is_trusted(**hop))
+            .or_else(|| hops.first())
+            .copied(),
+        _ => Some(peer),
+    }
+}
+
+/// Returns the [`Metadata`] recorded with a message posted by `req`: the client address (see
+/// [`client_ip`]) and the `User-Agent` header.
+pub fn request_metadata(req: &HttpRequest) -> Metadata {
+    Metadata {
+        ip: client_ip(req.peer_addr(), req.headers()).map(|ip| ip.to_string()),
+        user_agent: req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestEnv;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    fn forwarded_for(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("x-forwarded-for"),
+            HeaderValue::from_str(value).unwrap(),
+        );
+        headers
+    }
+
+    fn peer(ip: &str) -> Option<SocketAddr> {
+        Some(SocketAddr::new(ip.parse().unwrap(), 40000))
+    }
+
+    fn ip(ip: &str) -> Option<IpAddr> {
+        Some(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn forwarded_header_is_only_honored_from_trusted_peers() {
+        let _env = TestEnv::with(|config| {
+            config.trusted_proxies = vec!["10.0.0.0/8".parse().unwrap()];
+        });
+        let headers = forwarded_for("203.0.113.7, 10.0.0.2");
+
+        // An untrusted peer cannot spoof its address.
+        assert_eq!(
+            client_ip(peer("198.51.100.1"), &headers),
+            ip("198.51.100.1")
+        );
+        // A trusted proxy is skipped, as are trusted hops of the chain.
+        assert_eq!(client_ip(peer("10.0.0.1"), &headers), ip("203.0.113.7"));
+        // Only the right-most untrusted hop counts; earlier ones could be forged by the client.
+        let headers = forwarded_for("192.0.2.1, 203.0.113.7");
+        assert_eq!(client_ip(peer("10.0.0.1"), &headers), ip("203.0.113.7"));
+        // Without a usable header, the peer address is used.
+        assert_eq!(
+            client_ip(peer("10.0.0.1"), &HeaderMap::new()),
+            ip("10.0.0.1")
+        );
+        let headers = forwarded_for("not-an-ip");
+        assert_eq!(client_ip(peer("10.0.0.1"), &headers), ip("10.0.0.1"));
+        // IPv4-mapped peers are matched as IPv4.
+        let headers = forwarded_for("203.0.113.7");
+        assert_eq!(
+            client_ip(peer("::ffff:10.0.0.1"), &headers),
+            ip("203.0.113.7")
+        );
+        assert_eq!(client_ip(None, &headers), None);
+    }
+
+    #[test]
+    fn no_peer_is_trusted_by_default() {
+        let _env = TestEnv::with(|config| config.trusted_proxies = vec![]);
+        let headers = forwarded_for("203.0.113.7");
+        assert_eq!(client_ip(peer("127.0.0.1"), &headers), ip("127.0.0.1"));
+    }
+}
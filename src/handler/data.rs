@@ -1,6 +1,17 @@
+use crate::config;
+use crate::handler::events;
+use crate::handler::filter;
+use crate::handler::validation;
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
-
-static DATA_FILENAME: &str = "data.json";
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::time::{Duration, SystemTime};
 
 /// Represents a user message.
 ///
@@ -12,9 +23,30 @@ static DATA_FILENAME: &str = "data.json";
 /// - `posted`: A timestamp indicating when the message was posted, stored as a string.
 /// - `sender`: The name or identifier of the sender of the message.
 /// - `content`: The content of the message, stored as a string.
+/// - `attachments`: URLs of images or other resources hosted elsewhere.
+/// - `updated`: A timestamp indicating when the message was last updated, empty if never.
+/// - `reactions`: The number of reactions per emoji (see [`react`]).
+/// - `tags`: Normalized topic labels (see [`set_tags`]).
+/// - `status`: Where the message stands in a support workflow (see [`set_status`]).
+/// - `expires_at`: When set, the time after which the message is hidden (see
+///   [`Message::is_expired`]).
+/// - `metadata`: Who posted the message (see [`Metadata`]). Stored, but left out of public
+///   responses.
+/// - `slug`: A readable name derived from the content, unique among stored messages (see
+///   [`slugify`]).
+///
+/// Missing fields deserialize to their default (empty) value, so incomplete input is reported by
+/// [`Message::validate`] instead of failing deserialization.
+///
+/// This is the stored form of a message. Public API responses serialize a `PublicMessage`
+/// instead (see [`crate::handler::api`]), so storage-only fields such as `metadata` only reach
+/// API key holders.
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[serde(default)]
 pub struct Message {
-    /// Unique identifier for the message.
+    /// Unique identifier for the message. Accepted as a number or a string of digits (see
+    /// [`deserialize_id`]).
+    #[serde(deserialize_with = "deserialize_id")]
     pub id: i32,
 
     /// The time at which the message was posted, represented as a string.
@@ -25,6 +57,998 @@ pub struct Message {
 
     /// The content of the message.
     pub content: String,
+
+    /// The URLs attached to the message (`http(s)` or uploaded files), in display order.
+    pub attachments: Vec<String>,
+
+    /// The time at which the message was last updated, or an empty string if it never was.
+    pub updated: String,
+
+    /// The number of reactions received, per emoji. Emoji nobody reacted with are absent.
+    pub reactions: BTreeMap<String, u32>,
+
+    /// Lowercase labels without duplicates, in the order they were given.
+    pub tags: Vec<String>,
+
+    /// The workflow status, `open` for messages stored before statuses existed.
+    pub status: PostStatus,
+
+    /// The time after which the message is hidden, in [`STORED_FORMAT`], or `None` if it never
+    /// expires.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+
+    /// Information about the client that posted the message, or `None` for messages created
+    /// before it was recorded or by imports.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Box<Metadata>>,
+
+    /// The slug given on creation, or an empty string for messages stored before slugs existed
+    /// that were not edited since.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub slug: String,
+}
+
+/// Information about the client that posted a message, kept for moderators.
+///
+/// # Fields
+/// - `ip`: The client address (see [`crate::handler::client_ip`]), if known.
+/// - `user_agent`: The `User-Agent` header of the request, if any.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Hash, Default)]
+#[serde(default)]
+pub struct Metadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+}
+
+/// Deserializes a message ID given either as a JSON number or as a string such as `"42"`, for
+/// clients that keep IDs as strings (see `?id_as_string=true` in [`crate::handler::middleware`]).
+///
+/// # Example
+/// ```rust
+/// use actix_posts::handler::data::Message;
+/// let message: Message = serde_json::from_str(r#"{ "id": "42" }"#).unwrap();
+/// assert_eq!(message.id, 42);
+/// let message: Message = serde_json::from_str(r#"{ "id": 42 }"#).unwrap();
+/// assert_eq!(message.id, 42);
+/// assert!(serde_json::from_str::<Message>(r#"{ "id": "forty-two" }"#).is_err());
+/// ```
+pub fn deserialize_id<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<i32, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Id {
+        Number(i32),
+        Text(String),
+    }
+    match Id::deserialize(deserializer)? {
+        Id::Number(id) => Ok(id),
+        Id::Text(text) => text
+            .trim()
+            .parse()
+            .map_err(|_| serde::de::Error::custom(format!("invalid message id: {:?}", text))),
+    }
+}
+
+/// The format timestamps are displayed in (see [`display_timestamp`]). Timestamps stored before
+/// [`STORED_FORMAT`] was introduced use it too, in server local time.
+pub const POSTED_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// The format of the stored `posted` and `updated` timestamps: UTC, marked by a trailing `Z`
+/// (e.g. `2024-12-26 12:01:05Z`), so stored data does not depend on the server's time zone.
+pub const STORED_FORMAT: &str = "%Y-%m-%d %H:%M:%SZ";
+
+/// The version of the [`Message`] format carried by exports (see [`Export`]). Bump it whenever a
+/// field is added, removed, or changes meaning, so older servers refuse exports they cannot
+/// read.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// The body of an export: the messages, tagged with the [`SCHEMA_VERSION`] they were written
+/// with.
+///
+/// # Fields
+/// - `schema_version`: The message format version.
+/// - `messages`: The messages, in the format of the data file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Export<T> {
+    pub schema_version: u32,
+    pub messages: Vec<T>,
+}
+
+/// The format of calendar dates in API responses and queries, such as the days of
+/// [`counts_by_day`].
+pub const DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Parses a stored timestamp: UTC in [`STORED_FORMAT`], or server local time in
+/// [`POSTED_FORMAT`] for older data.
+fn parse_timestamp(value: &str) -> Option<DateTime<Local>> {
+    match NaiveDateTime::parse_from_str(value, STORED_FORMAT) {
+        Ok(naive) => Some(Utc.from_utc_datetime(&naive).with_timezone(&Local)),
+        Err(_) => NaiveDateTime::parse_from_str(value, POSTED_FORMAT)
+            .ok()
+            .and_then(|naive| Local.from_local_datetime(&naive).single()),
+    }
+}
+
+/// Returns the current time as a stored timestamp, in [`STORED_FORMAT`].
+///
+/// Every `posted` and `updated` value written by the server comes from here.
+pub fn current_timestamp() -> String {
+    Utc::now().format(STORED_FORMAT).to_string()
+}
+
+/// The time zone timestamps are displayed in.
+///
+/// # Variants
+/// - `Local`: The server's time zone (the default).
+/// - `Fixed(offset)`: A fixed offset from UTC, e.g. `+09:00`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DisplayZone {
+    #[default]
+    Local,
+    Fixed(FixedOffset),
+}
+
+impl DisplayZone {
+    /// Parses a zone from its configuration value: `local`, `utc`, or an offset such as `+09:00`.
+    ///
+    /// Returns `None` for anything else.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "local" => Some(DisplayZone::Local),
+            "utc" | "z" => Some(DisplayZone::Fixed(FixedOffset::east_opt(0)?)),
+            _ => value.parse().ok().map(DisplayZone::Fixed),
+        }
+    }
+}
+
+/// Formats a stored timestamp in [`POSTED_FORMAT`] and the configured display zone
+/// (`ACTIX_POSTS_DISPLAY_TZ`, see [`crate::config`]).
+///
+/// Values that cannot be parsed, such as an empty `updated`, are returned unchanged.
+pub fn display_timestamp(value: &str) -> String {
+    let Some(time) = parse_timestamp(value) else {
+        return value.to_string();
+    };
+    match config::get().display_tz {
+        DisplayZone::Local => time.format(POSTED_FORMAT).to_string(),
+        DisplayZone::Fixed(offset) => time
+            .with_timezone(&offset)
+            .format(POSTED_FORMAT)
+            .to_string(),
+    }
+}
+
+impl Message {
+    /// Parses the `posted` timestamp.
+    ///
+    /// Returns `None` when the value is empty or in neither [`STORED_FORMAT`] nor
+    /// [`POSTED_FORMAT`].
+    pub fn posted_at(&self) -> Option<DateTime<Local>> {
+        parse_timestamp(&self.posted)
+    }
+
+    /// Returns the calendar day of `posted` in UTC, so days do not depend on the server's time
+    /// zone. Returns `None` when `posted` cannot be parsed.
+    pub fn posted_day(&self) -> Option<NaiveDate> {
+        self.posted_at()
+            .map(|posted| posted.with_timezone(&Utc).date_naive())
+    }
+
+    /// Returns the time of the last change: `updated` if set, `posted` otherwise.
+    ///
+    /// Returns `None` when neither can be parsed.
+    pub fn modified_at(&self) -> Option<DateTime<Local>> {
+        parse_timestamp(&self.updated).or_else(|| self.posted_at())
+    }
+
+    /// Returns a fingerprint of the fields an edit replaces (`sender`, `content`, `attachments`,
+    /// and `expires_at`), which changes whenever one of them does.
+    ///
+    /// The edit form sends it back, so [`update_if_unchanged`] can tell that someone else saved
+    /// the message in the meantime. Reactions, tags, and status do not affect it.
+    ///
+    /// # Example
+    /// ```rust
+    /// use actix_posts::handler::data::Message;
+    /// let mut message = Message { content: "hello".to_string(), ..Default::default() };
+    /// let version = message.version();
+    /// message.tags = vec!["greeting".to_string()];
+    /// assert_eq!(message.version(), version);
+    /// message.content = "hello!".to_string();
+    /// assert_ne!(message.version(), version);
+    /// ```
+    pub fn version(&self) -> String {
+        let fields = (
+            &self.sender,
+            &self.content,
+            &self.attachments,
+            &self.expires_at,
+        );
+        hex::encode(Sha256::digest(
+            serde_json::to_vec(&fields).unwrap_or_default(),
+        ))
+    }
+
+    /// Parses the `expires_at` timestamp.
+    ///
+    /// Returns `None` when the message never expires or the value cannot be parsed.
+    pub fn expiry(&self) -> Option<DateTime<Local>> {
+        self.expires_at.as_deref().and_then(parse_timestamp)
+    }
+
+    /// Returns `true` once `expires_at` has passed.
+    ///
+    /// Expired messages stay in the data file but are left out of every read ([`get`],
+    /// [`get_many`], [`get_all_shared`], and what is built on them). An unparsable `expires_at`
+    /// never expires, so a bad value cannot hide a message.
+    ///
+    /// # Example
+    /// ```rust
+    /// use actix_posts::handler::data::Message;
+    /// let mut message = Message::default();
+    /// assert!(!message.is_expired());
+    /// message.expires_at = Some("2000-01-01 00:00:00Z".to_string());
+    /// assert!(message.is_expired());
+    /// ```
+    pub fn is_expired(&self) -> bool {
+        self.expiry().is_some_and(|expiry| expiry <= Local::now())
+    }
+}
+
+/// What happened to a message, as recorded in the change journal (see [`changes_since`]).
+///
+/// # Variants
+/// - `Created`: The message was posted, imported, or restored.
+/// - `Updated`: Any stored field changed, including reactions, tags, and status.
+/// - `Deleted`: The message was removed.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+/// An entry of the change journal.
+///
+/// # Fields
+/// - `seq`: The position of the entry in the journal, starting at `1` and never reused.
+/// - `kind`: What happened (see [`ChangeKind`]).
+/// - `id`: The ID of the message.
+/// - `at`: When it happened, in [`STORED_FORMAT`].
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub seq: u64,
+    pub kind: ChangeKind,
+    pub id: i32,
+    pub at: String,
+}
+
+/// The number of entries kept in the change journal. Older entries are dropped, and clients
+/// whose cursor points before them must read the whole store again (see [`changes_since`]).
+pub const MAX_CHANGES: usize = 10_000;
+
+/// A saved version of a message's content.
+///
+/// Revision `1` is the content as first posted; every update that changes the content adds the
+/// next revision. Messages that were never edited have no stored revisions (see [`revisions`]).
+///
+/// # Fields
+/// - `id`: The ID of the message.
+/// - `revision`: The revision number, starting at `1`.
+/// - `content`: The content of the message at that revision.
+/// - `recorded`: The time at which the revision was saved, as stored in `posted` or `updated`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct Revision {
+    /// The ID of the message.
+    pub id: i32,
+
+    /// The revision number, starting at `1`.
+    pub revision: u32,
+
+    /// The content of the message at that revision.
+    pub content: String,
+
+    /// The time at which the revision was saved.
+    pub recorded: String,
+}
+
+/// Errors reported by the data layer when a change cannot be applied.
+///
+/// # Variants
+/// - `Duplicate(i32)`: A message with the same sender and content already exists while the
+///   unique `(sender, content)` constraint is enabled. Holds the ID of the existing message.
+/// - `EditWindowClosed(i64)`: The message was posted longer ago than the configured edit window.
+///   Holds the window in minutes.
+/// - `IdsExhausted`: No more IDs can be allocated, because the new IDs would exceed `i32::MAX`.
+/// - `ReadOnly`: The primary data file cannot be written (e.g. the disk is full). The store
+///   stays read-only until a write succeeds again (see [`is_read_only`]).
+/// - `Transient(reason)`: Writing the primary data file failed in a way that may not happen
+///   again (see [`is_transient`]); nothing was written. [`with_retries`] retries such writes.
+/// - `TooManyTags(i32)`: A bulk tag change would leave a message with more tags than
+///   `ACTIX_POSTS_MAX_TAGS`. Holds the ID of the first such message.
+/// - `InvalidTransition(from, to)`: The status change is only allowed with `reopen` (see
+///   [`PostStatus::can_become`]).
+/// - `Modified`: The message was changed since the version the edit is based on (see
+///   [`update_if_unchanged`]).
+/// - `QuotaExceeded(max)`: The sender already has `ACTIX_POSTS_MAX_PER_SENDER` posts and the
+///   policy is [`QuotaPolicy::Reject`] (or nothing can be evicted). Holds the maximum.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataError {
+    Duplicate(i32),
+    EditWindowClosed(i64),
+    IdsExhausted,
+    ReadOnly,
+    Transient(String),
+    TooManyTags(i32),
+    InvalidTransition(PostStatus, PostStatus),
+    Modified,
+    QuotaExceeded(usize),
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataError::Duplicate(id) => {
+                write!(
+                    f,
+                    "A post with the same sender and content already exists (id {})",
+                    id
+                )
+            }
+            DataError::EditWindowClosed(minutes) => {
+                write!(
+                    f,
+                    "Posts can only be edited within {} minutes of posting",
+                    minutes
+                )
+            }
+            DataError::IdsExhausted => write!(f, "No more post IDs are available"),
+            DataError::ReadOnly => write!(
+                f,
+                "The board is read-only because the data file cannot be written"
+            ),
+            DataError::Transient(reason) => {
+                write!(f, "The data file could not be written ({})", reason)
+            }
+            DataError::TooManyTags(id) => write!(
+                f,
+                "Post {} would have more than {} tags",
+                id,
+                config::get().max_tags
+            ),
+            DataError::InvalidTransition(from, to) => write!(
+                f,
+                "A post cannot go from {} to {} without reopen",
+                from.as_str(),
+                to.as_str()
+            ),
+            DataError::Modified => write!(f, "The post was changed by someone else"),
+            DataError::QuotaExceeded(max) => {
+                write!(f, "A sender can have at most {} posts", max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DataError {}
+
+/// What happens to a new post whose sender already has `ACTIX_POSTS_MAX_PER_SENDER` posts.
+///
+/// # Variants
+/// - `Reject`: The post is refused with [`DataError::QuotaExceeded`] (the default).
+/// - `Evict`: The sender's oldest posts in the primary data file are removed to make room.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QuotaPolicy {
+    #[default]
+    Reject,
+    Evict,
+}
+
+impl QuotaPolicy {
+    /// Parses a policy from its configuration name (`reject` or `evict`).
+    ///
+    /// Returns `None` for unknown names.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "reject" => Some(QuotaPolicy::Reject),
+            "evict" => Some(QuotaPolicy::Evict),
+            _ => None,
+        }
+    }
+}
+
+/// How a slug already taken by another message is made unique.
+///
+/// # Variants
+/// - `IdSuffix`: The ID of the message is appended, as in `hello-world-42` (the default).
+/// - `CounterSuffix`: The first free number from `2` is appended, as in `hello-world-2`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SlugStrategy {
+    #[default]
+    IdSuffix,
+    CounterSuffix,
+}
+
+impl SlugStrategy {
+    /// Parses a strategy from its configuration name (`id-suffix` or `counter-suffix`).
+    ///
+    /// Returns `None` for unknown names.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "id-suffix" => Some(SlugStrategy::IdSuffix),
+            "counter-suffix" => Some(SlugStrategy::CounterSuffix),
+            _ => None,
+        }
+    }
+}
+
+/// The maximum number of characters of a slug before a suffix is added.
+const MAX_SLUG_CHARS: usize = 50;
+
+/// Returns the slug of a message with the given content, before collisions are resolved.
+///
+/// The first line is lowercased and every run of other characters than letters and digits
+/// becomes a single `-`, trimmed at both ends. Content without letters or digits gives `post`.
+///
+/// # Example
+/// ```rust
+/// use actix_posts::handler::data::slugify;
+///
+/// assert_eq!(slugify("Hello, World!\nSecond line"), "hello-world");
+/// assert_eq!(slugify("こんにちは 世界"), "こんにちは-世界");
+/// assert_eq!(slugify("!!!"), "post");
+/// ```
+pub fn slugify(content: &str) -> String {
+    let first_line = content.lines().next().unwrap_or_default();
+    let mut slug = String::new();
+    for c in first_line.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            if slug.chars().count() == MAX_SLUG_CHARS {
+                break;
+            }
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "post".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+/// Returns the slug of `message`, made unique among `taken` with the configured
+/// [`SlugStrategy`] (`ACTIX_POSTS_SLUG_STRATEGY`).
+fn unique_slug(message: &Message, taken: &HashSet<String>) -> String {
+    let base = slugify(&message.content);
+    if !taken.contains(&base) {
+        return base;
+    }
+    if config::get().slug_strategy == SlugStrategy::IdSuffix {
+        let slug = format!("{}-{}", base, message.id);
+        if !taken.contains(&slug) {
+            return slug;
+        }
+    }
+    // Also the fallback of `IdSuffix`, should a stored slug happen to end with the same ID.
+    (2..)
+        .map(|n| format!("{}-{}", base, n))
+        .find(|slug| !taken.contains(slug))
+        .unwrap_or(base)
+}
+
+/// Keeps the slug `message` already carries, as imported or restored messages do, unless it is
+/// empty or among `taken`; the message then receives a new one (see [`unique_slug`]).
+fn claim_slug(message: &mut Message, taken: &HashSet<String>) {
+    if message.slug.is_empty() || taken.contains(&message.slug) {
+        message.slug = unique_slug(message, taken);
+    }
+}
+
+/// Returns the slugs of `messages`, leaving out the message with ID `except`.
+fn slugs_except(messages: &[Message], except: i32) -> HashSet<String> {
+    messages
+        .iter()
+        .filter(|m| m.id != except && !m.slug.is_empty())
+        .map(|m| m.slug.clone())
+        .collect()
+}
+
+/// How [`update_many`] handles a batch in which some updates cannot be applied.
+///
+/// # Variants
+/// - `Strict`: Nothing is written unless every update can be applied (the default).
+/// - `Lenient`: The updates that can be applied are written; the others are reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BatchMode {
+    #[default]
+    Strict,
+    Lenient,
+}
+
+impl BatchMode {
+    /// Parses a mode from its configuration name (`strict` or `lenient`).
+    ///
+    /// Returns `None` for unknown names.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "strict" => Some(BatchMode::Strict),
+            "lenient" => Some(BatchMode::Lenient),
+            _ => None,
+        }
+    }
+}
+
+/// The fields of a message changed by one update of [`update_many`]; absent fields are left
+/// untouched.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct MessageChanges {
+    pub sender: Option<String>,
+    pub content: Option<String>,
+    pub attachments: Option<Vec<String>>,
+    pub tags: Option<Vec<String>>,
+    pub status: Option<PostStatus>,
+}
+
+impl MessageChanges {
+    /// Returns `true` when the changes edit the message itself, as opposed to only its tags or
+    /// status. Only edits bump `updated` and are subject to the edit window.
+    pub fn edits_message(&self) -> bool {
+        self.sender.is_some() || self.content.is_some() || self.attachments.is_some()
+    }
+
+    /// Returns `true` when the changes touch `field` (e.g. `"sender"`).
+    pub fn touches(&self, field: &str) -> bool {
+        match field {
+            "sender" => self.sender.is_some(),
+            "content" => self.content.is_some(),
+            "attachments" => self.attachments.is_some(),
+            "tags" => self.tags.is_some(),
+            "status" => self.status.is_some(),
+            _ => false,
+        }
+    }
+
+    /// Copies the changed fields into `message`. Tags are normalized; nothing else is checked.
+    pub fn apply_to(&self, message: &mut Message) {
+        if let Some(sender) = &self.sender {
+            message.sender = sender.clone();
+        }
+        if let Some(content) = &self.content {
+            message.content = content.clone();
+        }
+        if let Some(attachments) = &self.attachments {
+            message.attachments = attachments.clone();
+        }
+        if let Some(tags) = &self.tags {
+            message.tags = validation::normalize_tags(tags);
+        }
+        if let Some(status) = self.status {
+            message.status = status;
+        }
+    }
+}
+
+/// One update of [`update_many`]: the ID of a message and the changes to apply to it.
+#[derive(Deserialize, Debug, Clone)]
+pub struct BatchUpdate {
+    pub id: i32,
+    pub changes: MessageChanges,
+}
+
+/// The result of one update of [`update_many`].
+///
+/// # Variants
+/// - `Updated(message)`: The update was written; holds the stored message.
+/// - `NotFound`: No message with that ID exists in the primary data file.
+/// - `Failed(error)`: The update cannot be applied, e.g. [`DataError::EditWindowClosed`] or
+///   [`DataError::InvalidTransition`].
+/// - `Skipped`: The update could be applied, but was not written because another one failed in
+///   [`BatchMode::Strict`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum BatchOutcome {
+    Updated(Box<Message>),
+    NotFound,
+    Failed(DataError),
+    Skipped,
+}
+
+/// The order in which messages are listed.
+///
+/// # Variants
+/// - `Newest`: Most recently posted messages first (the default).
+/// - `Oldest`: Earliest posted messages first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    #[default]
+    Newest,
+    Oldest,
+}
+
+impl SortOrder {
+    /// Parses a sort order from its query-string name (`newest` or `oldest`).
+    ///
+    /// Returns `None` for unknown names.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "newest" => Some(SortOrder::Newest),
+            "oldest" => Some(SortOrder::Oldest),
+            _ => None,
+        }
+    }
+
+    /// Returns the query-string name of the sort order.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortOrder::Newest => "newest",
+            SortOrder::Oldest => "oldest",
+        }
+    }
+
+    /// Returns the keys messages are sorted by, primary key first, each followed by its
+    /// direction (e.g. `"posted desc"`).
+    pub fn keys(&self) -> [&'static str; 2] {
+        match self {
+            SortOrder::Newest => ["posted desc", "id desc"],
+            SortOrder::Oldest => ["posted asc", "id asc"],
+        }
+    }
+}
+
+/// The workflow status of a message.
+///
+/// # Variants
+/// - `Open`: The message awaits an answer (the default).
+/// - `Closed`: The message was dealt with; it stays listed.
+/// - `Archived`: The message is hidden from listings unless asked for (see [`StatusFilter`]).
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PostStatus {
+    #[default]
+    Open,
+    Closed,
+    Archived,
+}
+
+impl PostStatus {
+    /// Parses a status from its name (`open`, `closed`, or `archived`).
+    ///
+    /// Returns `None` for unknown names.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "open" => Some(PostStatus::Open),
+            "closed" => Some(PostStatus::Closed),
+            "archived" => Some(PostStatus::Archived),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the status.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PostStatus::Open => "open",
+            PostStatus::Closed => "closed",
+            PostStatus::Archived => "archived",
+        }
+    }
+
+    /// Returns `true` when a message may go from this status to `next`.
+    ///
+    /// Every transition is allowed except leaving `Archived`, which needs `reopen`.
+    ///
+    /// # Example
+    /// ```rust
+    /// use actix_posts::handler::data::PostStatus;
+    /// assert!(PostStatus::Open.can_become(PostStatus::Archived, false));
+    /// assert!(!PostStatus::Archived.can_become(PostStatus::Open, false));
+    /// assert!(PostStatus::Archived.can_become(PostStatus::Open, true));
+    /// ```
+    pub fn can_become(self, next: PostStatus, reopen: bool) -> bool {
+        self == next || self != PostStatus::Archived || reopen
+    }
+}
+
+/// Selects the messages of a listing by status.
+///
+/// # Variants
+/// - `Active`: Open and closed messages, i.e. everything but archived ones (the default).
+/// - `Only(status)`: The messages with that status.
+/// - `All`: Every message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StatusFilter {
+    #[default]
+    Active,
+    Only(PostStatus),
+    All,
+}
+
+impl StatusFilter {
+    /// Parses a filter from its query-string name: a status name or `all`.
+    ///
+    /// Returns `None` for unknown names.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "all" => Some(StatusFilter::All),
+            status => PostStatus::parse(status).map(StatusFilter::Only),
+        }
+    }
+
+    /// Returns the query-string name of the filter (`active` for the default).
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StatusFilter::Active => "active",
+            StatusFilter::Only(status) => status.as_str(),
+            StatusFilter::All => "all",
+        }
+    }
+
+    /// Returns `true` when `message` is selected.
+    pub fn matches(&self, message: &Message) -> bool {
+        match self {
+            StatusFilter::Active => message.status != PostStatus::Archived,
+            StatusFilter::Only(status) => message.status == *status,
+            StatusFilter::All => true,
+        }
+    }
+
+    /// Keeps the selected messages, sharing `messages` when every one of them is selected.
+    pub fn apply(&self, messages: Arc<Vec<Message>>) -> Arc<Vec<Message>> {
+        if messages.iter().all(|message| self.matches(message)) {
+            return messages;
+        }
+        Arc::new(
+            messages
+                .iter()
+                .filter(|message| self.matches(message))
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+/// How imported messages are combined with the stored ones.
+///
+/// # Variants
+/// - `Append`: Imported messages are added next to the stored ones and always receive new IDs.
+/// - `Replace`: Stored messages of the primary data file are discarded and replaced by the
+///   imported ones, which keep their IDs when those are positive and unused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImportMode {
+    #[default]
+    Append,
+    Replace,
+}
+
+impl ImportMode {
+    /// Parses an import mode from its query-string name (`append` or `replace`).
+    ///
+    /// Returns `None` for unknown names.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "append" => Some(ImportMode::Append),
+            "replace" => Some(ImportMode::Replace),
+            _ => None,
+        }
+    }
+}
+
+/// A distinct sender and the number of messages they posted.
+///
+/// # Fields
+/// - `sender`: The sender, as stored.
+/// - `count`: The number of their messages. Omitted from responses that only list names.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SenderCount {
+    pub sender: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<usize>,
+}
+
+/// Reports how much disk space the store uses.
+///
+/// # Fields
+/// - `files`: The size of every data file, primary file first.
+/// - `bytes`: The total size of the data files.
+/// - `count`: The number of stored messages (see [`TimeRange::count`]).
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct StorageStats {
+    pub files: Vec<FileSize>,
+    pub bytes: u64,
+    pub count: usize,
+}
+
+/// The size of one data file, `0` when it does not exist.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct FileSize {
+    pub path: String,
+    pub bytes: u64,
+}
+
+/// A message exactly as it is persisted, for operators inspecting data issues.
+///
+/// # Fields
+/// - `file`: The data file the message is read from (the first file holding its ID).
+/// - `stored`: The JSON object from that file, untouched: fields unknown to [`Message`] are kept
+///   and missing ones are not filled in with defaults.
+/// - `history`: The revisions recorded in the history file, oldest first. Unlike [`revisions`],
+///   nothing is made up for messages that were never edited.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct StoredMessage {
+    pub file: String,
+    pub stored: serde_json::Value,
+    pub history: Vec<Revision>,
+}
+
+/// Selects the messages affected by a bulk operation such as [`bulk_tag`].
+///
+/// A message matches when it satisfies every criterion that is set; a filter without criteria
+/// matches every message.
+///
+/// # Fields
+/// - `sender`: The exact sender.
+/// - `from`, `to`: The first and last day of `posted` (inclusive, UTC). Messages whose `posted`
+///   cannot be parsed never match a date bound.
+/// - `ids`: The IDs of the messages.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct MessageFilter {
+    pub sender: Option<String>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub ids: Option<Vec<i32>>,
+}
+
+impl MessageFilter {
+    /// Returns `true` when no criterion is set.
+    pub fn is_empty(&self) -> bool {
+        self.sender.is_none() && self.from.is_none() && self.to.is_none() && self.ids.is_none()
+    }
+
+    /// Returns `true` when `message` satisfies every criterion.
+    pub fn matches(&self, message: &Message) -> bool {
+        if self
+            .sender
+            .as_ref()
+            .is_some_and(|sender| *sender != message.sender)
+        {
+            return false;
+        }
+        if self
+            .ids
+            .as_ref()
+            .is_some_and(|ids| !ids.contains(&message.id))
+        {
+            return false;
+        }
+        if self.from.is_some() || self.to.is_some() {
+            let Some(day) = message.posted_day() else {
+                return false;
+            };
+            let days = self.from.unwrap_or(NaiveDate::MIN)..=self.to.unwrap_or(NaiveDate::MAX);
+            return days.contains(&day);
+        }
+        true
+    }
+}
+
+/// A record of the primary data file that [`repair`] dropped.
+///
+/// # Fields
+/// - `index`: The position of the record in the stored array, starting at `0`.
+/// - `reason`: Why it was dropped.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct DroppedRecord {
+    pub index: usize,
+    pub reason: String,
+}
+
+/// A message that [`repair`] gave a new ID.
+///
+/// # Fields
+/// - `index`: The position of the record in the stored array, starting at `0`.
+/// - `from`: The ID it was stored with.
+/// - `to`: Its new ID.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RenumberedRecord {
+    pub index: usize,
+    pub from: i32,
+    pub to: i32,
+}
+
+/// Describes what [`repair`] fixed in the primary data file.
+///
+/// # Fields
+/// - `kept`: The number of messages in the repaired file.
+/// - `dropped`: The records that were removed, in file order.
+/// - `renumbered`: The messages that received a new ID, in file order.
+/// - `truncated`: Whether the file ended in invalid JSON, everything after the last complete
+///   record being lost.
+/// - `dry_run`: Whether the repair was only simulated.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct RepairReport {
+    pub kept: usize,
+    pub dropped: Vec<DroppedRecord>,
+    pub renumbered: Vec<RenumberedRecord>,
+    pub truncated: bool,
+    pub dry_run: bool,
+}
+
+/// An imported message that [`import`] refused.
+///
+/// # Fields
+/// - `index`: The position of the message in the import, starting at `0`.
+/// - `field`: The field that broke the rule (`content` for a duplicate, `sender` for the quota).
+/// - `reason`: Why it was refused.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RejectedRecord {
+    pub index: usize,
+    pub field: String,
+    pub reason: String,
+}
+
+/// Describes the effect of an import on the store.
+///
+/// # Fields
+/// - `removed`: The IDs of the stored messages discarded by the import.
+/// - `created`: The IDs given to the imported messages, in input order.
+/// - `rejected`: The messages that broke a rule of the store, in input order. When there are any,
+///   nothing is imported and `removed` and `created` are empty.
+/// - `dry_run`: Whether the import was only simulated.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct ImportReport {
+    /// The IDs of the stored messages discarded by the import.
+    pub removed: Vec<i32>,
+
+    /// The IDs given to the imported messages.
+    pub created: Vec<i32>,
+
+    /// The messages that were refused.
+    pub rejected: Vec<RejectedRecord>,
+
+    /// Whether the import was only simulated.
+    pub dry_run: bool,
+}
+
+/// Summarizes the time span covered by the stored messages.
+///
+/// # Fields
+/// - `oldest`: The earliest `posted` timestamp, or `None` when the store is empty.
+/// - `newest`: The latest `posted` timestamp, or `None` when the store is empty.
+/// - `count`: The total number of stored messages.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct TimeRange {
+    /// The earliest `posted` timestamp.
+    pub oldest: Option<String>,
+
+    /// The latest `posted` timestamp.
+    pub newest: Option<String>,
+
+    /// The number of stored messages.
+    pub count: usize,
+}
+
+/// The number of messages posted on one calendar day.
+///
+/// # Fields
+/// - `date`: The day, serialized as `YYYY-MM-DD`.
+/// - `count`: The number of messages posted that day.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct DailyCount {
+    #[serde(serialize_with = "serialize_date")]
+    pub date: NaiveDate,
+    pub count: usize,
+}
+
+fn serialize_date<S: serde::Serializer>(
+    date: &NaiveDate,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&date.format(DATE_FORMAT))
 }
 
 /// Reads a JSON file and deserializes its content into a `Vec<Message>`.
@@ -57,113 +1081,1353 @@ pub struct Message {
 /// }
 /// ```
 ///
-/// # Dependencies
-/// - Uses the standard library's [`std::fs::read_to_string`] for reading file content.
-/// - Requires the `serde_json` crate to deserialize JSON into the `Vec<Message>` type.
-pub fn read_messages_from_file(filename: &str) -> Vec<Message> {
-    std::fs::read_to_string(filename)
-        .ok()
-        .and_then(|data| serde_json::from_str::<Vec<Message>>(&data).ok())
-        .unwrap_or_default()
+/// # Dependencies
+/// - Uses the standard library's [`std::fs::read_to_string`] for reading file content.
+/// - Requires the `serde_json` crate to deserialize JSON into the `Vec<Message>` type.
+pub fn read_messages_from_file(filename: &str) -> Vec<Message> {
+    std::fs::read_to_string(filename)
+        .ok()
+        .and_then(|data| serde_json::from_str::<Vec<Message>>(&data).ok())
+        .unwrap_or_default()
+}
+
+/// Reads several JSON files and merges their messages into a single `Vec<Message>`.
+///
+/// Files are read in order with [`read_messages_from_file`], so a missing or invalid file simply
+/// contributes no messages. When the same `id` appears in more than one file, the message from the
+/// earliest file wins; this lets a live (primary) file shadow older copies kept in archives.
+///
+/// # Arguments
+/// - `filenames`: The paths of the files to read, primary file first.
+///
+/// # Returns
+/// The merged messages, deduplicated by `id`, in file order.
+///
+/// # Example
+/// ```rust
+/// use crate::actix_posts::handler::data::read_messages_from_files;
+/// let messages = read_messages_from_files(&["data.json", "archive.json"]);
+/// println!("Found {} messages.", messages.len());
+/// ```
+pub fn read_messages_from_files<S: AsRef<str>>(filenames: &[S]) -> Vec<Message> {
+    let mut seen = HashSet::new();
+    filenames
+        .iter()
+        .flat_map(|filename| read_messages_from_file(filename.as_ref()))
+        .filter(|message| seen.insert(message.id))
+        .collect()
+}
+
+/// Reads the records of a JSON array one at a time, keeping those before the first syntax error.
+///
+/// # Returns
+/// The records that could be parsed, and `true` when the text is not a complete array (e.g. a
+/// file cut short by a crash). Text that does not start with `[` has no records.
+///
+/// # Example
+/// ```rust
+/// use actix_posts::handler::data::read_records;
+/// let (records, truncated) = read_records(r#"[{"id": 1}, {"id": 2}, {"id""#);
+/// assert_eq!(records.len(), 2);
+/// assert!(truncated);
+/// ```
+pub fn read_records(text: &str) -> (Vec<serde_json::Value>, bool) {
+    let Some(mut rest) = text.trim_start().strip_prefix('[') else {
+        return (vec![], !text.trim().is_empty());
+    };
+    let mut records = vec![];
+    loop {
+        rest = rest.trim_start();
+        if let Some(after) = rest.strip_prefix(']') {
+            return (records, !after.trim().is_empty());
+        }
+        if !records.is_empty() {
+            match rest.strip_prefix(',') {
+                Some(after) => rest = after,
+                None => return (records, true),
+            }
+        }
+        let mut values = serde_json::Deserializer::from_str(rest).into_iter();
+        match values.next() {
+            Some(Ok(value)) => {
+                records.push(value);
+                rest = &rest[values.byte_offset()..];
+            }
+            _ => return (records, true),
+        }
+    }
+}
+
+/// Rewrites the primary data file without the problems left by manual edits or a partial write.
+///
+/// # Arguments
+/// - `renumber`: When `true`, a message whose ID is already used by an earlier record (or by an
+///   archive file) or is not positive receives a new ID. When `false`, it is dropped.
+/// - `dry_run`: When `true`, the report is computed but the store is left untouched.
+///
+/// # Behavior
+/// 1. Reads the records of the file with [`read_records`], so the complete records before a
+///    syntax error survive.
+/// 2. Drops the records that are not valid messages (e.g. not an object, or a non-numeric `id`).
+/// 3. Renumbers or drops the duplicate IDs, keeping the first record of each ID. New IDs follow
+///    the highest ID in every data file.
+/// 4. Replaces the file atomically when anything was fixed; the read caches and ID index are
+///    rebuilt on the next read. Renumbered messages are journaled as created (see
+///    [`changes_since`]).
+///
+/// # Returns
+/// - `Ok(report)`: A [`RepairReport`] of the fixes.
+/// - `Err(DataError::IdsExhausted)` if the new IDs would exceed `i32::MAX`.
+/// - `Err(DataError::ReadOnly)` if the primary data file cannot be written.
+pub fn repair(renumber: bool, dry_run: bool) -> Result<RepairReport, DataError> {
+    with_write_lock(|| {
+        let text = std::fs::read_to_string(primary_filename()).unwrap_or_default();
+        let (records, truncated) = read_records(&text);
+        let mut report = RepairReport {
+            truncated,
+            dry_run,
+            ..Default::default()
+        };
+        let mut used: HashSet<i32> = read_stored_files(&config::get().data_files[1..])
+            .iter()
+            .map(|m| m.id)
+            .collect();
+        let mut messages = vec![];
+        let mut duplicates = vec![];
+        for (index, record) in records.into_iter().enumerate() {
+            if !record.is_object() {
+                report.dropped.push(DroppedRecord {
+                    index,
+                    reason: "not an object".to_string(),
+                });
+                continue;
+            }
+            match serde_json::from_value::<Message>(record) {
+                Ok(message) if message.id > 0 && used.insert(message.id) => messages.push(message),
+                Ok(message) if renumber => {
+                    duplicates.push((index, messages.len()));
+                    messages.push(message);
+                }
+                Ok(message) => report.dropped.push(DroppedRecord {
+                    index,
+                    reason: format!("duplicate or invalid id {}", message.id),
+                }),
+                Err(error) => report.dropped.push(DroppedRecord {
+                    index,
+                    reason: error.to_string(),
+                }),
+            }
+        }
+        let max = used.iter().max().copied().unwrap_or_default();
+        let mut next = next_ids(max, duplicates.len())?..;
+        for (index, position) in duplicates {
+            let to = next.next().unwrap_or_default();
+            report.renumbered.push(RenumberedRecord {
+                index,
+                from: messages[position].id,
+                to,
+            });
+            messages[position].id = to;
+        }
+        report.kept = messages.len();
+        let fixed = truncated || !report.dropped.is_empty() || !report.renumbered.is_empty();
+        if !dry_run && fixed {
+            write_primary(&messages)?;
+            let created: Vec<i32> = report.renumbered.iter().map(|r| r.to).collect();
+            record_changes(ChangeKind::Created, &created);
+        }
+        Ok(report)
+    })
+}
+
+/// Returns the data file that receives every write: the first configured data file.
+fn primary_filename() -> &'static str {
+    &config::get().data_files[0]
+}
+
+/// Reads the messages of every configured data file (see [`read_stored_files`]).
+fn read_all() -> Vec<Message> {
+    read_stored_files(&config::get().data_files)
+}
+
+/// The data files held in memory when `ACTIX_POSTS_IN_MEMORY` is enabled, by file name.
+static MEMORY: Mutex<Option<HashMap<String, Vec<Message>>>> = Mutex::new(None);
+
+/// Reads the messages of a data file: from memory when `ACTIX_POSTS_IN_MEMORY` is enabled, from
+/// disk otherwise (see [`read_messages_from_file`]).
+///
+/// In memory mode, a file is read from disk only once, the first time it is needed (normally by
+/// [`load_into_memory`] at startup). Later changes to the file made outside the server are not
+/// seen until [`reload`].
+fn read_stored(filename: &str) -> Vec<Message> {
+    if !config::get().in_memory {
+        return read_messages_from_file(filename);
+    }
+    let mut memory = MEMORY.lock().unwrap_or_else(PoisonError::into_inner);
+    memory
+        .get_or_insert_with(HashMap::new)
+        .entry(filename.to_string())
+        .or_insert_with(|| read_messages_from_file(filename))
+        .clone()
+}
+
+/// Merges several data files like [`read_messages_from_files`], reading each with
+/// [`read_stored`].
+fn read_stored_files(filenames: &[String]) -> Vec<Message> {
+    let mut seen = HashSet::new();
+    filenames
+        .iter()
+        .flat_map(|filename| read_stored(filename))
+        .filter(|message| seen.insert(message.id))
+        .collect()
+}
+
+/// Loads every configured data file into memory when `ACTIX_POSTS_IN_MEMORY` is enabled, so no
+/// request has to read them from disk. Called at startup.
+///
+/// # Returns
+/// The number of messages loaded, or `None` when memory mode is disabled.
+pub fn load_into_memory() -> Option<usize> {
+    config::get()
+        .in_memory
+        .then(|| get_all_shared(SortOrder::Oldest).len())
+}
+
+/// Reads every data file from disk again, for changes made outside the server.
+///
+/// In memory mode this is the only way such changes are picked up. Otherwise the files are
+/// read again whenever they change anyway, and this only forces it. The response caches are
+/// invalidated in both cases.
+///
+/// # Returns
+/// The number of messages read.
+pub fn reload() -> usize {
+    {
+        let _lock = write_lock();
+        *MEMORY.lock().unwrap_or_else(PoisonError::into_inner) = None;
+        GENERATION.fetch_add(1, Ordering::AcqRel);
+    }
+    get_all_shared(SortOrder::Oldest).len()
+}
+
+/// Forgets everything read or learned about the data files, for tests that switch to other
+/// files: the memory copies, the snapshot, the view counts, the messages kept for [`restore`],
+/// and read-only mode. Cached responses are dropped too, since the generation moves on.
+#[cfg(test)]
+pub(crate) fn reset() {
+    let _lock = write_lock();
+    *MEMORY.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    *SNAPSHOT.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    DELETED
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clear();
+    *VIEWS.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    READ_ONLY.store(false, Ordering::Release);
+    GENERATION.fetch_add(1, Ordering::AcqRel);
+}
+
+/// Identifies the state of the data files a [`Snapshot`] was read from: the write generation and
+/// the modification time and size of every file, so changes made outside the server are noticed
+/// too. In memory mode, only the generation counts, so reads never touch the disk.
+type SnapshotKey = (u64, Vec<Option<(SystemTime, u64)>>);
+
+/// Maps message IDs to their position in [`Snapshot::oldest`].
+type IdIndex = HashMap<i32, usize>;
+
+/// The merged messages of every data file, shared between readers.
+///
+/// The ID index is only built by the first lookup by ID (see [`get`]), so listings never pay
+/// for it.
+struct Snapshot {
+    key: SnapshotKey,
+    newest: Arc<Vec<Message>>,
+    oldest: Arc<Vec<Message>>,
+    index: Arc<OnceLock<IdIndex>>,
+}
+
+static SNAPSHOT: Mutex<Option<Snapshot>> = Mutex::new(None);
+
+fn snapshot_key() -> SnapshotKey {
+    if config::get().in_memory {
+        return (generation(), vec![]);
+    }
+    let files = config::get()
+        .data_files
+        .iter()
+        .map(|filename| {
+            std::fs::metadata(filename)
+                .and_then(|metadata| Ok((metadata.modified()?, metadata.len())))
+                .ok()
+        })
+        .collect();
+    (generation(), files)
+}
+
+/// Returns every message in the requested order without copying them.
+///
+/// The data files are only read again once they changed; until then every caller shares the
+/// same vector. Use [`get_all_sorted`] when an owned copy is needed.
+///
+/// # Arguments
+/// - `order`: The [`SortOrder`] of the returned messages (see [`get_all_sorted`]).
+pub fn get_all_shared(order: SortOrder) -> Arc<Vec<Message>> {
+    let messages = with_snapshot(|current| match order {
+        SortOrder::Newest => current.newest.clone(),
+        SortOrder::Oldest => current.oldest.clone(),
+    });
+    without_expired(messages)
+}
+
+/// Drops the expired messages (see [`Message::is_expired`]), copying the vector only when there
+/// are any.
+///
+/// Expiry depends on the current time, so it is applied on every read instead of when the
+/// snapshot is built.
+fn without_expired(messages: Arc<Vec<Message>>) -> Arc<Vec<Message>> {
+    if !messages.iter().any(Message::is_expired) {
+        return messages;
+    }
+    Arc::new(
+        messages
+            .iter()
+            .filter(|m| !m.is_expired())
+            .cloned()
+            .collect(),
+    )
+}
+
+/// Runs `read` on the current [`Snapshot`], reading the data files again first if they changed.
+fn with_snapshot<T>(read: impl FnOnce(&Snapshot) -> T) -> T {
+    let key = snapshot_key();
+    let mut snapshot = SNAPSHOT.lock().unwrap_or_else(PoisonError::into_inner);
+    let current = match snapshot.take() {
+        Some(current) if current.key == key => current,
+        _ => {
+            let mut oldest = read_all();
+            // Compare instants, since stored timestamps may be UTC or (older ones) local time.
+            oldest.sort_by_cached_key(|m| (m.posted_at(), m.id));
+            let newest = oldest.iter().rev().cloned().collect();
+            Snapshot {
+                key,
+                newest: Arc::new(newest),
+                oldest: Arc::new(oldest),
+                index: Arc::default(),
+            }
+        }
+    };
+    let result = read(&current);
+    *snapshot = Some(current);
+    result
+}
+
+/// Looks messages up by ID through the snapshot's index, building the index on first use.
+///
+/// Returns `None` without building the index when the store is empty (including when the data
+/// files are missing).
+fn lookup_by_id<T>(find: impl FnOnce(&[Message], &IdIndex) -> T) -> Option<T> {
+    let (messages, index) =
+        with_snapshot(|current| (current.oldest.clone(), current.index.clone()));
+    if messages.is_empty() {
+        return None;
+    }
+    let index = index.get_or_init(|| {
+        let mut index = IdIndex::with_capacity(messages.len());
+        for (position, message) in messages.iter().enumerate() {
+            index.entry(message.id).or_insert(position);
+        }
+        index
+    });
+    Some(find(&messages, index))
+}
+
+/// Serializes the read-modify-write cycles of the mutating functions, so concurrent requests
+/// cannot lose each other's changes or be allocated the same IDs.
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Acquires the global write lock. A panic in another writer does not poison the store, since
+/// every write replaces the whole file.
+fn write_lock() -> MutexGuard<'static, ()> {
+    WRITE_LOCK.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Counts the writes to the primary data file, and the reloads (see [`reload`]), since startup.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Returns a number that changes whenever the stored messages are written.
+///
+/// Caches record the generation a value was computed at and discard it once the generation
+/// moves on (see [`crate::handler::cache`]).
+pub fn generation() -> u64 {
+    GENERATION.load(Ordering::Acquire)
+}
+
+/// The time this process started serving, in milliseconds since the Unix epoch.
+static STARTED_MS: LazyLock<u128> = LazyLock::new(|| {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis()
+});
+
+/// Returns an opaque token that changes whenever the stored messages are written, for clients
+/// that need to notice when a listing changed between two page fetches.
+///
+/// [`generation`] restarts from `0` with the process, so the token also identifies the run: a
+/// token from before a restart never matches one issued after it.
+pub fn generation_token() -> String {
+    format!("{}-{}", *STARTED_MS, generation())
+}
+
+/// Set while the primary data file cannot be written.
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
+/// Returns `true` while the store is read-only because the last write to the primary data file
+/// failed.
+///
+/// The flag is cleared by the next successful write, or by [`probe_writable`].
+pub fn is_read_only() -> bool {
+    READ_ONLY.load(Ordering::Acquire)
+}
+
+/// Records the outcome of a write to the primary data file, logging mode changes.
+fn set_writable(writable: bool, error: Option<&dyn fmt::Display>) {
+    let was_read_only = READ_ONLY.swap(!writable, Ordering::AcqRel);
+    match (was_read_only, writable) {
+        (false, false) => log::error!(
+            "cannot write {}: {}; switching to read-only mode",
+            primary_filename(),
+            error.map(ToString::to_string).unwrap_or_default()
+        ),
+        (true, true) => log::info!("{} is writable again", primary_filename()),
+        _ => {}
+    }
+}
+
+/// Writes `contents` to a temporary file next to `path`, then renames it over `path`.
+///
+/// A failed write (e.g. on a full disk) leaves the previous file intact.
+fn write_replacing(path: &str, contents: &str) -> std::io::Result<()> {
+    #[cfg(test)]
+    if tests::take_injected_failure() {
+        return Err(std::io::ErrorKind::Interrupted.into());
+    }
+    let mut temporary = PathBuf::from(path).into_os_string();
+    temporary.push(".tmp");
+    std::fs::write(&temporary, contents)
+        .and_then(|()| std::fs::rename(&temporary, path))
+        .inspect_err(|_| {
+            let _ = std::fs::remove_file(&temporary);
+        })
+}
+
+/// Returns `true` for I/O errors that may not happen again when the operation is retried.
+fn is_transient(error: &std::io::Error) -> bool {
+    matches!(
+        error.kind(),
+        std::io::ErrorKind::Interrupted
+            | std::io::ErrorKind::WouldBlock
+            | std::io::ErrorKind::TimedOut
+    )
+}
+
+/// Writes a side file (the history, the change journal, or the view counts) like
+/// [`write_replacing`], retrying transient failures (see [`is_transient`]) up to
+/// `ACTIX_POSTS_WRITE_RETRIES` times.
+///
+/// Side files are written while the write lock is held, so the retries are made at once,
+/// without the pause [`with_retries`] makes for the primary data file.
+fn write_with_retry(path: &str, contents: &str) -> std::io::Result<()> {
+    let mut attempt = 0;
+    loop {
+        match write_replacing(path, contents) {
+            Err(error) if is_transient(&error) && attempt < config::get().write_retries => {
+                attempt += 1;
+                log::warn!("transient failure writing {}: {}; retrying", path, error);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Runs the read-modify-write `operation` under the write lock.
+///
+/// A transient failure to write the primary data file is returned as [`DataError::Transient`];
+/// [`with_retries`] runs the operation again.
+fn with_write_lock<T>(operation: impl FnOnce() -> Result<T, DataError>) -> Result<T, DataError> {
+    let _lock = write_lock();
+    operation()
+}
+
+/// Runs the store write `operation` (e.g. `|| data::create(message.clone())`), and runs it again
+/// when it fails with [`DataError::Transient`].
+///
+/// Up to `ACTIX_POSTS_WRITE_RETRIES` retries are made, after a doubling pause (see
+/// [`crate::config`]). The pause is awaited, so the worker serves other requests meanwhile, and
+/// the write lock is not held, so other writes go on: `operation` starts over from the stored
+/// messages, which those writes may have changed. Once retries are exhausted, the store switches
+/// to read-only mode and [`DataError::ReadOnly`] is returned.
+pub async fn with_retries<T>(
+    mut operation: impl FnMut() -> Result<T, DataError>,
+) -> Result<T, DataError> {
+    let config = config::get();
+    let mut delay = Duration::from_millis(config.write_retry_delay_ms);
+    let mut attempt = 0;
+    loop {
+        let error = match operation() {
+            Err(DataError::Transient(error)) => error,
+            result => return result,
+        };
+        if attempt == config.write_retries {
+            set_writable(false, Some(&error));
+            return Err(DataError::ReadOnly);
+        }
+        attempt += 1;
+        log::warn!(
+            "transient failure writing {}: {}; retry {} of {} in {:?}",
+            primary_filename(),
+            error,
+            attempt,
+            config.write_retries,
+            delay
+        );
+        actix_rt::time::sleep(delay).await;
+        delay *= 2;
+    }
+}
+
+/// Serializes `messages` and replaces the primary data file with them.
+///
+/// Must be called with the write lock held (see [`with_write_lock`]).
+///
+/// # Returns
+/// - `Ok(())` when the file was written.
+/// - `Err(DataError::Transient)` when the write failed transiently (see [`is_transient`]). The
+///   previous file is kept, and the write can be retried (see [`with_retries`]).
+/// - `Err(DataError::ReadOnly)` otherwise. The previous file is kept, and the store switches to
+///   read-only mode.
+fn write_primary(messages: &[Message]) -> Result<(), DataError> {
+    let contents = serde_json::to_string(messages).unwrap();
+    match write_replacing(primary_filename(), &contents) {
+        Ok(()) => {
+            if config::get().in_memory {
+                MEMORY
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .get_or_insert_with(HashMap::new)
+                    .insert(primary_filename().to_string(), messages.to_vec());
+            }
+            set_writable(true, None);
+            GENERATION.fetch_add(1, Ordering::AcqRel);
+            Ok(())
+        }
+        Err(error) if is_transient(&error) => Err(DataError::Transient(error.to_string())),
+        Err(error) => {
+            set_writable(false, Some(&error));
+            Err(DataError::ReadOnly)
+        }
+    }
+}
+
+/// Checks whether the primary data file can be written again by writing a scratch file next to
+/// it, and leaves read-only mode when it can.
+///
+/// The data file itself is not touched. Returns `true` when the store is writable.
+pub fn probe_writable() -> bool {
+    let _lock = write_lock();
+    let mut probe = PathBuf::from(primary_filename()).into_os_string();
+    probe.push(".probe");
+    let result = std::fs::write(&probe, b"probe").and_then(|()| std::fs::remove_file(&probe));
+    let error = result
+        .as_ref()
+        .err()
+        .map(|error| error as &dyn fmt::Display);
+    set_writable(result.is_ok(), error);
+    result.is_ok()
+}
+
+/// Reads the stored revisions. A missing or invalid history file holds no revisions.
+fn read_history() -> Vec<Revision> {
+    std::fs::read_to_string(&config::get().history_file)
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+/// Replaces the history file. Failures are logged: losing a revision must not fail the write
+/// that produced it.
+fn write_history(revisions: &[Revision]) {
+    let path = &config::get().history_file;
+    if let Err(error) = write_with_retry(path, &serde_json::to_string(revisions).unwrap()) {
+        log::error!("cannot write {}: {}", path, error);
+    }
+}
+
+/// Records a content change of a stored message as a new revision.
+///
+/// The first change also records the previous content as revision `1`. Must be called with the
+/// write lock held.
+fn record_revision(stored: &Message, updated: &Message) {
+    let mut history = read_history();
+    let mut latest = history
+        .iter()
+        .filter(|r| r.id == stored.id)
+        .map(|r| r.revision)
+        .max()
+        .unwrap_or_default();
+    if latest == 0 {
+        latest = 1;
+        history.push(Revision {
+            id: stored.id,
+            revision: latest,
+            content: stored.content.clone(),
+            recorded: if stored.updated.is_empty() {
+                stored.posted.clone()
+            } else {
+                stored.updated.clone()
+            },
+        });
+    }
+    history.push(Revision {
+        id: updated.id,
+        revision: latest + 1,
+        content: updated.content.clone(),
+        recorded: updated.updated.clone(),
+    });
+    write_history(&history);
+}
+
+/// Reads the change journal. A missing or invalid file holds no changes.
+fn read_changes() -> Vec<Change> {
+    std::fs::read_to_string(&config::get().changes_file)
+        .ok()
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+/// Appends one entry per ID to the change journal, keeping its last [`MAX_CHANGES`] entries,
+/// and publishes deletions to live event streams (see [`crate::handler::events`]).
+///
+/// Must be called with the write lock held, after the change was written. Failures are logged,
+/// like those of the history file.
+fn record_changes(kind: ChangeKind, ids: &[i32]) {
+    if ids.is_empty() {
+        return;
+    }
+    let mut journal = read_changes();
+    let mut seq = journal.last().map(|change| change.seq).unwrap_or_default();
+    let at = current_timestamp();
+    let recorded: Vec<Change> = ids
+        .iter()
+        .map(|&id| {
+            seq += 1;
+            Change {
+                seq,
+                kind,
+                id,
+                at: at.clone(),
+            }
+        })
+        .collect();
+    journal.extend(recorded.iter().cloned());
+    let excess = journal.len().saturating_sub(MAX_CHANGES);
+    journal.drain(..excess);
+    let path = &config::get().changes_file;
+    if let Err(error) = write_with_retry(path, &serde_json::to_string(&journal).unwrap()) {
+        log::error!("cannot write {}: {}", path, error);
+    }
+    if kind == ChangeKind::Deleted {
+        recorded.into_iter().for_each(events::publish_deleted);
+    }
+}
+
+/// Returns the changes recorded after the journal entry `since`, oldest first.
+///
+/// Clients mirroring the store read every message once, then follow the journal from the `seq`
+/// of the last change they applied (`0` to start at the beginning).
+///
+/// Messages hidden by their expiry (see [`Message::is_expired`]) are not journaled, and neither
+/// are changes made to the data files outside the server.
+///
+/// # Returns
+/// - `Some(changes)` with the changes after `since`, empty when there are none.
+/// - `None` when the journal can no longer answer from `since`: the entries following it were
+///   dropped (see [`MAX_CHANGES`]), or `since` is beyond the last entry because the journal was
+///   reset. The client has to read the whole store again.
+pub fn changes_since(since: u64) -> Option<Vec<Change>> {
+    let journal = read_changes();
+    let first = journal.first().map_or(1, |change| change.seq);
+    let last = journal.last().map_or(0, |change| change.seq);
+    if since + 1 < first || since > last {
+        return None;
+    }
+    Some(
+        journal
+            .into_iter()
+            .filter(|change| change.seq > since)
+            .collect(),
+    )
+}
+
+/// Drops the revisions and view counts of removed messages, so a reused ID does not inherit
+/// them. Must be called with the write lock held.
+fn forget_history(ids: &[i32]) {
+    let mut history = read_history();
+    let count = history.len();
+    history.retain(|r| !ids.contains(&r.id));
+    if history.len() != count {
+        write_history(&history);
+    }
+    let removed = with_views(|views| {
+        let count = views.counts.len();
+        views.counts.retain(|id, _| !ids.contains(id));
+        views.dirty |= views.counts.len() != count;
+        views.dirty
+    });
+    if removed {
+        flush_views();
+    }
+}
+
+/// The view counts of the post pages, by message ID, and whether they changed since they were
+/// last written to the views file.
+#[derive(Default)]
+struct ViewCounts {
+    counts: HashMap<i32, u64>,
+    dirty: bool,
+}
+
+/// The view counts, loaded from the views file on first use (see [`with_views`]). Kept apart from
+/// the store, so counting a view takes neither the write lock nor a file write.
+static VIEWS: Mutex<Option<ViewCounts>> = Mutex::new(None);
+
+/// Serializes the writes of the views file by [`flush_views`].
+static VIEWS_FILE_LOCK: Mutex<()> = Mutex::new(());
+
+/// How often [`flush_views_periodically`] writes the view counts to the views file.
+pub const VIEWS_FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Runs `f` on the view counts, loading them first if needed. A missing or invalid views file
+/// holds no views.
+fn with_views<R>(f: impl FnOnce(&mut ViewCounts) -> R) -> R {
+    let mut views = VIEWS.lock().unwrap_or_else(PoisonError::into_inner);
+    let views = views.get_or_insert_with(|| ViewCounts {
+        counts: std::fs::read_to_string(&config::get().views_file)
+            .ok()
+            .and_then(|data| serde_json::from_str(&data).ok())
+            .unwrap_or_default(),
+        dirty: false,
+    });
+    f(views)
+}
+
+/// Returns the number of times each post page was viewed, by message ID, including the views not
+/// written to the views file yet.
+pub fn view_counts() -> HashMap<i32, u64> {
+    with_views(|views| views.counts.clone())
+}
+
+/// Counts one view of the message `id` in memory; [`flush_views`] writes the counts out later.
+///
+/// Nothing is counted in maintenance or read-only mode, when the board must not change its files.
+pub fn record_view(id: i32) {
+    if config::get().maintenance || is_read_only() {
+        return;
+    }
+    with_views(|views| {
+        *views.counts.entry(id).or_default() += 1;
+        views.dirty = true;
+    });
+}
+
+/// Writes the view counts to the views file if they changed since the last write. Failures are
+/// logged, like those of the history file, and the counts are written again on the next call.
+pub fn flush_views() {
+    let _lock = VIEWS_FILE_LOCK
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    let Some(contents) = with_views(|views| {
+        std::mem::take(&mut views.dirty).then(|| serde_json::to_string(&views.counts).unwrap())
+    }) else {
+        return;
+    };
+    let path = &config::get().views_file;
+    if let Err(error) = write_with_retry(path, &contents) {
+        log::error!("cannot write {}: {}", path, error);
+        with_views(|views| views.dirty = true);
+    }
+}
+
+/// Writes the view counts every [`VIEWS_FLUSH_INTERVAL`] (see [`flush_views`]).
+///
+/// Runs until the server stops; spawn it once at startup, and call [`flush_views`] on shutdown.
+pub async fn flush_views_periodically() {
+    let mut interval = actix_rt::time::interval(VIEWS_FLUSH_INTERVAL);
+    loop {
+        interval.tick().await;
+        flush_views();
+    }
+}
+
+/// Retrieves all messages from the data file and sorts them by the posted timestamp in descending order.
+///
+/// This function reads the messages stored in the configured data files,
+/// deserializes them into a vector of `Message` structs, and then sorts the messages
+/// by their `posted` timestamp (most recent messages first).
+///
+/// # Returns
+/// A vector of `Message` structs sorted in descending order by their `posted` timestamp.
+///
+/// # Behavior
+/// - If the data file cannot be read or contains invalid JSON, it will return an empty vector.
+/// - The returned vector is a copy of the shared messages, see [`get_all_shared`].
+///
+/// # Dependencies
+/// - Relies on `get_all_shared` to merge and sort the configured data files.
+///
+/// # Notes
+/// The returned order ensures that the most recent message (based on the `posted` timestamp)
+/// is at the beginning of the vector.
+pub fn get_all() -> Vec<Message> {
+    get_all_sorted(SortOrder::Newest)
+}
+
+/// Retrieves all messages from the data file in the requested order.
+///
+/// # Arguments
+/// - `order`: The [`SortOrder`] to apply to the `posted` timestamps.
+///
+/// # Returns
+/// A vector of `Message` structs sorted according to `order`. If the data file cannot be read
+/// or contains invalid JSON, it will return an empty vector. This is an owned copy of
+/// [`get_all_shared`].
+///
+/// # Behavior
+/// Messages with identical `posted` timestamps are ordered by `id`, descending for
+/// [`SortOrder::Newest`] and ascending for [`SortOrder::Oldest`], so the order is deterministic
+/// and one order is always the exact reverse of the other (see [`SortOrder::keys`]).
+pub fn get_all_sorted(order: SortOrder) -> Vec<Message> {
+    get_all_shared(order).as_ref().clone()
+}
+
+/// Retrieves the messages created after a given message.
+///
+/// # Arguments
+/// - `id`: The ID of the last message the caller has seen.
+///
+/// # Returns
+/// Every message with an ID greater than `id`, in ascending ID order.
+pub fn get_since(id: i32) -> Vec<Message> {
+    let mut messages: Vec<Message> = get_all_shared(SortOrder::Oldest)
+        .iter()
+        .filter(|m| m.id > id)
+        .cloned()
+        .collect();
+    messages.sort_by_key(|m| m.id);
+    messages
+}
+
+/// Retrieves a single message by its ID.
+///
+/// # Arguments
+/// - `id`: An integer representing the ID of the message to retrieve.
+///
+/// # Returns
+/// - `Some(Message)` with the stored message if found.
+/// - `None` if no message has that ID, the message has expired, or the store is empty.
+///
+/// # Behavior
+/// - Looks the ID up in an index of the shared messages (see [`get_all_shared`]), built on the
+///   first lookup after the data files change, and clones only the matching message.
+/// - An empty or missing store is answered without building the index.
+pub fn get(id: i32) -> Option<Message> {
+    lookup_by_id(|messages, index| index.get(&id).map(|&position| messages[position].clone()))
+        .flatten()
+        .filter(|message| !message.is_expired())
+}
+
+/// Retrieves the revisions of a message's content.
+///
+/// # Arguments
+/// - `id`: The ID of the message.
+///
+/// # Returns
+/// The revisions in ascending order. A message that was never edited has a single revision
+/// holding its current content; an unknown message has none.
+pub fn revisions(id: i32) -> Vec<Revision> {
+    let mut revisions: Vec<Revision> = read_history().into_iter().filter(|r| r.id == id).collect();
+    if revisions.is_empty() {
+        if let Some(message) = get_all_shared(SortOrder::Oldest)
+            .iter()
+            .find(|m| m.id == id)
+        {
+            revisions.push(Revision {
+                id,
+                revision: 1,
+                content: message.content.clone(),
+                recorded: message.posted.clone(),
+            });
+        }
+    }
+    revisions.sort_by_key(|r| r.revision);
+    revisions
+}
+
+/// Reads a message as persisted, bypassing [`Message`] (see [`StoredMessage`]).
+///
+/// Data files are searched in order, as [`read_messages_from_files`] does, and are read again
+/// on every call instead of going through the snapshot.
+///
+/// # Returns
+/// - `Some(stored)` with the raw message, its file, and its recorded revisions.
+/// - `None` if no data file holds a message with that ID.
+pub fn stored(id: i32) -> Option<StoredMessage> {
+    let (file, stored) = config::get().data_files.iter().find_map(|filename| {
+        let data = std::fs::read_to_string(filename).ok()?;
+        let values: Vec<serde_json::Value> = serde_json::from_str(&data).ok()?;
+        let stored = values
+            .into_iter()
+            .find(|value| value.get("id").and_then(serde_json::Value::as_i64) == Some(id.into()))?;
+        Some((filename.clone(), stored))
+    })?;
+    let mut history: Vec<Revision> = read_history().into_iter().filter(|r| r.id == id).collect();
+    history.sort_by_key(|r| r.revision);
+    Some(StoredMessage {
+        file,
+        stored,
+        history,
+    })
 }
 
-/// Retrieves all messages from the data file and sorts them by the posted timestamp in descending order.
+/// Retrieves several messages by their IDs.
 ///
-/// This function reads the messages stored in the file specified by `DATA_FILENAME`,
-/// deserializes them into a vector of `Message` structs, and then sorts the messages
-/// by their `posted` timestamp (most recent messages first).
+/// # Arguments
+/// - `ids`: The IDs to look up. Duplicates are allowed.
 ///
 /// # Returns
-/// A vector of `Message` structs sorted in descending order by their `posted` timestamp.
+/// One entry per requested ID, in request order: `Some(message)` when it exists, `None`
+/// otherwise.
+pub fn get_many(ids: &[i32]) -> Vec<Option<Message>> {
+    lookup_by_id(|messages, index| {
+        ids.iter()
+            .map(|id| {
+                index
+                    .get(id)
+                    .map(|&position| messages[position].clone())
+                    .filter(|message| !message.is_expired())
+            })
+            .collect()
+    })
+    .unwrap_or_else(|| vec![None; ids.len()])
+}
+
+/// Retrieves the chronologically earliest message.
 ///
-/// # Behavior
-/// - If the data file cannot be read or contains invalid JSON, it will return an empty vector.
-/// - Sorting is done in-place on the vector before returning.
+/// Messages are compared by their `posted` timestamp; ties are broken by the lower `id`.
 ///
-/// # Dependencies
-/// - Relies on `read_messages_from_file` to read and deserialize the data.
-/// - Relies on `DATA_FILENAME` for the file path.
+/// # Returns
+/// - `Some(Message)` with the earliest message.
+/// - `None` when the store is empty.
+pub fn first() -> Option<Message> {
+    get_all_shared(SortOrder::Oldest).first().cloned()
+}
+
+/// Retrieves the most recently posted message.
 ///
-/// # Notes
-/// The returned order ensures that the most recent message (based on the `posted` timestamp)
-/// is at the beginning of the vector.
-pub fn get_all() -> Vec<Message> {
-    let mut messages = read_messages_from_file(DATA_FILENAME);
-    messages.sort_by(|a, b| b.posted.cmp(&a.posted));
-    messages
+/// Messages are compared by their `posted` timestamp; ties are broken by the higher `id`.
+///
+/// # Returns
+/// - `Some(Message)` with the latest message.
+/// - `None` when the store is empty.
+pub fn latest() -> Option<Message> {
+    get_all_shared(SortOrder::Newest).first().cloned()
 }
 
-/// Retrieves a single message by its ID.
+/// Returns a random number, seeded differently on every call.
 ///
-/// This function reads all messages from the file specified by `DATA_FILENAME` and searches
-/// for a message with the given `id`. If a matching message is found, it is returned. If no
-/// match is found, it returns the default value for `Message`.
+/// Uses the randomly keyed hasher of the standard library, which is enough for picking posts
+/// but not for anything security related.
+fn random_u64() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+    static CALLS: AtomicU64 = AtomicU64::new(0);
+    let mut hasher = std::collections::hash_map::RandomState::new().build_hasher();
+    hasher.write_u64(CALLS.fetch_add(1, Ordering::Relaxed));
+    hasher.finish()
+}
+
+/// Retrieves a random message, each matching message being equally likely.
 ///
 /// # Arguments
-/// - `id`: An integer representing the ID of the message to retrieve.
+/// - `sender`: When set, only messages from this exact sender are considered.
+/// - `tag`: When set, only messages carrying this tag are considered.
 ///
 /// # Returns
-/// - A `Message` struct with the corresponding ID if found.
-/// - Returns the default value of `Message` if no match is found.
+/// - `Some(Message)` with the chosen message.
+/// - `None` when no message matches, including when the store is empty.
+pub fn random(sender: Option<&str>, tag: Option<&str>) -> Option<Message> {
+    let messages = get_all_shared(SortOrder::Oldest);
+    let candidates: Vec<&Message> = messages
+        .iter()
+        .filter(|m| sender.is_none() || sender == Some(m.sender.as_str()))
+        .filter(|m| tag.is_none() || m.tags.iter().any(|t| Some(t.as_str()) == tag))
+        .collect();
+    if candidates.is_empty() {
+        return None;
+    }
+    let index = (random_u64() % candidates.len() as u64) as usize;
+    Some(candidates[index].clone())
+}
+
+/// Computes the earliest and latest `posted` timestamps along with the message count.
 ///
-/// # Behavior
-/// - Uses `into_iter()` to consume the vector of messages.
-/// - Searches for a `Message` where `id` (converted to `u32`) matches the provided ID using `find`.
-/// - Falls back to `Message::default()` if no match is found.
+/// # Returns
+/// A [`TimeRange`] describing the stored messages. When the data file is empty, missing, or
+/// invalid, both timestamps are `None` and the count is `0`.
 ///
-/// # Dependencies
-/// - Relies on `read_messages_from_file` to load and deserialize messages from the file.
-/// - Relies on `DATA_FILENAME` for the file path.
-/// - Requires `Message` to implement `Default`.
-pub fn get(id: i32) -> Message {
-    let messages = read_messages_from_file(DATA_FILENAME);
-    messages
+/// Both timestamps are given in UTC in [`STORED_FORMAT`], including those stored in server local
+/// time by older versions. A `posted` value that cannot be parsed is returned as stored.
+pub fn time_range() -> TimeRange {
+    let messages = get_all_shared(SortOrder::Oldest);
+    let utc = |message: &Message| {
+        message.posted_at().map_or_else(
+            || message.posted.clone(),
+            |posted| posted.with_timezone(&Utc).format(STORED_FORMAT).to_string(),
+        )
+    };
+    TimeRange {
+        oldest: messages.first().map(utc),
+        newest: messages.last().map(utc),
+        count: messages.len(),
+    }
+}
+
+/// Measures the data files and counts the stored messages.
+///
+/// Sizes come from the file system metadata; a missing or unreadable file counts as `0` bytes.
+pub fn storage_stats() -> StorageStats {
+    let files: Vec<FileSize> = config::get()
+        .data_files
+        .iter()
+        .map(|path| FileSize {
+            path: path.clone(),
+            bytes: std::fs::metadata(path).map_or(0, |metadata| metadata.len()),
+        })
+        .collect();
+    StorageStats {
+        bytes: files.iter().map(|file| file.bytes).sum(),
+        files,
+        count: get_all_shared(SortOrder::Oldest).len(),
+    }
+}
+
+/// Lists the distinct senders with their number of messages, sorted by sender.
+///
+/// Senders are compared exactly, so names differing in case are listed separately.
+pub fn distinct_senders() -> Vec<SenderCount> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    let messages = get_all_shared(SortOrder::Oldest);
+    for message in messages.iter() {
+        *counts.entry(&message.sender).or_default() += 1;
+    }
+    counts
         .into_iter()
-        .find(|m| m.id == id)
-        .unwrap_or_default()
+        .map(|(sender, count)| SenderCount {
+            sender: sender.to_string(),
+            count: Some(count),
+        })
+        .collect()
+}
+
+/// Counts the messages posted on each calendar day.
+///
+/// Days are taken from `posted` in UTC, whatever the server's time zone (see
+/// [`Message::posted_day`]). Messages whose `posted` cannot be parsed are not counted.
+///
+/// # Returns
+/// One [`DailyCount`] per day with at least one message, oldest day first.
+pub fn counts_by_day() -> Vec<DailyCount> {
+    let mut counts: BTreeMap<NaiveDate, usize> = BTreeMap::new();
+    for day in get_all_shared(SortOrder::Oldest)
+        .iter()
+        .filter_map(Message::posted_day)
+    {
+        *counts.entry(day).or_default() += 1;
+    }
+    counts
+        .into_iter()
+        .map(|(date, count)| DailyCount { date, count })
+        .collect()
+}
+
+/// Groups the messages matching `status` by the calendar day they were posted on.
+///
+/// Days are keyed as [`DATE_FORMAT`] in UTC, like [`counts_by_day`], so the keys sort
+/// chronologically. Messages whose `posted` cannot be parsed are left out.
+///
+/// # Returns
+/// One entry per day with at least one message, oldest day first; each day lists its messages
+/// oldest first.
+pub fn grouped_by_day(status: StatusFilter) -> BTreeMap<String, Vec<Message>> {
+    let mut groups: BTreeMap<String, Vec<Message>> = BTreeMap::new();
+    for message in get_all_shared(SortOrder::Oldest)
+        .iter()
+        .filter(|message| status.matches(message))
+    {
+        if let Some(day) = message.posted_day() {
+            groups
+                .entry(day.format(DATE_FORMAT).to_string())
+                .or_default()
+                .push(message.clone());
+        }
+    }
+    groups
+}
+
+/// Groups the messages matching `status` by sender, sorted by sender.
+///
+/// Senders are compared exactly, as in [`distinct_senders`]. Each sender lists their messages
+/// oldest first.
+pub fn grouped_by_sender(status: StatusFilter) -> BTreeMap<String, Vec<Message>> {
+    let mut groups: BTreeMap<String, Vec<Message>> = BTreeMap::new();
+    for message in get_all_shared(SortOrder::Oldest)
+        .iter()
+        .filter(|message| status.matches(message))
+    {
+        groups
+            .entry(message.sender.clone())
+            .or_default()
+            .push(message.clone());
+    }
+    groups
 }
 
 /// Adds a new message to the storage with a unique ID.
 ///
 /// This function handles the creation of a new `Message` by reading the existing messages from
-/// a JSON file, determining the current highest ID, assigning a new unique ID to the provided
-/// message, and saving the updated message list back to the file.
+/// the configured data files, determining the current highest ID, assigning a new unique ID to the
+/// provided message, and saving the updated message list back to the primary data file.
 ///
 /// # Arguments
 ///
 /// * `message` - A mutable `Message` object that contains the data for the new message. The `id`
-/// will be overridden and assigned a unique value.
+///   will be overridden and assigned a unique value.
 ///
 /// # Returns
 ///
-/// * `Message` - The newly created message, including its assigned unique ID.
+/// * `Ok(Message)` - The newly created message, including its assigned unique ID.
+/// * `Err(DataError::Duplicate(id))` - The unique `(sender, content)` constraint is enabled
+///   (`ACTIX_POSTS_UNIQUE_SENDER_CONTENT`) and the message with ID `id` already has the same
+///   sender and content. Nothing is written in that case.
 ///
 /// # Behavior
 ///
-/// 1. Reads the current list of messages from the file specified by `DATA_FILENAME`.
-/// 2. Finds the highest existing message ID in the list.
-/// 3. Sets the new message's `id` to one higher than the current maximum ID or `1` if the list is empty.
-/// 4. Writes the updated list of messages (including the new message) back to the file.
-/// 5. Returns the newly added message.
+/// 1. Reads the current list of messages from the primary data file.
+/// 2. If the unique constraint is enabled, looks for an existing message with the same sender
+///    and content across all configured data files.
+/// 3. Finds the highest existing message ID across all configured data files, so new IDs never
+///    collide with archived messages.
+/// 4. Sets the new message's `id` to one higher than the current maximum ID or `1` if the list is empty,
+///    masks filtered words when the word filter runs in mask mode (see [`filter::mask_message`]),
+///    and gives it a slug no other message has (see [`slugify`] and [`SlugStrategy`]).
+/// 5. Writes the updated list of messages (including the new message) back to the primary data file.
+/// 6. Publishes the new message to live event streams (see [`crate::handler::events`]).
+/// 7. Returns the newly added message.
 ///
-/// # Panics
+/// # Errors
 ///
-/// This function will panic if any of the following occur:
-/// - The data file (`DATA_FILENAME`) cannot be read or written.
-/// - Serialization or deserialization of the message list fails.
+/// Returns `Err(DataError::ReadOnly)` if the primary data file cannot be written.
 ///
 /// # Notes
 ///
 /// This function assumes that `read_messages_from_file` and `serde_json` are used correctly and are
-/// compatible with the `Message` structure. The file specified by `DATA_FILENAME` must be accessible
-/// and valid in JSON format.
-pub fn create(mut message: Message) -> Message {
-    let mut messages = read_messages_from_file(DATA_FILENAME);
-    let max = messages.iter().map(|m| m.id).max().unwrap_or_default();
-    message.id = max + 1;
-    messages.push(message);
-    std::fs::write(DATA_FILENAME, serde_json::to_string(&messages).unwrap()).unwrap();
-    messages.pop().unwrap()
+/// compatible with the `Message` structure. The primary data file must be valid JSON.
+pub fn create(message: Message) -> Result<Message, DataError> {
+    with_write_lock(|| {
+        let mut created = insert_block(vec![message.clone()], false)?;
+        Ok(created.pop().unwrap())
+    })
+}
+
+/// Returns `message` as [`create`] would store it right now, without storing it.
+///
+/// Filtered words are masked and the unique `(sender, content)` constraint and the sender quota
+/// are checked as on creation. IDs are only allocated when a message is created, so the returned
+/// message has the placeholder ID `0`; `posted` is kept as given.
+///
+/// # Errors
+///
+/// The errors [`create`] would return, except `DataError::ReadOnly`.
+pub fn preview(message: Message) -> Result<Message, DataError> {
+    let _lock = write_lock();
+    let mut previewed = insert_block(vec![message], true)?;
+    let mut message = previewed.pop().unwrap();
+    message.id = 0;
+    Ok(message)
+}
+
+/// Creates several messages at once, allocating them a contiguous block of IDs.
+///
+/// The whole batch is stored under the global write lock, so the IDs of concurrent batches never
+/// interleave.
+///
+/// # Arguments
+///
+/// * `messages` - The messages to create, in the order their IDs are allocated. Their `id`
+///   values are ignored.
+///
+/// # Returns
+///
+/// - `Ok(ids)` with the inclusive range of allocated IDs; `messages[i]` received
+///   `ids.start() + i`. The range is empty for an empty batch.
+/// - `Err(DataError::Duplicate)` if the unique `(sender, content)` constraint is enabled and a
+///   message matches a stored one or an earlier one of the batch.
+/// - `Err(DataError::IdsExhausted)` if the batch would need IDs beyond `i32::MAX`.
+/// - `Err(DataError::ReadOnly)` if the primary data file cannot be written.
+///
+/// Nothing is written when an error is returned.
+pub fn create_many(messages: Vec<Message>) -> Result<RangeInclusive<i32>, DataError> {
+    with_write_lock(|| {
+        let created = insert_block(messages.clone(), false)?;
+        match (created.first(), created.last()) {
+            (Some(first), Some(last)) => Ok(first.id..=last.id),
+            _ => Ok(RangeInclusive::new(1, 0)),
+        }
+    })
+}
+
+/// Returns the first of `count` consecutive new IDs following `max`.
+///
+/// # Returns
+/// - `Ok(start)` when `start..start + count` fits in an `i32`.
+/// - `Err(DataError::IdsExhausted)` otherwise, instead of overflowing.
+fn next_ids(max: i32, count: usize) -> Result<i32, DataError> {
+    let start = max.checked_add(1).ok_or(DataError::IdsExhausted)?;
+    let count = i32::try_from(count).map_err(|_| DataError::IdsExhausted)?;
+    if count > 0 {
+        start
+            .checked_add(count - 1)
+            .ok_or(DataError::IdsExhausted)?;
+    }
+    Ok(start)
+}
+
+/// Applies `ACTIX_POSTS_MAX_PER_SENDER` to the messages about to be created.
+///
+/// The posts of each sender are counted once over `all` (every data file, expired posts
+/// excluded), then each incoming message is checked in turn. Under [`QuotaPolicy::Evict`], the
+/// sender's oldest posts are removed from `messages` (the primary data file) until there is
+/// room; posts kept in archive files cannot be evicted.
+///
+/// # Returns
+/// - `Ok(ids)` with the IDs of the evicted messages, empty without a quota.
+/// - `Err(DataError::QuotaExceeded(max))` when a message does not fit. `messages` is left
+///   untouched in that case.
+fn enforce_sender_quota(
+    messages: &mut Vec<Message>,
+    all: &[Message],
+    incoming: &[Message],
+) -> Result<Vec<i32>, DataError> {
+    let Some(max) = config::get().max_per_sender else {
+        return Ok(vec![]);
+    };
+    let (evicted, refused) = sender_quota(messages, all, incoming, max);
+    if !refused.is_empty() {
+        return Err(DataError::QuotaExceeded(max));
+    }
+    messages.retain(|m| !evicted.contains(&m.id));
+    Ok(evicted)
+}
+
+/// Checks the incoming messages against a quota of `max` posts per sender, as described in
+/// [`enforce_sender_quota`], without changing `messages`.
+///
+/// # Returns
+/// The IDs of the messages to evict, and the positions in `incoming` of the messages that do not
+/// fit. Those are not counted against their sender.
+fn sender_quota(
+    messages: &[Message],
+    all: &[Message],
+    incoming: &[Message],
+    max: usize,
+) -> (Vec<i32>, Vec<usize>) {
+    let policy = config::get().sender_quota_policy;
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for message in all.iter().filter(|m| !m.is_expired()) {
+        *counts.entry(&message.sender).or_default() += 1;
+    }
+    let mut evicted = vec![];
+    let mut refused = vec![];
+    for (index, message) in incoming.iter().enumerate() {
+        let count = counts.entry(&message.sender).or_default();
+        let mut fits = true;
+        while *count >= max {
+            let oldest = messages
+                .iter()
+                .filter(|m| m.sender == message.sender && !m.is_expired())
+                .filter(|m| !evicted.contains(&m.id))
+                .min_by_key(|m| (m.posted_at(), m.id));
+            match oldest {
+                Some(oldest) if policy == QuotaPolicy::Evict => evicted.push(oldest.id),
+                _ => {
+                    fits = false;
+                    break;
+                }
+            }
+            *count -= 1;
+        }
+        if fits {
+            *count += 1;
+        } else {
+            refused.push(index);
+        }
+    }
+    (evicted, refused)
+}
+
+/// Appends messages to the primary data file with consecutive new IDs and publishes them.
+///
+/// With `dry_run`, the messages are prepared and checked the same way, but nothing is written,
+/// recorded, or published.
+///
+/// Must be called with the write lock held.
+fn insert_block(mut incoming: Vec<Message>, dry_run: bool) -> Result<Vec<Message>, DataError> {
+    let mut messages = read_stored(primary_filename());
+    let all = read_all();
+    let max = all.iter().map(|m| m.id).max().unwrap_or_default();
+    let start = next_ids(max, incoming.len())?;
+    let mut slugs = slugs_except(&all, 0);
+    for (id, message) in (start..).zip(incoming.iter_mut()) {
+        message.id = id;
+        filter::mask_message(message);
+        message.slug = unique_slug(message, &slugs);
+        slugs.insert(message.slug.clone());
+    }
+    if config::get().unique_sender_content {
+        for (i, message) in incoming.iter().enumerate() {
+            if let Some(existing) = all
+                .iter()
+                .chain(&incoming[..i])
+                .find(|m| m.sender == message.sender && m.content == message.content)
+            {
+                return Err(DataError::Duplicate(existing.id));
+            }
+        }
+    }
+    if incoming.is_empty() {
+        return Ok(incoming);
+    }
+    let evicted = enforce_sender_quota(&mut messages, &all, &incoming)?;
+    if dry_run {
+        return Ok(incoming);
+    }
+    messages.extend(incoming.iter().cloned());
+    write_primary(&messages)?;
+    forget_history(&evicted);
+    record_changes(ChangeKind::Deleted, &evicted);
+    let ids: Vec<i32> = incoming.iter().map(|m| m.id).collect();
+    record_changes(ChangeKind::Created, &ids);
+    incoming.iter().for_each(events::publish);
+    Ok(incoming)
 }
 
 /// Updates an existing message in the storage.
@@ -175,36 +2439,387 @@ pub fn create(mut message: Message) -> Message {
 /// # Arguments
 ///
 /// * `message` - A reference to a `Message` object containing the updated data. The object must
-/// have an `id` field that matches an existing message in the storage.
+///   have an `id` field that matches an existing message in the primary data file.
 ///
 /// # Behavior
 ///
-/// 1. Reads the current list of messages stored in the file specified by `DATA_FILENAME`.
+/// 1. Reads the current list of messages stored in the primary data file.
 /// 2. Searches for a message with the same `id` as the provided one.
-/// 3. If a match is found, replaces the existing message with the provided one.
-/// 4. Writes the updated list of messages back to the file.
+/// 3. If the edit window (`ACTIX_POSTS_EDIT_WINDOW_MINUTES`) is configured and the stored message
+///    was posted longer ago, returns `Err(DataError::EditWindowClosed)` without writing. The
+///    stored `posted` value is used, not the one of the provided message, and is kept on update.
+/// 4. If a match is found, replaces the existing message with the provided one (with filtered
+///    words masked, see [`filter::mask_message`]) and sets its `updated` timestamp to the current
+///    time. The stored slug is kept; a message without one receives one as on creation.
+/// 5. Writes the updated list of messages back to the file.
+/// 6. If the content changed, records it as a new [`Revision`] (see [`revisions`]).
 ///
 /// If no message with the same `id` exists, the function performs no updates and no errors are raised.
+/// Messages that only exist in archive files are read-only and are therefore never updated.
+/// Use [`update_ignoring_edit_window`] for administrative changes.
 ///
-/// # Panics
+/// # Errors
 ///
-/// This function will panic if any of the following occur:
-/// - The data file (`DATA_FILENAME`) cannot be read or written.
-/// - Serialization or deserialization of the message list fails.
+/// Returns `Err(DataError::ReadOnly)` if the primary data file cannot be written.
 ///
 /// # Limitations
 ///
 /// This function assumes that:
-/// - The file specified by `DATA_FILENAME` exists, is accessible, and is properly formatted.
+/// - The primary data file exists and is properly formatted.
 /// - The list of messages fits in memory since it loads the entire file contents at once.
 ///
 /// In production scenarios, improved error handling and support for larger datasets may be necessary.
-pub fn update(message: &Message) {
-    let mut messages = read_messages_from_file(DATA_FILENAME);
-    if let Some(index) = messages.iter().position(|m| m.id == message.id) {
-        messages[index] = message.clone();
-        std::fs::write(DATA_FILENAME, serde_json::to_string(&messages).unwrap()).unwrap();
-    }
+pub fn update(message: &Message) -> Result<(), DataError> {
+    write_update(message, true, None)
+}
+
+/// Updates an existing message like [`update`], provided its stored [`Message::version`] is
+/// still `version`.
+///
+/// The comparison happens under the write lock, so two edits based on the same version cannot
+/// both succeed.
+///
+/// # Errors
+///
+/// - `Err(DataError::Modified)` if the stored message has another version. Nothing is written.
+/// - The errors of [`update`].
+pub fn update_if_unchanged(message: &Message, version: &str) -> Result<(), DataError> {
+    write_update(message, true, Some(version))
+}
+
+/// Updates an existing message like [`update`], but regardless of the edit window.
+///
+/// # Errors
+///
+/// Returns `Err(DataError::ReadOnly)` if the primary data file cannot be written.
+pub fn update_ignoring_edit_window(message: &Message) -> Result<(), DataError> {
+    write_update(message, false, None)
+}
+
+/// Returns `true` when `message` was posted longer ago than the configured edit window.
+///
+/// Messages whose `posted` value cannot be parsed are treated as editable.
+fn edit_window_closed(message: &Message, minutes: i64) -> bool {
+    message
+        .posted_at()
+        .map(|posted| Local::now() - posted > chrono::Duration::minutes(minutes))
+        .unwrap_or(false)
+}
+
+fn write_update(
+    message: &Message,
+    check_edit_window: bool,
+    version: Option<&str>,
+) -> Result<(), DataError> {
+    with_write_lock(|| {
+        let mut messages = read_stored(primary_filename());
+        if let Some(index) = messages.iter().position(|m| m.id == message.id) {
+            if version.is_some_and(|version| messages[index].version() != version) {
+                return Err(DataError::Modified);
+            }
+            let mut message = message.clone();
+            if let (true, Some(minutes)) = (check_edit_window, config::get().edit_window_minutes) {
+                if edit_window_closed(&messages[index], minutes) {
+                    return Err(DataError::EditWindowClosed(minutes));
+                }
+                // Keep the stored timestamp so an edit cannot move the message back into the window.
+                message.posted = messages[index].posted.clone();
+            }
+            // Reactions, tags, and status are only changed through `react`, `set_tags`, and
+            // `set_status`; the metadata describes the creation and never changes.
+            message.reactions = messages[index].reactions.clone();
+            message.tags = messages[index].tags.clone();
+            message.status = messages[index].status;
+            message.metadata = messages[index].metadata.clone();
+            filter::mask_message(&mut message);
+            // The slug is kept so that links to it survive edits; older messages receive one.
+            message.slug = messages[index].slug.clone();
+            if message.slug.is_empty() {
+                message.slug = unique_slug(&message, &slugs_except(&read_all(), message.id));
+            }
+            message.updated = current_timestamp();
+            let stored = std::mem::replace(&mut messages[index], message);
+            write_primary(&messages)?;
+            record_changes(ChangeKind::Updated, &[stored.id]);
+            if stored.content != messages[index].content {
+                record_revision(&stored, &messages[index]);
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Adds a reaction to a message.
+///
+/// The caller is expected to have checked `emoji` against the allowlist (see
+/// [`crate::handler::validation::REACTIONS`]). The `updated` timestamp is left untouched, since
+/// the message itself did not change.
+///
+/// # Arguments
+///
+/// * `id` - The ID of the message.
+/// * `emoji` - The reaction to count.
+///
+/// # Returns
+///
+/// - `Ok(Some(message))` with the updated message.
+/// - `Ok(None)` if no message with that ID exists in the primary data file.
+/// - `Err(DataError::ReadOnly)` if the primary data file cannot be written.
+pub fn react(id: i32, emoji: &str) -> Result<Option<Message>, DataError> {
+    with_write_lock(|| {
+        let mut messages = read_stored(primary_filename());
+        let Some(message) = messages.iter_mut().find(|m| m.id == id) else {
+            return Ok(None);
+        };
+        let count = message.reactions.entry(emoji.to_string()).or_default();
+        *count = count.saturating_add(1);
+        let message = message.clone();
+        write_primary(&messages)?;
+        record_changes(ChangeKind::Updated, &[message.id]);
+        Ok(Some(message))
+    })
+}
+
+/// Replaces the tags of a message.
+///
+/// The tags are normalized first (see [`crate::handler::validation::normalize_tags`]). Like
+/// reactions, tags are not an edit of the message, so `updated` is left untouched.
+///
+/// # Arguments
+///
+/// * `id` - The ID of the message.
+/// * `tags` - The new tags; an empty list clears them.
+///
+/// # Returns
+///
+/// - `Ok(Some(message))` with the updated message.
+/// - `Ok(None)` if no message with that ID exists in the primary data file.
+/// - `Err(DataError::ReadOnly)` if the primary data file cannot be written.
+pub fn set_tags(id: i32, tags: &[String]) -> Result<Option<Message>, DataError> {
+    with_write_lock(|| {
+        let mut messages = read_stored(primary_filename());
+        let Some(message) = messages.iter_mut().find(|m| m.id == id) else {
+            return Ok(None);
+        };
+        message.tags = validation::normalize_tags(tags);
+        let message = message.clone();
+        write_primary(&messages)?;
+        record_changes(ChangeKind::Updated, &[message.id]);
+        Ok(Some(message))
+    })
+}
+
+/// Moves a message to another workflow status. `updated` is left untouched, as with
+/// [`set_tags`].
+///
+/// # Arguments
+///
+/// * `id` - The ID of the message.
+/// * `status` - The new status.
+/// * `reopen` - Allows leaving [`PostStatus::Archived`] (see [`PostStatus::can_become`]).
+///
+/// # Returns
+///
+/// - `Ok(Some(message))` with the updated message.
+/// - `Ok(None)` if no message with that ID exists in the primary data file.
+/// - `Err(DataError::InvalidTransition(from, to))` if the transition needs `reopen`.
+/// - `Err(DataError::ReadOnly)` if the primary data file cannot be written.
+pub fn set_status(id: i32, status: PostStatus, reopen: bool) -> Result<Option<Message>, DataError> {
+    with_write_lock(|| {
+        let mut messages = read_stored(primary_filename());
+        let Some(message) = messages.iter_mut().find(|m| m.id == id) else {
+            return Ok(None);
+        };
+        if !message.status.can_become(status, reopen) {
+            return Err(DataError::InvalidTransition(message.status, status));
+        }
+        message.status = status;
+        let message = message.clone();
+        write_primary(&messages)?;
+        record_changes(ChangeKind::Updated, &[message.id]);
+        Ok(Some(message))
+    })
+}
+
+/// Sets the `updated` timestamp of a message to the current time, leaving everything else
+/// untouched. No revision is recorded, since the content does not change.
+///
+/// # Arguments
+///
+/// * `id` - The ID of the message.
+///
+/// # Returns
+///
+/// - `Ok(Some(message))` with the updated message.
+/// - `Ok(None)` if no message with that ID exists in the primary data file.
+/// - `Err(DataError::ReadOnly)` if the primary data file cannot be written.
+pub fn touch(id: i32) -> Result<Option<Message>, DataError> {
+    with_write_lock(|| {
+        let mut messages = read_stored(primary_filename());
+        let Some(message) = messages.iter_mut().find(|m| m.id == id) else {
+            return Ok(None);
+        };
+        message.updated = current_timestamp();
+        let message = message.clone();
+        write_primary(&messages)?;
+        record_changes(ChangeKind::Updated, &[message.id]);
+        Ok(Some(message))
+    })
+}
+
+/// Adds and removes tags on every message matching `filter`, in a single write.
+///
+/// Both tag lists are normalized first (see [`crate::handler::validation::normalize_tags`]).
+/// Removals are applied before additions, and added tags go after the kept ones. `updated` is
+/// left untouched, as with [`set_tags`].
+///
+/// # Arguments
+///
+/// * `filter` - Selects the messages to retag.
+/// * `add` - The tags to add when missing.
+/// * `remove` - The tags to remove when present.
+/// * `dry_run` - When `true`, the affected IDs are computed but the store is left untouched.
+///
+/// # Returns
+///
+/// - `Ok(ids)` with the IDs of the matching messages whose tags changed (or, in a dry run, would
+///   change), in storage order.
+/// - `Err(DataError::TooManyTags(id))` if a message would end up with more tags than
+///   `ACTIX_POSTS_MAX_TAGS`. Nothing is written in that case.
+/// - `Err(DataError::ReadOnly)` if the primary data file cannot be written.
+pub fn bulk_tag(
+    filter: &MessageFilter,
+    add: &[String],
+    remove: &[String],
+    dry_run: bool,
+) -> Result<Vec<i32>, DataError> {
+    let add = validation::normalize_tags(add);
+    let remove = validation::normalize_tags(remove);
+    with_write_lock(|| {
+        let mut messages = read_stored(primary_filename());
+        let mut changed = vec![];
+        for message in messages.iter_mut().filter(|m| filter.matches(m)) {
+            let mut tags: Vec<String> = message
+                .tags
+                .iter()
+                .filter(|tag| !remove.contains(tag))
+                .cloned()
+                .collect();
+            for tag in &add {
+                if !tags.contains(tag) {
+                    tags.push(tag.clone());
+                }
+            }
+            if tags.len() > config::get().max_tags && tags.len() > message.tags.len() {
+                return Err(DataError::TooManyTags(message.id));
+            }
+            if tags != message.tags {
+                message.tags = tags;
+                changed.push(message.id);
+            }
+        }
+        if !dry_run && !changed.is_empty() {
+            write_primary(&messages)?;
+            record_changes(ChangeKind::Updated, &changed);
+        }
+        Ok(changed)
+    })
+}
+
+/// Applies several partial updates in a single read-modify-write of the primary data file.
+///
+/// The caller is expected to have validated the changed fields. Updates are applied in order, so
+/// two updates of the same message both take effect. Changes to `sender`, `content`, or
+/// `attachments` are edits: they are masked (see [`filter::mask_message`]), set `updated`, record
+/// a revision when the content changes, and are refused outside the edit window when
+/// `check_edit_window` is set. Tags and status are changed as by [`set_tags`] and [`set_status`]
+/// (without `reopen`).
+///
+/// # Arguments
+///
+/// * `updates` - The updates to apply.
+/// * `check_edit_window` - Whether edits are refused outside the configured edit window.
+/// * `mode` - Whether a failed update prevents the others from being written.
+///
+/// # Returns
+///
+/// - `Ok(outcomes)` with one [`BatchOutcome`] per update, in order. In [`BatchMode::Strict`],
+///   nothing is written as soon as one update is not [`BatchOutcome::Updated`], and the other
+///   outcomes become [`BatchOutcome::Skipped`].
+/// - `Err(DataError::ReadOnly)` if the primary data file cannot be written.
+pub fn update_many(
+    updates: &[BatchUpdate],
+    check_edit_window: bool,
+    mode: BatchMode,
+) -> Result<Vec<BatchOutcome>, DataError> {
+    with_write_lock(|| {
+        let mut messages = read_stored(primary_filename());
+        let mut originals: HashMap<i32, Message> = HashMap::new();
+        let mut outcomes = Vec::with_capacity(updates.len());
+        for update in updates {
+            let Some(message) = messages.iter_mut().find(|m| m.id == update.id) else {
+                outcomes.push(BatchOutcome::NotFound);
+                continue;
+            };
+            let changes = &update.changes;
+            if let (true, true, Some(minutes)) = (
+                check_edit_window,
+                changes.edits_message(),
+                config::get().edit_window_minutes,
+            ) {
+                if edit_window_closed(message, minutes) {
+                    outcomes.push(BatchOutcome::Failed(DataError::EditWindowClosed(minutes)));
+                    continue;
+                }
+            }
+            if let Some(status) = changes.status {
+                if !message.status.can_become(status, false) {
+                    outcomes.push(BatchOutcome::Failed(DataError::InvalidTransition(
+                        message.status,
+                        status,
+                    )));
+                    continue;
+                }
+            }
+            originals
+                .entry(message.id)
+                .or_insert_with(|| message.clone());
+            changes.apply_to(message);
+            if changes.edits_message() {
+                filter::mask_message(message);
+                message.updated = current_timestamp();
+            }
+            outcomes.push(BatchOutcome::Updated(Box::new(message.clone())));
+        }
+        let failed = outcomes
+            .iter()
+            .any(|outcome| !matches!(outcome, BatchOutcome::Updated(_)));
+        if failed && mode == BatchMode::Strict {
+            return Ok(outcomes
+                .into_iter()
+                .map(|outcome| match outcome {
+                    BatchOutcome::Updated(_) => BatchOutcome::Skipped,
+                    outcome => outcome,
+                })
+                .collect());
+        }
+        if originals.is_empty() {
+            return Ok(outcomes);
+        }
+        write_primary(&messages)?;
+        let mut changed: Vec<i32> = originals.keys().copied().collect();
+        changed.sort_unstable();
+        record_changes(ChangeKind::Updated, &changed);
+        for id in changed {
+            let stored = &originals[&id];
+            if let Some(message) = messages.iter().find(|m| m.id == id) {
+                if stored.content != message.content {
+                    record_revision(stored, message);
+                }
+            }
+        }
+        Ok(outcomes)
+    })
 }
 
 /// Removes a message from the storage based on its ID.
@@ -218,30 +2833,529 @@ pub fn update(message: &Message) {
 ///
 /// # Behavior
 ///
-/// 1. Reads the current list of messages stored in the file specified by `DATA_FILENAME`.
-/// 2. Filters out the message with the specified `id` using the `retain` method.
+/// 1. Reads the current list of messages stored in the primary data file.
+/// 2. Finds the message with the specified `id` and takes it out of the list.
 /// 3. Writes the updated list of messages back to the file.
 ///
-/// If no message with the provided `id` exists, the function silently proceeds without making changes.
+/// If no message with the provided `id` exists, nothing is written. Archive files are never
+/// modified.
 ///
-/// # Panics
+/// # Returns
 ///
-/// This function will panic if any of the following occur:
-/// - The data file (`DATA_FILENAME`) cannot be read or written.
-/// - Serialization or deserialization of the message list fails.
+/// - `Ok(Some(message))` with the removed message.
+/// - `Ok(None)` if no message with that ID exists in the primary data file.
+/// - `Err(DataError::ReadOnly)` if the primary data file cannot be written.
 ///
 /// # Limitations
 ///
-/// - The function assumes that the file specified by `DATA_FILENAME` exists, is accessible, and
-///   contains valid JSON-formatted data.
+/// - The function assumes that the primary data file exists and contains valid JSON-formatted
+///   data.
 /// - Removes messages entirely based on the `id` field. Make sure the `id` is accurate.
 ///
 /// # Notes
 ///
-/// This function uses `retain` to filter out messages, which is efficient for small to moderately sized datasets.
+/// This function scans the messages linearly, which is efficient for small to moderately sized datasets.
 /// For larger datasets, a more scalable solution may need to be considered.
-pub fn remove(id: i32) {
-    let mut messages = read_messages_from_file(DATA_FILENAME);
-    messages.retain(|item| item.id != id);
-    std::fs::write(DATA_FILENAME, serde_json::to_string(&messages).unwrap()).unwrap();
+pub fn remove(id: i32) -> Result<Option<Message>, DataError> {
+    with_write_lock(|| {
+        let mut messages = read_stored(primary_filename());
+        let Some(index) = messages.iter().position(|item| item.id == id) else {
+            return Ok(None);
+        };
+        let removed = messages.remove(index);
+        write_primary(&messages)?;
+        forget_history(&[id]);
+        record_changes(ChangeKind::Deleted, &[id]);
+        keep_deleted(&removed);
+        Ok(Some(removed))
+    })
+}
+
+/// The messages deleted by [`remove`] in the last `ACTIX_POSTS_UNDO_SECONDS`, with the time of
+/// their deletion, so [`restore`] can put them back. Kept in memory only.
+static DELETED: Mutex<Vec<(SystemTime, Message)>> = Mutex::new(vec![]);
+
+/// Returns the deleted messages, without those whose undo window is over.
+fn deleted() -> MutexGuard<'static, Vec<(SystemTime, Message)>> {
+    let window = Duration::from_secs(config::get().undo_seconds);
+    let mut deleted = DELETED.lock().unwrap_or_else(PoisonError::into_inner);
+    deleted.retain(|(at, _)| at.elapsed().is_ok_and(|elapsed| elapsed < window));
+    deleted
+}
+
+fn keep_deleted(message: &Message) {
+    if config::get().undo_seconds > 0 {
+        let mut deleted = deleted();
+        deleted.retain(|(_, kept)| kept.id != message.id);
+        deleted.push((SystemTime::now(), message.clone()));
+    }
+}
+
+/// Puts back a message deleted by [`remove`] less than `ACTIX_POSTS_UNDO_SECONDS` ago.
+///
+/// The message is restored as it was deleted, with its ID, but without its revision history. If
+/// another message took its slug in the meantime, it receives a new one.
+///
+/// # Returns
+///
+/// - `Ok(Some(message))` with the restored message.
+/// - `Ok(None)` if the message was not deleted recently enough, or its ID was taken by a new
+///   message in the meantime. The message cannot be restored anymore in that case.
+/// - `Err(DataError::ReadOnly)` if the primary data file cannot be written. The message can be
+///   restored again within the window.
+pub fn restore(id: i32) -> Result<Option<Message>, DataError> {
+    let Some(deleted_message) = deleted()
+        .iter()
+        .find(|(_, message)| message.id == id)
+        .map(|(_, message)| message.clone())
+    else {
+        return Ok(None);
+    };
+    let restored = with_write_lock(|| {
+        let all = read_all();
+        if all.iter().any(|m| m.id == id) {
+            return Ok(None);
+        }
+        // A new message may have taken the slug in the meantime.
+        let mut message = deleted_message.clone();
+        let taken = slugs_except(&all, id);
+        if taken.contains(&message.slug) {
+            message.slug = unique_slug(&message, &taken);
+        }
+        let mut messages = read_stored(primary_filename());
+        let index = messages.partition_point(|m| m.id < id);
+        messages.insert(index, message.clone());
+        write_primary(&messages)?;
+        record_changes(ChangeKind::Created, &[id]);
+        Ok(Some(message.clone()))
+    })?;
+    deleted().retain(|(_, kept)| kept.id != id);
+    Ok(restored)
+}
+
+/// Removes every message whose ID is listed in `ids`.
+///
+/// # Arguments
+///
+/// * `ids` - The IDs of the messages to remove. Unknown IDs are ignored.
+/// * `dry_run` - When `true`, the affected IDs are computed but the store is left untouched.
+///
+/// # Returns
+///
+/// - `Ok(ids)` with the IDs of the messages that were (or, in a dry run, would be) removed, in
+///   storage order.
+/// - `Err(DataError::ReadOnly)` if the primary data file cannot be written.
+pub fn remove_many(ids: &[i32], dry_run: bool) -> Result<Vec<i32>, DataError> {
+    with_write_lock(|| {
+        let mut messages = read_stored(primary_filename());
+        let removed: Vec<i32> = messages
+            .iter()
+            .map(|m| m.id)
+            .filter(|id| ids.contains(id))
+            .collect();
+        if !dry_run && !removed.is_empty() {
+            messages.retain(|m| !removed.contains(&m.id));
+            write_primary(&messages)?;
+            forget_history(&removed);
+            record_changes(ChangeKind::Deleted, &removed);
+        }
+        Ok(removed)
+    })
+}
+
+/// Removes every message of the primary data file that matches `predicate`.
+///
+/// # Returns
+///
+/// - `Ok(ids)` with the IDs of the removed messages, in storage order. Nothing is written when
+///   no message matches.
+/// - `Err(DataError::ReadOnly)` if the primary data file cannot be written.
+pub fn remove_where(predicate: impl Fn(&Message) -> bool) -> Result<Vec<i32>, DataError> {
+    with_write_lock(|| {
+        let mut messages = read_stored(primary_filename());
+        let removed: Vec<i32> = messages
+            .iter()
+            .filter(|m| predicate(m))
+            .map(|m| m.id)
+            .collect();
+        if !removed.is_empty() {
+            messages.retain(|m| !predicate(m));
+            write_primary(&messages)?;
+            forget_history(&removed);
+            record_changes(ChangeKind::Deleted, &removed);
+        }
+        Ok(removed)
+    })
+}
+
+/// Imports a batch of messages into the primary data file.
+///
+/// # Arguments
+///
+/// * `incoming` - The messages to import. Their `posted` values are stored as given.
+/// * `mode` - Whether the messages are appended or replace the primary data file (see
+///   [`ImportMode`]).
+/// * `dry_run` - When `true`, the report is computed but the store is left untouched.
+///
+/// # Returns
+///
+/// - `Ok(report)`: An [`ImportReport`] listing the discarded and the newly assigned IDs, or the
+///   refused messages. Nothing is written when a message is refused.
+/// - `Err(DataError::IdsExhausted)` if the new IDs would exceed `i32::MAX`. Nothing is written
+///   in that case.
+/// - `Err(DataError::ReadOnly)` if the primary data file cannot be written.
+///
+/// # Behavior
+///
+/// Appended messages receive a contiguous block of new IDs, allocated under the global write lock
+/// like [`create_many`]. IDs used by messages kept in archive files are never reused, so imported messages cannot be
+/// shadowed by (or shadow) archived ones.
+///
+/// Imported messages follow the rules of [`create`]: filtered words are masked, slugs are kept
+/// only while no other message has them (see [`claim_slug`]), and the unique `(sender, content)`
+/// constraint and `ACTIX_POSTS_MAX_PER_SENDER` are applied against the messages that remain
+/// after the import. Field validation is left to the caller, as for
+/// [`create`].
+pub fn import(
+    incoming: Vec<Message>,
+    mode: ImportMode,
+    dry_run: bool,
+) -> Result<ImportReport, DataError> {
+    with_write_lock(|| {
+        let mut incoming = incoming.clone();
+        let mut messages = read_stored(primary_filename());
+        let archives = &config::get().data_files[1..];
+        let (removed, mut used): (Vec<i32>, HashSet<i32>) = match mode {
+            ImportMode::Append => (vec![], read_all().iter().map(|m| m.id).collect()),
+            ImportMode::Replace => (
+                messages.drain(..).map(|m| m.id).collect(),
+                read_stored_files(archives).iter().map(|m| m.id).collect(),
+            ),
+        };
+
+        let mut keep = vec![false; incoming.len()];
+        if mode == ImportMode::Replace {
+            for (message, keep) in incoming.iter().zip(keep.iter_mut()) {
+                *keep = message.id > 0 && used.insert(message.id);
+            }
+        }
+        let max = used.iter().max().copied().unwrap_or_default();
+        let mut next = next_ids(max, keep.iter().filter(|keep| !**keep).count())?..;
+        for (message, keep) in incoming.iter_mut().zip(keep) {
+            if !keep {
+                message.id = next.next().unwrap_or_default();
+            }
+        }
+
+        let kept: Vec<Message> = match mode {
+            ImportMode::Append => read_all(),
+            ImportMode::Replace => read_stored_files(archives),
+        };
+        let mut slugs = slugs_except(&kept, 0);
+        for message in incoming.iter_mut() {
+            filter::mask_message(message);
+            claim_slug(message, &slugs);
+            slugs.insert(message.slug.clone());
+        }
+        let mut rejected = vec![];
+        if config::get().unique_sender_content {
+            for (index, message) in incoming.iter().enumerate() {
+                if let Some(existing) = kept
+                    .iter()
+                    .chain(&incoming[..index])
+                    .find(|m| m.sender == message.sender && m.content == message.content)
+                {
+                    rejected.push(RejectedRecord {
+                        index,
+                        field: "content".to_string(),
+                        reason: DataError::Duplicate(existing.id).to_string(),
+                    });
+                }
+            }
+        }
+        let mut evicted = vec![];
+        if let Some(max) = config::get().max_per_sender {
+            let (accepted, candidates): (Vec<usize>, Vec<Message>) = incoming
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| !rejected.iter().any(|record| record.index == *index))
+                .map(|(index, message)| (index, message.clone()))
+                .unzip();
+            let refused;
+            (evicted, refused) = sender_quota(&messages, &kept, &candidates, max);
+            rejected.extend(refused.into_iter().map(|index| RejectedRecord {
+                index: accepted[index],
+                field: "sender".to_string(),
+                reason: DataError::QuotaExceeded(max).to_string(),
+            }));
+            rejected.sort_by_key(|record| record.index);
+        }
+        if !rejected.is_empty() {
+            return Ok(ImportReport {
+                rejected,
+                dry_run,
+                ..Default::default()
+            });
+        }
+
+        let removed: Vec<i32> = removed.into_iter().chain(evicted).collect();
+        let created: Vec<i32> = incoming.iter().map(|m| m.id).collect();
+        if !dry_run {
+            messages.retain(|m| !removed.contains(&m.id));
+            messages.extend(incoming);
+            write_primary(&messages)?;
+            forget_history(&removed);
+            record_changes(ChangeKind::Deleted, &removed);
+            record_changes(ChangeKind::Created, &created);
+        }
+        Ok(ImportReport {
+            removed,
+            created,
+            rejected,
+            dry_run,
+        })
+    })
+}
+
+/// Removes every message from the primary data file.
+///
+/// Archive files are left untouched, so their messages remain readable.
+///
+/// # Returns
+///
+/// - `Ok(ids)` with the IDs of the removed messages, in storage order.
+/// - `Err(DataError::ReadOnly)` if the primary data file cannot be written.
+pub fn clear() -> Result<Vec<i32>, DataError> {
+    with_write_lock(|| {
+        let removed: Vec<i32> = read_stored(primary_filename())
+            .iter()
+            .map(|m| m.id)
+            .collect();
+        write_primary(&[])?;
+        forget_history(&removed);
+        record_changes(ChangeKind::Deleted, &removed);
+        Ok(removed)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{message, TestEnv};
+    use std::sync::atomic::AtomicU32;
+    use std::time::Instant;
+
+    /// The number of upcoming writes that fail as interrupted (see [`write_replacing`]).
+    static INJECTED_FAILURES: AtomicU32 = AtomicU32::new(0);
+
+    /// Consumes one injected write failure, if any is left.
+    pub(super) fn take_injected_failure() -> bool {
+        INJECTED_FAILURES
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |left| {
+                left.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    #[actix_rt::test]
+    async fn transient_write_failures_are_retried_without_blocking_the_worker() {
+        let env = TestEnv::with(|config| {
+            config.write_retries = 2;
+            config.write_retry_delay_ms = 500;
+        });
+        // A single attempt reports the failure and writes nothing.
+        INJECTED_FAILURES.store(1, Ordering::Release);
+        let failed = create(message(0, "Nao", "failed"));
+        assert!(
+            matches!(failed, Err(DataError::Transient(_))),
+            "{:?}",
+            failed
+        );
+        assert!(env.stored().is_empty());
+        assert!(!is_read_only());
+
+        INJECTED_FAILURES.store(1, Ordering::Release);
+        let started = Instant::now();
+        let retried = actix_rt::spawn(with_retries(|| create(message(0, "Nao", "retried"))));
+        while INJECTED_FAILURES.load(Ordering::Acquire) > 0 {
+            actix_rt::task::yield_now().await;
+        }
+        // The first attempt failed; this thread serves another write during the pause.
+        let other = create(message(0, "Kai", "meanwhile")).unwrap();
+        assert!(started.elapsed() < Duration::from_millis(400));
+        let retried = retried.await.unwrap().unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(500));
+        assert_eq!((other.id, retried.id), (1, 2), "the retry starts over");
+        assert_eq!(env.stored().len(), 2);
+        assert!(!is_read_only());
+
+        // Three failures in a row exhaust the two retries.
+        INJECTED_FAILURES.store(3, Ordering::Release);
+        let lost = with_retries(|| create(message(0, "Mio", "lost"))).await;
+        assert_eq!(lost, Err(DataError::ReadOnly));
+        assert!(is_read_only());
+        assert_eq!(env.stored().len(), 2);
+        assert!(probe_writable());
+    }
+
+    #[test]
+    fn data_files_are_merged_with_the_first_file_winning() {
+        let env = TestEnv::with(|config| {
+            let replica = config.data_files[0].replace("data.json", "replica.json");
+            config.data_files.push(replica);
+        });
+        let replica = config::get().data_files[1].clone();
+        std::fs::write(
+            &replica,
+            serde_json::to_string(&[
+                message(2, "Replica", "stale"),
+                message(3, "Replica", "only here"),
+            ])
+            .unwrap(),
+        )
+        .unwrap();
+        env.seed(&[message(1, "Nao", "hello"), message(2, "Nao", "fresh")]);
+
+        let messages = get_all_sorted(SortOrder::Oldest);
+        let ids: Vec<i32> = messages.iter().map(|m| m.id).collect();
+        assert_eq!(ids, [1, 2, 3]);
+        assert_eq!(get(2).unwrap().content, "fresh");
+        assert_eq!(get(3).unwrap().sender, "Replica");
+
+        // Writes only go to the primary file.
+        create(message(0, "Kai", "new")).unwrap();
+        assert_eq!(env.stored().len(), 3);
+        assert_eq!(read_messages_from_file(&replica).len(), 2);
+    }
+
+    #[test]
+    fn concurrent_batches_get_disjoint_contiguous_ids() {
+        let env = TestEnv::with(|_| {});
+        env.seed(&[message(1, "Nao", "hello")]);
+
+        let batches: Vec<RangeInclusive<i32>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..4)
+                .map(|batch| {
+                    scope.spawn(move || {
+                        let messages = (0..25)
+                            .map(|i| message(0, &format!("Sender{}", batch), &format!("#{}", i)))
+                            .collect();
+                        create_many(messages).unwrap()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        });
+
+        let mut starts: Vec<i32> = batches.iter().map(|ids| *ids.start()).collect();
+        starts.sort();
+        assert_eq!(starts, [2, 27, 52, 77]);
+        let stored = env.stored();
+        assert_eq!(stored.len(), 101);
+        for ids in &batches {
+            assert_eq!(ids.clone().count(), 25);
+            let senders: HashSet<&str> = stored
+                .iter()
+                .filter(|m| ids.contains(&m.id))
+                .map(|m| m.sender.as_str())
+                .collect();
+            assert_eq!(senders.len(), 1, "the batch {:?} was interleaved", ids);
+        }
+        let ids: HashSet<i32> = stored.iter().map(|m| m.id).collect();
+        assert_eq!(ids.len(), stored.len());
+    }
+
+    #[test]
+    fn lookups_in_an_empty_store_do_not_build_the_index() {
+        let env = TestEnv::with(|_| {});
+        let index_built = || with_snapshot(|current| current.index.get().is_some());
+        assert_eq!(get(1), None, "missing data file");
+        assert!(!index_built());
+        std::fs::write(&config::get().data_files[0], "[]").unwrap();
+        reset();
+        assert_eq!(get(1), None, "empty data file");
+        assert!(!index_built());
+
+        let mut stored = message(2, "Kai", "hi");
+        stored.tags = vec!["rust".to_string()];
+        env.seed(&[message(1, "Nao", "hello"), stored.clone()]);
+        assert_eq!(get(2), Some(stored));
+        assert!(index_built());
+        assert_eq!(get(3), None);
+    }
+
+    #[test]
+    fn memory_mode_serves_reads_without_the_files() {
+        let env = TestEnv::with(|config| config.in_memory = true);
+        env.seed(&[message(1, "Nao", "one"), message(2, "Mio", "two")]);
+        assert_eq!(load_into_memory(), Some(2));
+
+        // With the file gone, reads can only be served from memory.
+        let path = primary_filename();
+        let moved = format!("{}.moved", path);
+        std::fs::rename(path, &moved).unwrap();
+        let ids = || {
+            get_all_sorted(SortOrder::Oldest)
+                .iter()
+                .map(|m| m.id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ids(), [1, 2]);
+        assert_eq!(get(2).unwrap().content, "two");
+
+        // Writes reach both the memory copy and the file.
+        std::fs::rename(&moved, path).unwrap();
+        create(message(3, "Kai", "three")).unwrap();
+        assert_eq!(ids(), [1, 2, 3]);
+        assert_eq!(env.stored().len(), 3);
+
+        // Edits made outside the server wait for an explicit reload.
+        std::fs::write(
+            path,
+            serde_json::to_string(&[message(9, "Rin", "outside")]).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(ids(), [1, 2, 3]);
+        assert_eq!(reload(), 1);
+        assert_eq!(ids(), [9]);
+    }
+
+    #[test]
+    fn views_are_counted_in_memory_and_flushed_outside_maintenance() {
+        let _env = TestEnv::with(|_| {});
+        let path = config::get().views_file.clone();
+        let stored = || -> HashMap<i32, u64> {
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap()
+        };
+
+        // Views do not wait for the write lock, and reach the file on the next flush only.
+        let lock = write_lock();
+        record_view(1);
+        record_view(1);
+        drop(lock);
+        assert_eq!(view_counts(), HashMap::from([(1, 2)]));
+        assert!(std::fs::metadata(&path).is_err());
+        flush_views();
+        assert_eq!(stored(), HashMap::from([(1, 2)]));
+
+        READ_ONLY.store(true, Ordering::Release);
+        record_view(1);
+        READ_ONLY.store(false, Ordering::Release);
+        assert_eq!(view_counts(), HashMap::from([(1, 2)]));
+
+        // Removing a post forgets its views in memory and in the file.
+        forget_history(&[1]);
+        assert!(view_counts().is_empty());
+        assert!(stored().is_empty());
+    }
+
+    #[test]
+    fn views_are_not_counted_in_maintenance() {
+        let _env = TestEnv::with(|config| config.maintenance = true);
+        record_view(1);
+        assert!(view_counts().is_empty());
+    }
 }
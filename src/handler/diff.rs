@@ -0,0 +1,127 @@
+//! Differences between two revisions of a message's content.
+//!
+//! The revisions come from the edit history kept by the data layer (see
+//! [`crate::handler::data::revisions`]) and are compared line by line or word by word.
+
+use crate::handler::data;
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+
+/// The granularity of a diff.
+///
+/// # Variants
+/// - `Line`: Whole lines are inserted or deleted (the default).
+/// - `Word`: Words and the whitespace between them are inserted or deleted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DiffMode {
+    #[default]
+    Line,
+    Word,
+}
+
+impl DiffMode {
+    /// Parses a diff mode from its query-string name (`line` or `word`).
+    ///
+    /// Returns `None` for unknown names.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "line" => Some(DiffMode::Line),
+            "word" => Some(DiffMode::Word),
+            _ => None,
+        }
+    }
+
+    /// Returns the query-string name of the diff mode.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiffMode::Line => "line",
+            DiffMode::Word => "word",
+        }
+    }
+}
+
+/// A run of text that is kept, removed, or added between two revisions.
+///
+/// # Fields
+/// - `tag`: `equal`, `delete`, or `insert`.
+/// - `value`: The text of the run.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct DiffChange {
+    pub tag: &'static str,
+    pub value: String,
+}
+
+/// The difference between two revisions of a message.
+///
+/// # Fields
+/// - `id`: The ID of the message.
+/// - `from`: The older revision number.
+/// - `to`: The newer revision number.
+/// - `mode`: The granularity of the changes (`line` or `word`).
+/// - `changes`: The runs of text turning revision `from` into revision `to`, in order.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct RevisionDiff {
+    pub id: i32,
+    pub from: u32,
+    pub to: u32,
+    pub mode: &'static str,
+    pub changes: Vec<DiffChange>,
+}
+
+/// Compares two texts.
+///
+/// Adjacent changes with the same tag are merged, so a replaced paragraph is reported as one
+/// deletion followed by one insertion.
+pub fn diff(old: &str, new: &str, mode: DiffMode) -> Vec<DiffChange> {
+    let text_diff = match mode {
+        DiffMode::Line => TextDiff::from_lines(old, new),
+        DiffMode::Word => TextDiff::from_words(old, new),
+    };
+    let mut changes: Vec<DiffChange> = vec![];
+    for change in text_diff.iter_all_changes() {
+        let tag = match change.tag() {
+            ChangeTag::Equal => "equal",
+            ChangeTag::Delete => "delete",
+            ChangeTag::Insert => "insert",
+        };
+        match changes.last_mut() {
+            Some(last) if last.tag == tag => last.value.push_str(change.value()),
+            _ => changes.push(DiffChange {
+                tag,
+                value: change.value().to_string(),
+            }),
+        }
+    }
+    changes
+}
+
+/// Compares two stored revisions of a message.
+///
+/// # Arguments
+/// - `id`: The ID of the message.
+/// - `from`: The older revision, defaulting to the one before `to`.
+/// - `to`: The newer revision, defaulting to the latest one.
+/// - `mode`: The granularity of the diff.
+///
+/// # Returns
+/// - `Some(diff)` when both revisions exist.
+/// - `None` when the message or either revision does not exist.
+pub fn between(
+    id: i32,
+    from: Option<u32>,
+    to: Option<u32>,
+    mode: DiffMode,
+) -> Option<RevisionDiff> {
+    let revisions = data::revisions(id);
+    let to = to.or_else(|| revisions.last().map(|r| r.revision))?;
+    let from = from.unwrap_or_else(|| to.saturating_sub(1).max(1));
+    let find = |number: u32| revisions.iter().find(|r| r.revision == number);
+    let (old, new) = (find(from)?, find(to)?);
+    Some(RevisionDiff {
+        id,
+        from,
+        to,
+        mode: mode.as_str(),
+        changes: diff(&old.content, &new.content, mode),
+    })
+}
@@ -0,0 +1,285 @@
+//! Live notifications of newly created messages, served as Server-Sent Events.
+//!
+//! ## Endpoint
+//!
+//! - **`GET /api/stream`**: A `text/event-stream` of `post` events. Each event carries the
+//!   message as JSON, and its SSE `id` is the message ID. A client reconnecting with a
+//!   `Last-Event-ID: N` header first receives every message with an ID greater than `N`, then
+//!   live events; without the header only new messages are streamed.
+//!
+//!   Removed messages are announced by `delete` events carrying the journal entry of the
+//!   deletion (see [`crate::handler::data::Change`]). They have no SSE `id`, so they do not
+//!   move `Last-Event-ID`, and are not replayed: a reconnecting client catches up on deletions
+//!   through `GET /api/posts/changes`.
+//!
+//! At most `ACTIX_POSTS_MAX_SUBSCRIBERS` clients are streamed to at once (see
+//! [`crate::config`]); further ones receive `503 Service Unavailable` with the `OVERLOADED`
+//! code. The current number is reported by `GET /health`. A comment line is sent every
+//! [`HEARTBEAT_INTERVAL`], so a client that went away is noticed and its slot released even
+//! while no message is created.
+
+use crate::config;
+use crate::handler::api;
+use crate::handler::api::public::PublicMessage;
+use crate::handler::data;
+use crate::handler::data::{Change, Message};
+use actix_web::web::Bytes;
+use actix_web::{get, HttpRequest, HttpResponse, Responder};
+use futures_util::stream;
+use futures_util::StreamExt;
+use std::convert::Infallible;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::LazyLock;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+/// The number of events buffered for slow subscribers before they start missing events.
+const CHANNEL_CAPACITY: usize = 64;
+
+/// How often an idle stream sends a comment, which reveals clients that disconnected.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// A change announced to live event streams.
+///
+/// # Variants
+/// - `Created(message)`: A message was created.
+/// - `Deleted(change)`: A message was removed; `change` is its journal entry.
+#[derive(Debug, Clone)]
+pub enum Event {
+    Created(Message),
+    Deleted(Change),
+}
+
+static CHANNEL: LazyLock<broadcast::Sender<Event>> =
+    LazyLock::new(|| broadcast::channel(CHANNEL_CAPACITY).0);
+
+/// The number of clients currently connected to [`api_stream`].
+static SUBSCRIBERS: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the number of clients currently connected to the event stream.
+pub fn subscriber_count() -> usize {
+    SUBSCRIBERS.load(Ordering::Acquire)
+}
+
+/// Holds a slot in [`SUBSCRIBERS`] for the lifetime of a response stream, releasing it when
+/// the stream is dropped (i.e. when the client disconnects).
+struct Subscriber;
+
+impl Subscriber {
+    /// Takes a slot, or returns `None` when `ACTIX_POSTS_MAX_SUBSCRIBERS` are already connected.
+    fn join() -> Option<Self> {
+        let count = SUBSCRIBERS.fetch_add(1, Ordering::AcqRel);
+        match config::get().max_subscribers {
+            Some(max) if count >= max => {
+                SUBSCRIBERS.fetch_sub(1, Ordering::AcqRel);
+                None
+            }
+            _ => Some(Subscriber),
+        }
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        SUBSCRIBERS.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Subscribes to newly created and removed messages.
+///
+/// Subscribe before reading the store, so a message created in between is not missed.
+pub fn subscribe() -> broadcast::Receiver<Event> {
+    CHANNEL.subscribe()
+}
+
+/// Notifies every connected stream that a message was created.
+///
+/// Publishing never blocks and is a no-op when nobody is listening.
+pub fn publish(message: &Message) {
+    let _ = CHANNEL.send(Event::Created(message.clone()));
+}
+
+/// Notifies every connected stream that a message was removed.
+pub fn publish_deleted(change: Change) {
+    let _ = CHANNEL.send(Event::Deleted(change));
+}
+
+/// Formats a message as an SSE `post` event.
+fn format_post(message: &Message) -> Bytes {
+    let data = serde_json::to_string(&PublicMessage::from(message)).unwrap_or_default();
+    Bytes::from(format!(
+        "id: {}\nevent: post\ndata: {}\n\n",
+        message.id, data
+    ))
+}
+
+/// Formats an event: a `post` event for a creation, a `delete` event without `id` for a
+/// removal.
+fn format_event(event: &Event) -> Bytes {
+    match event {
+        Event::Created(message) => format_post(message),
+        Event::Deleted(change) => Bytes::from(format!(
+            "event: delete\ndata: {}\n\n",
+            serde_json::to_string(change).unwrap_or_default()
+        )),
+    }
+}
+
+/// Streams newly created messages, replaying the ones missed since `Last-Event-ID`.
+///
+/// The subscription is opened before the missed messages are read, so a message created in
+/// between is delivered exactly once: live events with an ID not greater than the last replayed
+/// one are dropped.
+#[get("/stream")]
+pub async fn api_stream(req: HttpRequest) -> impl Responder {
+    let Some(subscriber) = Subscriber::join() else {
+        return api::api_too_many_subscribers();
+    };
+    let receiver = subscribe();
+    let last_event_id: Option<i32> = req
+        .headers()
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok());
+    let missed = last_event_id.map(data::get_since).unwrap_or_default();
+    let replayed_up_to = missed
+        .iter()
+        .map(|m| m.id)
+        .max()
+        .or(last_event_id)
+        .unwrap_or(0);
+
+    let replay = stream::iter(missed.into_iter().map(Event::Created));
+    // The subscriber slot lives as long as the stream.
+    let live = stream::unfold(
+        (receiver, subscriber),
+        |(mut receiver, subscriber)| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((event, (receiver, subscriber))),
+                    Err(RecvError::Lagged(skipped)) => {
+                        log::warn!("event stream lagged, {} events skipped", skipped);
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        },
+    )
+    .filter(move |event| {
+        std::future::ready(match event {
+            Event::Created(message) => message.id > replayed_up_to,
+            Event::Deleted(_) => true,
+        })
+    });
+    let heartbeat = stream::unfold(
+        actix_rt::time::interval(HEARTBEAT_INTERVAL),
+        |mut interval| async move {
+            interval.tick().await;
+            Some((Bytes::from_static(b": heartbeat\n\n"), interval))
+        },
+    );
+    let events = stream::select(
+        replay.chain(live).map(|event| format_event(&event)),
+        heartbeat,
+    )
+    .map(Ok::<_, Infallible>);
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handler::data;
+    use crate::testing::{self, message, TestEnv};
+    use actix_web::body::MessageBody;
+    use actix_web::http::StatusCode;
+    use actix_web::test::{self, TestRequest};
+    use serde_json::Value;
+    use std::pin::pin;
+    use std::time::Duration;
+
+    /// Reads `body` until `count` post events arrived, and returns their SSE IDs.
+    async fn post_ids(body: impl MessageBody, count: usize) -> Vec<i32> {
+        let mut body = pin!(body);
+        let mut ids = vec![];
+        while ids.len() < count {
+            let chunk = std::future::poll_fn(|cx| body.as_mut().poll_next(cx));
+            let chunk = actix_rt::time::timeout(Duration::from_secs(5), chunk)
+                .await
+                .expect("an event in time")
+                .expect("an open stream");
+            let Ok(chunk) = chunk else {
+                panic!("the stream failed");
+            };
+            let chunk = String::from_utf8(chunk.to_vec()).unwrap();
+            ids.extend(
+                chunk
+                    .lines()
+                    .filter_map(|line| line.strip_prefix("id: ")?.parse::<i32>().ok()),
+            );
+        }
+        ids
+    }
+
+    #[actix_web::test]
+    async fn a_reconnecting_client_first_receives_the_posts_it_missed() {
+        let env = TestEnv::with(|_| {});
+        env.seed(&[
+            message(1, "Nao", "one"),
+            message(2, "Kai", "two"),
+            message(3, "Mio", "three"),
+        ]);
+        let app = testing::service!();
+
+        let request = TestRequest::get()
+            .uri("/api/stream")
+            .insert_header(("last-event-id", "1"));
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "text/event-stream"
+        );
+        let reconnected = response.into_body();
+
+        let request = TestRequest::get().uri("/api/stream");
+        let fresh = test::call_service(&app, request.to_request())
+            .await
+            .into_body();
+
+        data::create(message(0, "Nao", "four")).unwrap();
+        assert_eq!(post_ids(reconnected, 3).await, [2, 3, 4]);
+        assert_eq!(post_ids(fresh, 1).await, [4]);
+    }
+
+    #[actix_web::test]
+    async fn subscribers_beyond_the_limit_are_turned_away() {
+        let _env = TestEnv::with(|config| config.max_subscribers = Some(2));
+        let app = testing::service!();
+        let subscribe = || TestRequest::get().uri("/api/stream").to_request();
+        let subscribers = || async {
+            let request = TestRequest::get().uri("/health").to_request();
+            let body: Value = test::call_and_read_body_json(&app, request).await;
+            body["subscribers"].as_u64().unwrap()
+        };
+
+        let first = test::call_service(&app, subscribe()).await;
+        let second = test::call_service(&app, subscribe()).await;
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(second.status(), StatusCode::OK);
+        assert_eq!(subscribers().await, 2);
+        let third = test::call_service(&app, subscribe()).await;
+        assert_eq!(third.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body: Value = test::read_body_json(third).await;
+        assert_eq!(body["code"], "OVERLOADED");
+        assert_eq!(subscribers().await, 2);
+
+        drop(first);
+        assert_eq!(subscribers().await, 1);
+        let again = test::call_service(&app, subscribe()).await;
+        assert_eq!(again.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,507 @@
+//! Syndication feeds of the posted messages.
+//!
+//! ## Endpoints
+//!
+//! - **`GET /feed.xml`**: An RSS 2.0 feed. Accepts the following query parameters:
+//!   - `limit`: The number of items, between 1 and [`MAX_FEED_LIMIT`] (default [`DEFAULT_FEED_LIMIT`]).
+//!   - `order`: `newest` (default) or `oldest`.
+//!   - `sender`: Only include messages posted by this sender.
+//!   - `tag`: Only include messages carrying this tag, compared after normalization (so `Rust`
+//!     matches `rust`).
+//! - **`GET /feed.ics`**: An iCalendar document with one `VEVENT` per message, starting at its
+//!   `posted` time. Accepts the same `limit`, `order`, `sender`, and `tag` parameters. Messages whose
+//!   timestamp cannot be parsed are skipped.
+//! - **`GET /sitemap.xml`**: A sitemap listing the post index and the canonical URL of every post,
+//!   oldest first, with the time of its last change as `lastmod`. When there are more URLs than
+//!   `ACTIX_POSTS_SITEMAP_MAX_URLS`, it is a sitemap index pointing at `/sitemap.xml?page=N`
+//!   (starting at `1`) instead.
+
+use crate::config;
+use crate::handler::data;
+use crate::handler::data::{Message, SortOrder};
+use crate::handler::validation;
+use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+use chrono::Utc;
+use serde::Deserialize;
+
+/// The number of feed items returned when `limit` is not given.
+pub const DEFAULT_FEED_LIMIT: usize = 20;
+
+/// The largest accepted value of the `limit` parameter.
+pub const MAX_FEED_LIMIT: usize = 100;
+
+#[derive(Deserialize, Debug)]
+pub struct SitemapQuery {
+    page: Option<usize>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct FeedQuery {
+    limit: Option<usize>,
+    order: Option<String>,
+    sender: Option<String>,
+    tag: Option<String>,
+}
+
+/// Escapes the characters that are not allowed verbatim in XML text and attribute values.
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Builds the title of a feed entry from the sender and the first line of the content.
+fn entry_title(message: &Message) -> String {
+    let first_line = message.content.lines().next().unwrap_or_default();
+    format!("{}: {}", message.sender, first_line)
+}
+
+/// The maximum number of characters of content included in an event summary.
+const SUMMARY_SNIPPET_LEN: usize = 40;
+
+/// Escapes a value for use in an iCalendar text property (RFC 5545, section 3.3.11).
+fn escape_ics(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace(['\n', '\r'], "\\n")
+}
+
+/// Folds a content line so that no line exceeds 75 octets (RFC 5545, section 3.1).
+///
+/// Continuation lines start with a single space, and multi-byte characters are never split.
+fn fold_ics_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            width = 1;
+        }
+        folded.push(c);
+        width += c.len_utf8();
+    }
+    folded
+}
+
+/// Builds the summary of a calendar event from the sender and the start of the content.
+fn event_summary(message: &Message) -> String {
+    let snippet: String = message
+        .content
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .take(SUMMARY_SNIPPET_LEN)
+        .collect();
+    format!("{}: {}", message.sender, snippet)
+}
+
+/// Selects the messages to publish according to the feed query.
+///
+/// # Returns
+/// - `Ok(messages)` with at most `limit` messages in the requested order.
+/// - `Err(reason)` when `limit` or `order` is out of range.
+fn select_messages(query: &FeedQuery) -> Result<Vec<Message>, String> {
+    let limit = query.limit.unwrap_or(DEFAULT_FEED_LIMIT);
+    if !(1..=MAX_FEED_LIMIT).contains(&limit) {
+        return Err(format!("limit must be between 1 and {}", MAX_FEED_LIMIT));
+    }
+    let order = match query.order.as_deref() {
+        Some(order) => SortOrder::parse(order)
+            .ok_or_else(|| "order must be either newest or oldest".to_string())?,
+        None => SortOrder::Newest,
+    };
+    let tag = query
+        .tag
+        .as_ref()
+        .map(|tag| validation::normalize_tags(std::slice::from_ref(tag)));
+    Ok(data::get_all_sorted(order)
+        .into_iter()
+        .filter(|message| query.sender.iter().all(|sender| message.sender == *sender))
+        .filter(|message| tag.iter().flatten().all(|tag| message.tags.contains(tag)))
+        .take(limit)
+        .collect())
+}
+
+/// Serves the messages as an RSS 2.0 feed.
+///
+/// Item links are absolute URLs built with [`HttpRequest::url_for`], so they point at the host
+/// and base path the feed was requested from.
+///
+/// # Returns
+/// - `200 OK` with an `application/rss+xml` document.
+/// - `400 Bad Request` when `limit` or `order` is invalid.
+#[get("/feed.xml")]
+pub async fn rss(req: HttpRequest, query: web::Query<FeedQuery>) -> impl Responder {
+    let messages = match select_messages(&query) {
+        Ok(messages) => messages,
+        Err(reason) => return HttpResponse::BadRequest().body(reason),
+    };
+    let channel_link = req
+        .url_for_static("index")
+        .map(|url| url.to_string())
+        .unwrap_or_default();
+
+    let items: String = messages
+        .iter()
+        .map(|message| {
+            let link = req
+                .url_for("show", [message.id.to_string()])
+                .map(|url| url.to_string())
+                .unwrap_or_default();
+            let pub_date = message.posted_at()
+                .map(|posted| format!("<pubDate>{}</pubDate>", posted.to_rfc2822()))
+                .unwrap_or_default();
+            format!(
+                "<item><title>{}</title><link>{}</link><guid>{}</guid>{}<description>{}</description></item>",
+                escape_xml(&entry_title(message)),
+                escape_xml(&link),
+                escape_xml(&link),
+                pub_date,
+                escape_xml(&message.content),
+            )
+        })
+        .collect();
+    let body = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?><rss version="2.0"><channel><title>Posts</title><link>{}</link><description>Posts</description>{}</channel></rss>"#,
+        escape_xml(&channel_link),
+        items
+    );
+
+    HttpResponse::Ok()
+        .content_type("application/rss+xml; charset=utf-8")
+        .body(body)
+}
+
+/// Serves the messages as an iCalendar (`text/calendar`) document.
+///
+/// Each message becomes a `VEVENT` whose `DTSTART` is the `posted` time converted to UTC and
+/// whose summary is the sender followed by a snippet of the content. Messages whose `posted`
+/// value cannot be parsed are skipped rather than failing the whole document.
+///
+/// # Returns
+/// - `200 OK` with a `text/calendar` document.
+/// - `400 Bad Request` when `limit` or `order` is invalid.
+#[get("/feed.ics")]
+pub async fn ics(req: HttpRequest, query: web::Query<FeedQuery>) -> impl Responder {
+    let messages = match select_messages(&query) {
+        Ok(messages) => messages,
+        Err(reason) => return HttpResponse::BadRequest().body(reason),
+    };
+    let host = req.connection_info().host().to_string();
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//actix-posts//Posts//JA".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+    for message in &messages {
+        let Some(posted) = message.posted_at() else {
+            continue;
+        };
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:post-{}@{}", message.id, host));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        lines.push(format!(
+            "DTSTART:{}",
+            posted.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ")
+        ));
+        lines.push(format!("SUMMARY:{}", escape_ics(&event_summary(message))));
+        lines.push(format!("DESCRIPTION:{}", escape_ics(&message.content)));
+        if let Ok(url) = req.url_for("show", [message.id.to_string()]) {
+            lines.push(format!("URL:{}", url));
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let body: String = lines
+        .iter()
+        .map(|line| fold_ics_line(line) + "\r\n")
+        .collect();
+    HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .body(body)
+}
+
+/// Formats an entry of a sitemap `urlset`.
+fn sitemap_url(loc: &str, lastmod: Option<String>) -> String {
+    let lastmod = lastmod
+        .map(|lastmod| format!("<lastmod>{}</lastmod>", lastmod))
+        .unwrap_or_default();
+    format!("<url><loc>{}</loc>{}</url>", escape_xml(loc), lastmod)
+}
+
+/// Serves the sitemap of the board, or one page of it.
+///
+/// URLs are absolute and built with [`HttpRequest::url_for`], so they honor the host and base
+/// path the sitemap was requested from. `lastmod` is the `updated` time of a post, falling back
+/// to `posted` (see [`Message::modified_at`]); the index uses the latest of them.
+///
+/// # Returns
+/// - `200 OK` with an `application/xml` `urlset`, or a `sitemapindex` when the URLs do not fit
+///   in one document and no `page` was requested.
+/// - `404 Not Found` for a `page` past the last one.
+#[get("/sitemap.xml")]
+pub async fn sitemap(req: HttpRequest, query: web::Query<SitemapQuery>) -> impl Responder {
+    let messages = data::get_all_shared(SortOrder::Oldest);
+    let index_lastmod = messages.iter().filter_map(Message::modified_at).max();
+    let mut urls = vec![sitemap_url(
+        &req.url_for_static("index")
+            .map(|url| url.to_string())
+            .unwrap_or_default(),
+        index_lastmod.map(|lastmod| lastmod.to_rfc3339()),
+    )];
+    urls.extend(messages.iter().map(|message| {
+        sitemap_url(
+            &req.url_for("show", [message.id.to_string()])
+                .map(|url| url.to_string())
+                .unwrap_or_default(),
+            message.modified_at().map(|lastmod| lastmod.to_rfc3339()),
+        )
+    }));
+
+    let max_urls = config::get().sitemap_max_urls;
+    let pages = urls.len().div_ceil(max_urls);
+    let body = match query.page {
+        None if pages > 1 => {
+            let base = req
+                .url_for_static("sitemap")
+                .map(|url| url.to_string())
+                .unwrap_or_default();
+            let sitemaps: String = (1..=pages)
+                .map(|page| {
+                    format!(
+                        "<sitemap><loc>{}</loc></sitemap>",
+                        escape_xml(&format!("{}?page={}", base, page))
+                    )
+                })
+                .collect();
+            format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?><sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">{}</sitemapindex>"#,
+                sitemaps
+            )
+        }
+        page => {
+            let page = page.unwrap_or(1);
+            if page == 0 || page > pages {
+                return HttpResponse::NotFound().body("Page Not Found!");
+            }
+            let start = (page - 1) * max_urls;
+            let urls: String = urls[start..urls.len().min(start + max_urls)].concat();
+            format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?><urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">{}</urlset>"#,
+                urls
+            )
+        }
+    };
+
+    HttpResponse::Ok()
+        .content_type("application/xml; charset=utf-8")
+        .body(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{self, message, TestEnv};
+    use actix_web::http::StatusCode;
+    use actix_web::test::{self, TestRequest};
+
+    /// Returns the post IDs linked by the items of an RSS document, in document order.
+    fn item_ids(rss: &str) -> Vec<i32> {
+        rss.split("<guid>")
+            .skip(1)
+            .map(|item| {
+                let link = &item[..item.find("</guid>").unwrap()];
+                link.rsplit('/').next().unwrap().parse().unwrap()
+            })
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn rss_honors_limit_order_and_filters() {
+        let env = TestEnv::with(|_| {});
+        let messages: Vec<_> = (1..=25)
+            .map(|id| {
+                let mut message = message(id, if id % 2 == 0 { "Kai" } else { "Nao" }, "hello");
+                if id % 5 == 0 {
+                    message.tags = vec!["rust".to_string()];
+                }
+                message
+            })
+            .collect();
+        env.seed(&messages);
+        let app = testing::service!();
+        let fetch = |uri: &'static str| TestRequest::get().uri(uri).to_request();
+
+        let body = test::call_and_read_body(&app, fetch("/feed.xml")).await;
+        let ids = item_ids(&String::from_utf8_lossy(&body));
+        assert_eq!(ids, (6..=25).rev().collect::<Vec<_>>());
+
+        let body = test::call_and_read_body(&app, fetch("/feed.xml?limit=3&order=oldest")).await;
+        assert_eq!(item_ids(&String::from_utf8_lossy(&body)), [1, 2, 3]);
+
+        let body = test::call_and_read_body(&app, fetch("/feed.xml?sender=Kai&limit=2")).await;
+        assert_eq!(item_ids(&String::from_utf8_lossy(&body)), [24, 22]);
+
+        let body = test::call_and_read_body(&app, fetch("/feed.xml?tag=Rust")).await;
+        assert_eq!(
+            item_ids(&String::from_utf8_lossy(&body)),
+            [25, 20, 15, 10, 5]
+        );
+
+        for uri in [
+            "/feed.xml?limit=0",
+            "/feed.xml?limit=101",
+            "/feed.xml?order=random",
+        ] {
+            let response = test::call_service(&app, fetch(uri)).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", uri);
+        }
+    }
+
+    #[actix_web::test]
+    async fn ics_is_a_valid_calendar_of_the_parsable_posts() {
+        let env = TestEnv::with(|_| {});
+        let mut broken = message(2, "Kai", "no date");
+        broken.posted = "yesterday".to_string();
+        env.seed(&[
+            message(1, "Nao", "hello, world; a \\ backslash\nsecond line"),
+            broken,
+            message(3, "Mio", &"長い本文".repeat(30)),
+        ]);
+        let app = testing::service!();
+
+        let response =
+            test::call_service(&app, TestRequest::get().uri("/feed.ics").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "text/calendar; charset=utf-8"
+        );
+        let body = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        assert!(body.ends_with("END:VCALENDAR\r\n"));
+        let lines: Vec<&str> = body.trim_end_matches("\r\n").split("\r\n").collect();
+        assert!(lines
+            .iter()
+            .all(|line| !line.contains('\n') && line.len() <= 75));
+        // Unfolds the continuation lines.
+        let unfolded = body.replace("\r\n ", "");
+        let lines: Vec<&str> = unfolded.trim_end_matches("\r\n").split("\r\n").collect();
+        assert_eq!(lines.first(), Some(&"BEGIN:VCALENDAR"));
+        assert_eq!(lines.last(), Some(&"END:VCALENDAR"));
+        assert!(lines.contains(&"VERSION:2.0"));
+        assert!(lines.iter().any(|line| line.starts_with("PRODID:")));
+        let count = |line: &str| lines.iter().filter(|l| **l == line).count();
+        assert_eq!(count("BEGIN:VEVENT"), 2);
+        assert_eq!(count("END:VEVENT"), 2);
+        let starts: Vec<&str> = lines
+            .iter()
+            .filter_map(|line| line.strip_prefix("DTSTART:"))
+            .collect();
+        assert_eq!(starts, ["20240101T000003Z", "20240101T000001Z"]);
+        assert!(lines.contains(&"SUMMARY:Nao: hello\\, world\\; a \\\\ backslash"));
+        assert!(lines.contains(&"DESCRIPTION:hello\\, world\\; a \\\\ backslash\\nsecond line"));
+        let uids: Vec<&str> = lines
+            .iter()
+            .filter_map(|line| line.strip_prefix("UID:")?.split('@').next())
+            .collect();
+        assert_eq!(uids, ["post-3", "post-1"]);
+
+        let request = TestRequest::get().uri("/feed.ics?limit=1").to_request();
+        let body = test::call_and_read_body(&app, request).await;
+        let body = String::from_utf8_lossy(&body);
+        assert_eq!(body.matches("BEGIN:VEVENT").count(), 1);
+        assert!(body.contains("DTSTART:20240101T000003Z"));
+    }
+
+    /// Returns the `(loc, lastmod)` pairs of a sitemap `urlset`, in document order.
+    fn sitemap_urls(xml: &str) -> Vec<(String, Option<String>)> {
+        let between = |text: &str, start: &str, end: &str| {
+            let from = text.find(start)? + start.len();
+            Some(text[from..from + text[from..].find(end)?].to_string())
+        };
+        xml.split("<url>")
+            .skip(1)
+            .map(|url| {
+                (
+                    between(url, "<loc>", "</loc>").unwrap(),
+                    between(url, "<lastmod>", "</lastmod>"),
+                )
+            })
+            .collect()
+    }
+
+    #[actix_web::test]
+    async fn sitemap_lists_every_post_with_its_last_change() {
+        let env = TestEnv::with(|_| {});
+        let mut edited = message(2, "Kai", "edited");
+        edited.updated = "2024-02-01 10:00:00Z".to_string();
+        env.seed(&[message(1, "Nao", "hello"), edited]);
+        let app = testing::service!();
+        let instant = |lastmod: &Option<String>| {
+            chrono::DateTime::parse_from_rfc3339(lastmod.as_deref().unwrap())
+                .unwrap()
+                .to_utc()
+                .to_rfc3339()
+        };
+
+        let request = TestRequest::get().uri("/sitemap.xml").to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get("content-type").unwrap(),
+            "application/xml; charset=utf-8"
+        );
+        let xml = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        let urls = sitemap_urls(&xml);
+        let locs: Vec<&str> = urls.iter().map(|(loc, _)| loc.as_str()).collect();
+        assert_eq!(
+            locs,
+            [
+                "http://localhost:8080/posts",
+                "http://localhost:8080/posts/1",
+                "http://localhost:8080/posts/2",
+            ]
+        );
+        let lastmods: Vec<String> = urls.iter().map(|(_, lastmod)| instant(lastmod)).collect();
+        assert_eq!(
+            lastmods,
+            [
+                "2024-02-01T10:00:00+00:00",
+                "2024-01-01T00:00:01+00:00",
+                "2024-02-01T10:00:00+00:00",
+            ]
+        );
+    }
+
+    #[actix_web::test]
+    async fn large_sitemaps_are_split_into_pages() {
+        let env = TestEnv::with(|config| config.sitemap_max_urls = 2);
+        env.seed(&[message(1, "Nao", "one"), message(2, "Kai", "two")]);
+        let app = testing::service!();
+        let get = |uri: &str| TestRequest::get().uri(uri).to_request();
+
+        let xml = test::call_and_read_body(&app, get("/sitemap.xml")).await;
+        let xml = String::from_utf8_lossy(&xml);
+        assert!(xml.contains("<sitemapindex"));
+        assert!(xml.contains("<loc>http://localhost:8080/sitemap.xml?page=1</loc>"));
+        assert!(xml.contains("<loc>http://localhost:8080/sitemap.xml?page=2</loc>"));
+        assert!(!xml.contains("page=3"));
+
+        let xml = test::call_and_read_body(&app, get("/sitemap.xml?page=2")).await;
+        let urls = sitemap_urls(&String::from_utf8_lossy(&xml));
+        assert_eq!(urls.len(), 1);
+        assert_eq!(urls[0].0, "http://localhost:8080/posts/2");
+        let response = test::call_service(&app, get("/sitemap.xml?page=3")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,100 @@
+//! Moderation of filtered words.
+//!
+//! The words listed in `ACTIX_POSTS_FILTER_WORDS` are matched case-insensitively in the sender
+//! and content of new and updated messages. Depending on `ACTIX_POSTS_FILTER_MODE` (see
+//! [`crate::config`]), such messages are either rejected by [`Message::validate`] or stored with
+//! every occurrence replaced by asterisks (see [`mask_message`]).
+
+use crate::config;
+use crate::handler::data::Message;
+
+/// What happens to a message containing a filtered word.
+///
+/// # Variants
+/// - `Reject`: Validation fails with a field error (the default).
+/// - `Mask`: The message is accepted and each occurrence is replaced by `*`s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FilterMode {
+    #[default]
+    Reject,
+    Mask,
+}
+
+impl FilterMode {
+    /// Parses a filter mode from its configuration name (`reject` or `mask`).
+    ///
+    /// Returns `None` for unknown names.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "reject" => Some(FilterMode::Reject),
+            "mask" => Some(FilterMode::Mask),
+            _ => None,
+        }
+    }
+}
+
+fn chars_eq_ignore_case(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+/// Returns the `(start, length)` in characters of every occurrence of a filtered word.
+fn occurrences(text: &[char], words: &[String]) -> Vec<(usize, usize)> {
+    let words: Vec<Vec<char>> = words.iter().map(|word| word.chars().collect()).collect();
+    let mut found = vec![];
+    let mut start = 0;
+    while start < text.len() {
+        let matched = words
+            .iter()
+            .filter(|word| !word.is_empty() && start + word.len() <= text.len())
+            .filter(|word| {
+                text[start..start + word.len()]
+                    .iter()
+                    .zip(word.iter())
+                    .all(|(a, b)| chars_eq_ignore_case(*a, *b))
+            })
+            .map(|word| word.len())
+            .max();
+        match matched {
+            Some(length) => {
+                found.push((start, length));
+                start += length;
+            }
+            None => start += 1,
+        }
+    }
+    found
+}
+
+/// Returns `true` when `text` contains one of the configured filtered words.
+pub fn contains_filtered(text: &str) -> bool {
+    let words = &config::get().filter_words;
+    !words.is_empty() && !occurrences(&text.chars().collect::<Vec<_>>(), words).is_empty()
+}
+
+/// Replaces every occurrence of the given words in `text` by as many `*` as it has characters.
+///
+/// # Example
+/// ```rust
+/// use actix_posts::handler::filter::mask;
+/// let words = vec!["darn".to_string()];
+/// assert_eq!(mask("Darn it, darnit", &words), "**** it, ****it");
+/// ```
+pub fn mask(text: &str, words: &[String]) -> String {
+    let mut chars: Vec<char> = text.chars().collect();
+    for (start, length) in occurrences(&chars, words) {
+        chars[start..start + length].fill('*');
+    }
+    chars.into_iter().collect()
+}
+
+/// Masks the filtered words in the sender and content of `message` when the filter runs in
+/// [`FilterMode::Mask`]. Does nothing in [`FilterMode::Reject`], where validation already
+/// refused such messages.
+pub fn mask_message(message: &mut Message) {
+    let config = config::get();
+    if config.filter_mode != FilterMode::Mask || config.filter_words.is_empty() {
+        return;
+    }
+    message.sender = mask(&message.sender, &config.filter_words);
+    message.content = mask(&message.content, &config.filter_words);
+}
@@ -0,0 +1,65 @@
+//! Service health reporting and recovery from storage failures.
+//!
+//! ## Endpoint
+//!
+//! - **`GET /health`**: Reports whether the board accepts writes. Always answers `200 OK` while
+//!   the server runs, since reads keep working in every mode:
+//!
+//! ```json
+//! { "status": "read_only", "writable": false, "maintenance": false, "subscribers": 2 }
+//! ```
+//!
+//! `status` is `ok`, `maintenance` (writes disabled by configuration), or `read_only` (the data
+//! file cannot be written, see [`crate::handler::data::is_read_only`]). `subscribers` is the
+//! number of clients connected to the event stream (see [`crate::handler::events`]).
+
+use crate::config;
+use crate::handler::data;
+use crate::handler::events;
+use actix_web::{get, HttpResponse, Responder};
+use serde::Serialize;
+use std::time::Duration;
+
+/// How often the data file is probed while the store is read-only.
+const PROBE_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Debug)]
+struct Health {
+    status: &'static str,
+    writable: bool,
+    maintenance: bool,
+    subscribers: usize,
+}
+
+#[get("/health")]
+pub async fn health() -> impl Responder {
+    let maintenance = config::get().maintenance;
+    let read_only = data::is_read_only();
+    let status = if read_only {
+        "read_only"
+    } else if maintenance {
+        "maintenance"
+    } else {
+        "ok"
+    };
+    HttpResponse::Ok().json(Health {
+        status,
+        writable: !read_only && !maintenance,
+        maintenance,
+        subscribers: events::subscriber_count(),
+    })
+}
+
+/// Probes the data file every [`PROBE_INTERVAL`] while the store is read-only, so the board
+/// recovers without waiting for a write request (which is rejected in that mode).
+///
+/// Runs until the server stops; spawn it once at startup.
+pub async fn probe_storage() {
+    let mut interval = actix_rt::time::interval(PROBE_INTERVAL);
+    loop {
+        interval.tick().await;
+        if data::is_read_only() {
+            data::probe_writable();
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Placeholders expanded in the content of a message when it is shown.
+//!
+//! A post may contain `{{date}}`, `{{sender}}`, or `{{id}}`, which the post page replaces by the
+//! day it was posted, its sender, and its ID. The stored content keeps the placeholders.
+//!
+//! Only the names in this fixed list are recognized. Expansion is a single pass of plain string
+//! substitution: the content is never evaluated as a template, and a value that itself contains
+//! `{{...}}` (e.g. a sender named `{{id}}`) is inserted literally. Unknown names and unclosed
+//! braces are left as written.
+
+use crate::handler::data::{Message, DATE_FORMAT};
+
+/// Opens a placeholder.
+const OPEN: &str = "{{";
+
+/// Closes a placeholder.
+const CLOSE: &str = "}}";
+
+/// Returns the value of the placeholder `name` for `message`, or `None` for unknown names.
+///
+/// `date` is `None` as well when `posted` cannot be parsed, so the placeholder stays visible.
+fn value(message: &Message, name: &str) -> Option<String> {
+    match name {
+        "date" => message
+            .posted_at()
+            .map(|posted| posted.format(DATE_FORMAT).to_string()),
+        "sender" => Some(message.sender.clone()),
+        "id" => Some(message.id.to_string()),
+        _ => None,
+    }
+}
+
+/// Returns the content of `message` with its placeholders expanded.
+///
+/// Spaces inside the braces are ignored, so `{{ date }}` works too.
+///
+/// # Example
+/// ```rust
+/// use actix_posts::handler::data::Message;
+/// use actix_posts::handler::macros::expand;
+/// let message = Message {
+///     id: 7,
+///     posted: "2023-10-10 01:23:45".to_string(),
+///     sender: "Nao".to_string(),
+///     content: "{{sender}} on {{ date }}: see {{unknown}} and {{id".to_string(),
+///     ..Default::default()
+/// };
+/// assert_eq!(expand(&message), "Nao on 2023-10-10: see {{unknown}} and {{id");
+/// ```
+pub fn expand(message: &Message) -> String {
+    let mut expanded = String::with_capacity(message.content.len());
+    let mut rest = message.content.as_str();
+    while let Some(start) = rest.find(OPEN) {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + OPEN.len()..];
+        let placeholder = after
+            .find(CLOSE)
+            .and_then(|end| Some((end, value(message, after[..end].trim())?)));
+        match placeholder {
+            Some((end, value)) => {
+                expanded.push_str(&value);
+                rest = &after[end + CLOSE.len()..];
+            }
+            None => {
+                expanded.push_str(OPEN);
+                rest = after;
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
@@ -0,0 +1,45 @@
+//! JSON Merge Patch (RFC 7386).
+//!
+//! A merge patch is a JSON document shaped like its target: every member replaces the member of
+//! the same name, `null` removes it, nested objects are merged recursively, and absent members
+//! are left untouched. Anything other than an object, arrays included, replaces the target
+//! wholesale. `PATCH /api/posts/{id}` applies such a patch when sent with
+//! [`MERGE_PATCH_CONTENT_TYPE`].
+
+use serde_json::Value;
+
+/// The media type of a JSON Merge Patch body.
+pub const MERGE_PATCH_CONTENT_TYPE: &str = "application/merge-patch+json";
+
+/// Applies the merge patch `patch` to `target`, as specified by RFC 7386.
+///
+/// # Example
+/// ```rust
+/// use actix_posts::handler::merge::merge_patch;
+/// use serde_json::json;
+/// let mut target = json!({ "a": "b", "c": { "d": "e", "f": "g" } });
+/// merge_patch(&mut target, &json!({ "a": "z", "c": { "f": null } }));
+/// assert_eq!(target, json!({ "a": "z", "c": { "d": "e" } }));
+/// let mut target = json!({ "a": ["b"] });
+/// merge_patch(&mut target, &json!({ "a": ["c", "d"] }));
+/// assert_eq!(target, json!({ "a": ["c", "d"] }));
+/// ```
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    let Value::Object(target) = target else {
+        return;
+    };
+    for (key, value) in patch {
+        if value.is_null() {
+            target.remove(key);
+        } else {
+            merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+        }
+    }
+}
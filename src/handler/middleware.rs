@@ -0,0 +1,272 @@
+//! Application-wide middleware functions.
+//!
+//! The functions in this module are meant to be registered with
+//! [`actix_web::middleware::from_fn`] in [`crate::app::build`].
+
+use crate::config;
+use crate::handler::{api, data, routes};
+use actix_web::body::{to_bytes, BoxBody, MessageBody};
+use actix_web::dev::{ResourceDef, ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{web, Error};
+use serde::Deserialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The number of requests currently handled by [`concurrency_limit`].
+static IN_FLIGHT: AtomicUsize = AtomicUsize::new(0);
+
+/// The delay clients are asked to wait before retrying a shed request, in seconds.
+pub const RETRY_AFTER_SECONDS: u64 = 1;
+
+/// Releases a slot taken in [`IN_FLIGHT`] when dropped, even if the handler fails.
+struct InFlight;
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        IN_FLIGHT.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// The HTML routes that modify the stored messages although they are reached with `GET`: the
+/// delete link of the post page.
+const HTML_MUTATIONS: &[&str] = &["/posts/{id}/delete"];
+
+/// The HTML routes that accept `POST` but only change the session, not the store.
+const HTML_READS: &[&str] = &["/login", "/logout"];
+
+/// The API routes that accept a non-safe HTTP method but only read the store.
+const API_READS: &[&str] = &[
+    "/api/posts/preview",
+    "/api/v1/posts/preview",
+    "/api/export/sign",
+    "/api/v1/export/sign",
+];
+
+/// Returns `true` when the request would modify the stored messages.
+///
+/// Requests with a non-safe HTTP method do, except for the routes of [`HTML_READS`] and
+/// [`API_READS`]; `GET` requests only do on the routes of [`HTML_MUTATIONS`].
+fn is_mutation(req: &ServiceRequest) -> bool {
+    let path = req.path().trim_end_matches('/');
+    let any_of = |patterns: &[&str]| {
+        patterns
+            .iter()
+            .any(|pattern| ResourceDef::new(*pattern).is_match(path))
+    };
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) {
+        any_of(HTML_MUTATIONS)
+    } else {
+        !any_of(HTML_READS) && !any_of(API_READS)
+    }
+}
+
+/// Rejects mutating requests with `503 Service Unavailable` while maintenance mode is enabled or
+/// the store is read-only because its data file cannot be written (see [`data::is_read_only`]).
+///
+/// API requests receive the usual JSON error envelope, while web requests receive a plain-text
+/// page. Read-only requests are passed through untouched.
+pub async fn maintenance_guard(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    if config::get().maintenance && is_mutation(&req) {
+        let response = if req.path().starts_with("/api") {
+            api::api_maintenance()
+        } else {
+            routes::maintenance()
+        };
+        return Ok(req.into_response(response));
+    }
+    if data::is_read_only() && is_mutation(&req) {
+        let response = if req.path().starts_with("/api") {
+            api::api_read_only()
+        } else {
+            routes::read_only()
+        };
+        return Ok(req.into_response(response));
+    }
+    Ok(next.call(req).await?.map_into_boxed_body())
+}
+
+#[derive(Deserialize)]
+struct IdQueries {
+    #[serde(default)]
+    id_as_string: bool,
+}
+
+/// Serializes message IDs as JSON strings (`"id": "42"`) in the JSON responses of requests with
+/// `?id_as_string=true`, for clients such as JavaScript that lose precision on large integers.
+///
+/// Other responses, and requests without the parameter, are passed through untouched. Message
+/// IDs are accepted as strings on input regardless (see [`data::deserialize_id`]). Meant to wrap
+/// the API scopes.
+pub async fn string_ids(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let enabled = web::Query::<IdQueries>::from_query(req.query_string())
+        .map(|query| query.id_as_string)
+        .unwrap_or(false);
+    let response = next.call(req).await?.map_into_boxed_body();
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+    if !enabled || !is_json {
+        return Ok(response);
+    }
+    let (req, response) = response.into_parts();
+    let (response, body) = response.into_parts();
+    let body = to_bytes(body).await?;
+    let body = match serde_json::from_slice::<serde_json::Value>(&body) {
+        Ok(mut value) => {
+            api::stringify_ids(&mut value);
+            serde_json::to_vec(&value).map_or(body, Into::into)
+        }
+        Err(_) => body,
+    };
+    let mut response = response.set_body(body).map_into_boxed_body();
+    response.headers_mut().remove(header::CONTENT_LENGTH);
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    Ok(ServiceResponse::new(req, response))
+}
+
+/// Sheds requests beyond `ACTIX_POSTS_MAX_IN_FLIGHT` concurrent ones with
+/// `503 Service Unavailable` and a `Retry-After` header.
+///
+/// A request occupies a slot until its handler returns a response; streamed bodies (e.g.
+/// `/api/stream`) are not counted while they are being sent. Without a configured limit every
+/// request is passed through.
+pub async fn concurrency_limit(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    let Some(max) = config::get().max_in_flight else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    if IN_FLIGHT.fetch_add(1, Ordering::AcqRel) >= max {
+        IN_FLIGHT.fetch_sub(1, Ordering::AcqRel);
+        let response = if req.path().starts_with("/api") {
+            api::api_overloaded()
+        } else {
+            routes::overloaded()
+        };
+        return Ok(req.into_response(response));
+    }
+    let _slot = InFlight;
+    Ok(next.call(req).await?.map_into_boxed_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{self, message, with_key, TestEnv};
+    use actix_web::http::{header, Method, StatusCode};
+    use actix_web::test::{self, TestRequest};
+
+    #[actix_web::test]
+    async fn maintenance_rejects_mutations_and_serves_reads() {
+        let env = TestEnv::with(|config| {
+            config.maintenance = true;
+            config.users = vec![format!("nao:{}", testing::SECRET_HASH).parse().unwrap()];
+        });
+        env.seed(&[message(1, "Nao", "hello")]);
+        let app = testing::service!();
+
+        let mutations = [
+            (Method::POST, "/api/posts"),
+            (Method::PUT, "/api/posts"),
+            (Method::PATCH, "/api/posts"),
+            (Method::DELETE, "/api/posts"),
+            (Method::POST, "/api/posts/create"),
+            (Method::PUT, "/api/posts/update"),
+            (Method::PATCH, "/api/posts/1"),
+            (Method::DELETE, "/api/posts/1/delete"),
+            (Method::DELETE, "/api/v1/posts/1/delete"),
+            (Method::POST, "/posts/create"),
+            (Method::GET, "/posts/1/delete"),
+            (Method::GET, "/posts/1/delete/"),
+        ];
+        for (method, path) in mutations {
+            let request = with_key(TestRequest::default().method(method.clone()).uri(path));
+            let response = test::call_service(&app, request.to_request()).await;
+            assert_eq!(
+                response.status(),
+                StatusCode::SERVICE_UNAVAILABLE,
+                "{} {}",
+                method,
+                path
+            );
+        }
+
+        for path in ["/api/posts", "/api/posts/1", "/posts", "/posts/1", "/login"] {
+            let response =
+                test::call_service(&app, TestRequest::get().uri(path).to_request()).await;
+            assert_eq!(response.status(), StatusCode::OK, "GET {}", path);
+        }
+        let request = testing::json(
+            TestRequest::post().uri("/api/posts/preview"),
+            serde_json::json!({ "sender": "Nao", "content": "draft" }),
+        );
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let request = with_key(TestRequest::post().uri("/api/export/sign"));
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        // Logging in and out only touches the session, so both stay available.
+        for (path, target) in [("/login", "/posts/new"), ("/logout", "/posts")] {
+            let request = TestRequest::post()
+                .uri(path)
+                .set_form([("name", "nao"), ("password", "secret")]);
+            let response = test::call_service(&app, request.to_request()).await;
+            assert_eq!(response.status(), StatusCode::SEE_OTHER, "{}", path);
+            assert_eq!(response.headers().get(header::LOCATION).unwrap(), target);
+        }
+        assert_eq!(env.stored().len(), 1);
+    }
+
+    #[actix_web::test]
+    async fn requests_beyond_the_in_flight_limit_are_shed() {
+        let env = TestEnv::with(|config| config.max_in_flight = Some(2));
+        env.seed(&[message(1, "Nao", "hello")]);
+        let app = testing::service!();
+        // Each poll holds its slot for a second, since no post newer than 1 arrives.
+        let poll = || {
+            test::call_service(
+                &app,
+                TestRequest::get()
+                    .uri("/api/posts/poll?since=1&timeout=1")
+                    .to_request(),
+            )
+        };
+
+        let (first, second, shed) = futures_util::join!(poll(), poll(), async {
+            actix_rt::time::sleep(std::time::Duration::from_millis(100)).await;
+            let mut statuses = vec![];
+            for path in ["/api/posts", "/posts"] {
+                let response =
+                    test::call_service(&app, TestRequest::get().uri(path).to_request()).await;
+                let retry_after = response.headers().get(header::RETRY_AFTER).cloned();
+                statuses.push((response.status(), retry_after));
+            }
+            statuses
+        });
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(second.status(), StatusCode::OK);
+        for (status, retry_after) in shed {
+            assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+            assert_eq!(
+                retry_after.unwrap().to_str().unwrap(),
+                super::RETRY_AFTER_SECONDS.to_string()
+            );
+        }
+
+        let response =
+            test::call_service(&app, TestRequest::get().uri("/api/posts").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK, "slots are released");
+    }
+}
@@ -1,21 +1,195 @@
+use crate::config;
+use crate::handler::auth;
+use crate::handler::auth::{EditRefused, LoginRequired};
+use crate::handler::client_ip::request_metadata;
 use crate::handler::data;
-use crate::handler::data::Message;
+use crate::handler::data::{DataError, Message, PostStatus, SortOrder, StatusFilter};
+use crate::handler::diff;
+use crate::handler::diff::DiffMode;
+use crate::handler::macros;
+use crate::handler::middleware::RETRY_AFTER_SECONDS;
+use crate::handler::signing::UrlSigner;
+use crate::handler::strings;
+use crate::handler::strings::Text;
+use crate::handler::validation;
+use crate::handler::validation::{FieldError, ANONYMOUS_SENDER, REACTIONS};
 use actix_session::Session;
-use actix_web::{get, post, web, HttpResponse, Responder};
+use actix_web::http::{header, StatusCode};
+use actix_web::{get, post, web, Either, HttpRequest, HttpResponse, Responder};
 use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages, Level};
 use chrono::{DateTime, Local};
 use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
 use tera::Context;
 
-#[get("/posts")]
-pub async fn index(tmpl: web::Data<tera::Tera>, messages: IncomingFlashMessages) -> impl Responder {
-    let posts = data::get_all();
+/// Session key under which the sender name of the last successful post is remembered.
+const SENDER_SESSION_KEY: &str = "sender";
+
+/// Session key under which the Unix time of the last successful post is remembered.
+const LAST_POST_SESSION_KEY: &str = "last_post";
+
+/// Creates a template context pre-populated with the values shared by every page.
+fn base_context() -> Context {
     let mut context = Context::new();
-    messages.iter().for_each(|message| match message.level() {
-        Level::Success => context.insert("success", message.content()),
-        Level::Error => context.insert("error", message.content()),
-        _ => (),
-    });
+    context.insert("maintenance", &config::get().maintenance);
+    context
+}
+
+/// Groups the incoming flash messages by level.
+///
+/// Every message is kept, so several flashes of the same level are all rendered instead of the
+/// last one overwriting the others.
+///
+/// # Returns
+/// A map from the template variable name (`successes`, `errors`, `infos`) to the contents of
+/// the messages of that level, ready to be inserted into a [`Context`]. Every key is present,
+/// even when no message of that level was received.
+fn collect_flashes<'a>(
+    messages: impl IntoIterator<Item = &'a FlashMessage>,
+) -> HashMap<&'static str, Vec<String>> {
+    let mut flashes = HashMap::from([("successes", vec![]), ("errors", vec![]), ("infos", vec![])]);
+    for message in messages {
+        let key = match message.level() {
+            Level::Success => "successes",
+            Level::Error => "errors",
+            Level::Info => "infos",
+            _ => continue,
+        };
+        if let Some(contents) = flashes.get_mut(key) {
+            contents.push(message.content().to_string());
+        }
+    }
+    flashes
+}
+
+/// Redirects the root path to the configured landing page (`/posts` by default).
+///
+/// The redirect is temporary, so changing `ACTIX_POSTS_ROOT_REDIRECT` takes effect for clients
+/// that already visited.
+#[get("/")]
+pub async fn root() -> impl Responder {
+    web::Redirect::to(config::get().root_redirect.clone())
+}
+
+/// Session key under which the last order chosen on the board is remembered.
+const SORT_SESSION_KEY: &str = "sort";
+
+#[derive(Deserialize, Debug)]
+pub struct IndexQuery {
+    sort: Option<String>,
+}
+
+/// The orders the board can be listed in.
+///
+/// # Variants
+/// - `Newest`: Most recently posted first (the default).
+/// - `Oldest`: Earliest posted first.
+/// - `MostLiked`: Most reactions first, counting every emoji.
+/// - `MostViewed`: Most viewed post pages first (see [`data::record_view`]).
+///
+/// Posts that tie on reactions or views keep the newest-first order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum IndexSort {
+    #[default]
+    Newest,
+    Oldest,
+    MostLiked,
+    MostViewed,
+}
+
+impl IndexSort {
+    /// Parses an order from its query-string name (e.g. `most-liked`).
+    ///
+    /// Returns `None` for unknown names.
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "most-liked" => Some(IndexSort::MostLiked),
+            "most-viewed" => Some(IndexSort::MostViewed),
+            _ => match SortOrder::parse(value)? {
+                SortOrder::Newest => Some(IndexSort::Newest),
+                SortOrder::Oldest => Some(IndexSort::Oldest),
+            },
+        }
+    }
+
+    /// Returns the query-string name of the order.
+    fn as_str(&self) -> &'static str {
+        match self {
+            IndexSort::Newest => SortOrder::Newest.as_str(),
+            IndexSort::Oldest => SortOrder::Oldest.as_str(),
+            IndexSort::MostLiked => "most-liked",
+            IndexSort::MostViewed => "most-viewed",
+        }
+    }
+
+    /// Returns every stored message in this order.
+    fn sorted(&self) -> Vec<Message> {
+        if *self == IndexSort::Oldest {
+            return data::get_all_sorted(SortOrder::Oldest);
+        }
+        let mut posts = data::get_all_sorted(SortOrder::Newest);
+        match self {
+            IndexSort::MostLiked => posts.sort_by_cached_key(|post| {
+                std::cmp::Reverse(
+                    post.reactions
+                        .values()
+                        .map(|&count| u64::from(count))
+                        .sum::<u64>(),
+                )
+            }),
+            IndexSort::MostViewed => {
+                let views = data::view_counts();
+                posts.sort_by_key(|post| {
+                    std::cmp::Reverse(views.get(&post.id).copied().unwrap_or_default())
+                });
+            }
+            IndexSort::Newest | IndexSort::Oldest => {}
+        }
+        posts
+    }
+}
+
+/// Lists the posts of the board.
+///
+/// The order is taken from the `sort` query parameter (`newest`, `oldest`, `most-liked`, or
+/// `most-viewed`) and remembered in the session, so later visits without the parameter keep it.
+/// While the undo window of a post this session deleted is open (see [`destroy`]), the list offers
+/// to undo the deletion.
+#[get("/posts")]
+pub async fn index(
+    tmpl: web::Data<tera::Tera>,
+    signer: web::Data<UrlSigner>,
+    query: web::Query<IndexQuery>,
+    messages: IncomingFlashMessages,
+    session: Session,
+) -> impl Responder {
+    let sort = query
+        .sort
+        .as_deref()
+        .and_then(IndexSort::parse)
+        .or_else(|| {
+            session
+                .get::<String>(SORT_SESSION_KEY)
+                .ok()
+                .flatten()
+                .as_deref()
+                .and_then(IndexSort::parse)
+        })
+        .unwrap_or_default();
+    let _ = session.insert(SORT_SESSION_KEY, sort.as_str());
+    let mut posts = sort.sorted();
+    posts.retain(|post| StatusFilter::Active.matches(post));
+    let mut context = base_context();
+    context.insert("sort", sort.as_str());
+    collect_flashes(messages.iter())
+        .iter()
+        .for_each(|(key, contents)| context.insert(*key, contents));
+    if let Some(id) = pending_undo(&session) {
+        context.insert("undo_id", &id);
+        context.insert("csrf_token", &auth::csrf_token(&session, &signer));
+    }
+    context.insert("is_empty", &posts.is_empty());
+    context.insert("count", &posts.len());
     context.insert("posts", &posts);
     let body_str = tmpl.render("index.html", &context).unwrap();
     HttpResponse::Ok()
@@ -23,57 +197,390 @@ pub async fn index(tmpl: web::Data<tera::Tera>, messages: IncomingFlashMessages)
         .body(body_str)
 }
 
+/// Shows a single post.
+///
+/// Every post has one canonical URL, `/posts/{id}` with the ID in its plain decimal form. Other
+/// spellings that parse to the same ID (e.g. `/posts/007` or `/posts/+7`) are permanently
+/// redirected to it, and the page declares it with `<link rel="canonical">`, so search engines
+/// do not index the same post twice.
+///
+/// Placeholders such as `{{date}}` in the content are expanded for display (see [`macros`]).
+/// Every view of an existing post is counted for the "most viewed" order of the board.
 #[get("/posts/{id}")]
 pub async fn show(
+    req: HttpRequest,
     tmpl: web::Data<tera::Tera>,
     info: web::Path<i32>,
     messages: IncomingFlashMessages,
-) -> impl Responder {
+) -> Either<HttpResponse, web::Redirect> {
     let info = info.into_inner();
-    let post = data::get(info);
-    let mut context = Context::new();
-    messages.iter().for_each(|message| match message.level() {
-        Level::Success => context.insert("success", message.content()),
-        Level::Error => context.insert("error", message.content()),
-        _ => (),
-    });
+    let canonical_url = req.url_for("show", [info.to_string()]).ok();
+    if req.match_info().get("id") != Some(info.to_string().as_str()) {
+        if let Some(url) = canonical_url {
+            return Either::Right(
+                web::Redirect::to(url.to_string()).using_status_code(StatusCode::MOVED_PERMANENTLY),
+            );
+        }
+    }
+    // An unknown ID renders the "not found" message of the template (`post.id == 0`).
+    let mut post = data::get(info).unwrap_or_default();
+    if post.id != 0 {
+        data::record_view(post.id);
+    }
+    post.content = macros::expand(&post);
+    let mut context = base_context();
+    collect_flashes(messages.iter())
+        .iter()
+        .for_each(|(key, contents)| context.insert(*key, contents));
     context.insert("post", &post);
+    let reactions: Vec<(&str, u32)> = REACTIONS
+        .iter()
+        .map(|emoji| (*emoji, post.reactions.get(*emoji).copied().unwrap_or(0)))
+        .collect();
+    context.insert("reactions", &reactions);
+    if post.id != 0 {
+        context.insert("canonical_url", &canonical_url.map(|url| url.to_string()));
+    }
     let body_str = tmpl.render("show.html", &context).unwrap();
+    Either::Left(
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(body_str),
+    )
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DiffQuery {
+    from: Option<u32>,
+    to: Option<u32>,
+    mode: Option<String>,
+}
+
+/// Shows what changed between two revisions of a post (see [`diff::between`]).
+///
+/// Unknown modes fall back to a line diff. Missing revisions render a "not found" message.
+#[get("/posts/{id}/diff")]
+pub async fn show_diff(
+    tmpl: web::Data<tera::Tera>,
+    info: web::Path<i32>,
+    query: web::Query<DiffQuery>,
+) -> impl Responder {
+    let id = info.into_inner();
+    let mode = query
+        .mode
+        .as_deref()
+        .and_then(DiffMode::parse)
+        .unwrap_or_default();
+    let revisions = data::revisions(id);
+    let mut context = base_context();
+    context.insert("id", &id);
+    context.insert("mode", mode.as_str());
+    context.insert("revisions", &revisions);
+    context.insert("diff", &diff::between(id, query.from, query.to, mode));
+    let body_str = tmpl.render("diff.html", &context).unwrap();
     HttpResponse::Ok()
         .content_type("text/html; charset=utf-8")
         .body(body_str)
 }
 
-#[get("/posts/new")]
-pub async fn new(tmpl: web::Data<tera::Tera>, session: Session) -> impl Responder {
-    let mut context = Context::new();
-    let sender = if let Some(sender) = session.get::<String>("sender").unwrap() {
-        sender.clone()
+/// Tera filter showing a stored timestamp in the display time zone (see
+/// [`data::display_timestamp`]). Registered as `display_time`; non-string values pass through.
+pub fn display_time(
+    value: &tera::Value,
+    _args: &HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    Ok(match value.as_str() {
+        Some(stored) => tera::Value::String(data::display_timestamp(stored)),
+        None => value.clone(),
+    })
+}
+
+/// Renders `form.html` for the given action (`create` or `update`) and post.
+fn render_form(tmpl: &tera::Tera, mut context: Context, action: &str, post: &Message) -> String {
+    let button = if action == "create" {
+        "投稿"
     } else {
-        "anonymous".to_string()
+        "更新"
+    };
+    context.insert("action", action);
+    context.insert("post", post);
+    context.insert("button", button);
+    context.insert("max_sender", &config::get().max_sender);
+    // New posts are made under the name of the logged-in user, which cannot be changed.
+    if action == "create" && auth::login_enabled() {
+        context.insert("identity", &post.sender);
+    }
+    // The version the edit is based on, unless the caller carries one over.
+    if action == "update" && !context.contains_key("version") {
+        context.insert("version", &post.version());
+    }
+    tmpl.render("form.html", &context).unwrap()
+}
+
+/// Re-renders the form with the submitted values and the validation errors.
+///
+/// The response uses `422 Unprocessable Entity` so nothing is saved and the user can correct
+/// the input without retyping it. `version` is the version the submitted edit was based on,
+/// kept so the corrected edit is still checked against it.
+fn render_invalid_form(
+    tmpl: &tera::Tera,
+    action: &str,
+    post: &Message,
+    errors: &[FieldError],
+    version: Option<&str>,
+) -> HttpResponse {
+    let mut context = base_context();
+    if let Some(version) = version {
+        context.insert("version", version);
+    }
+    let errors: Vec<String> = errors
+        .iter()
+        .map(|error| format!("{}: {}", error.field, error.reason))
+        .collect();
+    context.insert("errors", &errors);
+    let body_str = render_form(tmpl, context, action, post);
+    HttpResponse::UnprocessableEntity()
+        .content_type("text/html; charset=utf-8")
+        .body(body_str)
+}
+
+/// Returns the sender name remembered in the session, if any.
+///
+/// A missing, blank, or undecodable value is treated as "nothing remembered" rather than as an
+/// error, so a stale cookie never breaks the form.
+fn remembered_sender(session: &Session) -> Option<String> {
+    session
+        .get::<String>(SENDER_SESSION_KEY)
+        .ok()
+        .flatten()
+        .filter(|sender| !sender.trim().is_empty())
+}
+
+/// Returns the number of seconds left before the session may post again, or `None` when it may
+/// post now (see `ACTIX_POSTS_POST_COOLDOWN_SECONDS` in [`crate::config`]).
+///
+/// Sessions without a readable last-post time are never held back.
+fn cooldown_remaining(session: &Session, now: DateTime<Local>) -> Option<i64> {
+    let cooldown = config::get().post_cooldown_seconds?;
+    let last_post = session.get::<i64>(LAST_POST_SESSION_KEY).ok().flatten()?;
+    let remaining = last_post + cooldown - now.timestamp();
+    (remaining > 0).then_some(remaining)
+}
+
+/// Redirects to the login page with a flash explaining why.
+fn login_redirect() -> web::Redirect {
+    FlashMessage::error(strings::get(Text::LoginRequired)).send();
+    web::Redirect::to("/login").see_other()
+}
+
+/// Shows the form for a new post.
+///
+/// When login is enabled (see [`auth`]), the form is only shown to logged-in users and its
+/// sender is their name; other visitors are sent to the login page.
+#[get("/posts/new")]
+pub async fn new(
+    tmpl: web::Data<tera::Tera>,
+    messages: IncomingFlashMessages,
+    session: Session,
+) -> Either<HttpResponse, web::Redirect> {
+    let mut context = base_context();
+    collect_flashes(messages.iter())
+        .iter()
+        .for_each(|(key, contents)| context.insert(*key, contents));
+    let sender = match auth::sender_identity(&session) {
+        Ok(Some(identity)) => identity,
+        Ok(None) => remembered_sender(&session).unwrap_or_else(|| {
+            if config::get().allow_anonymous {
+                ANONYMOUS_SENDER.to_string()
+            } else {
+                String::new()
+            }
+        }),
+        Err(LoginRequired) => return Either::Right(login_redirect()),
     };
     let post = Message {
         sender,
         ..Default::default()
     };
-    context.insert("action", "create");
-    context.insert("post", &post);
-    context.insert("button", "投稿");
-    let body_str = tmpl.render("form.html", &context).unwrap();
+    let body_str = render_form(&tmpl, context, "create", &post);
+    Either::Left(
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(body_str),
+    )
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LoginForm {
+    name: String,
+    password: String,
+}
+
+/// Shows the login form. Answers `404 Not Found` when login is disabled.
+#[get("/login")]
+pub async fn login_form(
+    tmpl: web::Data<tera::Tera>,
+    messages: IncomingFlashMessages,
+) -> HttpResponse {
+    if !auth::login_enabled() {
+        return HttpResponse::NotFound().body("Page Not Found!");
+    }
+    let mut context = base_context();
+    collect_flashes(messages.iter())
+        .iter()
+        .for_each(|(key, contents)| context.insert(*key, contents));
+    let body_str = tmpl.render("login.html", &context).unwrap();
     HttpResponse::Ok()
         .content_type("text/html; charset=utf-8")
         .body(body_str)
 }
 
+/// Logs the session in and continues to the new post form, or back to the login form when the
+/// name or password is wrong.
+#[post("/login")]
+pub async fn login(params: web::Form<LoginForm>, session: Session) -> impl Responder {
+    if !auth::login_enabled() {
+        return Either::Left(HttpResponse::NotFound().body("Page Not Found!"));
+    }
+    if !auth::log_in(&session, &params.name, &params.password) {
+        FlashMessage::error(strings::get(Text::LoginFailed)).send();
+        return Either::Right(web::Redirect::to("/login").see_other());
+    }
+    FlashMessage::success(strings::format(Text::LoggedIn, &[("name", &params.name)])).send();
+    Either::Right(web::Redirect::to("/posts/new").see_other())
+}
+
+#[post("/logout")]
+pub async fn logout(session: Session) -> impl Responder {
+    auth::log_out(&session);
+    FlashMessage::success(strings::get(Text::LoggedOut)).send();
+    web::Redirect::to("/posts").see_other()
+}
+
+/// Refuses the moderation pages to sessions that are not logged in as a moderator.
+///
+/// # Returns
+/// - `None` for moderators.
+/// - `404 Not Found` when login is disabled, so the pages do not exist.
+/// - A redirect to the login page for visitors.
+/// - `403 Forbidden` for users who are not moderators.
+fn refuse_non_moderators(session: &Session) -> Option<Either<HttpResponse, web::Redirect>> {
+    if !auth::login_enabled() {
+        return Some(Either::Left(
+            HttpResponse::NotFound().body("Page Not Found!"),
+        ));
+    }
+    if auth::identity(session).is_none() {
+        return Some(Either::Right(login_redirect()));
+    }
+    if !auth::is_moderator(session) {
+        return Some(Either::Left(HttpResponse::Forbidden().body("Forbidden")));
+    }
+    None
+}
+
+/// Lists the posts of the board grouped by sender, each group with a link to delete all of its
+/// posts. Only for moderators (see [`auth::is_moderator`]).
+#[get("/moderation")]
+pub async fn moderation(
+    tmpl: web::Data<tera::Tera>,
+    messages: IncomingFlashMessages,
+    session: Session,
+) -> Either<HttpResponse, web::Redirect> {
+    if let Some(refusal) = refuse_non_moderators(&session) {
+        return refusal;
+    }
+    let mut senders: BTreeMap<String, Vec<Message>> = BTreeMap::new();
+    for post in data::get_all_sorted(SortOrder::Newest) {
+        senders.entry(post.sender.clone()).or_default().push(post);
+    }
+    let mut context = base_context();
+    collect_flashes(messages.iter())
+        .iter()
+        .for_each(|(key, contents)| context.insert(*key, contents));
+    context.insert("senders", &senders);
+    let body_str = tmpl.render("moderation.html", &context).unwrap();
+    Either::Left(
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(body_str),
+    )
+}
+
+#[derive(Deserialize, Debug)]
+pub struct SenderQuery {
+    sender: String,
+}
+
+/// Asks a moderator to confirm deleting every post of a sender.
+///
+/// The confirmation form carries the CSRF token of the login (see [`auth::csrf_token`]).
+#[get("/moderation/delete")]
+pub async fn moderation_confirm(
+    tmpl: web::Data<tera::Tera>,
+    signer: web::Data<UrlSigner>,
+    query: web::Query<SenderQuery>,
+    session: Session,
+) -> Either<HttpResponse, web::Redirect> {
+    if let Some(refusal) = refuse_non_moderators(&session) {
+        return refusal;
+    }
+    let count = data::get_all_shared(SortOrder::Newest)
+        .iter()
+        .filter(|post| post.sender == query.sender)
+        .count();
+    let mut context = base_context();
+    context.insert("sender", &query.sender);
+    context.insert("count", &count);
+    context.insert("csrf_token", &auth::csrf_token(&session, &signer));
+    let body_str = tmpl.render("moderation_delete.html", &context).unwrap();
+    Either::Left(
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(body_str),
+    )
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ModerationDeleteForm {
+    sender: String,
+    csrf_token: String,
+}
+
+/// Deletes every post of a sender, then returns to the moderation page with the number of
+/// deleted posts.
+///
+/// Answers `403 Forbidden` when the form does not carry the CSRF token of the login.
+#[post("/moderation/delete")]
+pub async fn moderation_delete(
+    signer: web::Data<UrlSigner>,
+    params: web::Form<ModerationDeleteForm>,
+    session: Session,
+) -> Either<HttpResponse, web::Redirect> {
+    if let Some(refusal) = refuse_non_moderators(&session) {
+        return refusal;
+    }
+    if !auth::check_csrf(&session, &signer, &params.csrf_token) {
+        return Either::Left(HttpResponse::Forbidden().body("Forbidden"));
+    }
+    let remove = || data::remove_where(|post| post.sender == params.sender);
+    match data::with_retries(remove).await {
+        Ok(removed) => FlashMessage::success(strings::format(
+            Text::SenderDeleted,
+            &[("sender", &params.sender), ("count", &removed.len())],
+        ))
+        .send(),
+        Err(error) => FlashMessage::error(error.to_string()).send(),
+    }
+    Either::Right(web::Redirect::to("/moderation").see_other())
+}
+
 #[get("/posts/{id}/edit")]
 pub async fn edit(tmpl: web::Data<tera::Tera>, info: web::Path<i32>) -> impl Responder {
     let info = info.into_inner();
-    let post = data::get(info);
-    let mut context = Context::new();
-    context.insert("action", "update");
-    context.insert("post", &post);
-    context.insert("button", "更新");
-    let body_str = tmpl.render("form.html", &context).unwrap();
+    let post = data::get(info).unwrap_or_default();
+    let context = base_context();
+    let body_str = render_form(&tmpl, context, "update", &post);
     HttpResponse::Ok()
         .content_type("text/html; charset=utf-8")
         .body(body_str)
@@ -85,48 +592,330 @@ pub struct CreateForm {
     posted: String,
     sender: String,
     content: String,
+    /// Attachment URLs, one per line.
+    #[serde(default)]
+    attachments: String,
+    /// The expiry time (see [`Message::expires_at`]), empty for none.
+    #[serde(default)]
+    expires_at: String,
+    /// The [`Message::version`] the edit form was rendered with. Empty (e.g. for the create
+    /// form) skips the check.
+    #[serde(default)]
+    version: String,
+}
+
+impl CreateForm {
+    /// Returns the expiry time, or `None` when the field is blank.
+    fn expiry(&self) -> Option<String> {
+        Some(self.expires_at.trim())
+            .filter(|value| !value.is_empty())
+            .map(str::to_string)
+    }
+
+    /// Splits the attachments field into URLs, ignoring blank lines.
+    fn attachment_list(&self) -> Vec<String> {
+        self.attachments
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
 }
 
 #[post("/posts/create")]
-pub async fn create(params: web::Form<CreateForm>, session: Session) -> impl Responder {
+pub async fn create(
+    req: HttpRequest,
+    tmpl: web::Data<tera::Tera>,
+    params: web::Form<CreateForm>,
+    session: Session,
+) -> Either<HttpResponse, web::Redirect> {
     let now: DateTime<Local> = Local::now();
+    // A logged-in user always posts under their own name, whatever the form says.
+    let sender = match auth::sender_identity(&session) {
+        Ok(identity) => identity.unwrap_or_else(|| params.sender.clone()),
+        Err(LoginRequired) => return Either::Right(login_redirect()),
+    };
     let mut message = Message {
         id: 0,
-        posted: now.format("%Y-%m-%d %H:%M:%S").to_string(),
-        sender: params.sender.clone(),
+        posted: data::current_timestamp(),
+        sender,
         content: params.content.clone(),
+        attachments: params.attachment_list(),
+        updated: String::new(),
+        reactions: BTreeMap::new(),
+        tags: vec![],
+        status: PostStatus::Open,
+        expires_at: params.expiry(),
+        metadata: Some(Box::new(request_metadata(&req))),
+        slug: String::new(),
+    };
+    if let Err(errors) = message.validate_as(auth::identity(&session).as_deref()) {
+        return Either::Left(render_invalid_form(
+            &tmpl, "create", &message, &errors, None,
+        ));
+    }
+    if let Some(remaining) = cooldown_remaining(&session, now) {
+        // Re-render the form so the user keeps what they typed.
+        let mut context = base_context();
+        context.insert(
+            "errors",
+            &[strings::format(Text::Cooldown, &[("seconds", &remaining)])],
+        );
+        let body_str = render_form(&tmpl, context, "create", &message);
+        return Either::Left(
+            HttpResponse::TooManyRequests()
+                .insert_header((header::RETRY_AFTER, remaining.to_string()))
+                .content_type("text/html; charset=utf-8")
+                .body(body_str),
+        );
+    }
+    message = match data::with_retries(|| data::create(message.clone())).await {
+        Ok(message) => message,
+        Err(DataError::Duplicate(id)) => {
+            FlashMessage::error(strings::get(Text::Duplicate)).send();
+            return Either::Right(web::Redirect::to(format!("/posts/{}", id)).see_other());
+        }
+        Err(DataError::QuotaExceeded(max)) => {
+            // Re-render the form so the user keeps what they typed.
+            let mut context = base_context();
+            context.insert(
+                "errors",
+                &[strings::format(Text::QuotaExceeded, &[("max", &max)])],
+            );
+            let body_str = render_form(&tmpl, context, "create", &message);
+            return Either::Left(
+                HttpResponse::UnprocessableEntity()
+                    .content_type("text/html; charset=utf-8")
+                    .body(body_str),
+            );
+        }
+        Err(error) => {
+            FlashMessage::error(error.to_string()).send();
+            return Either::Right(web::Redirect::to("/posts").see_other());
+        }
     };
-    message = data::create(message);
     if message.id == 0 {
-        FlashMessage::error("投稿でエラーが発生しました。").send();
+        FlashMessage::error(strings::get(Text::CreateFailed)).send();
     } else {
-        FlashMessage::success("投稿しました。").send();
+        // Remember the name actually used, so a sender changed on the form is preloaded the
+        // next time the form is opened.
+        let _ = session.insert(SENDER_SESSION_KEY, &message.sender);
+        let _ = session.insert(LAST_POST_SESSION_KEY, now.timestamp());
+        // `url_for` resolves against the mounted scope and the request's host, so the
+        // permalink stays valid when the app is served below a base path.
+        match req.url_for("show", [message.id.to_string()]) {
+            Ok(permalink) => FlashMessage::success(strings::format(
+                Text::CreatedWithPermalink,
+                &[("permalink", &permalink)],
+            ))
+            .send(),
+            Err(_) => FlashMessage::success(strings::get(Text::Created)).send(),
+        }
     }
-    let _ = session.insert("sender", params.sender.clone());
-    web::Redirect::to(format!("/posts/{}", message.id)).see_other()
+    Either::Right(web::Redirect::to(format!("/posts/{}", message.id)).see_other())
 }
 
 #[post("/posts/update")]
-pub async fn update(params: web::Form<CreateForm>) -> impl Responder {
+pub async fn update(
+    tmpl: web::Data<tera::Tera>,
+    params: web::Form<CreateForm>,
+    session: Session,
+) -> Either<HttpResponse, web::Redirect> {
+    // With login enabled, users only edit their own posts, and keep them under their name.
+    let stored = data::get(params.id).map(|post| post.sender);
+    match auth::check_editor(
+        &session,
+        stored.as_deref().unwrap_or_default(),
+        &params.sender,
+    ) {
+        Ok(()) => {}
+        Err(EditRefused::LoginRequired) => return Either::Right(login_redirect()),
+        Err(EditRefused::NotOwner) => {
+            FlashMessage::error(strings::get(Text::NotOwner)).send();
+            let post = format!("/posts/{}", params.id);
+            return Either::Right(web::Redirect::to(post).see_other());
+        }
+    }
     let message = Message {
         id: params.id,
         posted: params.posted.clone(),
         sender: params.sender.clone(),
         content: params.content.clone(),
+        attachments: params.attachment_list(),
+        updated: String::new(),
+        reactions: BTreeMap::new(),
+        tags: vec![],
+        status: PostStatus::Open,
+        expires_at: params.expiry(),
+        metadata: None,
+        slug: String::new(),
+    };
+    if let Err(errors) = message.validate_as(auth::identity(&session).as_deref()) {
+        return Either::Left(render_invalid_form(
+            &tmpl,
+            "update",
+            &message,
+            &errors,
+            Some(&params.version),
+        ));
+    }
+    let result = data::with_retries(|| {
+        if params.version.is_empty() {
+            data::update(&message)
+        } else {
+            data::update_if_unchanged(&message, &params.version)
+        }
+    })
+    .await;
+    match result {
+        Ok(()) => FlashMessage::success(strings::get(Text::Updated)).send(),
+        Err(DataError::Modified) => {
+            // Keep the user's text, but base the form on the current version: submitting it
+            // again deliberately replaces the other edit.
+            let mut context = base_context();
+            context.insert("errors", &[strings::get(Text::EditConflict)]);
+            if let Some(stored) = data::get(message.id) {
+                context.insert("version", &stored.version());
+            }
+            let body_str = render_form(&tmpl, context, "update", &message);
+            return Either::Left(
+                HttpResponse::Conflict()
+                    .content_type("text/html; charset=utf-8")
+                    .body(body_str),
+            );
+        }
+        Err(DataError::EditWindowClosed(minutes)) => {
+            FlashMessage::error(strings::format(
+                Text::EditWindowClosed,
+                &[("minutes", &minutes)],
+            ))
+            .send();
+        }
+        Err(DataError::Duplicate(_)) => {
+            FlashMessage::error(strings::get(Text::Duplicate)).send();
+        }
+        Err(error) => FlashMessage::error(error.to_string()).send(),
+    }
+    Either::Right(web::Redirect::to(format!("/posts/{}", message.id)).see_other())
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReactForm {
+    emoji: String,
+}
+
+/// Adds a reaction from the reaction bar of a post, then returns to the post.
+#[post("/posts/{id}/react")]
+pub async fn react(info: web::Path<i32>, params: web::Form<ReactForm>) -> impl Responder {
+    let id = info.into_inner();
+    let result = match validation::check_reaction(&params.emoji) {
+        Ok(()) => data::with_retries(|| data::react(id, &params.emoji))
+            .await
+            .map(|_| ())
+            .map_err(|error| error.to_string()),
+        Err(error) => Err(format!("{}: {}", error.field, error.reason)),
     };
-    data::update(&message);
-    FlashMessage::success("更新しました。").send();
-    web::Redirect::to(format!("/posts/{}", message.id)).see_other()
+    if let Err(reason) = result {
+        FlashMessage::error(reason).send();
+    }
+    web::Redirect::to(format!("/posts/{}", id)).see_other()
+}
+
+/// Session key under which the ID of the post last deleted from its page is kept, with the end of
+/// its undo window in seconds since the epoch. Until then, the list offers to undo the deletion,
+/// and only this session may do so (see [`restore`]).
+const UNDO_SESSION_KEY: &str = "undo";
+
+/// Returns the post ID and the end of the undo window this session recorded, if any.
+fn undo_entry(session: &Session) -> Option<(i32, i64)> {
+    session.get(UNDO_SESSION_KEY).ok().flatten()
+}
+
+/// Returns the ID of the post this session deleted last, while its undo window is open.
+fn pending_undo(session: &Session) -> Option<i32> {
+    undo_entry(session)
+        .filter(|(_, until)| Local::now().timestamp() < *until)
+        .map(|(id, _)| id)
 }
 
+/// Deletes a post and its revisions, then returns to the list.
+///
+/// For `ACTIX_POSTS_UNDO_SECONDS` after that, the deletion can be undone with the button the list
+/// shows next to the confirmation (see [`restore`]).
 #[get("/posts/{id}/delete")]
-pub async fn destroy(info: web::Path<i32>) -> impl Responder {
+pub async fn destroy(info: web::Path<i32>, session: Session) -> impl Responder {
     let info = info.into_inner();
-    data::remove(info);
-    FlashMessage::success("削除しました。").send();
+    match data::with_retries(|| data::remove(info)).await {
+        Ok(Some(_)) => {
+            FlashMessage::success(strings::get(Text::Deleted)).send();
+            let seconds = config::get().undo_seconds;
+            if seconds > 0 {
+                let until = Local::now().timestamp().saturating_add_unsigned(seconds);
+                let _ = session.insert(UNDO_SESSION_KEY, (info, until));
+            }
+        }
+        Ok(None) => {}
+        Err(error) => FlashMessage::error(error.to_string()).send(),
+    }
     web::Redirect::to("/posts").see_other()
 }
 
+#[derive(Deserialize, Debug)]
+pub struct CsrfForm {
+    csrf_token: String,
+}
+
+/// Restores a post deleted from its page a moment ago (see [`data::restore`]), then shows it.
+///
+/// Answers `403 Forbidden` when the form does not carry the CSRF token of the session, or when
+/// the post was not deleted by this session. Once the undo window is over, returns to the list
+/// with an error.
+#[post("/posts/{id}/restore")]
+pub async fn restore(
+    signer: web::Data<UrlSigner>,
+    info: web::Path<i32>,
+    params: web::Form<CsrfForm>,
+    session: Session,
+) -> Either<HttpResponse, web::Redirect> {
+    if !auth::check_csrf(&session, &signer, &params.csrf_token) {
+        return Either::Left(HttpResponse::Forbidden().body("Forbidden"));
+    }
+    let id = info.into_inner();
+    if undo_entry(&session).map(|(deleted, _)| deleted) != Some(id) {
+        return Either::Left(HttpResponse::Forbidden().body("Forbidden"));
+    }
+    match data::with_retries(|| data::restore(id)).await {
+        Ok(Some(_)) => {
+            session.remove(UNDO_SESSION_KEY);
+            FlashMessage::success(strings::get(Text::Restored)).send();
+            return Either::Right(web::Redirect::to(format!("/posts/{}", id)).see_other());
+        }
+        Ok(None) => FlashMessage::error(strings::get(Text::RestoreExpired)).send(),
+        Err(error) => FlashMessage::error(error.to_string()).send(),
+    }
+    Either::Right(web::Redirect::to("/posts").see_other())
+}
+
+/// Serves `/robots.txt`, asking crawlers to stay away from the mutating links.
+///
+/// The `Disallow` rules come from `ACTIX_POSTS_ROBOTS_DISALLOW` (see [`crate::config`]). With no
+/// rules, a single empty `Disallow` line allows everything.
+#[get("/robots.txt")]
+pub async fn robots() -> impl Responder {
+    let disallow = &config::get().robots_disallow;
+    let mut body = String::from("User-agent: *\n");
+    if disallow.is_empty() {
+        body.push_str("Disallow:\n");
+    }
+    for path in disallow {
+        body.push_str(&format!("Disallow: {}\n", path));
+    }
+    HttpResponse::Ok()
+        .content_type("text/plain; charset=utf-8")
+        .body(body)
+}
+
 /// Handles requests to non-existent routes by returning a 404 Not Found response.
 ///
 /// This asynchronous handler responds with an HTTP 404 status code and a simple
@@ -138,3 +927,976 @@ pub async fn destroy(info: web::Path<i32>) -> impl Responder {
 pub async fn not_found() -> impl Responder {
     HttpResponse::NotFound().body("Page Not Found!")
 }
+
+/// Builds the response returned by mutating web routes while maintenance mode is enabled.
+///
+/// # Returns
+/// An HTTP 503 response with a body message explaining that writes are temporarily disabled.
+pub fn maintenance() -> HttpResponse {
+    HttpResponse::ServiceUnavailable().body("Service Unavailable: the board is under maintenance.")
+}
+
+/// Builds the response returned to web requests shed by
+/// [`crate::handler::middleware::concurrency_limit`].
+///
+/// # Returns
+/// An HTTP 503 response with a `Retry-After` header and a body message asking to retry.
+pub fn overloaded() -> HttpResponse {
+    HttpResponse::ServiceUnavailable()
+        .insert_header((header::RETRY_AFTER, RETRY_AFTER_SECONDS))
+        .body("Service Unavailable: the server is busy, please retry shortly.")
+}
+
+/// Builds the response returned by mutating web routes while the data file cannot be written.
+///
+/// # Returns
+/// An HTTP 503 response with a body message explaining that the board is read-only.
+pub fn read_only() -> HttpResponse {
+    HttpResponse::ServiceUnavailable()
+        .body("Service Unavailable: the board is read-only because its data cannot be saved.")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app;
+    use crate::handler::filter::FilterMode;
+    use crate::testing::{self, message, TestEnv};
+    use actix_web::cookie::Cookie;
+    use actix_web::dev::ServiceResponse;
+    use actix_web::test::{self, TestRequest};
+    use serde_json::json;
+
+    /// Returns the contents of `posts` in the order they appear in `html`.
+    fn order_of<'a>(html: &str, posts: &[&'a str]) -> Vec<&'a str> {
+        let mut found: Vec<(usize, &str)> = posts
+            .iter()
+            .map(|post| (html.find(post).expect(post), *post))
+            .collect();
+        found.sort();
+        found.into_iter().map(|(_, post)| post).collect()
+    }
+
+    /// Returns the cookies set by `response`, e.g. the session after a login.
+    fn cookies<B>(response: &ServiceResponse<B>) -> Vec<Cookie<'static>> {
+        response
+            .response()
+            .cookies()
+            .map(|cookie| cookie.into_owned())
+            .collect()
+    }
+
+    /// Adds `cookies` to `request`.
+    fn with(cookies: &[Cookie<'static>], request: TestRequest) -> TestRequest {
+        cookies.iter().cloned().fold(request, TestRequest::cookie)
+    }
+
+    /// Returns the CSRF token of the form in `html`.
+    fn csrf_token(html: &str) -> &str {
+        let (_, token) = html.split_once(r#"name="csrf_token" value=""#).unwrap();
+        &token[..token.find('"').unwrap()]
+    }
+
+    /// Returns the target of a `303 See Other` response.
+    fn location<B>(response: &ServiceResponse<B>) -> String {
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let location = response.headers().get("location").unwrap();
+        location.to_str().unwrap().to_string()
+    }
+
+    #[actix_web::test]
+    async fn index_sorts_by_reactions_and_views_and_remembers_the_choice() {
+        let env = TestEnv::with(|_| {});
+        let mut liked = message(1, "Nao", "first-post");
+        liked.reactions.insert("👍".to_string(), 3);
+        let mut loved = message(2, "Kai", "second-post");
+        loved.reactions.insert("❤️".to_string(), 5);
+        env.seed(&[liked, loved, message(3, "Rin", "third-post")]);
+        let app = testing::service!();
+        let posts = ["first-post", "second-post", "third-post"];
+
+        let request = TestRequest::get()
+            .uri("/posts?sort=most-liked")
+            .to_request();
+        let html = test::call_and_read_body(&app, request).await;
+        let html = String::from_utf8_lossy(&html);
+        assert_eq!(
+            order_of(&html, &posts),
+            ["second-post", "first-post", "third-post"]
+        );
+
+        for _ in 0..2 {
+            test::call_service(&app, TestRequest::get().uri("/posts/1").to_request()).await;
+        }
+        test::call_service(&app, TestRequest::get().uri("/posts/3").to_request()).await;
+        let response = test::call_service(
+            &app,
+            TestRequest::get()
+                .uri("/posts?sort=most-viewed")
+                .to_request(),
+        )
+        .await;
+        let cookies: Vec<_> = response
+            .response()
+            .cookies()
+            .map(|c| c.into_owned())
+            .collect();
+        let html = String::from_utf8_lossy(&test::read_body(response).await).into_owned();
+        assert_eq!(
+            order_of(&html, &posts),
+            ["first-post", "third-post", "second-post"]
+        );
+
+        let request = cookies
+            .into_iter()
+            .fold(TestRequest::get().uri("/posts"), TestRequest::cookie);
+        let html = test::call_and_read_body(&app, request.to_request()).await;
+        let html = String::from_utf8_lossy(&html);
+        assert!(html.contains(r#"<option value="most-viewed" selected>"#));
+        assert_eq!(
+            order_of(&html, &posts),
+            ["first-post", "third-post", "second-post"]
+        );
+    }
+
+    #[test]
+    fn every_flash_of_a_level_is_rendered() {
+        let messages = [
+            FlashMessage::success("Saved the first post"),
+            FlashMessage::success("Saved the second post"),
+            FlashMessage::error("Something went wrong"),
+        ];
+        let flashes = collect_flashes(&messages);
+        assert_eq!(
+            flashes["successes"],
+            ["Saved the first post", "Saved the second post"]
+        );
+        assert_eq!(flashes["errors"], ["Something went wrong"]);
+        assert!(flashes["infos"].is_empty());
+
+        let mut context = base_context();
+        flashes
+            .iter()
+            .for_each(|(key, contents)| context.insert(*key, contents));
+        context.insert("sort", "newest");
+        context.insert("is_empty", &true);
+        context.insert("count", &0);
+        context.insert("posts", &Vec::<Message>::new());
+        let html = app::templates().render("index.html", &context).unwrap();
+        assert!(html.contains("Saved the first post"));
+        assert!(html.contains("Saved the second post"));
+        assert!(html.contains("Something went wrong"));
+    }
+
+    /// Builds the body of the post form.
+    fn form(id: i32, sender: &str, content: &str) -> [(&'static str, String); 4] {
+        [
+            ("id", id.to_string()),
+            ("posted", "2024-01-01 00:00:01Z".to_string()),
+            ("sender", sender.to_string()),
+            ("content", content.to_string()),
+        ]
+    }
+
+    #[actix_web::test]
+    async fn over_limit_form_submissions_are_refused_and_kept() {
+        let env = TestEnv::with(|config| config.max_content = 20);
+        env.seed(&[message(1, "Nao", "hello")]);
+        let app = testing::service!();
+        let long_sender = "s".repeat(config::get().max_sender + 1);
+        let long_content = "c".repeat(21);
+
+        for (sender, content, field) in [
+            (long_sender.as_str(), "fine", "sender"),
+            ("Kai", long_content.as_str(), "content"),
+        ] {
+            let request = TestRequest::post()
+                .uri("/posts/create")
+                .set_form(form(0, sender, content));
+            let response = test::call_service(&app, request.to_request()).await;
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+            let html = String::from_utf8_lossy(&test::read_body(response).await).into_owned();
+            assert!(html.contains(&format!("{}: must be at most", field)));
+            assert!(html.contains(content), "the form keeps the entered content");
+
+            let request = TestRequest::post()
+                .uri("/posts/update")
+                .set_form(form(1, sender, content));
+            let response = test::call_service(&app, request.to_request()).await;
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        }
+        let stored = env.stored();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].content, "hello");
+
+        let request =
+            TestRequest::post()
+                .uri("/posts/create")
+                .set_form(form(0, "Kai", &"c".repeat(20)));
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(env.stored().len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn the_sender_of_the_last_post_is_preloaded_on_the_form() {
+        let _env = TestEnv::with(|_| {});
+        let app = testing::service!();
+        let sender_input = |name: &str| format!(r#"value="{}" placeholder="名前を入力"#, name);
+
+        let html =
+            test::call_and_read_body(&app, TestRequest::get().uri("/posts/new").to_request()).await;
+        assert!(String::from_utf8_lossy(&html).contains(&sender_input(ANONYMOUS_SENDER)));
+
+        let request = TestRequest::post()
+            .uri("/posts/create")
+            .set_form(form(0, "Kai", "hello"));
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let cookies: Vec<_> = response
+            .response()
+            .cookies()
+            .map(|c| c.into_owned())
+            .collect();
+
+        let request = cookies
+            .into_iter()
+            .fold(TestRequest::get().uri("/posts/new"), TestRequest::cookie);
+        let html = test::call_and_read_body(&app, request.to_request()).await;
+        assert!(String::from_utf8_lossy(&html).contains(&sender_input("Kai")));
+    }
+
+    #[actix_web::test]
+    async fn a_logged_in_user_posts_under_their_own_name() {
+        let env = TestEnv::with(|config| {
+            config.users = vec![format!("nao:{}", testing::SECRET_HASH).parse().unwrap()];
+        });
+        let app = testing::service!();
+        let post_form = || {
+            TestRequest::post()
+                .uri("/posts/create")
+                .set_form(form(0, "Kai", "hello"))
+        };
+        let post_api = || {
+            testing::json(
+                TestRequest::post().uri("/api/posts/create"),
+                serde_json::json!({ "sender": "Kai", "content": "hi" }),
+            )
+        };
+
+        let response = test::call_service(&app, post_form().to_request()).await;
+        assert_eq!(location(&response), "/login");
+        let response = test::call_service(&app, post_api().to_request()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let request = TestRequest::post()
+            .uri("/login")
+            .set_form([("name", "nao"), ("password", "wrong")]);
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(location(&response), "/login");
+        assert!(env.stored().is_empty());
+
+        let request = TestRequest::post()
+            .uri("/login")
+            .set_form([("name", "nao"), ("password", "secret")]);
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(location(&response), "/posts/new");
+        let session = cookies(&response);
+        let html = test::call_and_read_body(
+            &app,
+            with(&session, TestRequest::get().uri("/posts/new")).to_request(),
+        )
+        .await;
+        let html = String::from_utf8_lossy(&html);
+        assert!(html.contains(r#"value="nao""#) && html.contains("readonly"));
+
+        let response = test::call_service(&app, with(&session, post_form()).to_request()).await;
+        assert_eq!(location(&response), "/posts/1");
+        let response = test::call_service(&app, with(&session, post_api()).to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let senders: Vec<_> = env.stored().into_iter().map(|m| m.sender).collect();
+        assert_eq!(senders, ["nao", "nao"], "the spoofed sender is replaced");
+
+        let request = with(&session, TestRequest::post().uri("/logout"));
+        let response = test::call_service(&app, request.to_request()).await;
+        let session = cookies(&response);
+        let response = test::call_service(&app, with(&session, post_form()).to_request()).await;
+        assert_eq!(location(&response), "/login");
+    }
+
+    #[actix_web::test]
+    async fn logged_in_users_only_edit_their_own_posts_under_their_name() {
+        let env = TestEnv::with(|config| {
+            config.users = ["nao", "kai"]
+                .map(|name| {
+                    format!("{}:{}", name, testing::SECRET_HASH)
+                        .parse()
+                        .unwrap()
+                })
+                .to_vec();
+        });
+        env.seed(&[message(1, "nao", "mine"), message(2, "kai", "theirs")]);
+        let app = testing::service!();
+        let patch = |id: i32, body: serde_json::Value| {
+            testing::json(
+                TestRequest::patch().uri(&format!("/api/posts/{}", id)),
+                body,
+            )
+        };
+
+        let request = patch(1, json!({ "content": "without a login" }));
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let request = TestRequest::post()
+            .uri("/login")
+            .set_form([("name", "nao"), ("password", "secret")]);
+        let session = cookies(&test::call_service(&app, request.to_request()).await);
+
+        // A spoofed sender is refused, and so is an edit of someone else's post.
+        for (id, body) in [
+            (1, json!({ "sender": "kai" })),
+            (1, json!({ "sender": "kai", "content": "from kai" })),
+            (2, json!({ "content": "hijacked" })),
+        ] {
+            let request = with(&session, patch(id, body.clone()));
+            let response = test::call_service(&app, request.to_request()).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN, "{}", body);
+        }
+        let request = testing::json(
+            TestRequest::put().uri("/api/posts/update"),
+            json!({ "id": 1, "posted": "2024-01-01 00:00:01Z", "sender": "kai", "content": "x" }),
+        );
+        let response = test::call_service(&app, with(&session, request).to_request()).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let request = testing::json(
+            TestRequest::put().uri("/api/posts/batch"),
+            json!([{ "id": 1, "changes": { "sender": "kai" } }]),
+        );
+        let body: serde_json::Value =
+            test::call_and_read_body_json(&app, with(&session, request).to_request()).await;
+        assert_eq!(body["result"]["Updates"][0]["status"], 403, "{}", body);
+        let request = TestRequest::post()
+            .uri("/posts/update")
+            .set_form(form(1, "kai", "from kai"));
+        let response = test::call_service(&app, with(&session, request).to_request()).await;
+        assert_eq!(location(&response), "/posts/1");
+        assert_eq!(
+            env.stored(),
+            [message(1, "nao", "mine"), message(2, "kai", "theirs")]
+        );
+
+        let request = with(&session, patch(1, json!({ "content": "edited" })));
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(env.stored()[0].content, "edited");
+    }
+
+    #[actix_web::test]
+    async fn a_deletion_can_be_undone_within_the_window() {
+        let env = TestEnv::with(|_| {});
+        let mut post = message(1, "Nao", "hello");
+        post.reactions.insert("👍".to_string(), 2);
+        env.seed(&[post.clone(), message(2, "Kai", "hi")]);
+        let app = testing::service!();
+        let undo = |token: &str| {
+            TestRequest::post()
+                .uri("/posts/1/restore")
+                .set_form([("csrf_token", token)])
+        };
+
+        let request = TestRequest::get().uri("/posts/1/delete");
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(location(&response), "/posts");
+        assert_eq!(env.stored().len(), 1);
+        let request = with(&cookies(&response), TestRequest::get().uri("/posts"));
+        let response = test::call_service(&app, request.to_request()).await;
+        // The page stores the CSRF token of the session.
+        let session = cookies(&response);
+        let html = test::read_body(response).await;
+        let html = String::from_utf8_lossy(&html);
+        assert!(html.contains(r#"action="/posts/1/restore""#), "{}", html);
+        let token = csrf_token(&html);
+
+        let response = test::call_service(&app, undo(token).to_request()).await;
+        assert_eq!(
+            response.status(),
+            StatusCode::FORBIDDEN,
+            "without the session"
+        );
+        let response = test::call_service(&app, with(&session, undo("0000")).to_request()).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(env.stored().len(), 1);
+
+        let response = test::call_service(&app, with(&session, undo(token)).to_request()).await;
+        assert_eq!(location(&response), "/posts/1");
+        assert_eq!(env.stored(), [post, message(2, "Kai", "hi")]);
+        let response = test::call_service(&app, with(&session, undo(token)).to_request()).await;
+        assert_eq!(location(&response), "/posts", "a post is restored once");
+        drop(app);
+        drop(env);
+
+        let env = TestEnv::with(|config| config.undo_seconds = 0);
+        env.seed(&[message(1, "Nao", "hello")]);
+        let app = testing::service!();
+        let request = TestRequest::get().uri("/posts/1/delete");
+        let response = test::call_service(&app, request.to_request()).await;
+        let session = cookies(&response);
+        let html = test::call_and_read_body(
+            &app,
+            with(&session, TestRequest::get().uri("/posts")).to_request(),
+        )
+        .await;
+        assert!(!String::from_utf8_lossy(&html).contains("/restore"));
+        assert!(data::restore(1).unwrap().is_none());
+    }
+
+    #[actix_web::test]
+    async fn a_deletion_is_undone_only_by_the_session_that_made_it() {
+        let env = TestEnv::with(|_| {});
+        env.seed(&[message(1, "Nao", "hello"), message(2, "Kai", "hi")]);
+        let app = testing::service!();
+        // Deletes `id` in a new session, then returns its cookies and the token of the undo form.
+        let delete = |id: i32| {
+            let app = &app;
+            async move {
+                let request = TestRequest::get().uri(&format!("/posts/{}/delete", id));
+                let deleted = cookies(&test::call_service(app, request.to_request()).await);
+                let request = with(&deleted, TestRequest::get().uri("/posts"));
+                let response = test::call_service(app, request.to_request()).await;
+                let session = [deleted, cookies(&response)].concat();
+                let html = String::from_utf8_lossy(&test::read_body(response).await).into_owned();
+                assert!(html.contains(&format!(r#"action="/posts/{}/restore""#, id)));
+                (session, csrf_token(&html).to_string())
+            }
+        };
+        let undo = |id: i32, token: &str| {
+            TestRequest::post()
+                .uri(&format!("/posts/{}/restore", id))
+                .set_form([("csrf_token", token)])
+        };
+
+        let (first, first_token) = delete(1).await;
+        let (second, second_token) = delete(2).await;
+        let request = with(&second, undo(1, &second_token));
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(env.stored().is_empty());
+
+        // The offer outlives the first page shown after the deletion.
+        let request = with(&first, TestRequest::get().uri("/posts"));
+        let html = test::call_and_read_body(&app, request.to_request()).await;
+        assert!(String::from_utf8_lossy(&html).contains(r#"action="/posts/1/restore""#));
+        let request = with(&first, undo(1, &first_token));
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(location(&response), "/posts/1");
+        assert_eq!(env.stored(), [message(1, "Nao", "hello")]);
+    }
+
+    #[actix_web::test]
+    async fn reserved_names_are_only_used_by_their_owner() {
+        let env = TestEnv::with(|config| {
+            config.reserved_senders = vec!["admin".to_string()];
+            config.max_sender = 5;
+        });
+        let app = testing::service!();
+        let post_api = |sender: &str| {
+            testing::json(
+                TestRequest::post().uri("/api/posts/create"),
+                serde_json::json!({ "sender": sender, "content": "hi" }),
+            )
+        };
+
+        for sender in ["admin", "ADMIN", "Kai123"] {
+            let response = test::call_service(&app, post_api(sender).to_request()).await;
+            assert_eq!(
+                response.status(),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "{}",
+                sender
+            );
+        }
+        let request = TestRequest::post()
+            .uri("/posts/create")
+            .set_form(form(0, "Admin", "hi"));
+        let html = test::call_and_read_body(&app, request.to_request()).await;
+        assert!(String::from_utf8_lossy(&html).contains("is a reserved name"));
+        assert!(env.stored().is_empty());
+
+        let request = testing::with_key(post_api("admin"));
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(env.stored().len(), 1);
+        drop(app);
+        drop(env);
+
+        let env = TestEnv::with(|config| {
+            config.reserved_senders = vec!["admin".to_string()];
+            config.users = ["admin", "nao"]
+                .map(|name| {
+                    format!("{}:{}", name, testing::SECRET_HASH)
+                        .parse()
+                        .unwrap()
+                })
+                .to_vec();
+        });
+        let app = testing::service!();
+        let log_in = |name: &str| {
+            TestRequest::post()
+                .uri("/login")
+                .set_form([("name", name), ("password", "secret")])
+                .to_request()
+        };
+        let admin = cookies(&test::call_service(&app, log_in("admin")).await);
+        let request = with(&admin, TestRequest::post().uri("/posts/create"));
+        let response =
+            test::call_service(&app, request.set_form(form(0, "", "hi")).to_request()).await;
+        assert_eq!(location(&response), "/posts/1");
+        let response = test::call_service(&app, with(&admin, post_api("")).to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let senders: Vec<_> = env.stored().into_iter().map(|m| m.sender).collect();
+        assert_eq!(senders, ["admin", "admin"]);
+
+        let nao = cookies(&test::call_service(&app, log_in("nao")).await);
+        let request = with(&nao, TestRequest::post().uri("/posts/update"))
+            .set_form(form(1, "admin", "edited"));
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(location(&response), "/posts/1", "the post is not nao's");
+        assert_eq!(env.stored()[0].content, "hi");
+    }
+
+    #[actix_web::test]
+    async fn moderators_delete_every_post_of_a_sender() {
+        let env = TestEnv::with(|config| {
+            config.users = ["nao", "rei"]
+                .map(|name| {
+                    format!("{}:{}", name, testing::SECRET_HASH)
+                        .parse()
+                        .unwrap()
+                })
+                .to_vec();
+            config.moderators = vec!["rei".to_string()];
+        });
+        env.seed(&[
+            message(1, "spammer", "buy"),
+            message(2, "Nao", "hello"),
+            message(3, "spammer", "buy now"),
+        ]);
+        let app = testing::service!();
+        let log_in = |name: &str| {
+            TestRequest::post()
+                .uri("/login")
+                .set_form([("name", name), ("password", "secret")])
+                .to_request()
+        };
+        let delete = |token: &str| {
+            TestRequest::post()
+                .uri("/moderation/delete")
+                .set_form([("sender", "spammer"), ("csrf_token", token)])
+        };
+
+        let response =
+            test::call_service(&app, TestRequest::get().uri("/moderation").to_request()).await;
+        assert_eq!(location(&response), "/login");
+        let nao = cookies(&test::call_service(&app, log_in("nao")).await);
+        let request = with(&nao, TestRequest::get().uri("/moderation"));
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let rei = cookies(&test::call_service(&app, log_in("rei")).await);
+        let request = with(&rei, TestRequest::get().uri("/moderation"));
+        let html = test::call_and_read_body(&app, request.to_request()).await;
+        let html = String::from_utf8_lossy(&html);
+        assert!(html.contains("spammer") && html.contains("Nao"), "{}", html);
+        let request = with(
+            &rei,
+            TestRequest::get().uri("/moderation/delete?sender=spammer"),
+        );
+        let response = test::call_service(&app, request.to_request()).await;
+        // The page stores the CSRF token of the session.
+        let rei = cookies(&response);
+        let html = test::read_body(response).await;
+        let html = String::from_utf8_lossy(&html);
+        assert!(html.contains("spammer の投稿 2 件"), "{}", html);
+        let token = csrf_token(&html);
+
+        for forged in ["", "0000", &token.replace(|c| c != '0', "0")] {
+            let response = test::call_service(&app, with(&rei, delete(forged)).to_request()).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        }
+        let response = test::call_service(&app, with(&nao, delete(token)).to_request()).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(env.stored().len(), 3);
+
+        let response = test::call_service(&app, with(&rei, delete(token)).to_request()).await;
+        assert_eq!(location(&response), "/moderation");
+        let ids: Vec<_> = env.stored().into_iter().map(|m| m.id).collect();
+        assert_eq!(ids, [2]);
+        let request = with(&rei, TestRequest::get().uri("/moderation"));
+        let request = with(&cookies(&response), request);
+        let html = test::call_and_read_body(&app, request.to_request()).await;
+        let html = String::from_utf8_lossy(&html);
+        assert!(
+            html.contains("spammerの投稿を2件削除しました。"),
+            "{}",
+            html
+        );
+    }
+
+    #[actix_web::test]
+    async fn a_second_post_within_the_cooldown_is_held_back() {
+        let env = TestEnv::with(|config| config.post_cooldown_seconds = Some(60));
+        let app = testing::service!();
+
+        let request = TestRequest::post()
+            .uri("/posts/create")
+            .set_form(form(0, "Kai", "first"));
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let cookies: Vec<_> = response
+            .response()
+            .cookies()
+            .map(|c| c.into_owned())
+            .collect();
+
+        let request = cookies.iter().cloned().fold(
+            TestRequest::post()
+                .uri("/posts/create")
+                .set_form(form(0, "Kai", "second")),
+            TestRequest::cookie,
+        );
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: i64 = response
+            .headers()
+            .get("retry-after")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!((59..=60).contains(&retry_after), "{}", retry_after);
+        let html = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        assert!(
+            html.contains(">second</textarea>"),
+            "the form keeps the content"
+        );
+        assert_eq!(env.stored().len(), 1);
+
+        // Another session is not held back, and the API is not affected.
+        let request =
+            TestRequest::post()
+                .uri("/posts/create")
+                .set_form(form(0, "Mio", "elsewhere"));
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let request = cookies.into_iter().fold(
+            testing::json(
+                TestRequest::post().uri("/api/posts/create"),
+                serde_json::json!({ "sender": "Kai", "content": "via the API" }),
+            ),
+            TestRequest::cookie,
+        );
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(env.stored().len(), 3);
+    }
+
+    #[actix_web::test]
+    async fn anonymous_posts_follow_the_configuration() {
+        for allow_anonymous in [true, false] {
+            let env = TestEnv::with(|config| config.allow_anonymous = allow_anonymous);
+            let app = testing::service!();
+
+            let html =
+                test::call_and_read_body(&app, TestRequest::get().uri("/posts/new").to_request())
+                    .await;
+            let suggested = format!(r#"value="{}""#, ANONYMOUS_SENDER);
+            assert_eq!(
+                String::from_utf8_lossy(&html).contains(&suggested),
+                allow_anonymous
+            );
+
+            let request = TestRequest::post().uri("/posts/create").set_form(form(
+                0,
+                ANONYMOUS_SENDER,
+                "from the form",
+            ));
+            let response = test::call_service(&app, request.to_request()).await;
+            let expected = if allow_anonymous {
+                StatusCode::SEE_OTHER
+            } else {
+                StatusCode::UNPROCESSABLE_ENTITY
+            };
+            assert_eq!(response.status(), expected);
+
+            let request = testing::json(
+                TestRequest::post().uri("/api/posts/create"),
+                serde_json::json!({ "sender": "Anonymous", "content": "from the API" }),
+            );
+            let response = test::call_service(&app, request.to_request()).await;
+            let expected = if allow_anonymous {
+                StatusCode::OK
+            } else {
+                StatusCode::UNPROCESSABLE_ENTITY
+            };
+            assert_eq!(response.status(), expected);
+
+            let request = testing::json(
+                TestRequest::post().uri("/api/posts/create"),
+                serde_json::json!({ "sender": "", "content": "nameless" }),
+            );
+            let response = test::call_service(&app, request.to_request()).await;
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+            assert_eq!(env.stored().len(), if allow_anonymous { 2 } else { 0 });
+        }
+    }
+
+    #[actix_web::test]
+    async fn filtered_words_are_rejected_or_masked_on_every_entry_point() {
+        for mode in [FilterMode::Reject, FilterMode::Mask] {
+            let env = TestEnv::with(|config| {
+                config.filter_words = vec!["spam".to_string()];
+                config.filter_mode = mode;
+            });
+            env.seed(&[message(1, "Nao", "hello")]);
+            let app = testing::service!();
+            let requests = [
+                TestRequest::post()
+                    .uri("/posts/create")
+                    .set_form(form(0, "Kai", "buy SPAM now")),
+                TestRequest::post()
+                    .uri("/posts/update")
+                    .set_form(form(1, "Nao", "buy SPAM now")),
+                testing::json(
+                    TestRequest::post().uri("/api/posts/create"),
+                    serde_json::json!({ "sender": "Rin", "content": "buy SPAM now" }),
+                ),
+                testing::json(
+                    TestRequest::put().uri("/api/posts/update"),
+                    serde_json::json!({ "id": 1, "sender": "Nao", "content": "buy SPAM now" }),
+                ),
+            ];
+
+            for request in requests {
+                let response = test::call_service(&app, request.to_request()).await;
+                if mode == FilterMode::Reject {
+                    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+                    let body = test::read_body(response).await;
+                    assert!(String::from_utf8_lossy(&body).contains("content"));
+                } else {
+                    assert!(response.status().is_success() || response.status().is_redirection());
+                }
+            }
+            let contents: Vec<String> = env.stored().into_iter().map(|m| m.content).collect();
+            match mode {
+                FilterMode::Reject => assert_eq!(contents, ["hello"]),
+                FilterMode::Mask => {
+                    assert_eq!(contents, ["buy **** now", "buy **** now", "buy **** now"])
+                }
+            }
+        }
+    }
+
+    #[actix_web::test]
+    async fn flashes_use_the_configured_wording() {
+        let env = TestEnv::with(|config| {
+            let dir = std::path::Path::new(&config.data_files[0])
+                .parent()
+                .unwrap();
+            let file = dir.join("strings.json");
+            std::fs::write(
+                &file,
+                r#"{ "created_with_permalink": "Posted at {permalink}.", "deleted": "Gone." }"#,
+            )
+            .unwrap();
+            config.strings_file = Some(file.to_string_lossy().into_owned());
+        });
+        env.seed(&[message(1, "Nao", "hello")]);
+        let app = testing::service!();
+        // Follows a redirect with the cookies it set, where the flash is shown.
+        fn follow<B>(response: &actix_web::dev::ServiceResponse<B>) -> TestRequest {
+            assert_eq!(response.status(), StatusCode::SEE_OTHER);
+            let location = response
+                .headers()
+                .get("location")
+                .unwrap()
+                .to_str()
+                .unwrap();
+            response
+                .response()
+                .cookies()
+                .fold(TestRequest::get().uri(location), |request, cookie| {
+                    request.cookie(cookie.into_owned())
+                })
+        }
+        let page = |request: TestRequest| async {
+            let html = test::call_and_read_body(&app, request.to_request()).await;
+            String::from_utf8_lossy(&html).replace("&#x2F;", "/")
+        };
+
+        let request = TestRequest::post()
+            .uri("/posts/create")
+            .set_form(form(0, "Kai", "hi"));
+        let response = test::call_service(&app, request.to_request()).await;
+        let html = page(follow(&response)).await;
+        assert!(html.contains("Posted at http://localhost:8080/posts/2."));
+
+        let request = TestRequest::get().uri("/posts/1/delete");
+        let response = test::call_service(&app, request.to_request()).await;
+        let html = page(follow(&response)).await;
+        assert!(html.contains("Gone."));
+        assert!(!html.contains(Text::Deleted.default_text()));
+    }
+
+    #[actix_web::test]
+    async fn markup_in_posts_is_rendered_as_text() {
+        let env = TestEnv::with(|_| {});
+        env.seed(&[message(
+            1,
+            "<b>Nao</b>",
+            "<script>alert(1)</script>\n<i>second</i> line",
+        )]);
+        let app = testing::service!();
+
+        for uri in ["/posts", "/posts/1"] {
+            let html =
+                test::call_and_read_body(&app, TestRequest::get().uri(uri).to_request()).await;
+            let html = String::from_utf8_lossy(&html);
+            assert!(
+                html.contains("&lt;script&gt;alert(1)&lt;&#x2F;script&gt;<br>&lt;i&gt;second&lt;&#x2F;i&gt; line"),
+                "{}: {}",
+                uri,
+                html
+            );
+            assert!(html.contains("&lt;b&gt;Nao&lt;&#x2F;b&gt;"), "{}", uri);
+            assert!(
+                !html.contains("<script>alert") && !html.contains("<i>second"),
+                "{}",
+                uri
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn placeholders_are_expanded_when_a_post_is_shown() {
+        let env = TestEnv::with(|_| {});
+        let content = "#{{id}} by {{ sender }} on {{date}}, {{unknown}} {{date";
+        env.seed(&[message(7, "Nao", content)]);
+        let app = testing::service!();
+
+        let html =
+            test::call_and_read_body(&app, TestRequest::get().uri("/posts/7").to_request()).await;
+        let html = String::from_utf8_lossy(&html);
+        assert!(
+            html.contains("#7 by Nao on 2024-01-01, {{unknown}} {{date"),
+            "{}",
+            html
+        );
+        assert_eq!(env.stored()[0].content, content);
+    }
+
+    #[actix_web::test]
+    async fn robots_txt_disallows_the_configured_paths() {
+        let robots_txt = |configure: fn(&mut crate::config::Config)| async move {
+            let _env = TestEnv::with(configure);
+            let app = testing::service!();
+            let response =
+                test::call_service(&app, TestRequest::get().uri("/robots.txt").to_request()).await;
+            assert_eq!(
+                response.headers().get("content-type").unwrap(),
+                "text/plain; charset=utf-8"
+            );
+            String::from_utf8(test::read_body(response).await.to_vec()).unwrap()
+        };
+
+        let defaults = robots_txt(|_| {}).await;
+        assert_eq!(
+            defaults,
+            "User-agent: *\nDisallow: /posts/*/delete\nDisallow: /posts/*/edit\nDisallow: /posts/new\n"
+        );
+        let custom =
+            robots_txt(|config| config.robots_disallow = vec!["/private".to_string()]).await;
+        assert_eq!(custom, "User-agent: *\nDisallow: /private\n");
+        let none = robots_txt(|config| config.robots_disallow = vec![]).await;
+        assert_eq!(none, "User-agent: *\nDisallow:\n");
+    }
+
+    #[actix_web::test]
+    async fn non_canonical_post_urls_redirect_permanently() {
+        let env = TestEnv::with(|_| {});
+        env.seed(&[message(7, "Nao", "hello")]);
+        let app = testing::service!();
+
+        for uri in ["/posts/007", "/posts/+7"] {
+            let response = test::call_service(&app, TestRequest::get().uri(uri).to_request()).await;
+            assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY, "{}", uri);
+            let location = response
+                .headers()
+                .get("location")
+                .unwrap()
+                .to_str()
+                .unwrap();
+            assert!(location.ends_with("/posts/7"), "{}", location);
+        }
+
+        let response =
+            test::call_service(&app, TestRequest::get().uri("/posts/7").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let html = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        let canonical = html
+            .split(r#"<link rel="canonical" href=""#)
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .expect("a canonical link")
+            .replace("&#x2F;", "/");
+        assert!(canonical.ends_with("/posts/7"), "{}", canonical);
+    }
+
+    #[actix_web::test]
+    async fn stale_edit_forms_do_not_overwrite_newer_edits() {
+        let env = TestEnv::with(|_| {});
+        env.seed(&[message(1, "Nao", "original")]);
+        let app = testing::service!();
+        let version = |html: &str| {
+            let (_, version) = html.split_once(r#"name="version" value=""#).unwrap();
+            version[..version.find('"').unwrap()].to_string()
+        };
+        let submit = |version: &str| {
+            let mut fields = form(1, "Nao", "from the browser").to_vec();
+            fields.push(("version", version.to_string()));
+            TestRequest::post()
+                .uri("/posts/update")
+                .set_form(fields)
+                .to_request()
+        };
+
+        let request = TestRequest::get().uri("/posts/1/edit").to_request();
+        let html =
+            String::from_utf8(test::call_and_read_body(&app, request).await.to_vec()).unwrap();
+        let stale = version(&html);
+
+        // Someone else edits the post while the form is open.
+        let request = testing::json(
+            TestRequest::put().uri("/api/posts/update"),
+            serde_json::json!({ "id": 1, "sender": "Nao", "content": "from elsewhere" }),
+        );
+        let response = test::call_service(&app, request.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = test::call_service(&app, submit(&stale)).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let html = String::from_utf8(test::read_body(response).await.to_vec()).unwrap();
+        assert!(html.contains(&strings::get(Text::EditConflict)));
+        assert!(
+            html.contains("from the browser"),
+            "the form keeps the entered content"
+        );
+        assert_eq!(env.stored()[0].content, "from elsewhere");
+
+        // The re-rendered form is based on the current version, so submitting it again wins.
+        let current = version(&html);
+        assert_ne!(current, stale);
+        let response = test::call_service(&app, submit(&current)).await;
+        assert_eq!(location(&response), "/posts/1");
+        assert_eq!(env.stored()[0].content, "from the browser");
+    }
+}
@@ -0,0 +1,135 @@
+//! Case-insensitive text search over the stored messages.
+//!
+//! A message matches when its sender or content contains the query, ignoring case. Results can
+//! either be the whole messages or [`SearchResult`]s carrying a short snippet of the content
+//! around the first match, with the match wrapped in [`MATCH_START`] and [`MATCH_END`].
+
+use crate::handler::data;
+use crate::handler::data::{Message, SortOrder};
+use serde::Serialize;
+
+/// Inserted before the matched text in a snippet.
+pub const MATCH_START: &str = "[[";
+
+/// Inserted after the matched text in a snippet.
+pub const MATCH_END: &str = "]]";
+
+/// Marks the side of a snippet where content was cut off.
+const ELLIPSIS: &str = "…";
+
+/// A message matching a search, reduced to a snippet of its content.
+///
+/// # Fields
+/// - `id`, `posted`, `sender`: Copied from the message.
+/// - `snippet`: Up to `context` characters on each side of the first match in the content, or
+///   `None` when only the sender matched.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    pub id: i32,
+    pub posted: String,
+    pub sender: String,
+    pub snippet: Option<String>,
+}
+
+fn chars_eq_ignore_case(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+/// Returns the character index of the first case-insensitive occurrence of `needle`.
+fn find_ignore_case(haystack: &[char], needle: &[char]) -> Option<usize> {
+    if needle.is_empty() || needle.len() > haystack.len() {
+        return None;
+    }
+    (0..=haystack.len() - needle.len()).find(|&start| {
+        haystack[start..start + needle.len()]
+            .iter()
+            .zip(needle)
+            .all(|(a, b)| chars_eq_ignore_case(*a, *b))
+    })
+}
+
+fn contains_ignore_case(haystack: &str, needle: &[char]) -> bool {
+    find_ignore_case(&haystack.chars().collect::<Vec<_>>(), needle).is_some()
+}
+
+/// Builds the snippet of `content` around the first match of `needle`.
+///
+/// # Example
+/// ```rust
+/// use actix_posts::handler::search::snippet;
+/// assert_eq!(snippet("Hello brave new world", "NEW", 4).unwrap(), "…ave [[new]] wor…");
+/// assert_eq!(snippet("Hello", "hel", 10).unwrap(), "[[Hel]]lo");
+/// assert!(snippet("Hello", "bye", 10).is_none());
+/// ```
+pub fn snippet(content: &str, needle: &str, context: usize) -> Option<String> {
+    let chars: Vec<char> = content.chars().collect();
+    let needle: Vec<char> = needle.chars().collect();
+    let start = find_ignore_case(&chars, &needle)?;
+    let end = start + needle.len();
+    let from = start.saturating_sub(context);
+    let to = (end + context).min(chars.len());
+
+    let mut snippet = String::new();
+    if from > 0 {
+        snippet.push_str(ELLIPSIS);
+    }
+    snippet.extend(&chars[from..start]);
+    snippet.push_str(MATCH_START);
+    snippet.extend(&chars[start..end]);
+    snippet.push_str(MATCH_END);
+    snippet.extend(&chars[end..to]);
+    if to < chars.len() {
+        snippet.push_str(ELLIPSIS);
+    }
+    Some(snippet)
+}
+
+/// Returns `true` when the sender or content of `message` contains `needle`, ignoring case.
+fn matches(message: &Message, needle: &[char]) -> bool {
+    contains_ignore_case(&message.content, needle) || contains_ignore_case(&message.sender, needle)
+}
+
+/// Returns the messages whose sender or content contains `query`, newest first.
+///
+/// A blank query matches nothing.
+pub fn search(query: &str) -> Vec<Message> {
+    let needle: Vec<char> = query.trim().chars().collect();
+    if needle.is_empty() {
+        return vec![];
+    }
+    data::get_all_shared(SortOrder::Newest)
+        .iter()
+        .filter(|m| matches(m, &needle))
+        .cloned()
+        .collect()
+}
+
+/// Returns the number of messages [`search`] would return, without copying them.
+pub fn search_count(query: &str) -> usize {
+    let needle: Vec<char> = query.trim().chars().collect();
+    if needle.is_empty() {
+        return 0;
+    }
+    data::get_all_shared(SortOrder::Newest)
+        .iter()
+        .filter(|m| matches(m, &needle))
+        .count()
+}
+
+/// Searches like [`search`], returning a snippet of each match instead of the whole message.
+///
+/// # Arguments
+/// - `query`: The text to look for.
+/// - `context`: The number of characters kept on each side of the match.
+pub fn search_with_snippets(query: &str, context: usize) -> Vec<SearchResult> {
+    let query = query.trim();
+    search(query)
+        .into_iter()
+        .map(|message| SearchResult {
+            snippet: snippet(&message.content, query, context),
+            id: message.id,
+            posted: message.posted,
+            sender: message.sender,
+        })
+        .collect()
+}
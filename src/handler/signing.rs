@@ -0,0 +1,72 @@
+//! Short-lived signed URLs.
+//!
+//! A signed URL carries an `expires` Unix timestamp and a `signature` query parameter holding the
+//! hex-encoded HMAC-SHA256 of the request path and the expiry. The key is derived from the
+//! session key generated at startup, so links stop working when the server restarts.
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Why a signed URL was refused.
+///
+/// # Variants
+/// - `Expired`: The `expires` timestamp is in the past.
+/// - `Invalid`: The signature does not match the path and expiry (e.g. it was tampered with).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureError {
+    Expired,
+    Invalid,
+}
+
+/// Signs and verifies URLs with a server-side secret.
+#[derive(Clone)]
+pub struct UrlSigner {
+    key: Vec<u8>,
+}
+
+impl UrlSigner {
+    /// Creates a signer from secret key material (e.g. [`actix_web::cookie::Key::signing`]).
+    pub fn new(key: &[u8]) -> Self {
+        UrlSigner { key: key.to_vec() }
+    }
+
+    fn mac(&self, path: &str, expires: i64) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(path.as_bytes());
+        mac.update(b"\n");
+        mac.update(expires.to_string().as_bytes());
+        mac
+    }
+
+    /// Returns the hex-encoded signature of `path` valid until `expires` (Unix seconds).
+    pub fn sign(&self, path: &str, expires: i64) -> String {
+        hex::encode(self.mac(path, expires).finalize().into_bytes())
+    }
+
+    /// Checks a signature produced by [`UrlSigner::sign`].
+    ///
+    /// The comparison runs in constant time.
+    ///
+    /// # Returns
+    /// - `Ok(())` when the signature matches and `expires` is not before `now`.
+    /// - `Err(SignatureError::Expired)` when the signature matches but has expired.
+    /// - `Err(SignatureError::Invalid)` otherwise.
+    pub fn verify(
+        &self,
+        path: &str,
+        expires: i64,
+        signature: &str,
+        now: i64,
+    ) -> Result<(), SignatureError> {
+        let signature = hex::decode(signature).map_err(|_| SignatureError::Invalid)?;
+        self.mac(path, expires)
+            .verify_slice(&signature)
+            .map_err(|_| SignatureError::Invalid)?;
+        if expires < now {
+            return Err(SignatureError::Expired);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+//! Detection of unknown fields in JSON request bodies.
+//!
+//! Serde ignores fields a struct does not declare, so a typo such as `{"sendr": "x"}` silently
+//! posts a message without a sender. `#[serde(deny_unknown_fields)]` would reject it, but cannot
+//! be turned off for clients that rely on the lenient behavior. [`unknown_field`] performs the
+//! same check on demand instead; the API runs it when `ACTIX_POSTS_STRICT_JSON` is enabled (see
+//! [`crate::config`]).
+//!
+//! The declared fields are read from the `Deserialize` implementation itself (see
+//! [`struct_fields`]), so they never drift from the struct definitions.
+
+use serde::de::value::Error;
+use serde::de::{DeserializeOwned, DeserializeSeed, SeqAccess, Visitor};
+use serde::{forward_to_deserialize_any, Deserializer};
+use serde_json::Value;
+
+/// A deserializer that records the field list a struct asks for, then fails.
+///
+/// Sequences hand their element type one more `FieldProbe`, so `Vec<T>` reports the fields of
+/// `T`.
+struct FieldProbe<'a>(&'a mut Option<&'static [&'static str]>);
+
+impl<'de> Deserializer<'de> for FieldProbe<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Error> {
+        Err(serde::de::Error::custom("not a struct"))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Error> {
+        *self.0 = Some(fields);
+        Err(serde::de::Error::custom("fields recorded"))
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(ElementProbe(self.0))
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct tuple tuple_struct map enum identifier
+        ignored_any
+    }
+}
+
+/// Yields a single element, probed with [`FieldProbe`].
+struct ElementProbe<'a>(&'a mut Option<&'static [&'static str]>);
+
+impl<'de> SeqAccess<'de> for ElementProbe<'_> {
+    type Error = Error;
+
+    fn next_element_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, Error> {
+        seed.deserialize(FieldProbe(self.0)).map(Some)
+    }
+}
+
+/// Returns the fields declared by the struct `T`, or by its elements when `T` is a sequence
+/// such as `Vec<Message>`. Returns `None` for any other type.
+///
+/// # Example
+/// ```rust
+/// use actix_posts::handler::data::Message;
+/// use actix_posts::handler::strict::struct_fields;
+/// assert!(struct_fields::<Message>().unwrap().contains(&"sender"));
+/// assert_eq!(struct_fields::<Vec<Message>>(), struct_fields::<Message>());
+/// assert!(struct_fields::<String>().is_none());
+/// ```
+pub fn struct_fields<T: DeserializeOwned>() -> Option<&'static [&'static str]> {
+    let mut fields = None;
+    let _ = T::deserialize(FieldProbe(&mut fields));
+    fields
+}
+
+/// Returns the first key of `value` that `T` does not declare, or `None` when every key is known.
+///
+/// Objects are checked against the fields of `T`, arrays element by element; nested values are
+/// not inspected. Types that are not structs accept anything.
+///
+/// # Example
+/// ```rust
+/// use actix_posts::handler::data::Message;
+/// use actix_posts::handler::strict::unknown_field;
+/// let typo = serde_json::json!({ "sendr": "Nao", "content": "hi" });
+/// assert_eq!(unknown_field::<Message>(&typo).as_deref(), Some("sendr"));
+/// let list = serde_json::json!([{ "sender": "Nao" }, { "sender": "Shino", "extra": 1 }]);
+/// assert_eq!(unknown_field::<Vec<Message>>(&list).as_deref(), Some("extra"));
+/// ```
+pub fn unknown_field<T: DeserializeOwned>(value: &Value) -> Option<String> {
+    let fields = struct_fields::<T>()?;
+    let objects: Vec<&Value> = match value {
+        Value::Array(items) => items.iter().collect(),
+        object => vec![object],
+    };
+    objects
+        .into_iter()
+        .filter_map(Value::as_object)
+        .flat_map(|object| object.keys())
+        .find(|key| !fields.contains(&key.as_str()))
+        .cloned()
+}
@@ -0,0 +1,213 @@
+//! The wording of the flash messages shown by the HTML pages.
+//!
+//! Every message is identified by a [`Text`] key and has a built-in (Japanese) default. A
+//! deployment can override any of them with a JSON object mapping keys to texts, loaded from
+//! `ACTIX_POSTS_STRINGS_FILE` (see [`crate::config`]):
+//!
+//! ```json
+//! { "updated": "Saved.", "edit_window_closed": "Too late: posts lock after {minutes} minutes." }
+//! ```
+//!
+//! Placeholders such as `{permalink}` are filled in by [`format`]. Keys missing from the file
+//! keep their default; unknown keys and an unreadable file are logged and ignored.
+
+use crate::config;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Identifies a flash message.
+///
+/// # Variants
+/// - `Created`: A post was created (`created`).
+/// - `CreatedWithPermalink`: A post was created; `{permalink}` is its URL
+///   (`created_with_permalink`).
+/// - `CreateFailed`: A post could not be created (`create_failed`).
+/// - `Updated`: A post was updated (`updated`).
+/// - `Deleted`: A post was deleted (`deleted`).
+/// - `Duplicate`: A post with the same sender and content already exists (`duplicate`).
+/// - `EditWindowClosed`: The edit window is over; `{minutes}` is its length
+///   (`edit_window_closed`).
+/// - `Cooldown`: The session posted too recently; `{seconds}` is the time left (`cooldown`).
+/// - `QuotaExceeded`: The sender has too many posts; `{max}` is the limit (`quota_exceeded`).
+/// - `EditConflict`: The post was changed while the edit form was open (`edit_conflict`).
+/// - `LoginRequired`: Posting requires a login (`login_required`).
+/// - `NotOwner`: A logged-in user tried to edit another user's post, or to change its sender
+///   (`not_owner`).
+/// - `LoginFailed`: The name or password was wrong (`login_failed`).
+/// - `LoggedIn`: The user logged in; `{name}` is their name (`logged_in`).
+/// - `LoggedOut`: The user logged out (`logged_out`).
+/// - `SenderDeleted`: A moderator deleted every post of a sender; `{sender}` is the sender and
+///   `{count}` the number of posts (`sender_deleted`).
+/// - `Restored`: A deleted post was restored (`restored`).
+/// - `RestoreExpired`: A deleted post can no longer be restored (`restore_expired`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Text {
+    Created,
+    CreatedWithPermalink,
+    CreateFailed,
+    Updated,
+    Deleted,
+    Duplicate,
+    EditWindowClosed,
+    Cooldown,
+    QuotaExceeded,
+    EditConflict,
+    LoginRequired,
+    NotOwner,
+    LoginFailed,
+    LoggedIn,
+    LoggedOut,
+    SenderDeleted,
+    Restored,
+    RestoreExpired,
+}
+
+impl Text {
+    const ALL: [Text; 18] = [
+        Text::Created,
+        Text::CreatedWithPermalink,
+        Text::CreateFailed,
+        Text::Updated,
+        Text::Deleted,
+        Text::Duplicate,
+        Text::EditWindowClosed,
+        Text::Cooldown,
+        Text::QuotaExceeded,
+        Text::EditConflict,
+        Text::LoginRequired,
+        Text::NotOwner,
+        Text::LoginFailed,
+        Text::LoggedIn,
+        Text::LoggedOut,
+        Text::SenderDeleted,
+        Text::Restored,
+        Text::RestoreExpired,
+    ];
+
+    /// Returns the key of the text in the strings file.
+    pub fn key(self) -> &'static str {
+        match self {
+            Text::Created => "created",
+            Text::CreatedWithPermalink => "created_with_permalink",
+            Text::CreateFailed => "create_failed",
+            Text::Updated => "updated",
+            Text::Deleted => "deleted",
+            Text::Duplicate => "duplicate",
+            Text::EditWindowClosed => "edit_window_closed",
+            Text::Cooldown => "cooldown",
+            Text::QuotaExceeded => "quota_exceeded",
+            Text::EditConflict => "edit_conflict",
+            Text::LoginRequired => "login_required",
+            Text::NotOwner => "not_owner",
+            Text::LoginFailed => "login_failed",
+            Text::LoggedIn => "logged_in",
+            Text::LoggedOut => "logged_out",
+            Text::SenderDeleted => "sender_deleted",
+            Text::Restored => "restored",
+            Text::RestoreExpired => "restore_expired",
+        }
+    }
+
+    /// Returns the built-in wording.
+    pub fn default_text(self) -> &'static str {
+        match self {
+            Text::Created => "投稿しました。",
+            Text::CreatedWithPermalink => "投稿しました。パーマリンク: {permalink}",
+            Text::CreateFailed => "投稿でエラーが発生しました。",
+            Text::Updated => "更新しました。",
+            Text::Deleted => "削除しました。",
+            Text::Duplicate => "同じ名前と内容の投稿が既にあります。",
+            Text::EditWindowClosed => "投稿から{minutes}分を過ぎたため編集できません。",
+            Text::Cooldown => "続けて投稿できません。{seconds}秒後にもう一度お試しください。",
+            Text::QuotaExceeded => "1人が投稿できるのは{max}件までです。",
+            Text::EditConflict => "編集中に他の人がこの投稿を更新しました。最新の内容を確認してから、もう一度更新してください。",
+            Text::LoginRequired => "投稿するにはログインしてください。",
+            Text::NotOwner => "自分の投稿だけを、自分の名前のまま編集できます。",
+            Text::LoginFailed => "名前またはパスワードが違います。",
+            Text::LoggedIn => "{name}としてログインしました。",
+            Text::LoggedOut => "ログアウトしました。",
+            Text::SenderDeleted => "{sender}の投稿を{count}件削除しました。",
+            Text::Restored => "元に戻しました。",
+            Text::RestoreExpired => "この投稿はもう元に戻せません。",
+        }
+    }
+}
+
+/// The overrides, together with the strings file they were loaded from.
+///
+/// The file is read on first use and again only if the configured path changes, which only
+/// happens in tests.
+type Overrides = (Option<String>, Arc<HashMap<Text, String>>);
+
+static OVERRIDES: Mutex<Option<Overrides>> = Mutex::new(None);
+
+/// Returns the overrides of the configured strings file.
+fn overrides() -> Arc<HashMap<Text, String>> {
+    let path = &config::get().strings_file;
+    let mut loaded = OVERRIDES.lock().unwrap_or_else(PoisonError::into_inner);
+    match &*loaded {
+        Some((loaded_path, overrides)) if loaded_path == path => overrides.clone(),
+        _ => {
+            let overrides = Arc::new(load_overrides(path.as_deref()));
+            *loaded = Some((path.clone(), overrides.clone()));
+            overrides
+        }
+    }
+}
+
+fn load_overrides(path: Option<&str>) -> HashMap<Text, String> {
+    let Some(path) = path else {
+        return HashMap::new();
+    };
+    let entries: HashMap<String, String> = match std::fs::read_to_string(path)
+        .map_err(|error| error.to_string())
+        .and_then(|json| serde_json::from_str(&json).map_err(|error| error.to_string()))
+    {
+        Ok(entries) => entries,
+        Err(error) => {
+            log::warn!("ignoring strings file {}: {}", path, error);
+            return HashMap::new();
+        }
+    };
+    entries
+        .into_iter()
+        .filter_map(
+            |(key, value)| match Text::ALL.into_iter().find(|text| text.key() == key) {
+                Some(text) => Some((text, value)),
+                None => {
+                    log::warn!("ignoring unknown string key {} in {}", key, path);
+                    None
+                }
+            },
+        )
+        .collect()
+}
+
+/// Returns the configured wording of `text`.
+pub fn get(text: Text) -> String {
+    overrides()
+        .get(&text)
+        .cloned()
+        .unwrap_or_else(|| text.default_text().to_string())
+}
+
+/// Returns the configured wording of `text` with its placeholders filled in (see [`fill`]).
+pub fn format(text: Text, args: &[(&str, &dyn Display)]) -> String {
+    fill(&get(text), args)
+}
+
+/// Replaces each `{name}` in `template` by the value given for `name`.
+///
+/// # Example
+/// ```rust
+/// use actix_posts::handler::strings::fill;
+/// assert_eq!(fill("Locked after {minutes} min", &[("minutes", &15)]), "Locked after 15 min");
+/// assert_eq!(fill("No {placeholder}", &[]), "No {placeholder}");
+/// ```
+pub fn fill(template: &str, args: &[(&str, &dyn Display)]) -> String {
+    args.iter()
+        .fold(template.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), &value.to_string())
+        })
+}
@@ -0,0 +1,144 @@
+//! Storage of uploaded image attachments.
+//!
+//! Uploaded files are written to the directory configured by `ACTIX_POSTS_UPLOAD_DIR` (see
+//! [`crate::config`]) under a name derived from the SHA-256 hash of their content, so uploading
+//! the same image twice stores it once. The files are served back under [`UPLOADS_PATH`].
+//!
+//! ## Accepted Files
+//!
+//! - At most `ACTIX_POSTS_MAX_UPLOAD` bytes.
+//! - A declared content type of `image/png`, `image/jpeg`, or `image/gif`, and content that
+//!   actually starts with the signature of that format.
+
+use crate::config;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::path::Path;
+
+/// The URL path under which uploaded files are served.
+pub const UPLOADS_PATH: &str = "/uploads";
+
+/// Errors reported when an uploaded file cannot be stored.
+///
+/// # Variants
+/// - `TooLarge(usize)`: The file exceeds the size limit. Holds the limit in bytes.
+/// - `UnsupportedType(String)`: The content type is not allowed. Holds the declared content type.
+/// - `ContentMismatch(String)`: The content does not start with the signature of its declared
+///   type. Holds the declared content type.
+/// - `Io(std::io::Error)`: The file could not be written.
+#[derive(Debug)]
+pub enum UploadError {
+    TooLarge(usize),
+    UnsupportedType(String),
+    ContentMismatch(String),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadError::TooLarge(limit) => {
+                write!(f, "Uploaded file exceeds the limit of {} bytes", limit)
+            }
+            UploadError::UnsupportedType(content_type) => write!(
+                f,
+                "Unsupported content type: {} (expected image/png, image/jpeg, or image/gif)",
+                content_type
+            ),
+            UploadError::ContentMismatch(content_type) => {
+                write!(f, "File content does not match its type {}", content_type)
+            }
+            UploadError::Io(error) => write!(f, "Uploaded file could not be stored: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for UploadError {}
+
+/// Returns the file extension and the leading signature bytes of an allowed content type.
+fn image_format(content_type: &str) -> Option<(&'static str, &'static [u8])> {
+    match content_type {
+        "image/png" => Some(("png", b"\x89PNG\r\n\x1a\n")),
+        "image/jpeg" => Some(("jpg", b"\xff\xd8\xff")),
+        "image/gif" => Some(("gif", b"GIF8")),
+        _ => None,
+    }
+}
+
+/// Returns `true` when `path` points at a stored upload (e.g. `/uploads/<hash>.png`).
+pub fn is_upload_path(path: &str) -> bool {
+    path.strip_prefix(UPLOADS_PATH)
+        .and_then(|name| name.strip_prefix('/'))
+        .is_some_and(|name| {
+            !name.is_empty()
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '.')
+                && !name.starts_with('.')
+        })
+}
+
+/// Checks the declared content type of an upload before its content is read.
+///
+/// # Returns
+/// - `Ok(())` when the type is allowed.
+/// - `Err(UploadError::UnsupportedType)` otherwise.
+pub fn check_content_type(content_type: &str) -> Result<(), UploadError> {
+    image_format(content_type)
+        .map(|_| ())
+        .ok_or_else(|| UploadError::UnsupportedType(content_type.to_string()))
+}
+
+/// An uploaded image that passed the checks but has not been written yet.
+///
+/// Keeping the write separate lets the handler validate the whole message first, so rejected
+/// posts leave no files behind.
+#[derive(Debug)]
+pub struct PendingUpload {
+    name: String,
+    bytes: Vec<u8>,
+}
+
+impl PendingUpload {
+    /// Returns the URL path the file is served from once saved (e.g. `/uploads/<sha256>.png`).
+    pub fn url_path(&self) -> String {
+        format!("{}/{}", UPLOADS_PATH, self.name)
+    }
+
+    /// Writes the file to the uploads directory, creating the directory if needed.
+    ///
+    /// Files are named after their content, so an existing file is left untouched.
+    pub fn save(&self) -> Result<(), UploadError> {
+        let dir = Path::new(&config::get().upload_dir);
+        std::fs::create_dir_all(dir).map_err(UploadError::Io)?;
+        let path = dir.join(&self.name);
+        if !path.exists() {
+            std::fs::write(&path, &self.bytes).map_err(UploadError::Io)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks an uploaded image and prepares it for storage.
+///
+/// # Arguments
+/// - `content_type`: The declared content type of the file.
+/// - `bytes`: The file content.
+///
+/// # Returns
+/// - `Ok(upload)` ready to be saved.
+/// - `Err(UploadError::TooLarge)` when `bytes` exceeds the configured limit.
+/// - `Err(UploadError::UnsupportedType)` when the type is not allowed.
+/// - `Err(UploadError::ContentMismatch)` when the content does not start with the signature of
+///   that type.
+pub fn prepare_image(content_type: &str, bytes: Vec<u8>) -> Result<PendingUpload, UploadError> {
+    let max_upload = config::get().max_upload;
+    if bytes.len() > max_upload {
+        return Err(UploadError::TooLarge(max_upload));
+    }
+    let (extension, signature) = image_format(content_type)
+        .ok_or_else(|| UploadError::UnsupportedType(content_type.to_string()))?;
+    if !bytes.starts_with(signature) {
+        return Err(UploadError::ContentMismatch(content_type.to_string()));
+    }
+    let name = format!("{}.{}", hex::encode(Sha256::digest(&bytes)), extension);
+    Ok(PendingUpload { name, bytes })
+}
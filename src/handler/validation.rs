@@ -0,0 +1,359 @@
+//! Validation rules for user-submitted messages.
+//!
+//! The same rules apply to every entry point, so the web form cannot be used to bypass limits
+//! enforced on the API (and vice versa).
+//!
+//! ## Limits
+//!
+//! - `sender`: required (not empty or whitespace only), at most `ACTIX_POSTS_MAX_SENDER`
+//!   characters. [`ANONYMOUS_SENDER`] is refused when `ACTIX_POSTS_ALLOW_ANONYMOUS` is disabled,
+//!   and the names of `ACTIX_POSTS_RESERVED_SENDERS` are refused unless the request carries the
+//!   API key (see [`Message::validate_privileged`]) or is logged in under that name (see
+//!   [`Message::validate_as`]).
+//! - `sender` and `content`: no filtered word while the word filter runs in reject mode (see
+//!   [`crate::handler::filter`]).
+//! - `content`: required (not empty or whitespace only), at least `ACTIX_POSTS_MIN_CONTENT`
+//!   characters once trimmed, and at most `ACTIX_POSTS_MAX_CONTENT` characters (see
+//!   [`crate::config`]).
+//! - `attachments`: at most [`MAX_ATTACHMENTS`] entries, each an absolute `http` or `https` URL
+//!   with a host, or the path of a stored upload (see [`crate::handler::upload`]).
+//! - `tags`: after normalization (see [`normalize_tags`]), at most `ACTIX_POSTS_MAX_TAGS` tags of
+//!   at most `ACTIX_POSTS_MAX_TAG_LENGTH` characters each (see [`check_tags`]).
+//! - `expires_at`: when set, a future time in UTC formatted as [`STORED_FORMAT`]
+//!   (`2024-12-31 23:59:59Z`).
+//!
+//! Lengths are counted in characters, not bytes, so multi-byte text is not penalized.
+//!
+//! Reactions are limited to the emoji listed in [`REACTIONS`] (see [`check_reaction`]).
+//!
+//! Content is not sanitized: it is stored as submitted, and the HTML pages escape it when
+//! rendering (Tera escapes every `.html` template by default; `item.html` escapes the content
+//! explicitly before turning line breaks into `<br>`).
+
+use crate::config;
+use crate::handler::data::{Message, SCHEMA_VERSION, STORED_FORMAT};
+use crate::handler::filter;
+use crate::handler::filter::FilterMode;
+use crate::handler::strict;
+use crate::handler::upload;
+use chrono::{NaiveDateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use url::Url;
+
+/// The default maximum number of characters allowed in a message's sender.
+pub const MAX_SENDER_LEN: usize = 80;
+
+/// The sender name suggested to users who do not give one.
+pub const ANONYMOUS_SENDER: &str = "anonymous";
+
+/// The maximum number of attachments allowed on a message.
+pub const MAX_ATTACHMENTS: usize = 4;
+
+/// The emoji messages can be reacted with, in display order.
+pub const REACTIONS: &[&str] = &["👍", "❤️", "😂", "😮", "😢", "🎉"];
+
+/// Describes why a single field of a message was rejected.
+///
+/// # Fields
+/// - `field`: The name of the offending field (e.g. `"sender"`).
+/// - `reason`: A human-readable explanation of the problem.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    /// The name of the offending field.
+    pub field: String,
+
+    /// A human-readable explanation of the problem.
+    pub reason: String,
+}
+
+impl FieldError {
+    fn new(field: &str, reason: String) -> Self {
+        FieldError {
+            field: field.to_string(),
+            reason,
+        }
+    }
+}
+
+/// The fields every stored message record must contain (see [`parse_record`]).
+pub const REQUIRED_FIELDS: &[&str] = &["sender", "content"];
+
+/// Parses a stored message record, e.g. one of an import, reporting every field that does not
+/// match the current [`Message`] schema.
+///
+/// The [`REQUIRED_FIELDS`] must be present; other absent fields get their default, as when
+/// reading the data file. The limits checked by [`Message::validate`] still apply afterwards.
+/// Missing required fields, fields the schema does not declare, and values of the wrong type are
+/// errors, one per field, instead of being ignored or failing the whole body.
+///
+/// # Example
+/// ```rust
+/// use actix_posts::handler::validation::parse_record;
+/// let record = serde_json::json!({ "sender": "Nao", "content": 1, "colour": "red" });
+/// let errors = parse_record(&record).unwrap_err();
+/// let fields: Vec<&str> = errors.iter().map(|error| error.field.as_str()).collect();
+/// assert_eq!(fields, ["colour", "content"]);
+/// let errors = parse_record(&serde_json::json!({ "sender": "Nao" })).unwrap_err();
+/// assert_eq!(errors[0].field, "content");
+/// assert!(parse_record(&serde_json::json!({ "sender": "Nao", "content": "Hi" })).is_ok());
+/// ```
+pub fn parse_record(record: &Value) -> Result<Message, Vec<FieldError>> {
+    let Value::Object(fields) = record else {
+        return Err(vec![FieldError::new("", "must be an object".to_string())]);
+    };
+    let known = strict::struct_fields::<Message>().unwrap_or_default();
+    let missing = REQUIRED_FIELDS
+        .iter()
+        .filter(|field| !fields.contains_key(**field))
+        .map(|field| {
+            FieldError::new(
+                field,
+                format!("is required by schema version {}", SCHEMA_VERSION),
+            )
+        });
+    let errors: Vec<FieldError> = fields
+        .iter()
+        .filter_map(|(field, value)| {
+            if !known.contains(&field.as_str()) {
+                return Some(FieldError::new(
+                    field,
+                    format!("is not part of schema version {}", SCHEMA_VERSION),
+                ));
+            }
+            let single = Value::Object([(field.clone(), value.clone())].into_iter().collect());
+            serde_json::from_value::<Message>(single)
+                .err()
+                .map(|error| FieldError::new(field, error.to_string()))
+        })
+        .chain(missing)
+        .collect();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    serde_json::from_value(record.clone())
+        .map_err(|error| vec![FieldError::new("", error.to_string())])
+}
+
+/// Checks that `emoji` is one of the allowed [`REACTIONS`].
+///
+/// # Returns
+/// - `Ok(())` when it is.
+/// - `Err(error)` for the `emoji` field otherwise.
+pub fn check_reaction(emoji: &str) -> Result<(), FieldError> {
+    if REACTIONS.contains(&emoji) {
+        Ok(())
+    } else {
+        Err(FieldError::new(
+            "emoji",
+            format!("must be one of {}", REACTIONS.join(" ")),
+        ))
+    }
+}
+
+/// Normalizes tags: trims and lowercases them, then drops empty and repeated tags, keeping the
+/// first occurrence of each.
+///
+/// # Example
+/// ```rust
+/// use actix_posts::handler::validation::normalize_tags;
+/// let tags = [" Rust ", "web", "rust", "", "Web"].map(String::from);
+/// assert_eq!(normalize_tags(&tags), ["rust", "web"]);
+/// ```
+pub fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = vec![];
+    for tag in tags {
+        let tag = tag.trim().to_lowercase();
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+/// Checks tags against the configured limits, after normalizing them (see [`normalize_tags`]).
+///
+/// # Arguments
+/// - `field`: The name of the field the tags were given in, used in the errors.
+/// - `tags`: The tags as given.
+///
+/// # Returns
+/// The errors found: one for `field` when there are more than `ACTIX_POSTS_MAX_TAGS` tags, and
+/// one for `field[i]` per tag longer than `ACTIX_POSTS_MAX_TAG_LENGTH` characters, where `i` is the
+/// position among the normalized tags. Empty when the tags are acceptable.
+pub fn check_tags(field: &str, tags: &[String]) -> Vec<FieldError> {
+    let config = config::get();
+    let tags = normalize_tags(tags);
+    let mut errors = vec![];
+    if tags.len() > config.max_tags {
+        errors.push(FieldError::new(
+            field,
+            format!("must have at most {} entries", config.max_tags),
+        ));
+    }
+    for (i, tag) in tags.iter().enumerate() {
+        if tag.chars().count() > config.max_tag_length {
+            errors.push(FieldError::new(
+                &format!("{}[{}]", field, i),
+                format!("must be at most {} characters", config.max_tag_length),
+            ));
+        }
+    }
+    errors
+}
+
+/// Returns `true` when `value` is an absolute `http` or `https` URL with a host.
+fn is_web_url(value: &str) -> bool {
+    Url::parse(value)
+        .map(|url| matches!(url.scheme(), "http" | "https") && url.host().is_some())
+        .unwrap_or(false)
+}
+
+impl Message {
+    /// Checks the user-controlled fields of the message against the configured limits.
+    ///
+    /// # Returns
+    /// - `Ok(())` when every field is acceptable.
+    /// - `Err(errors)` with one [`FieldError`] per rejected field otherwise.
+    ///
+    /// # Example
+    /// ```rust
+    /// use actix_posts::handler::data::Message;
+    /// let message = Message {
+    ///     sender: "x".repeat(81),
+    ///     content: "hello".to_string(),
+    ///     ..Default::default()
+    /// };
+    /// let errors = message.validate().unwrap_err();
+    /// assert_eq!(errors[0].field, "sender");
+    /// ```
+    pub fn validate(&self) -> Result<(), Vec<FieldError>> {
+        self.check(false, None)
+    }
+
+    /// Checks the message like [`Message::validate`] on behalf of the logged-in user `identity`
+    /// (see [`crate::handler::auth::identity`]), who may post under their own name even when it
+    /// is reserved. Without an identity, this is [`Message::validate`].
+    ///
+    /// # Example
+    /// ```rust
+    /// use actix_posts::handler::data::Message;
+    /// let message = Message {
+    ///     sender: "admin".to_string(),
+    ///     content: "Scheduled maintenance tonight".to_string(),
+    ///     ..Default::default()
+    /// };
+    /// assert!(message.validate_as(Some("admin")).is_ok());
+    /// assert_eq!(message.validate_as(Some("nao")).unwrap_err()[0].field, "sender");
+    /// assert_eq!(message.validate_as(None).unwrap_err()[0].field, "sender");
+    /// ```
+    pub fn validate_as(&self, identity: Option<&str>) -> Result<(), Vec<FieldError>> {
+        self.check(false, identity)
+    }
+
+    /// Checks the message like [`Message::validate`], but accepts reserved sender names. Meant
+    /// for API requests carrying the API key, which identify an operator.
+    ///
+    /// # Example
+    /// ```rust
+    /// use actix_posts::handler::data::Message;
+    /// let message = Message {
+    ///     sender: "Admin".to_string(),
+    ///     content: "Scheduled maintenance tonight".to_string(),
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(message.validate().unwrap_err()[0].field, "sender");
+    /// assert!(message.validate_privileged().is_ok());
+    /// ```
+    pub fn validate_privileged(&self) -> Result<(), Vec<FieldError>> {
+        self.check(true, None)
+    }
+
+    fn check(&self, privileged: bool, identity: Option<&str>) -> Result<(), Vec<FieldError>> {
+        let mut errors = vec![];
+        let max_sender = config::get().max_sender;
+        let sender = self.sender.trim();
+        if sender.is_empty() {
+            errors.push(FieldError::new("sender", "is required".to_string()));
+        } else if self.sender.chars().count() > max_sender {
+            errors.push(FieldError::new(
+                "sender",
+                format!("must be at most {} characters", max_sender),
+            ));
+        } else if !privileged
+            && !identity.is_some_and(|identity| sender.eq_ignore_ascii_case(identity))
+            && config::get()
+                .reserved_senders
+                .iter()
+                .any(|name| sender.eq_ignore_ascii_case(name))
+        {
+            errors.push(FieldError::new("sender", "is a reserved name".to_string()));
+        } else if !config::get().allow_anonymous
+            && self.sender.trim().eq_ignore_ascii_case(ANONYMOUS_SENDER)
+        {
+            errors.push(FieldError::new(
+                "sender",
+                "must be a name, anonymous posts are not allowed".to_string(),
+            ));
+        }
+        let max_content = config::get().max_content;
+        let min_content = config::get().min_content;
+        let content = self.content.trim();
+        if content.is_empty() {
+            errors.push(FieldError::new("content", "is required".to_string()));
+        } else if content.chars().count() < min_content {
+            errors.push(FieldError::new(
+                "content",
+                format!("must be at least {} characters", min_content),
+            ));
+        } else if self.content.chars().count() > max_content {
+            errors.push(FieldError::new(
+                "content",
+                format!("must be at most {} characters", max_content),
+            ));
+        }
+        if config::get().filter_mode == FilterMode::Reject {
+            for (field, value) in [("sender", &self.sender), ("content", &self.content)] {
+                if filter::contains_filtered(value) {
+                    errors.push(FieldError::new(
+                        field,
+                        "contains a filtered word".to_string(),
+                    ));
+                }
+            }
+        }
+        if self.attachments.len() > MAX_ATTACHMENTS {
+            errors.push(FieldError::new(
+                "attachments",
+                format!("must have at most {} entries", MAX_ATTACHMENTS),
+            ));
+        }
+        for (i, attachment) in self.attachments.iter().enumerate() {
+            if !is_web_url(attachment) && !upload::is_upload_path(attachment) {
+                errors.push(FieldError::new(
+                    &format!("attachments[{}]", i),
+                    "must be an http or https URL or an uploaded file".to_string(),
+                ));
+            }
+        }
+        errors.extend(check_tags("tags", &self.tags));
+        if let Some(expires_at) = &self.expires_at {
+            match NaiveDateTime::parse_from_str(expires_at, STORED_FORMAT) {
+                Ok(expiry) if expiry > Utc::now().naive_utc() => {}
+                Ok(_) => errors.push(FieldError::new(
+                    "expires_at",
+                    "must be in the future".to_string(),
+                )),
+                Err(_) => errors.push(FieldError::new(
+                    "expires_at",
+                    "must be a UTC time formatted as YYYY-MM-DD HH:MM:SSZ".to_string(),
+                )),
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! XML rendering of API responses.
+//!
+//! XML 1.0 cannot carry most control characters (e.g. `U+0007`) nor `U+FFFE`/`U+FFFF`, not even
+//! as character references, while the serializer copies them into the document unchanged. A
+//! message containing one would thus produce XML that no client can parse. [`to_string`] checks
+//! the serialized document and, depending on `ACTIX_POSTS_XML_INVALID_CHARS` (see
+//! [`crate::config`]), fails with [`XmlError::InvalidChar`] or strips or replaces such
+//! characters. JSON and the other formats are not affected.
+
+use crate::config;
+use serde::Serialize;
+use std::fmt;
+
+/// The character substituted for invalid characters in [`InvalidCharMode::Replace`].
+pub const REPLACEMENT: char = '\u{FFFD}';
+
+/// What happens to characters that XML cannot represent.
+///
+/// # Variants
+/// - `Reject`: The response is refused with [`XmlError::InvalidChar`] (the default).
+/// - `Strip`: The characters are removed.
+/// - `Replace`: Each character is replaced by [`REPLACEMENT`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InvalidCharMode {
+    #[default]
+    Reject,
+    Strip,
+    Replace,
+}
+
+impl InvalidCharMode {
+    /// Parses a mode from its configuration name (`reject`, `strip`, or `replace`).
+    ///
+    /// Returns `None` for unknown names.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "reject" => Some(InvalidCharMode::Reject),
+            "strip" => Some(InvalidCharMode::Strip),
+            "replace" => Some(InvalidCharMode::Replace),
+            _ => None,
+        }
+    }
+}
+
+/// Why a value could not be rendered as XML.
+///
+/// # Variants
+/// - `InvalidChar(c)`: The value contains `c`, which XML cannot represent.
+/// - `Serialize(reason)`: The serializer failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum XmlError {
+    InvalidChar(char),
+    Serialize(String),
+}
+
+impl fmt::Display for XmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XmlError::InvalidChar(c) => write!(
+                f,
+                "The content contains U+{:04X}, which cannot be represented in XML",
+                *c as u32
+            ),
+            XmlError::Serialize(reason) => write!(f, "XML serialization failed: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for XmlError {}
+
+/// Returns `true` when `c` may appear in an XML 1.0 document.
+///
+/// # Example
+/// ```rust
+/// use actix_posts::handler::xml::is_xml_char;
+/// assert!(is_xml_char('\n'));
+/// assert!(is_xml_char('あ'));
+/// assert!(!is_xml_char('\u{7}'));
+/// assert!(!is_xml_char('\u{FFFF}'));
+/// ```
+pub fn is_xml_char(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\r' | '\u{20}'..='\u{D7FF}' | '\u{E000}'..='\u{FFFD}')
+        || c >= '\u{10000}'
+}
+
+/// Applies `mode` to the characters of `text` that XML cannot represent.
+///
+/// # Returns
+/// - `Ok(text)` with every such character stripped or replaced, or unchanged when there are none.
+/// - `Err(XmlError::InvalidChar(c))` with the first such character in
+///   [`InvalidCharMode::Reject`].
+///
+/// # Example
+/// ```rust
+/// use actix_posts::handler::xml::{clean, InvalidCharMode, XmlError};
+/// let text = "bell\u{7}!".to_string();
+/// assert_eq!(clean(text.clone(), InvalidCharMode::Strip).unwrap(), "bell!");
+/// assert_eq!(clean(text.clone(), InvalidCharMode::Replace).unwrap(), "bell\u{FFFD}!");
+/// assert_eq!(
+///     clean(text, InvalidCharMode::Reject),
+///     Err(XmlError::InvalidChar('\u{7}'))
+/// );
+/// ```
+pub fn clean(text: String, mode: InvalidCharMode) -> Result<String, XmlError> {
+    let Some(invalid) = text.chars().find(|c| !is_xml_char(*c)) else {
+        return Ok(text);
+    };
+    match mode {
+        InvalidCharMode::Reject => Err(XmlError::InvalidChar(invalid)),
+        InvalidCharMode::Strip => Ok(text.chars().filter(|c| is_xml_char(*c)).collect()),
+        InvalidCharMode::Replace => Ok(text
+            .chars()
+            .map(|c| if is_xml_char(c) { c } else { REPLACEMENT })
+            .collect()),
+    }
+}
+
+/// Serializes `value` as an XML document, handling unrepresentable characters as configured.
+pub fn to_string<T: Serialize>(value: &T) -> Result<String, XmlError> {
+    let xml =
+        serde_xml_rs::to_string(value).map_err(|error| XmlError::Serialize(error.to_string()))?;
+    clean(xml, config::get().xml_invalid_chars)
+}
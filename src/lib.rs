@@ -1 +1,5 @@
-pub mod handler;
\ No newline at end of file
+pub mod app;
+pub mod config;
+pub mod handler;
+#[cfg(test)]
+mod testing;
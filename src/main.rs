@@ -1,53 +1,30 @@
-use actix_posts::handler::api::{
-    api_create, api_delete, api_index, api_not_found, api_show, api_update,
-};
-use actix_posts::handler::routes::{create, destroy, edit, index, new, not_found, show, update};
-use actix_session::storage::CookieSessionStore;
-use actix_session::SessionMiddleware;
+use actix_posts::app;
+use actix_posts::config;
+use actix_posts::handler::data;
+use actix_posts::handler::health::probe_storage;
 use actix_web::cookie::Key;
-use actix_web::middleware::Logger;
-use actix_web::{web, App, HttpServer};
-use actix_web_flash_messages::storage::SessionMessageStore;
-use actix_web_flash_messages::FlashMessagesFramework;
 use env_logger::Env;
 use std::io::Result;
+use std::net::TcpListener;
 
-fn build_cookie_session_middleware(key: Key) -> SessionMiddleware<CookieSessionStore> {
-    SessionMiddleware::builder(CookieSessionStore::default(), key).build()
-}
+/// The address the server listens on.
+const ADDRESS: &str = "127.0.0.1:8000";
 
 #[actix_rt::main]
 async fn main() -> Result<()> {
     env_logger::init_from_env(Env::default().default_filter_or("info"));
-    let key = Key::generate();
-    let message_store = SessionMessageStore::default();
-    let message_framework = FlashMessagesFramework::builder(message_store).build();
-    HttpServer::new(move || {
-        let tera = tera::Tera::new("templates/**/*.html").unwrap();
-        App::new()
-            .app_data(web::Data::new(tera))
-            .service(index)
-            .service(new)
-            .service(create)
-            .service(edit)
-            .service(update)
-            .service(destroy)
-            .service(show)
-            .service(
-                web::scope("/api")
-                    .service(api_index)
-                    .service(api_show)
-                    .service(api_create)
-                    .service(api_update)
-                    .service(api_delete)
-                    .default_service(web::to(api_not_found)),
-            )
-            .default_service(web::to(not_found))
-            .wrap(Logger::default())
-            .wrap(message_framework.clone())
-            .wrap(build_cookie_session_middleware(key.clone()))
-    })
-    .bind("127.0.0.1:8000")?
-    .run()
-    .await
+    if config::get().maintenance {
+        log::warn!("maintenance mode is enabled: mutating routes will return 503");
+    }
+    // The static handler needs an existing directory; otherwise it would fall back to the
+    // working directory.
+    std::fs::create_dir_all(&config::get().upload_dir)?;
+    if let Some(count) = data::load_into_memory() {
+        log::info!("serving {} messages from memory", count);
+    }
+    actix_rt::spawn(probe_storage());
+    actix_rt::spawn(data::flush_views_periodically());
+    let result = app::serve(Key::generate(), TcpListener::bind(ADDRESS)?)?.await;
+    data::flush_views();
+    result
 }
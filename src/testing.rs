@@ -0,0 +1,120 @@
+//! Helpers shared by the tests of the crate.
+//!
+//! The configuration and the data layer are process-wide, so tests that read or change them take
+//! a [`TestEnv`] first: it serializes them, points the data, history, changes, views, and upload files
+//! at a fresh temporary directory, and starts from an empty store.
+
+use crate::config;
+use crate::config::Config;
+use crate::handler::data;
+use crate::handler::data::Message;
+use actix_web::http::header;
+use actix_web::test::TestRequest;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Serializes the tests that use a [`TestEnv`].
+static LOCK: Mutex<()> = Mutex::new(());
+
+/// Numbers the temporary directories of one test run.
+static NEXT_DIR: AtomicUsize = AtomicUsize::new(0);
+
+/// The API key configured by [`TestEnv::with`].
+pub const API_KEY: &str = "test-key";
+
+/// An Argon2 hash of the password `secret`, for accounts (see `auth::Account`). Its cost
+/// parameters are low, so logging in stays fast in tests.
+pub const SECRET_HASH: &str =
+    "$argon2id$v=19$m=1024,t=1,p=1$YWN0aXgtcG9zdHMtdGVzdA$Pn578KgMla+fiB7XMq7tbpgGKuPOH5OSj+Y76K8rbIo";
+
+/// An isolated configuration and store, restored when dropped.
+pub struct TestEnv {
+    dir: PathBuf,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl TestEnv {
+    /// Sets up an empty store with the default configuration, adjusted by `configure`, except
+    /// that the API key is [`API_KEY`] and responses are not cached.
+    pub fn with(configure: impl FnOnce(&mut Config)) -> Self {
+        let lock = LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let dir = std::env::temp_dir().join(format!(
+            "actix-posts-test-{}-{}",
+            std::process::id(),
+            NEXT_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_string_lossy().into_owned();
+        let mut config = Config::from_env();
+        config.data_files = vec![path("data.json")];
+        config.history_file = path("history.json");
+        config.changes_file = path("changes.json");
+        config.views_file = path("views.json");
+        config.upload_dir = path("uploads");
+        config.api_key = Some(API_KEY.to_string());
+        config.cache_ttl = 0;
+        configure(&mut config);
+        config::set_override(Some(config));
+        data::reset();
+        TestEnv { dir, _lock: lock }
+    }
+
+    /// Replaces the primary data file with `messages`.
+    pub fn seed(&self, messages: &[Message]) {
+        std::fs::write(
+            &config::get().data_files[0],
+            serde_json::to_string(messages).unwrap(),
+        )
+        .unwrap();
+        data::reset();
+    }
+
+    /// Returns the messages stored in the primary data file, bypassing every cache.
+    pub fn stored(&self) -> Vec<Message> {
+        std::fs::read_to_string(&config::get().data_files[0])
+            .map(|contents| serde_json::from_str(&contents).unwrap())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for TestEnv {
+    fn drop(&mut self) {
+        config::set_override(None);
+        data::reset();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// Returns a message with the given ID, sender, and content, posted at a fixed time.
+pub fn message(id: i32, sender: &str, content: &str) -> Message {
+    Message {
+        id,
+        posted: format!("2024-01-01 00:00:{:02}Z", id.rem_euclid(60)),
+        sender: sender.to_string(),
+        content: content.to_string(),
+        ..Default::default()
+    }
+}
+
+/// Builds the application as served by `main.rs` (see [`crate::app::build`]) and starts it as a
+/// test service. Expands to an `.await`, so it is used inside async tests.
+macro_rules! service {
+    () => {
+        actix_web::test::init_service(crate::app::build(actix_web::cookie::Key::generate())).await
+    };
+}
+pub(crate) use service;
+
+/// Starts a request carrying the API key.
+pub fn with_key(request: TestRequest) -> TestRequest {
+    request.insert_header(("x-api-key", API_KEY))
+}
+
+/// Starts a JSON request body.
+pub fn json(request: TestRequest, body: serde_json::Value) -> TestRequest {
+    request
+        .insert_header((header::CONTENT_TYPE, "application/json"))
+        .set_payload(body.to_string())
+}